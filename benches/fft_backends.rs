@@ -0,0 +1,78 @@
+//! Throughput of the spectrogram backends on a long file:
+//! cargo bench --features fast-fft --bench fft_backends [-- SECONDS]
+
+use std::time::{Duration, Instant};
+
+use analwave::analysers::fft::{FftAmplitude, FftBackend, FftWindow};
+
+const SAMPLE_RATE: usize = 96_000;
+const CHANNELS: usize = 2;
+const FFT_SIZE: usize = 2048;
+
+/// Noise from a linear congruential generator, the same on every run.
+fn noise(frames: usize, seed: u64) -> Vec<f64> {
+    let mut state = seed;
+
+    (0..frames)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((state >> 33) as f64 / (1u64 << 31) as f64 - 0.5) * 2.0 * i32::MAX as f64
+        })
+        .collect()
+}
+
+fn run(backend: FftBackend, channels: &[Vec<f64>]) -> (Duration, Vec<Vec<Vec<f64>>>) {
+    let start = Instant::now();
+    let spectra = channels
+        .iter()
+        .map(|data| {
+            backend.spectrogram(
+                data,
+                FFT_SIZE,
+                FFT_SIZE / 2,
+                FftWindow::Hann,
+                FftAmplitude::Db,
+            )
+        })
+        .collect();
+
+    (start.elapsed(), spectra)
+}
+
+fn main() {
+    let seconds: usize = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(120);
+    let channels: Vec<Vec<f64>> = (0..CHANNELS)
+        .map(|channel| noise(seconds * SAMPLE_RATE, channel as u64 + 1))
+        .collect();
+
+    println!(
+        "{seconds} s of {CHANNELS} channels at {SAMPLE_RATE} Hz, {FFT_SIZE} bins, hop {}",
+        FFT_SIZE / 2
+    );
+
+    let (aus, reference) = run(FftBackend::Aus, &channels);
+    let (fast, spectra) = run(FftBackend::Fast, &channels);
+
+    let deviation = reference
+        .iter()
+        .flatten()
+        .flatten()
+        .zip(spectra.iter().flatten().flatten())
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f64::max);
+
+    for (name, elapsed) in [("aus", aus), ("fast", fast)] {
+        println!(
+            "{name:<5} {:>8.3} s  {:>7.1}x realtime",
+            elapsed.as_secs_f64(),
+            seconds as f64 / elapsed.as_secs_f64()
+        );
+    }
+    println!("speedup {:.2}x", aus.as_secs_f64() / fast.as_secs_f64());
+    println!("largest difference {deviation:.3e} dB");
+}
@@ -0,0 +1,116 @@
+//! A plugin that counts clicks, jumps between consecutive samples above a threshold:
+//! cargo build --example clicks_plugin --features plugins
+//! analwave -i file.wav --plugin target/debug/examples/libclicks_plugin.so \
+//!     --plugin-option clicks.threshold=0.5
+
+use std::collections::BTreeMap;
+
+use analwave::{
+    Error, Result,
+    analysers::{Analyser, AnalysisResult},
+    cli::AnalyseArgs,
+    json::Analysis,
+    registry::{Registration, Registry},
+    schema::SchemaEntry,
+    stream::StreamSpec,
+};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+/// Clicks listed in the output, the count includes all of them.
+const MAX_TIMES: usize = 100;
+
+#[derive(Serialize, JsonSchema)]
+struct ClickAnalysis {
+    /// Smallest jump counted as a click, as a fraction of full scale
+    threshold: f64,
+    count: usize,
+    /// Seconds of the first clicks
+    times: Vec<f64>,
+}
+
+struct ClickAnalyser {
+    count: usize,
+    previous: Vec<i32>,
+    sample_rate: f64,
+    threshold: f64,
+    times: Vec<f64>,
+}
+
+impl ClickAnalyser {
+    fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Self> {
+        let threshold = match args.plugin_option("clicks.threshold") {
+            Some(value) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|threshold| *threshold > 0.0 && *threshold <= 2.0)
+                .ok_or_else(|| {
+                    Error::Config(format!(
+                        "clicks.threshold has to be above 0 and at most 2, got {value}"
+                    ))
+                })?,
+            None => 0.5,
+        };
+
+        Ok(Self {
+            count: 0,
+            previous: vec![0; spec.channels as usize],
+            sample_rate: spec.sample_rate as f64,
+            threshold,
+            times: Vec::new(),
+        })
+    }
+}
+
+impl Analyser for ClickAnalyser {
+    fn name(&self) -> &'static str {
+        "clicks"
+    }
+
+    fn analyse(&mut self, _label: &str, frame_counter: usize, frame: &[i32]) {
+        let limit = self.threshold * i32::MAX as f64;
+        let click = frame
+            .iter()
+            .zip(&self.previous)
+            .any(|(sample, previous)| (*sample as f64 - *previous as f64).abs() > limit);
+        self.previous.copy_from_slice(frame);
+
+        if click && frame_counter > 0 {
+            self.count += 1;
+            if self.times.len() < MAX_TIMES {
+                self.times.push(frame_counter as f64 / self.sample_rate);
+            }
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let clicks = ClickAnalysis {
+            threshold: self.threshold,
+            count: self.count,
+            times: self.times.clone(),
+        };
+        let value = serde_json::to_value(clicks).map_err(|err| Error::analyser("clicks", err))?;
+
+        Ok(AnalysisResult::new(Analysis {
+            plugins: BTreeMap::from([("clicks".to_string(), value)]),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<ClickAnalysis>("clicks", generator)]
+    }
+}
+
+fn register(registry: &mut Registry) {
+    registry.register(Registration {
+        name: "clicks",
+        flags: &["--plugin-option clicks.threshold=<FRACTION>"],
+        keys: &["clicks"],
+        // Loading the plugin is what enables it
+        enabled: |_| true,
+        build: |args, spec| Ok(Box::new(ClickAnalyser::new(args, spec)?)),
+    });
+}
+
+analwave::declare_plugin!(register);
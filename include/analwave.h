@@ -0,0 +1,34 @@
+/* C interface of analwave, built with `cargo build --release --features ffi`. */
+
+#ifndef ANALWAVE_H
+#define ANALWAVE_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Analyses the WAV file at `path` with the options of `analwave analyse` given as a JSON
+ * object of long option names, e.g. {"underrun": true, "silence": true, "lufs": -50}.
+ * `config_json` may be NULL for the defaults. Output files the options ask for are written
+ * as on the command line.
+ *
+ * Returns the JSON report, to be released with analwave_free_string(), or NULL on errors.
+ */
+char *analwave_analyse_file(const char *path, const char *config_json);
+
+/* Return code bits of the last analysis on this thread, or the exit code of its error. */
+int analwave_last_exit_code(void);
+
+/* Error message of the last analysis on this thread, NULL if it succeeded. A panic inside
+ * the analysis fails the call with exit code 70 instead of unwinding into the caller. */
+const char *analwave_last_error(void);
+
+/* Releases a report returned by analwave_analyse_file(). */
+void analwave_free_string(char *report);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
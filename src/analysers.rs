@@ -1,14 +1,86 @@
-use wavers::Samples;
-
+pub mod activity;
+pub mod alignment;
+pub mod balance;
+pub mod bitdepth;
+pub mod codec;
+pub mod compliance;
+pub mod container;
+pub mod discontinuity;
+pub mod drift;
+pub mod dtmf;
+pub mod dynamics;
+#[cfg(feature = "fast-fft")]
+pub mod fast_fft;
 pub mod fft;
+pub mod filterbank;
+pub mod format;
+pub mod key;
 pub mod loudness;
+pub mod ltc;
+pub mod meter;
+pub mod normalize;
+pub mod novelty;
 pub mod peaks;
+pub mod pitch;
+pub mod portable;
+pub mod pumping;
+pub mod ratecheck;
+pub mod spectral;
+pub mod stereo;
+pub mod target;
+pub mod tempo;
+pub mod tone;
 pub mod underruns;
+pub mod vad;
+
+use schemars::SchemaGenerator;
+
+use crate::{Result, findings::Finding, json::Analysis, schema::SchemaEntry};
+
+/// What an analyser found, rendered by the [`crate::console`] and [`crate::json`] outputs.
+#[derive(Default)]
+pub struct AnalysisResult {
+    /// Return code bits of the checks that failed
+    pub flags: u8,
+    /// The segments and metrics, as the entries of the JSON output
+    pub analysis: Analysis,
+    /// Why the checks failed, e.g. a DR below `--min-dr`
+    pub failures: Vec<String>,
+}
+
+impl AnalysisResult {
+    pub fn new(analysis: Analysis) -> Self {
+        Self {
+            analysis,
+            ..Self::default()
+        }
+    }
+
+    /// Fails the checks with `flag` because of `failure`.
+    pub fn fail(&mut self, flag: u8, failure: String) {
+        self.flags |= flag;
+        self.failures.push(failure);
+    }
+}
 
-pub trait Analyser {
-    fn analyse(&mut self, label: &str, frame_counter: usize, frame: &Samples<i32>);
-    fn finish(&mut self, label: &str) -> u8;
-    fn json(&self) -> Vec<(String, serde_json::Value)> {
+pub trait Analyser: Send {
+    /// Name of the analyser in the timings.
+    fn name(&self) -> &'static str;
+    /// Events found while streaming, like the start of a silence, are printed as they happen.
+    fn analyse(&mut self, label: &str, frame_counter: usize, frame: &[i32]);
+    /// Ends the analysis at `label`, the position after the last frame, and returns the
+    /// results. Events still open at the end of the stream are printed here.
+    fn finish(&mut self, label: &str) -> Result<AnalysisResult>;
+    /// The stream was stopped after `num_frames` frames, short of its announced length.
+    /// Called before [`Analyser::finish`], which then reports up to there.
+    fn stop(&mut self, _num_frames: usize) {}
+    /// Segments that ended since the last call, for callers that follow the analysis while
+    /// streaming, e.g. [`crate::session::Session`]. Segments still open aren't included.
+    fn take_events(&mut self) -> Vec<Finding> {
+        Vec::new()
+    }
+    /// JSON Schema of the entries in the [`AnalysisResult::analysis`] of the analyser.
+    fn schema(&self, _generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
         Vec::new()
     }
 }
@@ -0,0 +1,170 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Time constant of the envelope the activity is measured on, and how long a channel stays
+/// active after it fell below the threshold, as for the activity factor of ITU-T P.56.
+const ENVELOPE_SECONDS: f64 = 0.03;
+const HANGOVER_SECONDS: f64 = 0.2;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Serialize, JsonSchema)]
+pub struct ActivityChannel {
+    pub channel: usize,
+    /// Share of the consecutive samples with a different sign, point `i` over the interval
+    /// from `i / rate` seconds
+    #[serde(rename = "zeroCrossingRate")]
+    pub zero_crossing_rate: Vec<f64>,
+    /// Share of the samples the channel is active in, per interval
+    pub activity: Vec<f64>,
+    /// Zero-crossing rate over the whole file
+    #[serde(rename = "meanZeroCrossingRate")]
+    pub mean_zero_crossing_rate: f64,
+    /// Share of the whole file the channel is active in
+    #[serde(rename = "activityFactor")]
+    pub activity_factor: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ActivityAnalysis {
+    pub channels: Vec<ActivityChannel>,
+    /// Points per second
+    pub rate: f64,
+    /// Level in dBFS the envelope has to reach for a channel to be active
+    pub threshold: f64,
+}
+
+#[derive(Clone, Default)]
+struct ChannelState {
+    previous: i32,
+    envelope: f64,
+    /// Frames left until the channel turns inactive
+    hangover: usize,
+    crossings: usize,
+    active: usize,
+    total_crossings: usize,
+    total_active: usize,
+    zero_crossing_rate: Vec<f64>,
+    activity: Vec<f64>,
+}
+
+/// Zero-crossing rate and activity of every channel, as a time series at `--activity-rate`.
+/// A channel is active while the envelope of its absolute value is above
+/// `--activity-threshold`, and for a short hangover after.
+pub struct ActivityAnalyser {
+    alpha: f64,
+    channels: Vec<ChannelState>,
+    frames: usize,
+    hangover_frames: usize,
+    interval_frames: usize,
+    /// Frames in the current interval
+    interval: usize,
+    rate: f64,
+    threshold: f64,
+    threshold_db: f64,
+}
+
+impl ActivityAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+
+        Self {
+            alpha: 1.0 - (-1.0 / (ENVELOPE_SECONDS * sample_rate)).exp(),
+            channels: vec![ChannelState::default(); spec.channels as usize],
+            frames: 0,
+            hangover_frames: (HANGOVER_SECONDS * sample_rate) as usize,
+            interval_frames: ((sample_rate / args.activity_rate).round() as usize).max(1),
+            interval: 0,
+            rate: args.activity_rate,
+            threshold: 10f64.powf(args.activity_threshold / 20.0),
+            threshold_db: args.activity_threshold,
+        }
+    }
+
+    fn finish_interval(&mut self) {
+        let frames = self.interval.max(1) as f64;
+
+        for state in self.channels.iter_mut() {
+            state
+                .zero_crossing_rate
+                .push(state.crossings as f64 / frames);
+            state.activity.push(state.active as f64 / frames);
+            state.crossings = 0;
+            state.active = 0;
+        }
+
+        self.interval = 0;
+    }
+}
+
+impl Analyser for ActivityAnalyser {
+    fn name(&self) -> &'static str {
+        "activity"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (state, sample) in self.channels.iter_mut().zip(frame.iter()) {
+            // The first sample has nothing to cross from
+            if self.frames > 0 && (*sample < 0) != (state.previous < 0) {
+                state.crossings += 1;
+                state.total_crossings += 1;
+            }
+            state.previous = *sample;
+
+            let level = (*sample as f64 / FULL_SCALE).abs();
+            state.envelope += self.alpha * (level - state.envelope);
+            if state.envelope >= self.threshold {
+                state.hangover = self.hangover_frames;
+            }
+            if state.envelope >= self.threshold || state.hangover > 0 {
+                state.active += 1;
+                state.total_active += 1;
+                state.hangover = state.hangover.saturating_sub(1);
+            }
+        }
+
+        self.frames += 1;
+        self.interval += 1;
+        if self.interval == self.interval_frames {
+            self.finish_interval();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        if self.interval > 0 {
+            self.finish_interval();
+        }
+
+        let frames = self.frames.max(1) as f64;
+        let channels = self
+            .channels
+            .iter_mut()
+            .enumerate()
+            .map(|(channel, state)| ActivityChannel {
+                channel,
+                zero_crossing_rate: std::mem::take(&mut state.zero_crossing_rate),
+                activity: std::mem::take(&mut state.activity),
+                mean_zero_crossing_rate: state.total_crossings as f64 / frames,
+                activity_factor: state.total_active as f64 / frames,
+            })
+            .collect();
+
+        Ok(AnalysisResult::new(Analysis {
+            activity: Some(ActivityAnalysis {
+                channels,
+                rate: self.rate,
+                threshold: self.threshold_db,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<ActivityAnalysis>(
+            "activity", generator,
+        )]
+    }
+}
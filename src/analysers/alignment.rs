@@ -0,0 +1,226 @@
+use aus::spectrum::{irfft, rfft};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult, drift::parabolic};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Windows whose normalized correlation peak is below this are too incoherent to measure.
+const MIN_CORRELATION: f64 = 0.3;
+
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct AlignmentWindow {
+    /// Start of the window in seconds
+    pub time: f64,
+    /// Delay of the second channel against the first in samples, positive if it lags,
+    /// `null` if the channels are silent or too different to tell
+    pub delay: Option<f64>,
+    /// Normalized height of the correlation peak
+    pub correlation: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AlignmentResult {
+    pub channels: [usize; 2],
+    /// Median delay over the windows in samples, positive if the second channel lags
+    pub delay: Option<f64>,
+    /// Delay furthest from zero in any window
+    #[serde(rename = "maxDelay")]
+    pub max_delay: Option<f64>,
+    /// Mean correlation of the windows with a delay
+    pub correlation: Option<f64>,
+    pub windows: Vec<AlignmentWindow>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct AlignmentAnalysis {
+    pub results: Vec<AlignmentResult>,
+    /// Length of the windows in seconds
+    #[serde(rename = "windowSize")]
+    pub window_size: f64,
+    /// Largest delay in samples that passes, from --max-skew-samples
+    #[serde(rename = "maxSkew")]
+    pub max_skew: Option<usize>,
+}
+
+/// Measures the delay between every pair of channels from the peak of their cross-correlation
+/// in windows of `--window-size`, e.g. a channel one sample behind after bad interleaving.
+pub struct AlignmentAnalyser {
+    buffers: Vec<Vec<f64>>,
+    fft_size: usize,
+    frames: usize,
+    max_skew: Option<usize>,
+    pairs: Vec<([usize; 2], Vec<AlignmentWindow>)>,
+    sample_rate: f64,
+    window_frames: usize,
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+
+    match values.len() {
+        0 => None,
+        n if n.is_multiple_of(2) => Some((values[middle - 1] + values[middle]) / 2.0),
+        _ => Some(values[middle]),
+    }
+}
+
+impl AlignmentAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let channels = spec.channels as usize;
+        let window_frames = ((spec.sample_rate as f32 * args.window_size) as usize).max(2);
+        let pairs = (0..channels)
+            .flat_map(|a| (a + 1..channels).map(move |b| ([a, b], Vec::new())))
+            .collect();
+
+        Self {
+            buffers: vec![Vec::with_capacity(window_frames); channels],
+            // Zero-padded to twice the window so the correlation is linear, not circular
+            fft_size: (2 * window_frames).next_power_of_two(),
+            frames: 0,
+            max_skew: args.max_skew_samples,
+            pairs,
+            sample_rate: spec.sample_rate as f64,
+            window_frames,
+        }
+    }
+
+    fn finish_window(&mut self) {
+        let length = self.buffers[0].len();
+        let time = (self.frames - length) as f64 / self.sample_rate;
+        let fft_size = self.fft_size;
+
+        let energies: Vec<f64> = self
+            .buffers
+            .iter()
+            .map(|buffer| buffer.iter().map(|v| v * v).sum())
+            .collect();
+        let spectra: Vec<_> = self
+            .buffers
+            .iter_mut()
+            .map(|buffer| {
+                buffer.resize(fft_size, 0.0);
+                rfft(buffer, fft_size)
+            })
+            .collect();
+
+        for ([a, b], windows) in self.pairs.iter_mut() {
+            let mut window = AlignmentWindow {
+                time,
+                delay: None,
+                correlation: None,
+            };
+
+            let norm = (energies[*a] * energies[*b]).sqrt() * fft_size as f64;
+            let cross: Vec<_> = spectra[*a]
+                .iter()
+                .zip(spectra[*b].iter())
+                .map(|(a, b)| a.conj() * b)
+                .collect();
+
+            if norm > 0.0
+                && let Ok(correlation) = irfft(&cross, fft_size)
+            {
+                // Lag l sits at index l, negative lags wrap around to the end
+                let at = |lag: i64| correlation[lag.rem_euclid(fft_size as i64) as usize];
+                let max_lag = (length / 2) as i64;
+                let best = (-max_lag..=max_lag)
+                    .max_by(|x, y| at(*x).total_cmp(&at(*y)))
+                    .unwrap_or(0);
+
+                let peak = at(best) / norm;
+                if peak >= MIN_CORRELATION {
+                    window.delay =
+                        Some(best as f64 + parabolic(at(best - 1), at(best), at(best + 1)));
+                    window.correlation = Some(peak);
+                }
+            }
+
+            windows.push(window);
+        }
+
+        for buffer in self.buffers.iter_mut() {
+            buffer.clear();
+        }
+    }
+
+    fn result(&self, channels: [usize; 2], windows: &[AlignmentWindow]) -> AlignmentResult {
+        let mut delays: Vec<f64> = windows.iter().filter_map(|window| window.delay).collect();
+        let correlations: Vec<f64> = windows
+            .iter()
+            .filter_map(|window| window.correlation)
+            .collect();
+
+        AlignmentResult {
+            channels,
+            max_delay: delays
+                .iter()
+                .copied()
+                .max_by(|a, b| a.abs().total_cmp(&b.abs())),
+            delay: median(&mut delays),
+            correlation: (!correlations.is_empty())
+                .then(|| correlations.iter().sum::<f64>() / correlations.len() as f64),
+            windows: windows.to_vec(),
+        }
+    }
+}
+
+impl Analyser for AlignmentAnalyser {
+    fn name(&self) -> &'static str {
+        "alignment"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (buffer, sample) in self.buffers.iter_mut().zip(frame.iter()) {
+            buffer.push(*sample as f64);
+        }
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(self.window_frames) && !self.pairs.is_empty() {
+            self.finish_window();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        // Short files still get one window, a short tail of a long file is dropped
+        let remaining = self.buffers.first().map_or(0, |buffer| buffer.len());
+        if remaining > 0 && (remaining >= self.window_frames / 4 || self.frames == remaining) {
+            self.finish_window();
+        }
+
+        let results: Vec<AlignmentResult> = self
+            .pairs
+            .iter()
+            .map(|(channels, windows)| self.result(*channels, windows))
+            .collect();
+
+        let skewed = self.max_skew.is_some_and(|max| {
+            results
+                .iter()
+                .filter_map(|result| result.max_delay)
+                .any(|delay| delay.abs().round() > max as f64)
+        });
+
+        let mut result = AnalysisResult::new(Analysis {
+            alignment: Some(AlignmentAnalysis {
+                results,
+                window_size: self.window_frames as f64 / self.sample_rate,
+                max_skew: self.max_skew,
+            }),
+            ..Analysis::default()
+        });
+        if skewed {
+            result.flags = crate::ERR_CHANNEL_IMBALANCE;
+        }
+
+        Ok(result)
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<AlignmentAnalysis>(
+            "alignment",
+            generator,
+        )]
+    }
+}
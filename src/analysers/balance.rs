@@ -0,0 +1,260 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Windows are flagged at this imbalance when no --max-channel-imbalance-db is given.
+const DEFAULT_IMBALANCE_DB: f64 = 6.0;
+
+/// Channels below this RMS level count as dead.
+const DEAD_DB: f64 = -90.0;
+
+/// Windows where even the loudest channel is below this level are not compared.
+const QUIET_DB: f64 = -70.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+fn rms_db(sum_squares: f64, frames: usize) -> f64 {
+    if frames == 0 || sum_squares <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+
+    20.0 * ((sum_squares / frames as f64).sqrt() / FULL_SCALE).log10()
+}
+
+#[derive(Clone, Default)]
+struct ChannelState {
+    sum_squares: f64,
+    peak: i64,
+    window_sum_squares: f64,
+}
+
+struct InternalSegment {
+    start: usize,
+    end: usize,
+    channel: usize,
+    imbalance: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ChannelLevel {
+    pub channel: usize,
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub rms: f64,
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub peak: f64,
+    /// dB below the loudest channel
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub imbalance: f64,
+    pub dead: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ImbalanceSegment {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+    pub channel: usize,
+    /// Largest imbalance within the segment, in dB below the loudest channel
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub imbalance: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ChannelBalanceAnalysis {
+    pub channels: Vec<ChannelLevel>,
+    pub results: Vec<ImbalanceSegment>,
+    /// Imbalance in dB above which windows are reported
+    pub threshold: f64,
+    #[serde(rename = "maxImbalance")]
+    pub max_imbalance: Option<f64>,
+}
+
+/// Compares per-channel RMS over the whole file and per window to find dead or quiet channels.
+pub struct ChannelBalanceAnalyser {
+    channels: Vec<ChannelState>,
+    frames: usize,
+    max_imbalance: Option<f64>,
+    /// Open imbalanced stretch per channel
+    open: Vec<Option<InternalSegment>>,
+    sample_rate: i32,
+    segments: Vec<InternalSegment>,
+    window_frames: usize,
+}
+
+impl ChannelBalanceAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let channels = spec.channels as usize;
+
+        Self {
+            channels: vec![ChannelState::default(); channels],
+            frames: 0,
+            max_imbalance: args.max_channel_imbalance_db,
+            open: (0..channels).map(|_| None).collect(),
+            sample_rate: spec.sample_rate,
+            segments: Vec::new(),
+            window_frames: ((spec.sample_rate as f32 * args.window_size) as usize).max(1),
+        }
+    }
+
+    fn threshold(&self) -> f64 {
+        self.max_imbalance.unwrap_or(DEFAULT_IMBALANCE_DB)
+    }
+
+    fn finish_window(&mut self, window_frames: usize) {
+        let levels: Vec<f64> = self
+            .channels
+            .iter()
+            .map(|state| rms_db(state.window_sum_squares, window_frames))
+            .collect();
+        let loudest = levels.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let start = self.frames - window_frames;
+
+        for (channel, level) in levels.iter().enumerate() {
+            let imbalance = loudest - level;
+
+            if loudest >= QUIET_DB && imbalance > self.threshold() {
+                match &mut self.open[channel] {
+                    Some(segment) => {
+                        segment.end = self.frames;
+                        segment.imbalance = segment.imbalance.max(imbalance);
+                    }
+                    open => {
+                        *open = Some(InternalSegment {
+                            start,
+                            end: self.frames,
+                            channel,
+                            imbalance,
+                        });
+                    }
+                }
+            } else if let Some(segment) = self.open[channel].take() {
+                self.segments.push(segment);
+            }
+        }
+
+        for state in self.channels.iter_mut() {
+            state.window_sum_squares = 0.0;
+        }
+    }
+
+    fn levels(&self) -> Vec<ChannelLevel> {
+        let rms: Vec<f64> = self
+            .channels
+            .iter()
+            .map(|state| rms_db(state.sum_squares, self.frames))
+            .collect();
+        let loudest = rms.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+        self.channels
+            .iter()
+            .zip(rms)
+            .enumerate()
+            .map(|(channel, (state, rms))| ChannelLevel {
+                channel,
+                rms,
+                peak: 20.0 * (state.peak as f64 / FULL_SCALE).log10(),
+                imbalance: if loudest.is_finite() {
+                    loudest - rms
+                } else {
+                    0.0
+                },
+                dead: rms < DEAD_DB,
+            })
+            .collect()
+    }
+}
+
+impl Analyser for ChannelBalanceAnalyser {
+    fn name(&self) -> &'static str {
+        "channel balance"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (state, sample) in self.channels.iter_mut().zip(frame.iter()) {
+            let value = *sample as f64;
+            let square = value * value;
+
+            state.sum_squares += square;
+            state.window_sum_squares += square;
+            state.peak = state.peak.max((*sample as i64).abs());
+        }
+
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(self.window_frames) {
+            self.finish_window(self.window_frames);
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let remaining = self.frames % self.window_frames;
+        if remaining > 0 {
+            self.finish_window(remaining);
+        }
+
+        for open in self.open.iter_mut() {
+            if let Some(segment) = open.take() {
+                self.segments.push(segment);
+            }
+        }
+        self.segments.sort_by_key(|segment| segment.start);
+
+        let levels = self.levels();
+        let mut imbalanced = self
+            .max_imbalance
+            .is_some_and(|max| levels.iter().any(|level| level.imbalance > max));
+
+        // Windowed segments are only collected above the threshold
+        if self.max_imbalance.is_some() && !self.segments.is_empty() {
+            imbalanced = true;
+        }
+
+        let segments: Vec<ImbalanceSegment> = self
+            .segments
+            .iter()
+            .map(|seg| {
+                let duration_samples = seg.end - seg.start;
+                ImbalanceSegment {
+                    start: seg.start as f32 / self.sample_rate as f32,
+                    end: seg.end as f32 / self.sample_rate as f32,
+                    duration: duration_samples as f32 / self.sample_rate as f32,
+                    start_sample: seg.start,
+                    end_sample: seg.end,
+                    duration_samples,
+                    channel: seg.channel,
+                    imbalance: seg.imbalance,
+                }
+            })
+            .collect();
+
+        let mut result = AnalysisResult::new(Analysis {
+            channel_balance: Some(ChannelBalanceAnalysis {
+                channels: levels,
+                results: segments,
+                threshold: self.threshold(),
+                max_imbalance: self.max_imbalance,
+            }),
+            ..Analysis::default()
+        });
+        if imbalanced {
+            result.flags = crate::ERR_CHANNEL_IMBALANCE;
+        }
+
+        Ok(result)
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<ChannelBalanceAnalysis>(
+            "channelBalance",
+            generator,
+        )]
+    }
+}
@@ -0,0 +1,150 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+#[derive(Serialize, JsonSchema)]
+pub struct BitDepthChannel {
+    pub channel: usize,
+    /// Bits from the top down to the lowest one that is ever set, `null` if the channel is
+    /// silent
+    #[serde(rename = "effectiveBits")]
+    pub effective_bits: Option<u32>,
+    /// Bits of the used range that never change, counted from the least significant bit of
+    /// the declared depth. Only checked if the channel has positive and negative samples,
+    /// otherwise the top bits don't change either.
+    #[serde(rename = "stuckBits")]
+    pub stuck_bits: Vec<u32>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct BitDepthAnalysis {
+    /// Bits per sample of the file header, `null` for floats or if unknown
+    #[serde(rename = "declaredBits")]
+    pub declared_bits: Option<u16>,
+    /// Most bits used by any channel, `null` if all are silent
+    #[serde(rename = "effectiveBits")]
+    pub effective_bits: Option<u32>,
+    /// Fewer bits are used than declared, or some bits are stuck
+    pub mismatch: bool,
+    pub channels: Vec<BitDepthChannel>,
+}
+
+/// Bits of one channel that were set in any and in every sample.
+#[derive(Clone, Copy)]
+struct BitUsage {
+    any: u32,
+    all: u32,
+    negative: bool,
+    positive: bool,
+}
+
+/// Finds out which bits of the samples actually toggle, e.g. a 24 bit file that is really 16
+/// bit with the low 8 bits always zero, or a least significant bit stuck at one.
+pub struct BitDepthAnalyser {
+    declared_bits: Option<u16>,
+    usage: Vec<BitUsage>,
+}
+
+impl BitDepthAnalyser {
+    pub fn new(spec: &StreamSpec) -> Self {
+        Self {
+            declared_bits: spec.bits_per_sample,
+            usage: vec![
+                BitUsage {
+                    any: 0,
+                    all: u32::MAX,
+                    negative: false,
+                    positive: false,
+                };
+                spec.channels as usize
+            ],
+        }
+    }
+
+    fn channel(&self, channel: usize, usage: &BitUsage) -> BitDepthChannel {
+        if usage.any == 0 {
+            return BitDepthChannel {
+                channel,
+                effective_bits: None,
+                stuck_bits: Vec::new(),
+            };
+        }
+
+        let lowest = usage.any.trailing_zeros();
+        // Bits below the declared depth are padding of the 32 bit samples
+        let padding = 32 - self.declared_bits.map_or(32, |bits| bits.min(32) as u32);
+
+        // Below the sign bit, which toggles with the sign of the samples
+        let stuck_bits = if usage.negative && usage.positive {
+            (lowest.max(padding)..31)
+                .filter(|bit| {
+                    let mask = 1 << bit;
+                    usage.all & mask != 0 || usage.any & mask == 0
+                })
+                .map(|bit| bit - padding)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        BitDepthChannel {
+            channel,
+            effective_bits: Some(32 - lowest),
+            stuck_bits,
+        }
+    }
+}
+
+impl Analyser for BitDepthAnalyser {
+    fn name(&self) -> &'static str {
+        "bit depth"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (usage, sample) in self.usage.iter_mut().zip(frame) {
+            let bits = *sample as u32;
+            usage.any |= bits;
+            usage.all &= bits;
+            usage.negative |= *sample < 0;
+            usage.positive |= *sample > 0;
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let channels: Vec<BitDepthChannel> = self
+            .usage
+            .iter()
+            .enumerate()
+            .map(|(channel, usage)| self.channel(channel, usage))
+            .collect();
+
+        let effective_bits = channels
+            .iter()
+            .filter_map(|channel| channel.effective_bits)
+            .max();
+        let mismatch = channels
+            .iter()
+            .any(|channel| !channel.stuck_bits.is_empty())
+            || effective_bits
+                .zip(self.declared_bits)
+                .is_some_and(|(effective, declared)| effective < declared as u32);
+
+        Ok(AnalysisResult::new(Analysis {
+            bit_depth: Some(BitDepthAnalysis {
+                declared_bits: self.declared_bits,
+                effective_bits,
+                mismatch,
+                channels,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<BitDepthAnalysis>(
+            "bitDepth", generator,
+        )]
+    }
+}
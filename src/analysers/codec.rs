@@ -0,0 +1,406 @@
+use std::collections::VecDeque;
+
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{
+    Analyser, AnalysisResult,
+    fft::{FftAmplitude, FftWindow},
+    portable,
+    vad::Biquad,
+};
+use crate::{Result, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Short slices, about a codec frame, so bands that drop out for a frame or two show up.
+const FFT_SIZE: usize = 1024;
+
+const HOP: usize = FFT_SIZE / 2;
+
+/// Bins per band, about 190 Hz at 48 kHz, the order of a codec scale factor band at the top.
+const BAND_BINS: usize = 4;
+
+/// Slices quieter than this say nothing about the codec, in dBFS.
+const MIN_LEVEL_DB: f64 = -60.0;
+
+/// Drop of the long-term spectrum at a lowpass shelf, in dB.
+const CUTOFF_DB: f64 = 30.0;
+
+/// Broadband stretch below a shelf and gap to the bands above it, in bands.
+const SHELF_BANDS: usize = 8;
+const TRANSITION_BANDS: usize = 2;
+
+/// Shelves this close to Nyquist are the anti-aliasing filter of a resampler or converter.
+const NYQUIST_MARGIN: f64 = 0.93;
+
+/// Holes are bands this far below the bands around them, which are no more than
+/// `HOLE_RANGE_DB` below the loudest band of the slice.
+const HOLE_DB: f64 = 30.0;
+const HOLE_RANGE_DB: f64 = 50.0;
+
+/// Bands on either side a hole is compared to, leaving out the nearest ones so holes can be
+/// several bands wide.
+const CONTEXT_BANDS: usize = 8;
+const CONTEXT_GAP: usize = 2;
+
+/// Lowest band checked for holes, in Hz.
+const HOLE_LOW_HZ: f64 = 2000.0;
+
+/// Share of checked bands that are holes at which their score is 1.
+const HOLE_RATE_FULL: f64 = 0.02;
+
+/// Pre-echo is tracked in 2 ms blocks of the signal above 4 kHz.
+const BLOCK_SECONDS: f64 = 0.002;
+const PRE_ECHO_HIGHPASS_HZ: f64 = 4000.0;
+
+/// Blocks right before a transient where a codec smears its noise (about one frame) and
+/// the blocks before them the level is compared to.
+const PRE_BLOCKS: usize = 10;
+const REFERENCE_BLOCKS: usize = 10;
+
+/// Rise of a block over the blocks before it that makes a transient, and its minimum level.
+const TRANSIENT_DB: f64 = 15.0;
+const TRANSIENT_MIN_DB: f64 = -50.0;
+
+/// Rise of the pre blocks over the reference blocks that counts as pre-echo.
+const PRE_ECHO_DB: f64 = 6.0;
+
+/// Fewer transients than this are too few to judge pre-echo by.
+const MIN_TRANSIENTS: usize = 10;
+
+/// Share of transients with pre-echo at which its score is 1.
+const PRE_ECHO_SHARE_FULL: f64 = 0.5;
+
+/// Weight of each kind of evidence in the combined score.
+const CUTOFF_WEIGHT: f64 = 0.7;
+const HOLES_WEIGHT: f64 = 0.6;
+const PRE_ECHO_WEIGHT: f64 = 0.4;
+
+/// Combined score from which the input is reported as a likely transcode.
+const LIKELY: f64 = 0.5;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum EvidenceKind {
+    /// Steep lowpass shelf of the long-term spectrum below Nyquist
+    Cutoff,
+    /// Bands dropping out from one slice to the next
+    SpectralHoles,
+    /// Noise rising right before transients
+    PreEcho,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CodecEvidence {
+    pub kind: EvidenceKind,
+    /// Frequency band the evidence was found in, in Hz
+    #[serde(rename = "lowFrequency")]
+    pub low_frequency: f64,
+    #[serde(rename = "highFrequency")]
+    pub high_frequency: f64,
+    /// Strength of the evidence from 0 to 1
+    pub score: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CodecAnalysis {
+    /// Likelihood that the input went through a lossy codec, from 0 to 1
+    pub score: f64,
+    #[serde(rename = "likelyTranscode")]
+    pub likely_transcode: bool,
+    /// Frequency of the lowpass shelf in Hz, `null` if there is none
+    pub cutoff: Option<f64>,
+    /// Share of the checked bands that dropped out
+    #[serde(rename = "holeRate")]
+    pub hole_rate: f64,
+    pub transients: usize,
+    /// Transients with noise rising right before them
+    #[serde(rename = "preEchoes")]
+    pub pre_echoes: usize,
+    /// Only kinds with a score above 0
+    pub evidence: Vec<CodecEvidence>,
+}
+
+fn db(power: f64) -> f64 {
+    10.0 * power.max(1e-30).log10()
+}
+
+/// Mean level in dB of the bands around `band`.
+fn context(levels: &[f64], band: usize) -> f64 {
+    let around = (band.saturating_sub(CONTEXT_BANDS)..(band + CONTEXT_BANDS + 1).min(levels.len()))
+        .filter(|other| other.abs_diff(band) > CONTEXT_GAP);
+    let (sum, count) = around.fold((0.0, 0), |(sum, count), other| {
+        (sum + levels[other], count + 1)
+    });
+    sum / count.max(1) as f64
+}
+
+/// Level of the mean power of blocks given in dB.
+fn mean_db<'a>(levels: impl Iterator<Item = &'a f64>) -> f64 {
+    let (sum, count) = levels.fold((0.0, 0), |(sum, count), level| {
+        (sum + 10f64.powf(level / 10.0), count + 1)
+    });
+    db(sum / count.max(1) as f64)
+}
+
+/// Looks for the fingerprints of lossy codecs: the lowpass shelf they cut the top of the
+/// spectrum off with, bands that drop out when the bit allocation runs short and quantization
+/// noise smeared in front of transients. The channels are mixed to mono.
+pub struct CodecAnalyser {
+    band_hz: f64,
+    block_energy: f64,
+    block_frames: usize,
+    block_len: usize,
+    blocks: VecDeque<f64>,
+    checked: Vec<usize>,
+    highpass: Biquad,
+    highpass_hz: f64,
+    hold: usize,
+    holes: Vec<usize>,
+    mix: Vec<f64>,
+    nyquist: f64,
+    pre_echoes: usize,
+    previous: Option<Vec<f64>>,
+    slices: usize,
+    spectrum: Vec<f64>,
+    transients: usize,
+    window: Vec<f64>,
+}
+
+impl CodecAnalyser {
+    pub fn new(spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let bands = (FFT_SIZE / 2) / BAND_BINS;
+        let highpass_hz = PRE_ECHO_HIGHPASS_HZ.min(0.45 * sample_rate);
+
+        Self {
+            band_hz: sample_rate / FFT_SIZE as f64 * BAND_BINS as f64,
+            block_energy: 0.0,
+            block_frames: 0,
+            block_len: ((sample_rate * BLOCK_SECONDS) as usize).max(1),
+            blocks: VecDeque::with_capacity(PRE_BLOCKS + REFERENCE_BLOCKS + 1),
+            checked: vec![0; bands],
+            highpass: Biquad::new(highpass_hz, sample_rate, true),
+            highpass_hz,
+            hold: 0,
+            holes: vec![0; bands],
+            mix: Vec::with_capacity(FFT_SIZE),
+            nyquist: sample_rate / 2.0,
+            pre_echoes: 0,
+            previous: None,
+            slices: 0,
+            spectrum: vec![0.0; bands],
+            transients: 0,
+            window: portable::window(FftWindow::Blackman, FFT_SIZE),
+        }
+    }
+
+    fn analyse_slice(&mut self) {
+        let energy = self.mix.iter().map(|value| value * value).sum::<f64>();
+        if db(energy / FFT_SIZE as f64) < MIN_LEVEL_DB {
+            self.previous = None;
+            return;
+        }
+
+        let power = portable::slice(&self.mix, &self.window, FFT_SIZE, FftAmplitude::Power);
+        let bands: Vec<f64> = power[..FFT_SIZE / 2]
+            .chunks(BAND_BINS)
+            .map(|band| band.iter().sum::<f64>())
+            .collect();
+
+        for (total, band) in self.spectrum.iter_mut().zip(bands.iter()) {
+            *total += band;
+        }
+        self.slices += 1;
+
+        let levels: Vec<f64> = bands.iter().map(|band| db(*band)).collect();
+        let loudest = levels.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let first = ((HOLE_LOW_HZ / self.band_hz) as usize).max(1);
+
+        for band in first..levels.len() {
+            if context(&levels, band) < loudest - HOLE_RANGE_DB {
+                continue;
+            }
+
+            self.checked[band] += 1;
+
+            // A band that is quiet in consecutive slices is a gap in the music, not the codec
+            let hole = |levels: &[f64]| levels[band] < context(levels, band) - HOLE_DB;
+            if hole(&levels)
+                && self
+                    .previous
+                    .as_deref()
+                    .is_some_and(|previous| !hole(previous))
+            {
+                self.holes[band] += 1;
+            }
+        }
+
+        self.previous = Some(levels);
+    }
+
+    fn finish_block(&mut self) {
+        let level = db(self.block_energy / self.block_len as f64);
+        self.block_energy = 0.0;
+        self.block_frames = 0;
+
+        if self.blocks.len() == PRE_BLOCKS + REFERENCE_BLOCKS {
+            if self.hold > 0 {
+                self.hold -= 1;
+            } else {
+                self.check_transient(level);
+            }
+            self.blocks.pop_front();
+        }
+
+        self.blocks.push_back(level);
+    }
+
+    fn check_transient(&mut self, level: f64) {
+        let before = mean_db(self.blocks.iter());
+        if level < TRANSIENT_MIN_DB || level < before + TRANSIENT_DB {
+            return;
+        }
+
+        let reference = mean_db(self.blocks.iter().take(REFERENCE_BLOCKS));
+        let pre = mean_db(self.blocks.iter().skip(REFERENCE_BLOCKS));
+
+        self.transients += 1;
+        if pre >= reference + PRE_ECHO_DB {
+            self.pre_echoes += 1;
+        }
+        self.hold = PRE_BLOCKS + REFERENCE_BLOCKS;
+    }
+
+    /// Strongest drop of the long-term spectrum from a broadband stretch to everything above.
+    fn cutoff(&self) -> Option<f64> {
+        if self.slices == 0 {
+            return None;
+        }
+
+        let levels: Vec<f64> = self.spectrum.iter().map(|band| db(*band)).collect();
+        let last = ((self.nyquist * NYQUIST_MARGIN / self.band_hz) as usize).min(levels.len());
+
+        (SHELF_BANDS..last.saturating_sub(TRANSITION_BANDS))
+            .filter_map(|edge| {
+                let below = levels[edge - SHELF_BANDS..edge]
+                    .iter()
+                    .copied()
+                    .fold(f64::INFINITY, f64::min);
+                let above = levels[edge + TRANSITION_BANDS..]
+                    .iter()
+                    .copied()
+                    .fold(f64::NEG_INFINITY, f64::max);
+                let drop = below - above;
+
+                (drop >= CUTOFF_DB).then_some((edge, drop))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(edge, _)| edge as f64 * self.band_hz)
+    }
+
+    fn evidence(&self, cutoff: Option<f64>) -> (f64, Vec<CodecEvidence>) {
+        let mut evidence = Vec::new();
+
+        if let Some(cutoff) = cutoff {
+            // Full at the 16 kHz of low bitrate MP3, fading out towards 20.5 kHz
+            evidence.push(CodecEvidence {
+                kind: EvidenceKind::Cutoff,
+                low_frequency: cutoff,
+                high_frequency: self.nyquist,
+                score: ((20500.0 - cutoff) / 4000.0).clamp(0.0, 1.0),
+            });
+        }
+
+        // Bands above the shelf are empty anyway
+        let top = cutoff.map_or(self.holes.len(), |cutoff| (cutoff / self.band_hz) as usize);
+        let holes: usize = self.holes[..top].iter().sum();
+        let checked: usize = self.checked[..top].iter().sum();
+        let hole_rate = holes as f64 / checked.max(1) as f64;
+
+        let first = self.holes[..top].iter().position(|holes| *holes > 0);
+        let last = self.holes[..top].iter().rposition(|holes| *holes > 0);
+        if let (Some(first), Some(last)) = (first, last) {
+            evidence.push(CodecEvidence {
+                kind: EvidenceKind::SpectralHoles,
+                low_frequency: first as f64 * self.band_hz,
+                high_frequency: (last + 1) as f64 * self.band_hz,
+                score: (hole_rate / HOLE_RATE_FULL).min(1.0),
+            });
+        }
+
+        if self.transients >= MIN_TRANSIENTS && self.pre_echoes > 0 {
+            let share = self.pre_echoes as f64 / self.transients as f64;
+            evidence.push(CodecEvidence {
+                kind: EvidenceKind::PreEcho,
+                low_frequency: self.highpass_hz,
+                high_frequency: self.nyquist,
+                score: (share / PRE_ECHO_SHARE_FULL).min(1.0),
+            });
+        }
+
+        evidence.retain(|evidence| evidence.score > 0.0);
+        (hole_rate, evidence)
+    }
+}
+
+impl Analyser for CodecAnalyser {
+    fn name(&self) -> &'static str {
+        "codec"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        let value = sum / frame.len().max(1) as f64;
+
+        self.mix.push(value);
+        if self.mix.len() == FFT_SIZE {
+            self.analyse_slice();
+            self.mix.drain(..HOP);
+        }
+
+        let high = self.highpass.process(value);
+        self.block_energy += high * high;
+        self.block_frames += 1;
+        if self.block_frames == self.block_len {
+            self.finish_block();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let cutoff = self.cutoff();
+        let (hole_rate, evidence) = self.evidence(cutoff);
+
+        // Independent kinds of evidence add up without going past 1
+        let score = 1.0
+            - evidence
+                .iter()
+                .map(|evidence| {
+                    let weight = match evidence.kind {
+                        EvidenceKind::Cutoff => CUTOFF_WEIGHT,
+                        EvidenceKind::SpectralHoles => HOLES_WEIGHT,
+                        EvidenceKind::PreEcho => PRE_ECHO_WEIGHT,
+                    };
+                    1.0 - weight * evidence.score
+                })
+                .product::<f64>();
+
+        Ok(AnalysisResult::new(Analysis {
+            codec: Some(CodecAnalysis {
+                score,
+                likely_transcode: score >= LIKELY,
+                cutoff,
+                hole_rate,
+                transients: self.transients,
+                pre_echoes: self.pre_echoes,
+                evidence,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<CodecAnalysis>("codec", generator)]
+    }
+}
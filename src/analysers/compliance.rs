@@ -0,0 +1,224 @@
+use ebur128::{EbuR128, Error as EbuR128Error, Mode};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Error, Result, cli::AnalyseArgs, exclude::Exclusions, json::Analysis, layout::loudness_meter,
+    schema::SchemaEntry, stream::StreamSpec, warn,
+};
+
+/// Frames buffered before they are added to the loudness meter.
+const METER_CHUNK_FRAMES: usize = 4096;
+
+/// Programme loudness of EBU R128 in LUFS, and how far it may be off in LU.
+const TARGET_LUFS: f64 = -23.0;
+const TOLERANCE_LU: f64 = 0.5;
+
+/// Maximum true peak of EBU R128 in dBTP.
+const MAX_TRUE_PEAK: f64 = -1.0;
+
+/// Absolute gate of ITU-R BS.1770 in LUFS, blocks below it don't count towards the
+/// integrated loudness.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Pass,
+    Fail,
+}
+
+impl Verdict {
+    fn new(passed: bool) -> Self {
+        if passed { Self::Pass } else { Self::Fail }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pass => "PASS",
+            Self::Fail => "FAIL",
+        }
+    }
+}
+
+/// One requirement of the standard and how the file measured against it.
+#[derive(Serialize, JsonSchema)]
+pub struct Criterion {
+    /// integratedLoudness, truePeak or loudnessRange
+    pub name: &'static str,
+    /// Measured value in `unit`, null for silence
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub measured: f64,
+    /// Lowest value that passes, absent without a lower limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Highest value that passes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    pub unit: &'static str,
+    pub verdict: Verdict,
+}
+
+impl Criterion {
+    /// The values that pass, e.g. `-23.5 to -22.5 LUFS` or `at most -1.0 dBTP`.
+    pub fn limit(&self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("{min:.1} to {max:.1} {}", self.unit),
+            (Some(min), None) => format!("at least {min:.1} {}", self.unit),
+            (None, Some(max)) => format!("at most {max:.1} {}", self.unit),
+            (None, None) => "any".to_string(),
+        }
+    }
+
+    fn new(
+        name: &'static str,
+        measured: f64,
+        min: Option<f64>,
+        max: Option<f64>,
+        unit: &'static str,
+    ) -> Self {
+        // Silence is -inf and only passes upper limits
+        let passed = min.is_none_or(|min| measured >= min) && max.is_none_or(|max| measured <= max);
+
+        Self {
+            name,
+            measured,
+            min,
+            max,
+            unit,
+            verdict: Verdict::new(passed),
+        }
+    }
+}
+
+/// The gates of ITU-R BS.1770 the integrated loudness was measured with.
+#[derive(Serialize, JsonSchema)]
+pub struct Gating {
+    /// Blocks quieter than this in LUFS are ignored
+    #[serde(rename = "absoluteThreshold")]
+    pub absolute_threshold: f64,
+    /// Blocks quieter than this in LUFS, 10 LU below the loudness of the blocks above the
+    /// absolute gate, are ignored as well
+    #[serde(rename = "relativeThreshold")]
+    pub relative_threshold: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ComplianceAnalysis {
+    /// Standard the file was checked against
+    pub standard: &'static str,
+    pub criteria: Vec<Criterion>,
+    pub gating: Gating,
+    /// Fail if any criterion failed
+    pub verdict: Verdict,
+}
+
+/// Checks integrated loudness, true peak and loudness range against EBU R128.
+pub struct ComplianceAnalyser {
+    interleaved: Vec<i32>,
+    meter: EbuR128,
+    channels: u32,
+    max_lra: f64,
+    /// Frames of `--exclude`, left out of the measurement
+    excluded: Exclusions,
+}
+
+impl ComplianceAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Self> {
+        let meter = loudness_meter(spec, Mode::I | Mode::LRA | Mode::TRUE_PEAK)
+            .map_err(|err: EbuR128Error| Error::analyser("compliance", err))?;
+
+        Ok(Self {
+            interleaved: Vec::with_capacity(METER_CHUNK_FRAMES * spec.channels as usize),
+            meter,
+            channels: spec.channels as u32,
+            max_lra: args.max_lra,
+            excluded: Exclusions::new(&args.exclude, spec),
+        })
+    }
+
+    fn flush_meter(&mut self) {
+        if let Err(err) = self.meter.add_frames_i32(&self.interleaved) {
+            warn!("error adding frame to loudness measurement: {:?}", &err);
+        }
+        self.interleaved.clear();
+    }
+
+    fn true_peak(&self) -> f64 {
+        let peak = (0..self.channels)
+            .filter_map(|channel| self.meter.true_peak(channel).ok())
+            .fold(0.0, f64::max);
+
+        20.0 * peak.log10()
+    }
+}
+
+impl Analyser for ComplianceAnalyser {
+    fn name(&self) -> &'static str {
+        "compliance"
+    }
+
+    fn analyse(&mut self, _label: &str, frame_counter: usize, frame: &[i32]) {
+        if self.excluded.contains(frame_counter) {
+            return;
+        }
+        self.interleaved.extend_from_slice(frame);
+
+        if self.interleaved.len() >= self.interleaved.capacity() {
+            self.flush_meter();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.flush_meter();
+
+        let integrated = self.meter.loudness_global().unwrap_or(f64::NEG_INFINITY);
+        let range = self.meter.loudness_range().unwrap_or(0.0);
+        let criteria = vec![
+            Criterion::new(
+                "integratedLoudness",
+                integrated,
+                Some(TARGET_LUFS - TOLERANCE_LU),
+                Some(TARGET_LUFS + TOLERANCE_LU),
+                "LUFS",
+            ),
+            Criterion::new(
+                "truePeak",
+                self.true_peak(),
+                None,
+                Some(MAX_TRUE_PEAK),
+                "dBTP",
+            ),
+            Criterion::new("loudnessRange", range, None, Some(self.max_lra), "LU"),
+        ];
+        let passed = criteria
+            .iter()
+            .all(|criterion| criterion.verdict == Verdict::Pass);
+
+        let mut result = AnalysisResult::new(Analysis {
+            compliance: Some(ComplianceAnalysis {
+                standard: "EBU R128",
+                criteria,
+                gating: Gating {
+                    absolute_threshold: ABSOLUTE_GATE,
+                    relative_threshold: self.meter.relative_threshold().unwrap_or(ABSOLUTE_GATE),
+                },
+                verdict: Verdict::new(passed),
+            }),
+            ..Analysis::default()
+        });
+        if !passed {
+            result.flags = crate::ERR_LOUDNESS_TARGET;
+        }
+
+        Ok(result)
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<ComplianceAnalysis>(
+            "compliance",
+            generator,
+        )]
+    }
+}
@@ -0,0 +1,85 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+#[derive(Serialize, JsonSchema)]
+pub struct ContainerAnalysis {
+    /// Frames the header of the file announces, `null` if it doesn't say
+    #[serde(rename = "declaredFrames")]
+    pub declared_frames: Option<usize>,
+    /// Frames that could actually be decoded
+    #[serde(rename = "decodedFrames")]
+    pub decoded_frames: usize,
+    /// Frames the header announces that weren't there
+    #[serde(rename = "missingFrames")]
+    pub missing_frames: usize,
+    /// `missingFrames` in seconds
+    #[serde(rename = "missingDuration")]
+    pub missing_duration: f64,
+    /// The payload ends before the header says it should
+    pub truncated: bool,
+}
+
+/// Compares the length the header of the input announces with the frames that were decoded,
+/// which fall short if the file was cut off while it was copied or recorded.
+pub struct ContainerAnalyser {
+    declared_frames: Option<usize>,
+    decoded_frames: usize,
+    sample_rate: i32,
+    /// The stream was stopped early, so the frames it didn't get to aren't missing
+    stopped: bool,
+}
+
+impl ContainerAnalyser {
+    pub fn new(spec: &StreamSpec) -> Self {
+        Self {
+            // Streams of unknown length announce `usize::MAX`
+            declared_frames: (spec.num_frames != usize::MAX).then_some(spec.num_frames),
+            decoded_frames: 0,
+            sample_rate: spec.sample_rate,
+            stopped: false,
+        }
+    }
+}
+
+impl Analyser for ContainerAnalyser {
+    fn name(&self) -> &'static str {
+        "container"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, _frame: &[i32]) {
+        self.decoded_frames += 1;
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let missing_frames = match self.declared_frames {
+            Some(declared) if !self.stopped => declared.saturating_sub(self.decoded_frames),
+            _ => 0,
+        };
+        let missing_duration = missing_frames as f64 / self.sample_rate.max(1) as f64;
+
+        Ok(AnalysisResult::new(Analysis {
+            container: Some(ContainerAnalysis {
+                declared_frames: self.declared_frames,
+                decoded_frames: self.decoded_frames,
+                missing_frames,
+                missing_duration,
+                truncated: missing_frames > 0,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn stop(&mut self, _num_frames: usize) {
+        self.stopped = true;
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<ContainerAnalysis>(
+            "container",
+            generator,
+        )]
+    }
+}
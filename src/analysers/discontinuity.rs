@@ -0,0 +1,203 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Result,
+    cli::AnalyseArgs,
+    findings::{Finding, Kind},
+    json::Analysis,
+    output,
+    output::frame_to_time,
+    progress,
+    schema::SchemaEntry,
+    stream::StreamSpec,
+};
+
+/// Time constant of the local RMS in seconds.
+const RMS_SECONDS: f64 = 0.01;
+
+/// Steps smaller than this are never reported, so dither and near-silence don't trigger, in
+/// dBFS.
+const MIN_STEP_DB: f64 = -40.0;
+
+/// Events on a channel closer than this to the previous one are part of the same glitch, in
+/// seconds.
+const HOLDOFF_SECONDS: f64 = 0.01;
+
+/// Floor of the local RMS, one 24 bit step, so digital silence still gives finite ratios.
+const MIN_RMS: f64 = 1.0 / 8388608.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Clone, Default)]
+struct ChannelState {
+    /// The two previous samples
+    history: [f64; 2],
+    /// Exponential moving average of the squared prediction error
+    mean_square: f64,
+    last_event: Option<usize>,
+}
+
+struct Event {
+    frame: usize,
+    channel: usize,
+    step: f64,
+    ratio: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Discontinuity {
+    pub time: f32,
+    pub sample: usize,
+    pub channel: usize,
+    /// Size of the sample step in dBFS
+    pub step: f64,
+    /// Prediction error above the local RMS in dB
+    pub ratio: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DiscontinuityAnalysis {
+    pub results: Vec<Discontinuity>,
+    /// Prediction error above the local RMS in dB that is reported
+    pub threshold: f64,
+}
+
+/// Finds single-sample jumps, e.g. from dropped capture buffers. Each sample is predicted by
+/// extrapolating the two before it, which smooth signals follow closely, and a prediction
+/// error far above its recent RMS is a discontinuity.
+pub struct DiscontinuityAnalyser {
+    alpha: f64,
+    events: Vec<Event>,
+    /// Events already returned by `take_events`
+    taken: usize,
+    frames: usize,
+    holdoff_frames: usize,
+    sample_rate: i32,
+    states: Vec<ChannelState>,
+    threshold: f64,
+    warmup_frames: usize,
+}
+
+impl DiscontinuityAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+
+        Self {
+            alpha: 1.0 - (-1.0 / (RMS_SECONDS * sample_rate)).exp(),
+            events: Vec::new(),
+            taken: 0,
+            frames: 0,
+            holdoff_frames: (HOLDOFF_SECONDS * sample_rate) as usize,
+            sample_rate: spec.sample_rate,
+            states: vec![ChannelState::default(); spec.channels as usize],
+            threshold: args.discontinuity_threshold,
+            // The local RMS needs a time constant to settle
+            warmup_frames: (RMS_SECONDS * sample_rate) as usize + 2,
+        }
+    }
+}
+
+impl Analyser for DiscontinuityAnalyser {
+    fn name(&self) -> &'static str {
+        "discontinuities"
+    }
+
+    fn analyse(&mut self, label: &str, _frame_counter: usize, frame: &[i32]) {
+        let min_step = 10f64.powf(MIN_STEP_DB / 20.0);
+        let ratio_threshold = 10f64.powf(self.threshold / 20.0);
+
+        for (channel, (state, sample)) in self.states.iter_mut().zip(frame.iter()).enumerate() {
+            let value = *sample as f64 / FULL_SCALE;
+            let [previous, before] = state.history;
+            let error = value - (2.0 * previous - before);
+            let step = (value - previous).abs();
+            let rms = state.mean_square.sqrt().max(MIN_RMS);
+
+            if self.frames >= self.warmup_frames
+                && step >= min_step
+                && error.abs() > ratio_threshold * rms
+                && state
+                    .last_event
+                    .is_none_or(|last| self.frames - last > self.holdoff_frames)
+            {
+                let event = Event {
+                    frame: self.frames,
+                    channel,
+                    step: 20.0 * step.log10(),
+                    ratio: 20.0 * (error.abs() / rms).log10(),
+                };
+
+                progress::event("discontinuity");
+                output!(
+                    "[{}] DISCONTINUITY: CH:{} - step {:.2} dBFS, {:.2} dB above local RMS @ {}",
+                    label,
+                    channel,
+                    event.step,
+                    event.ratio,
+                    frame_to_time(self.frames, self.sample_rate)
+                );
+
+                state.last_event = Some(self.frames);
+                self.events.push(event);
+            }
+
+            state.mean_square += self.alpha * (error * error - state.mean_square);
+            state.history = [value, previous];
+        }
+
+        self.frames += 1;
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let events = self
+            .events
+            .iter()
+            .map(|event| Discontinuity {
+                time: event.frame as f32 / self.sample_rate as f32,
+                sample: event.frame,
+                channel: event.channel,
+                step: event.step,
+                ratio: event.ratio,
+            })
+            .collect();
+
+        let mut result = AnalysisResult::new(Analysis {
+            discontinuities: Some(DiscontinuityAnalysis {
+                results: events,
+                threshold: self.threshold,
+            }),
+            ..Analysis::default()
+        });
+        if !self.events.is_empty() {
+            result.flags = crate::ERR_CONTAINS_UNDERRUN;
+        }
+
+        Ok(result)
+    }
+
+    fn take_events(&mut self) -> Vec<Finding> {
+        let events = self.events[self.taken..]
+            .iter()
+            .map(|event| {
+                Finding::new(
+                    Kind::Discontinuity,
+                    Some(event.channel),
+                    event.frame,
+                    event.frame,
+                )
+            })
+            .collect();
+        self.taken = self.events.len();
+
+        events
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<DiscontinuityAnalysis>(
+            "discontinuities",
+            generator,
+        )]
+    }
+}
@@ -0,0 +1,236 @@
+use aus::spectrum::{irfft, rfft};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Frames per correlation block (about 1.4 seconds at 48 kHz).
+const BLOCK_FRAMES: usize = 1 << 16;
+
+/// Blocks are zero-padded to this size so the correlation is linear, not circular.
+const FFT_SIZE: usize = 2 * BLOCK_FRAMES;
+
+/// Largest offset between channels searched for, in samples.
+const MAX_LAG: usize = BLOCK_FRAMES / 2;
+
+/// Blocks whose normalized correlation peak is below this are too incoherent to use.
+const MIN_CORRELATION: f64 = 0.3;
+
+/// Offset of one block between two channels.
+struct Lag {
+    /// Center of the block in frames
+    position: f64,
+    /// Delay of the second channel against the first, in samples
+    lag: f64,
+    correlation: f64,
+}
+
+struct Pair {
+    channels: (usize, usize),
+    lags: Vec<Lag>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DriftResult {
+    pub channels: [usize; 2],
+    /// Relative clock drift of the second channel in parts per million, positive if it
+    /// falls further behind over time
+    pub ppm: Option<f64>,
+    #[serde(rename = "offsetStart")]
+    pub offset_start: Option<f64>,
+    #[serde(rename = "offsetEnd")]
+    pub offset_end: Option<f64>,
+    pub blocks: usize,
+    pub correlation: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DriftAnalysis {
+    pub results: Vec<DriftResult>,
+    /// Frames per correlation block
+    #[serde(rename = "blockSize")]
+    pub block_size: usize,
+}
+
+/// Estimates the relative clock drift between channel pairs from the offset of their
+/// cross-correlation peak over time.
+pub struct DriftAnalyser {
+    buffers: Vec<Vec<f64>>,
+    frames: usize,
+    num_frames: usize,
+    pairs: Vec<Pair>,
+}
+
+/// Sub-sample position of a peak from the values around it.
+pub(super) fn parabolic(left: f64, center: f64, right: f64) -> f64 {
+    let denominator = left - 2.0 * center + right;
+    if denominator == 0.0 {
+        0.0
+    } else {
+        0.5 * (left - right) / denominator
+    }
+}
+
+impl DriftAnalyser {
+    pub fn new(_args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let channels = spec.channels as usize;
+        let pairs = (0..channels)
+            .flat_map(|a| (a + 1..channels).map(move |b| (a, b)))
+            .map(|channels| Pair {
+                channels,
+                lags: Vec::new(),
+            })
+            .collect();
+
+        Self {
+            buffers: vec![Vec::with_capacity(FFT_SIZE); channels],
+            frames: 0,
+            num_frames: spec.num_frames,
+            pairs,
+        }
+    }
+
+    fn finish_block(&mut self) {
+        let length = self.buffers[0].len();
+        let position = (self.frames - length) as f64 + length as f64 / 2.0;
+
+        let energies: Vec<f64> = self
+            .buffers
+            .iter()
+            .map(|buffer| buffer.iter().map(|v| v * v).sum())
+            .collect();
+        let spectra: Vec<_> = self
+            .buffers
+            .iter_mut()
+            .map(|buffer| {
+                buffer.resize(FFT_SIZE, 0.0);
+                rfft(buffer, FFT_SIZE)
+            })
+            .collect();
+
+        for pair in self.pairs.iter_mut() {
+            let (a, b) = pair.channels;
+            let norm = (energies[a] * energies[b]).sqrt() * FFT_SIZE as f64;
+            if norm <= 0.0 {
+                continue;
+            }
+
+            let cross: Vec<_> = spectra[a]
+                .iter()
+                .zip(spectra[b].iter())
+                .map(|(a, b)| a.conj() * b)
+                .collect();
+            let Ok(correlation) = irfft(&cross, FFT_SIZE) else {
+                continue;
+            };
+
+            // Lag l sits at index l, negative lags wrap around to the end
+            let at = |lag: i64| correlation[lag.rem_euclid(FFT_SIZE as i64) as usize];
+            let max_lag = MAX_LAG.min(length) as i64;
+            let Some(best) = (-max_lag..=max_lag).max_by(|x, y| at(*x).total_cmp(&at(*y))) else {
+                continue;
+            };
+
+            let peak = at(best) / norm;
+            if peak < MIN_CORRELATION {
+                continue;
+            }
+
+            pair.lags.push(Lag {
+                position,
+                lag: best as f64 + parabolic(at(best - 1), at(best), at(best + 1)),
+                correlation: peak,
+            });
+        }
+
+        for buffer in self.buffers.iter_mut() {
+            buffer.clear();
+        }
+    }
+
+    fn result(&self, pair: &Pair) -> DriftResult {
+        let n = pair.lags.len();
+        let mut result = DriftResult {
+            channels: [pair.channels.0, pair.channels.1],
+            ppm: None,
+            offset_start: None,
+            offset_end: None,
+            blocks: n,
+            correlation: None,
+        };
+
+        if n == 0 {
+            return result;
+        }
+
+        result.correlation =
+            Some(pair.lags.iter().map(|lag| lag.correlation).sum::<f64>() / n as f64);
+
+        // Least-squares fit of lag over position
+        let mean_x = pair.lags.iter().map(|lag| lag.position).sum::<f64>() / n as f64;
+        let mean_y = pair.lags.iter().map(|lag| lag.lag).sum::<f64>() / n as f64;
+        let sxx: f64 = pair
+            .lags
+            .iter()
+            .map(|lag| (lag.position - mean_x).powi(2))
+            .sum();
+        let sxy: f64 = pair
+            .lags
+            .iter()
+            .map(|lag| (lag.position - mean_x) * (lag.lag - mean_y))
+            .sum();
+
+        let slope = if n > 1 && sxx > 0.0 { sxy / sxx } else { 0.0 };
+        let intercept = mean_y - slope * mean_x;
+
+        if n > 1 {
+            result.ppm = Some(slope * 1e6);
+        }
+        result.offset_start = Some(intercept);
+        result.offset_end = Some(intercept + slope * self.num_frames as f64);
+
+        result
+    }
+}
+
+impl Analyser for DriftAnalyser {
+    fn name(&self) -> &'static str {
+        "drift"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (buffer, sample) in self.buffers.iter_mut().zip(frame.iter()) {
+            buffer.push(*sample as f64);
+        }
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(BLOCK_FRAMES) && !self.pairs.is_empty() {
+            self.finish_block();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        // Short files still get one block, a short tail of a long file is dropped
+        let remaining = self.buffers.first().map_or(0, |buffer| buffer.len());
+        if remaining > 0 && (remaining >= BLOCK_FRAMES / 4 || self.frames == remaining) {
+            self.finish_block();
+        }
+
+        Ok(AnalysisResult::new(Analysis {
+            drift: Some(DriftAnalysis {
+                results: self.pairs.iter().map(|pair| self.result(pair)).collect(),
+                block_size: BLOCK_FRAMES,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn stop(&mut self, num_frames: usize) {
+        self.num_frames = num_frames;
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<DriftAnalysis>("drift", generator)]
+    }
+}
@@ -0,0 +1,237 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult, tone::goertzel_power};
+use crate::{
+    Result, cli::AnalyseArgs, json::Analysis, output, output::frame_to_time, progress,
+    schema::SchemaEntry, stream::StreamSpec,
+};
+
+const ROWS: [f64; 4] = [697.0, 770.0, 852.0, 941.0];
+const COLUMNS: [f64; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+const SYMBOLS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Length of the detection blocks in seconds.
+const BLOCK_SECONDS: f64 = 0.02;
+
+/// Consecutive blocks needed for a digit (40 ms, the shortest valid DTMF tone).
+const MIN_BLOCKS: usize = 2;
+
+/// Blocks quieter than this mean square (about -50 dBFS) are not considered.
+const MIN_ENERGY: f64 = 1e-5;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Clone)]
+struct InternalSegment {
+    start: usize,
+    end: usize,
+    symbol: char,
+    blocks: usize,
+}
+
+#[derive(Clone, Default)]
+struct ChannelState {
+    buffer: Vec<f64>,
+    current: Option<InternalSegment>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DtmfSegment {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+    pub channel: usize,
+    pub symbol: char,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DtmfAnalysis {
+    pub results: Vec<DtmfSegment>,
+}
+
+/// Decodes DTMF digits per channel with Goertzel filters at the eight DTMF frequencies.
+pub struct DtmfAnalyser {
+    block_frames: usize,
+    frames: usize,
+    sample_rate: i32,
+    segments: Vec<(usize, InternalSegment)>,
+    states: Vec<ChannelState>,
+}
+
+/// Index and normalized power of the strongest frequency in a group, and whether it clearly
+/// dominates the rest of the group.
+fn strongest(
+    samples: &[f64],
+    energy: f64,
+    group: &[f64; 4],
+    sample_rate: f64,
+) -> (usize, f64, bool) {
+    let mut powers: Vec<(usize, f64)> = group
+        .iter()
+        .map(|frequency| {
+            2.0 * goertzel_power(samples, *frequency, sample_rate) / (samples.len() as f64 * energy)
+        })
+        .enumerate()
+        .collect();
+    powers.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let (index, power) = powers[0];
+    (index, power, power > 4.0 * powers[1].1)
+}
+
+fn detect(samples: &[f64], sample_rate: f64) -> Option<char> {
+    let energy: f64 = samples.iter().map(|v| v * v).sum();
+    if energy / (samples.len() as f64) < MIN_ENERGY {
+        return None;
+    }
+
+    let (row, row_power, row_clear) = strongest(samples, energy, &ROWS, sample_rate);
+    let (column, column_power, column_clear) = strongest(samples, energy, &COLUMNS, sample_rate);
+
+    // Both tones present, together making up most of the signal
+    if row_clear
+        && column_clear
+        && row_power > 0.2
+        && column_power > 0.2
+        && row_power + column_power > 0.75
+    {
+        Some(SYMBOLS[row][column])
+    } else {
+        None
+    }
+}
+
+impl DtmfAnalyser {
+    pub fn new(_args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        Self {
+            block_frames: ((spec.sample_rate as f64 * BLOCK_SECONDS) as usize).max(1),
+            frames: 0,
+            sample_rate: spec.sample_rate,
+            segments: Vec::new(),
+            states: vec![ChannelState::default(); spec.channels as usize],
+        }
+    }
+
+    fn finish_blocks(&mut self, label: &str) {
+        let sample_rate = self.sample_rate as f64;
+        let mut finished = Vec::new();
+
+        for (channel, state) in self.states.iter_mut().enumerate() {
+            if state.buffer.is_empty() {
+                continue;
+            }
+
+            let start = self.frames - state.buffer.len();
+            let symbol = detect(&state.buffer, sample_rate);
+            state.buffer.clear();
+
+            if let (Some(segment), Some(symbol)) = (state.current.as_mut(), symbol)
+                && segment.symbol == symbol
+            {
+                segment.end = self.frames;
+                segment.blocks += 1;
+                continue;
+            }
+
+            if let Some(segment) = state.current.take() {
+                finished.push((channel, segment));
+            }
+
+            state.current = symbol.map(|symbol| InternalSegment {
+                start,
+                end: self.frames,
+                symbol,
+                blocks: 1,
+            });
+        }
+
+        for (channel, segment) in finished {
+            self.record(label, channel, segment);
+        }
+    }
+
+    fn record(&mut self, label: &str, channel: usize, segment: InternalSegment) {
+        if segment.blocks < MIN_BLOCKS {
+            return;
+        }
+
+        progress::event("dtmf");
+        output!(
+            "[{}] DTMF         : CH:{} - '{}' ({:06.3}s) {} -> {}",
+            label,
+            channel,
+            segment.symbol,
+            (segment.end - segment.start) as f32 / self.sample_rate as f32,
+            frame_to_time(segment.start, self.sample_rate),
+            frame_to_time(segment.end, self.sample_rate)
+        );
+
+        self.segments.push((channel, segment));
+    }
+}
+
+impl Analyser for DtmfAnalyser {
+    fn name(&self) -> &'static str {
+        "dtmf"
+    }
+
+    fn analyse(&mut self, label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (state, sample) in self.states.iter_mut().zip(frame.iter()) {
+            state.buffer.push(*sample as f64 / FULL_SCALE);
+        }
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(self.block_frames) {
+            self.finish_blocks(label);
+        }
+    }
+
+    fn finish(&mut self, label: &str) -> Result<AnalysisResult> {
+        self.finish_blocks(label);
+
+        for channel in 0..self.states.len() {
+            if let Some(segment) = self.states[channel].current.take() {
+                self.record(label, channel, segment);
+            }
+        }
+
+        let segments: Vec<DtmfSegment> = self
+            .segments
+            .iter()
+            .map(|(channel, seg)| {
+                let duration_samples = seg.end - seg.start;
+                DtmfSegment {
+                    start: seg.start as f32 / self.sample_rate as f32,
+                    end: seg.end as f32 / self.sample_rate as f32,
+                    duration: duration_samples as f32 / self.sample_rate as f32,
+                    start_sample: seg.start,
+                    end_sample: seg.end,
+                    duration_samples,
+                    channel: *channel,
+                    symbol: seg.symbol,
+                }
+            })
+            .collect();
+
+        Ok(AnalysisResult::new(Analysis {
+            dtmf: Some(DtmfAnalysis { results: segments }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<DtmfAnalysis>("dtmf", generator)]
+    }
+}
@@ -0,0 +1,399 @@
+use ebur128::{EbuR128, Error as EbuR128Error, Mode};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    findings::{Finding, Kind},
+    layout::loudness_meter,
+    schema::SchemaEntry,
+    stream::StreamSpec,
+    warn,
+};
+
+/// Length of the blocks used for the DR score, as in the TT DR meter.
+const DR_BLOCK_SECONDS: usize = 3;
+
+/// Frames buffered before they are handed to the loudness meters.
+const METER_CHUNK_FRAMES: usize = 4096;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+/// Samples at or above this share of full scale count as clipped.
+const CLIP_LEVEL: f64 = 0.999;
+
+/// Clipped samples on a channel closer than this many seconds form one clipping segment.
+const CLIP_MERGE_SECONDS: f64 = 0.01;
+
+fn to_db(value: f64) -> f64 {
+    20.0 * value.log10()
+}
+
+#[derive(Default, Clone)]
+struct ChannelState {
+    sum_squares: f64,
+    peak: f64,
+    block_sum_squares: f64,
+    block_peak: f64,
+    block_frames: usize,
+    clipped: u64,
+    /// Open clipping segment as (start, end) frames
+    clip_run: Option<(usize, usize)>,
+    clip_runs: Vec<(usize, usize)>,
+    /// Clipping segments already returned by `take_events`
+    clip_runs_taken: usize,
+    /// (RMS, peak) of each finished DR block
+    blocks: Vec<(f64, f64)>,
+    buffer: Vec<i32>,
+}
+
+impl ChannelState {
+    fn finish_block(&mut self) {
+        if self.block_frames == 0 {
+            return;
+        }
+
+        // DR blocks use the RMS of a full-scale sine = 0 dB convention
+        let rms = (2.0 * self.block_sum_squares / self.block_frames as f64).sqrt();
+        self.blocks.push((rms, self.block_peak));
+        self.block_sum_squares = 0.0;
+        self.block_peak = 0.0;
+        self.block_frames = 0;
+    }
+
+    /// DR score from the loudest 20% of blocks and the second-highest block peak.
+    fn dr(&self) -> Option<f64> {
+        if self.blocks.is_empty() {
+            return None;
+        }
+
+        let mut rms: Vec<f64> = self.blocks.iter().map(|(rms, _)| *rms).collect();
+        rms.sort_by(|a, b| b.total_cmp(a));
+        let top = (self.blocks.len() / 5).max(1);
+        let rms_top = (rms[..top].iter().map(|v| v * v).sum::<f64>() / top as f64).sqrt();
+
+        let mut peaks: Vec<f64> = self.blocks.iter().map(|(_, peak)| *peak).collect();
+        peaks.sort_by(|a, b| b.total_cmp(a));
+        let peak = peaks.get(1).copied().unwrap_or(peaks[0]);
+
+        if rms_top <= 0.0 || peak <= 0.0 {
+            return None;
+        }
+
+        Some(to_db(peak / rms_top))
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DynamicsResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub peak: f64,
+    #[serde(rename = "truePeak")]
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub true_peak: f64,
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub rms: f64,
+    #[serde(rename = "crestFactor")]
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub crest_factor: f64,
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub loudness: f64,
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub plr: f64,
+    pub dr: Option<f64>,
+    /// Samples at 0.999 of full scale or above
+    #[serde(rename = "clippedSamples")]
+    pub clipped_samples: u64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ClippingSegment {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+    pub channel: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DynamicsAnalysis {
+    pub channels: Vec<DynamicsResult>,
+    pub overall: DynamicsResult,
+    #[serde(rename = "minDr")]
+    pub min_dr: Option<f64>,
+    #[serde(rename = "maxClippedSamples", skip_serializing_if = "Option::is_none")]
+    pub max_clipped_samples: Option<u64>,
+    /// Stretches of clipped samples on a channel, merged when closer than 10 ms
+    #[serde(rename = "clippingSegments", skip_serializing_if = "Vec::is_empty")]
+    pub clipping_segments: Vec<ClippingSegment>,
+}
+
+pub struct DynamicsAnalyser {
+    block_frames: usize,
+    channels: Vec<ChannelState>,
+    channel_meters: Vec<EbuR128>,
+    clip_merge_gap: usize,
+    frames: usize,
+    interleaved: Vec<i32>,
+    meter: EbuR128,
+    min_dr: Option<f64>,
+    max_clipped: Option<u64>,
+    sample_rate: i32,
+}
+
+impl DynamicsAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Self> {
+        let channels = spec.channels as usize;
+        let sample_rate = spec.sample_rate as u32;
+        let init_error = |err: EbuR128Error| Error::analyser("dynamics", err);
+
+        let meter = loudness_meter(spec, Mode::I | Mode::SAMPLE_PEAK | Mode::TRUE_PEAK)
+            .map_err(init_error)?;
+        let channel_meters = (0..channels)
+            .map(|_| EbuR128::new(1, sample_rate, Mode::I | Mode::TRUE_PEAK))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(init_error)?;
+
+        Ok(Self {
+            block_frames: spec.sample_rate as usize * DR_BLOCK_SECONDS,
+            channels: vec![ChannelState::default(); channels],
+            channel_meters,
+            clip_merge_gap: (spec.sample_rate as f64 * CLIP_MERGE_SECONDS) as usize,
+            frames: 0,
+            interleaved: Vec::with_capacity(METER_CHUNK_FRAMES * channels),
+            meter,
+            min_dr: args.min_dr,
+            max_clipped: args.max_clipped_samples,
+            sample_rate: spec.sample_rate,
+        })
+    }
+
+    fn flush_meters(&mut self) {
+        if let Err(err) = self.meter.add_frames_i32(&self.interleaved) {
+            warn!("error adding frame to loudness measurement: {:?}", &err);
+        }
+        self.interleaved.clear();
+
+        for (state, meter) in self.channels.iter_mut().zip(self.channel_meters.iter_mut()) {
+            if let Err(err) = meter.add_frames_i32(&state.buffer) {
+                warn!("error adding frame to loudness measurement: {:?}", &err);
+            }
+            state.buffer.clear();
+        }
+    }
+
+    fn channel_result(&self, index: usize) -> DynamicsResult {
+        let state = &self.channels[index];
+        let meter = &self.channel_meters[index];
+
+        let peak = to_db(state.peak / FULL_SCALE);
+        let rms = to_db((state.sum_squares / self.frames.max(1) as f64).sqrt() / FULL_SCALE);
+        let true_peak = meter.true_peak(0).map(to_db).unwrap_or(peak);
+        let loudness = meter.loudness_global().unwrap_or(f64::NEG_INFINITY);
+
+        DynamicsResult {
+            channel: Some(index),
+            peak,
+            true_peak,
+            rms,
+            crest_factor: peak - rms,
+            loudness,
+            plr: true_peak - loudness,
+            dr: state.dr(),
+            clipped_samples: state.clipped,
+        }
+    }
+
+    fn overall_result(&self) -> DynamicsResult {
+        let peak = self
+            .channels
+            .iter()
+            .fold(0.0f64, |peak, c| peak.max(c.peak));
+        let sum_squares: f64 = self.channels.iter().map(|c| c.sum_squares).sum();
+        let samples = (self.frames * self.channels.len()).max(1) as f64;
+
+        let peak = to_db(peak / FULL_SCALE);
+        let rms = to_db((sum_squares / samples).sqrt() / FULL_SCALE);
+        let true_peak = (0..self.channels.len())
+            .filter_map(|channel| self.meter.true_peak(channel as u32).ok())
+            .fold(None, |max: Option<f64>, v| {
+                Some(max.map_or(v, |max| max.max(v)))
+            })
+            .map(to_db)
+            .unwrap_or(peak);
+        let loudness = self.meter.loudness_global().unwrap_or(f64::NEG_INFINITY);
+
+        let scores: Vec<f64> = self.channels.iter().filter_map(|c| c.dr()).collect();
+        let dr = if scores.is_empty() {
+            None
+        } else {
+            Some(scores.iter().sum::<f64>() / scores.len() as f64)
+        };
+
+        DynamicsResult {
+            channel: None,
+            peak,
+            true_peak,
+            rms,
+            crest_factor: peak - rms,
+            loudness,
+            plr: true_peak - loudness,
+            dr,
+            clipped_samples: self.channels.iter().map(|c| c.clipped).sum(),
+        }
+    }
+
+    fn clipping_segments(&self) -> Vec<ClippingSegment> {
+        let sample_rate = self.sample_rate as f32;
+        let mut segments: Vec<ClippingSegment> = self
+            .channels
+            .iter()
+            .enumerate()
+            .flat_map(|(channel, state)| {
+                state
+                    .clip_runs
+                    .iter()
+                    .map(move |&(start, end)| ClippingSegment {
+                        start: start as f32 / sample_rate,
+                        end: end as f32 / sample_rate,
+                        duration: (end - start) as f32 / sample_rate,
+                        start_sample: start,
+                        end_sample: end,
+                        duration_samples: end - start,
+                        channel,
+                    })
+            })
+            .collect();
+        segments.sort_by_key(|segment| (segment.start_sample, segment.channel));
+
+        segments
+    }
+}
+
+impl Analyser for DynamicsAnalyser {
+    fn name(&self) -> &'static str {
+        "dynamics"
+    }
+
+    fn analyse(&mut self, _label: &str, frame_counter: usize, frame: &[i32]) {
+        for (state, sample) in self.channels.iter_mut().zip(frame.iter()) {
+            let value = *sample as f64;
+            let square = value * value;
+
+            state.sum_squares += square;
+            state.block_sum_squares += square;
+            state.peak = state.peak.max(value.abs());
+            state.block_peak = state.block_peak.max(value.abs());
+            if value.abs() >= CLIP_LEVEL * FULL_SCALE {
+                state.clipped += 1;
+
+                match state.clip_run.as_mut() {
+                    Some((_, end)) if frame_counter - *end < self.clip_merge_gap => {
+                        *end = frame_counter + 1;
+                    }
+                    _ => {
+                        state.clip_runs.extend(state.clip_run.take());
+                        state.clip_run = Some((frame_counter, frame_counter + 1));
+                    }
+                }
+            }
+            state.block_frames += 1;
+            state.buffer.push(*sample);
+
+            if state.block_frames >= self.block_frames {
+                state.finish_block();
+            }
+        }
+
+        self.interleaved.extend_from_slice(frame);
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(METER_CHUNK_FRAMES) {
+            self.flush_meters();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.flush_meters();
+
+        for state in self.channels.iter_mut() {
+            state.finish_block();
+            state.clip_runs.extend(state.clip_run.take());
+        }
+
+        let overall = self.overall_result();
+        let mut result = AnalysisResult::default();
+
+        if let (Some(min_dr), Some(dr)) = (self.min_dr, overall.dr)
+            && dr < min_dr
+        {
+            result.fail(
+                crate::ERR_LOW_DYNAMIC_RANGE,
+                format!("DR{:.0} is below the minimum of DR{}", dr, min_dr),
+            );
+        }
+
+        if let Some(max) = self.max_clipped
+            && overall.clipped_samples > max
+        {
+            result.fail(
+                crate::ERR_LOW_DYNAMIC_RANGE,
+                format!(
+                    "{} clipped samples, more than the maximum of {}",
+                    overall.clipped_samples, max
+                ),
+            );
+        }
+
+        result.analysis.dynamics = Some(DynamicsAnalysis {
+            channels: (0..self.channels.len())
+                .map(|index| self.channel_result(index))
+                .collect(),
+            overall,
+            min_dr: self.min_dr,
+            max_clipped_samples: self.max_clipped,
+            clipping_segments: self.clipping_segments(),
+        });
+
+        Ok(result)
+    }
+
+    fn take_events(&mut self) -> Vec<Finding> {
+        let mut events = Vec::new();
+
+        for (channel, state) in self.channels.iter_mut().enumerate() {
+            // Nothing can be merged into the open segment once the gap has passed
+            if state
+                .clip_run
+                .is_some_and(|(_, end)| self.frames - end >= self.clip_merge_gap)
+            {
+                state.clip_runs.extend(state.clip_run.take());
+            }
+
+            events.extend(
+                state.clip_runs[state.clip_runs_taken..]
+                    .iter()
+                    .map(|&(start, end)| Finding::new(Kind::Clipping, Some(channel), start, end)),
+            );
+            state.clip_runs_taken = state.clip_runs.len();
+        }
+        events.sort_by_key(|event| event.start);
+
+        events
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<DynamicsAnalysis>(
+            "dynamics", generator,
+        )]
+    }
+}
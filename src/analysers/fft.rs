@@ -1,7 +1,9 @@
 use std::{
-    fs::File,
-    io::BufWriter,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    ops::Range,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use aus::{
@@ -9,19 +11,359 @@ use aus::{
     analysis::{make_log_spectrogram, make_power_spectrogram},
     spectrum::{complex_to_polar_rstft, rstft},
 };
-use png::{BitDepth, ColorType, Encoder};
-use serde_json::Map;
-use wavers::{Samples, Wav};
+use clap::ValueEnum;
+use png::{BitDepth, ColorType, Encoder, StreamWriter, Writer};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
 
-use crate::cli::Cli;
+use crate::{
+    Error, Result, cancel, cli::AnalyseArgs, json::Analysis, npy, output, output::Step,
+    schema::SchemaEntry, stream::StreamSpec, warn,
+};
+
+use super::{Analyser, AnalysisResult, filterbank::Filterbank, portable};
+
+/// Map a normalized value (0.0..=1.0) to the blue -> green -> red spectrogram palette.
+pub fn heat_color(value: f64) -> [u8; 3] {
+    let blue = (value * 3.0).min(1.0);
+    let green = ((value - 0.33) * 3.0).clamp(0.0, 1.0);
+    let red = ((value - 0.66) * 3.0).clamp(0.0, 1.0);
+
+    [
+        (red * 255.0) as u8,
+        (green * 255.0) as u8,
+        (blue * 255.0) as u8,
+    ]
+}
+
+/// Colours of the spectrogram visualization, from the lowest to the highest value.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Colormap {
+    /// Black, blue, cyan, white
+    #[default]
+    Heat,
+    /// Black to white
+    Gray,
+    /// Purple, teal, yellow, perceptually uniform
+    Viridis,
+    /// Black, purple, orange, light yellow, perceptually uniform
+    Magma,
+}
+
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+impl Colormap {
+    /// Colour of `value` between 0 and 1.
+    pub fn color(self, value: f64) -> [u8; 3] {
+        match self {
+            Self::Heat => heat_color(value),
+            Self::Gray => [(value.clamp(0.0, 1.0) * 255.0) as u8; 3],
+            Self::Viridis => interpolate(&VIRIDIS, value),
+            Self::Magma => interpolate(&MAGMA, value),
+        }
+    }
+}
+
+/// Colour of `value` between 0 and 1 on the evenly spaced `stops`.
+fn interpolate(stops: &[[u8; 3]], value: f64) -> [u8; 3] {
+    let position = value.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let index = (position as usize).min(stops.len() - 2);
+    let fraction = position - index as f64;
+    let (low, high) = (stops[index], stops[index + 1]);
+
+    std::array::from_fn(|i| {
+        (low[i] as f64 + (high[i] as f64 - low[i] as f64) * fraction).round() as u8
+    })
+}
+
+/// Domain the spectrogram values are stored in.
+#[derive(ValueEnum, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FftAmplitude {
+    /// Log-compressed power in dB
+    Db,
+    /// Squared magnitude
+    Power,
+    /// Linear magnitude
+    Linear,
+}
+
+impl FftAmplitude {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Db => "db",
+            Self::Power => "power",
+            Self::Linear => "linear",
+        }
+    }
+}
+
+/// File format of the raw FFT output. Both formats hold the same little-endian f64s, one
+/// row per slice with the channels concatenated.
+#[derive(ValueEnum, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FftFormat {
+    /// Little-endian f64s in a 16 bit RGBA PNG, one row per slice (legacy)
+    Png,
+    /// NumPy array of f64s with the shape (slices, channels, bins)
+    Npy,
+    /// Raw little-endian f64s
+    F64,
+}
+
+impl FftFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Npy => "npy",
+            Self::F64 => "f64",
+        }
+    }
+}
+
+/// Window function applied to each FFT slice.
+#[derive(ValueEnum, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FftWindow {
+    Hann,
+    Hamming,
+    Blackman,
+    /// No window
+    Rect,
+}
+
+impl FftWindow {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hann => "hann",
+            Self::Hamming => "hamming",
+            Self::Blackman => "blackman",
+            Self::Rect => "rect",
+        }
+    }
+
+    fn window_type(&self) -> WindowType {
+        match self {
+            Self::Hann => WindowType::Hanning,
+            Self::Hamming => WindowType::Hamming,
+            Self::Blackman => WindowType::Blackman,
+            Self::Rect => WindowType::Rectangular,
+        }
+    }
+}
+
+/// Implementation the spectrogram is computed with.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FftBackend {
+    /// The STFT of the aus crate
+    Aus,
+    /// realfft, planned once per spectrogram and with SIMD kernels (needs the fast-fft feature)
+    Fast,
+}
+
+impl FftBackend {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Aus => "aus",
+            Self::Fast => "fast",
+        }
+    }
+
+    pub fn spectrogram(
+        &self,
+        data: &[f64],
+        fft_size: usize,
+        hop: usize,
+        window: FftWindow,
+        amplitude: FftAmplitude,
+    ) -> Vec<Vec<f64>> {
+        match self {
+            #[cfg(feature = "fast-fft")]
+            Self::Fast => super::fast_fft::spectrogram(data, fft_size, hop, window, amplitude),
+            // Without the feature, --fft-backend fast is rejected with the options
+            _ => spectrogram(data, fft_size, hop, window, amplitude),
+        }
+    }
+}
+
+/// Frequency axis of the spectrogram.
+#[derive(ValueEnum, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FftScale {
+    /// FFT bins, evenly spaced in frequency
+    Linear,
+    /// Bands evenly spaced in log frequency
+    Log,
+    /// Bands evenly spaced on the mel scale
+    Mel,
+}
+
+impl FftScale {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Log => "log",
+            Self::Mel => "mel",
+        }
+    }
+}
+
+/// Spectrogram of a single channel, one `Vec` of `fft_size / 2 + 1` bins per slice, with
+/// slices starting every `hop` frames.
+pub fn spectrogram(
+    data: &[f64],
+    fft_size: usize,
+    hop: usize,
+    window: FftWindow,
+    amplitude: FftAmplitude,
+) -> Vec<Vec<f64>> {
+    let imaginary = rstft(data, fft_size, hop, window.window_type());
+
+    let (mut magnitude, _) = complex_to_polar_rstft(&imaginary);
+
+    // aus windows a last slice of a single frame with 0 / 0 for Hann and Hamming, taken as 1
+    // like `portable::window` does, every bin of the padded frame is its magnitude
+    let tail = data
+        .len()
+        .saturating_sub(magnitude.len().saturating_sub(1) * hop);
+    if tail == 1
+        && matches!(window, FftWindow::Hann | FftWindow::Hamming)
+        && let Some(last) = magnitude.last_mut()
+    {
+        last.fill(data[data.len() - 1].abs());
+    }
+
+    if amplitude == FftAmplitude::Linear {
+        return magnitude;
+    }
 
-use super::Analyser;
+    let power = make_power_spectrogram(&magnitude);
+    if amplitude == FftAmplitude::Power {
+        return power;
+    }
+
+    make_log_spectrogram(&power, 10.0, portable::LOG_FLOOR, None)
+}
+
+/// Log-power spectrogram of a single channel, Hann windowed with 50% overlap.
+pub fn log_spectrogram(data: &[f64], fft_size: usize) -> Vec<Vec<f64>> {
+    spectrogram(
+        data,
+        fft_size,
+        fft_size / 2,
+        FftWindow::Hann,
+        FftAmplitude::Db,
+    )
+}
 
 pub struct FftVisualizer {
     pub min: Option<f64>,
     pub max: Option<f64>,
     pub path: PathBuf,
     pub data: Vec<f64>,
+    /// Split into tiles instead of one image, with `--fft-vis-tile-width`
+    pub tiling: Option<Tiling>,
+    pub colormap: Colormap,
+}
+
+/// How the visualization is split into tiles of `width` slices.
+pub struct Tiling {
+    pub width: usize,
+    /// Frames between the starts of consecutive slices
+    pub hop: usize,
+    pub sample_rate: i32,
+    pub num_frames: usize,
+}
+
+/// One tile of the visualization in the index.
+#[derive(Serialize)]
+pub struct Tile {
+    pub file: String,
+    /// Start of the first slice in seconds
+    pub start: f64,
+    /// Start of the slice after the last one in seconds, the end of the input for the last tile
+    pub end: f64,
+    #[serde(rename = "firstSlice")]
+    pub first_slice: usize,
+    pub slices: usize,
+}
+
+/// The index of the tiles, written next to them.
+#[derive(Serialize)]
+pub struct TileIndex {
+    #[serde(rename = "tileWidth")]
+    pub tile_width: usize,
+    /// Height of every tile, the values of a slice of all channels
+    pub height: usize,
+    /// Seconds between the starts of consecutive slices
+    #[serde(rename = "sliceDuration")]
+    pub slice_duration: f64,
+    pub duration: f64,
+    pub tiles: Vec<Tile>,
+}
+
+/// Rows of an image encoded between progress reports and checks for cancellation.
+const ROWS_PER_REPORT: usize = 256;
+
+/// Encodes the rows of `data` with `writer`, reporting how far it got on `step` as `what`.
+/// Stops with [`Error::Cancelled`] once the analysis is cancelled.
+fn encode_rows<W: Write>(
+    mut writer: Writer<W>,
+    data: &[u8],
+    row_len: usize,
+    step: &Step,
+    what: &str,
+    write_error: impl Fn(io::Error) -> Error,
+) -> Result<()> {
+    // Chunks as large as the rows between reports keep the overhead of the chunks small
+    let chunk_len = (row_len * ROWS_PER_REPORT).max(1);
+    let mut stream = writer
+        .stream_writer_with_size(chunk_len)
+        .map_err(|err| write_error(err.into()))?;
+
+    for (index, rows) in data.chunks(chunk_len).enumerate() {
+        if cancel::is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        step.report(format_args!(
+            "{what}, {}%",
+            100 * index * chunk_len / data.len()
+        ));
+        stream.write_all(rows).map_err(&write_error)?;
+    }
+
+    stream.finish().map_err(|err| write_error(err.into()))
+}
+
+/// Removes the incomplete file at `path` if writing it was cancelled.
+fn remove_cancelled(path: &Path, result: Result<()>) -> Result<()> {
+    if matches!(result, Err(Error::Cancelled)) {
+        let _ = fs::remove_file(path);
+    }
+
+    result
 }
 
 impl FftVisualizer {
@@ -34,6 +376,8 @@ impl FftVisualizer {
             max: None,
             data: vec![],
             path: path.as_ref().to_path_buf(),
+            tiling: None,
+            colormap: Colormap::default(),
         }
     }
 
@@ -62,7 +406,7 @@ impl FftVisualizer {
     where
         I: IntoIterator<Item = f64>,
     {
-        self.data.extend(data.into_iter().map(|v| {
+        self.data.extend(data.into_iter().inspect(|&v| {
             // Update min and max while mapping to avoid another iteration
             if self.min.is_none() || (self.min.is_some() && v < self.min.unwrap()) {
                 self.min = Some(v);
@@ -70,37 +414,119 @@ impl FftVisualizer {
             if self.max.is_none() || (self.max.is_some() && v > self.max.unwrap()) {
                 self.max = Some(v);
             }
-
-            v
         }));
     }
 
-    pub fn visualize(&self, width: usize, height: usize) {
+    /// Path of the tile with `index`, `<stem>_<index>.<extension>` next to the given path.
+    fn tile_path(&self, index: usize) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(extension) => format!("{stem}_{index:04}.{}", extension.to_string_lossy()),
+            None => format!("{stem}_{index:04}"),
+        };
+
+        self.path.with_file_name(name)
+    }
+
+    /// Path of the tile index, `<stem>_tiles.json` next to the given path.
+    pub fn index_path(&self) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{stem}_tiles.json"))
+    }
+
+    pub fn visualize(&self, width: usize, height: usize) -> Result<()> {
         if self.min.is_none() || self.max.is_none() {
-            println!("FFT Visualization: No valid data to visualize.");
+            warn!("FFT visualization: no valid data to visualize");
 
-            return;
+            return Ok(());
         }
 
-        let min = self.min.unwrap();
-        let max = self.max.unwrap();
-        let range = max - min;
+        let step = Step::new("FFT");
+        let Some(tiling) = &self.tiling else {
+            return self.write_image(&self.path, width, 0..height, &step, "visualization");
+        };
+
+        let seconds =
+            |frame: usize| frame.min(tiling.num_frames) as f64 / tiling.sample_rate as f64;
+        let mut tiles = Vec::new();
+
+        let num_tiles = height.div_ceil(tiling.width.max(1));
+        for (index, first) in (0..height).step_by(tiling.width.max(1)).enumerate() {
+            let end = (first + tiling.width).min(height);
+            let path = self.tile_path(index);
+            let what = format!("visualization tile {}/{num_tiles}", index + 1);
+            self.write_image(&path, width, first..end, &step, &what)?;
+
+            tiles.push(Tile {
+                file: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                start: seconds(first * tiling.hop),
+                end: if end == height {
+                    seconds(tiling.num_frames)
+                } else {
+                    seconds(end * tiling.hop)
+                },
+                first_slice: first,
+                slices: end - first,
+            });
+        }
+
+        let index = TileIndex {
+            tile_width: tiling.width,
+            height: width,
+            slice_duration: tiling.hop as f64 / tiling.sample_rate as f64,
+            duration: seconds(tiling.num_frames),
+            tiles,
+        };
+        let path = self.index_path();
+        let write_error = |err| Error::io("write FFT tile index to", &path, err);
+        let json = serde_json::to_string_pretty(&index).map_err(|err| write_error(err.into()))?;
+        std::fs::write(&path, json).map_err(write_error)?;
+
+        output!(
+            "Wrote {} FFT visualization tiles, listed in {}",
+            index.tiles.len(),
+            path.display()
+        );
 
+        Ok(())
+    }
+
+    /// Colour of `value` between the minimum and maximum.
+    pub fn color(&self, value: f64) -> [u8; 3] {
+        let (Some(min), Some(max)) = (self.min, self.max) else {
+            return self.colormap.color(0.0);
+        };
+        let level = ((value - min) / (max - min)).clamp(0.0, 1.0);
+
+        // Squaring for better contrast
+        self.colormap.color(level.powi(2))
+    }
+
+    /// Writes the `slices` of the data as an image to `path`, with time running from left to
+    /// right and the values of a slice from bottom to top.
+    fn write_image(
+        &self,
+        path: &Path,
+        width: usize,
+        slices: Range<usize>,
+        step: &Step,
+        what: &str,
+    ) -> Result<()> {
         // Convert to RGB
-        let rotated_width = height;
+        let rotated_width = slices.len();
         let rotated_height = width;
 
-        let mut rgb_data = vec![0u8; (rotated_width * rotated_height * 3) as usize];
+        let mut rgb_data = vec![0u8; rotated_width * rotated_height * 3];
 
-        for (i, value) in self
-            .data
+        for (i, &value) in self.data[slices.start * width..slices.end * width]
             .iter()
-            .map(|v| ((v - min) / range).clamp(0.0, 1.0).powi(2)) // Squaring for better contrast
             .enumerate()
         {
-            let blue = (value * 3.0).min(1.0);
-            let green = ((value - 0.33) * 3.0).max(0.0).min(1.0);
-            let red = ((value - 0.66) * 3.0).max(0.0).min(1.0);
+            let [red, green, blue] = self.color(value);
 
             // Rotate coordinates 90 degrees counter-clockwise
             let x = i % width;
@@ -109,34 +535,32 @@ impl FftVisualizer {
             let new_y = (width - 1) - x;
             let new_index = (new_y * rotated_width + new_x) * 3;
 
-            rgb_data[new_index] = (red * 255.0) as u8;
-            rgb_data[new_index + 1] = (green * 255.0) as u8;
-            rgb_data[new_index + 2] = (blue * 255.0) as u8;
+            rgb_data[new_index] = red;
+            rgb_data[new_index + 1] = green;
+            rgb_data[new_index + 2] = blue;
         }
 
-        let Ok(file) = File::create(&self.path) else {
-            println!("Could not create output PNG file");
-
-            return;
-        };
-
+        let write_error = |err| Error::io("write FFT visualization to", path, err);
+        let file = File::create(path).map_err(write_error)?;
         let mut w = BufWriter::new(file);
 
         let mut encoder = png::Encoder::new(&mut w, rotated_width as u32, rotated_height as u32);
         encoder.set_color(png::ColorType::Rgb);
         encoder.set_depth(png::BitDepth::Eight);
 
-        let Ok(mut writer) = encoder.write_header() else {
-            println!("Could not write PNG header");
-
-            return;
-        };
+        let writer = encoder
+            .write_header()
+            .map_err(|err| write_error(err.into()))?;
+        let encoded = encode_rows(
+            writer,
+            &rgb_data,
+            rotated_width * 3,
+            step,
+            what,
+            write_error,
+        );
 
-        let Ok(_) = writer.write_image_data(&rgb_data) else {
-            println!("Could not write FFT visualization data");
-
-            return;
-        };
+        remove_cancelled(path, encoded)
     }
 }
 
@@ -145,140 +569,533 @@ struct FftOutput {
     path: PathBuf,
 }
 
+/// Raw output written slice by slice with `--low-memory`.
+enum RawWriter {
+    Png(Box<StreamWriter<'static, BufWriter<File>>>),
+    Plain(BufWriter<File>),
+}
+
+impl RawWriter {
+    fn write_all(&mut self, row: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Png(writer) => writer.write_all(row),
+            Self::Plain(writer) => writer.write_all(row),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Png(writer) => writer.finish().map_err(Into::into),
+            Self::Plain(mut writer) => writer.flush(),
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FftFiles {
+    /// Raw spectrogram in the format given by `format`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visualization: Option<String>,
+    /// Index of the visualization tiles, with `--fft-vis-tile-width`
+    #[serde(rename = "visualizationTiles", skip_serializing_if = "Option::is_none")]
+    pub visualization_tiles: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FftAnalysis {
+    pub size: usize,
+    pub window: FftWindow,
+    /// Frames between the starts of consecutive slices
+    pub hop: usize,
+    /// Overlap of consecutive slices in percent
+    pub overlap: f64,
+    pub scale: FftScale,
+    /// Number of bands of the log and mel scales
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bands: Option<usize>,
+    pub amplitude: FftAmplitude,
+    pub format: FftFormat,
+    pub results: FftFiles,
+}
+
+/// Spectrogram computed slice by slice while streaming, used with `--low-memory`. It holds
+/// one FFT window per channel and writes each slice to the raw output right away.
+struct FftStream {
+    window: Vec<f64>,
+    frames: usize,
+    /// End of the last slice written, in frames
+    covered: usize,
+    slices: usize,
+    writer: Option<RawWriter>,
+    /// First write error, reported from `finish`
+    error: Option<Error>,
+}
+
+/// Sums slices of FFT bins into the bands of a scale.
+struct Scaling<'a> {
+    filterbank: Option<&'a Filterbank>,
+    amplitude: FftAmplitude,
+    portable: bool,
+}
+
+impl Scaling<'_> {
+    fn apply(&self, slice: Vec<f64>) -> Vec<f64> {
+        let Some(filterbank) = self.filterbank else {
+            return slice;
+        };
+
+        let bands = filterbank.apply(&slice);
+        if self.amplitude != FftAmplitude::Db {
+            return bands;
+        }
+
+        bands
+            .into_iter()
+            .map(|power| {
+                if self.portable {
+                    portable::db(power)
+                } else {
+                    (10.0 * power.log10()).max(portable::LOG_FLOOR)
+                }
+            })
+            .collect()
+    }
+}
+
 pub struct FftAnalyser {
+    amplitude: FftAmplitude,
+    backend: FftBackend,
+    deterministic: bool,
     fft_size: usize,
+    format: FftFormat,
+    hop: usize,
+    window: FftWindow,
+    scale: FftScale,
+    filterbank: Option<Filterbank>,
     channels: usize,
+    num_frames: usize,
     bins: Vec<Vec<f64>>, // [channel][bin]
     raw: Option<FftOutput>,
     vis: Option<FftVisualizer>,
+    stream: Option<FftStream>,
 }
 
-/** Writes FFT results to a .png file as little-endian raw f64s. */
+/** Writes FFT results to a file as little-endian raw f64s, see `FftFormat`. */
 impl FftAnalyser {
-    pub fn new(args: &Cli, wav: &Wav<i32>, path: Option<PathBuf>) -> Self {
-        let channels = wav.n_channels() as usize;
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec, path: Option<PathBuf>) -> Result<Self> {
+        let channels = spec.channels as usize;
 
-        Self {
+        let mut analyser = Self {
+            amplitude: args.fft_amplitude,
+            backend: args.fft_backend,
+            deterministic: args.deterministic,
             fft_size: args.fft_bins,
+            format: args.fft_format,
+            hop: args.fft_hop(),
+            window: args.fft_window,
+            scale: args.fft_scale,
+            filterbank: Filterbank::new(
+                args.fft_scale,
+                args.fft_bands,
+                args.fft_bins,
+                spec.sample_rate as f64,
+            ),
             channels,
+            num_frames: spec.num_frames,
             bins: vec![Vec::new(); channels],
             raw: path.map(|path| FftOutput {
                 results: vec![],
                 path,
             }),
-            vis: args.fft_vis.as_ref().map(|p| FftVisualizer::new(p)),
+            vis: args.fft_vis.as_ref().map(|path| FftVisualizer {
+                tiling: args.fft_vis_tile_width.map(|width| Tiling {
+                    width: width as usize,
+                    hop: args.fft_hop(),
+                    sample_rate: spec.sample_rate,
+                    num_frames: spec.num_frames,
+                }),
+                ..FftVisualizer::new(path)
+            }),
+            stream: None,
+        };
+
+        // A dry run only checks the options
+        if args.low_memory
+            && !args.dry_run
+            && let Some(raw) = analyser.raw.as_ref()
+        {
+            analyser.stream = Some(FftStream {
+                window: portable::window(args.fft_window, args.fft_bins),
+                frames: 0,
+                covered: 0,
+                slices: 0,
+                writer: Some(analyser.open_stream(&raw.path)?),
+                error: None,
+            });
         }
+
+        Ok(analyser)
     }
-}
 
-impl Analyser for FftAnalyser {
-    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &Samples<i32>) {
-        for (channel_index, sample) in frame.iter().enumerate() {
-            let bin = *sample as f64;
-            self.bins[channel_index].push(bin);
+    /// Values per slice and channel, the FFT bins or the bands of the scale.
+    fn num_bins(&self) -> usize {
+        self.filterbank
+            .as_ref()
+            .map_or(self.fft_size / 2 + 1, |filterbank| filterbank.num_bands())
+    }
+
+    fn width(&self) -> usize {
+        self.channels * self.num_bins()
+    }
+
+    /// Domain the FFT is computed in. Bands sum power, so dB are only taken after that.
+    fn fft_amplitude(&self) -> FftAmplitude {
+        match self.amplitude {
+            FftAmplitude::Db if self.filterbank.is_some() => FftAmplitude::Power,
+            amplitude => amplitude,
+        }
+    }
+
+    /// How slices are summed into the bands of the scale, apart from the analyser so the
+    /// channels can be scaled on other threads.
+    fn scaling(&self) -> Scaling<'_> {
+        Scaling {
+            filterbank: self.filterbank.as_ref(),
+            amplitude: self.amplitude,
+            // The streamed spectrogram always uses the portable math
+            portable: self.deterministic || self.stream.is_some(),
+        }
+    }
+
+    /// Number of slices in the spectrogram of `num_frames` frames, with a last, possibly
+    /// short, slice that reaches the end.
+    fn num_slices(&self) -> usize {
+        1 + self
+            .num_frames
+            .saturating_sub(self.fft_size)
+            .div_ceil(self.hop)
+    }
+
+    fn encoder<W: Write>(&self, w: W, height: usize) -> Encoder<'static, W> {
+        let mut encoder = Encoder::new(w, self.width() as u32, height as u32);
+        if self.deterministic {
+            let mut layout = format!(
+                "f64le;slices={};channels={};bins={};amplitude={}",
+                height,
+                self.channels,
+                self.num_bins(),
+                self.amplitude.as_str()
+            );
+            if self.scale != FftScale::Linear {
+                layout.push_str(&format!(";scale={}", self.scale.as_str()));
+            }
+            portable::configure_encoder(&mut encoder, &layout);
+        } else {
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_depth(BitDepth::Sixteen);
+        }
+
+        encoder
+    }
+
+    fn npy_header(&self, height: usize) -> Vec<u8> {
+        npy::header("<f8", &[height, self.channels, self.num_bins()])
+    }
+
+    /// Creates the raw output up front, its height is known from the announced length.
+    fn open_stream(&self, path: &Path) -> Result<RawWriter> {
+        let write_error = |err| Error::io("write FFT output to", path, err);
+        let mut w = BufWriter::new(File::create(path).map_err(write_error)?);
+
+        match self.format {
+            FftFormat::Png => self
+                .encoder(w, self.num_slices())
+                .write_header()
+                .and_then(|writer| writer.into_stream_writer())
+                .map(|writer| RawWriter::Png(Box::new(writer)))
+                .map_err(|err| write_error(err.into())),
+            FftFormat::Npy => {
+                w.write_all(&self.npy_header(self.num_slices()))
+                    .map_err(write_error)?;
+
+                Ok(RawWriter::Plain(w))
+            }
+            FftFormat::F64 => Ok(RawWriter::Plain(w)),
+        }
+    }
+
+    /// Writes the slice over the buffered frames of every channel.
+    fn write_slice(&mut self) {
+        let Some(stream) = self.stream.as_ref() else {
+            return;
+        };
+
+        let length = self.bins[0].len();
+        let short_window;
+        let window = if length < self.fft_size {
+            short_window = portable::window(self.window, length);
+            &short_window
+        } else {
+            &stream.window
+        };
+
+        let mut row = Vec::with_capacity(self.width() * 8);
+        for data in &self.bins {
+            let slice = portable::slice(data, window, self.fft_size, self.fft_amplitude());
+            let slice = self.scaling().apply(slice);
+            row.extend(slice.iter().flat_map(|v| v.to_le_bytes()));
+        }
+
+        let (Some(stream), Some(raw)) = (self.stream.as_mut(), self.raw.as_ref()) else {
+            return;
+        };
+        if let Some(writer) = stream.writer.as_mut()
+            && stream.error.is_none()
+            && let Err(err) = writer.write_all(&row)
+        {
+            stream.error = Some(Error::io("write FFT output to", &raw.path, err));
         }
+
+        stream.covered = stream.frames;
+        stream.slices += 1;
     }
 
-    fn finish(&mut self, _label: &str) -> u8 {
-        let mut spectra = vec![];
+    fn finish_stream(&mut self) -> Result<()> {
+        let Some(stream) = self.stream.as_ref() else {
+            return Ok(());
+        };
 
-        for i in 0..self.channels {
-            let data = &self.bins[i];
-            let imaginary = rstft(data, self.fft_size, self.fft_size / 2, WindowType::Hanning);
+        // The short last slice, unless the previous one already reached the end
+        if stream.slices == 0 || stream.frames > stream.covered {
+            self.write_slice();
+        }
 
-            let (magnitude, _) = complex_to_polar_rstft(&imaginary);
-            let power = make_power_spectrogram(&magnitude);
-            let log_spectrogram = make_log_spectrogram(&power, 10.0, 10e-8, None);
+        let num_slices = self.num_slices();
+        let width = self.width();
+        let (Some(stream), Some(raw)) = (self.stream.as_mut(), self.raw.as_ref()) else {
+            return Ok(());
+        };
+        if let Some(err) = stream.error.take() {
+            return Err(err);
+        }
 
-            spectra.push(log_spectrogram);
+        let write_error = |err| Error::io("write FFT output to", &raw.path, err);
+        if let Some(mut writer) = stream.writer.take() {
+            // The height was fixed from the announced length, rows of frames that never
+            // arrived are left zero
+            let missing = num_slices.saturating_sub(stream.slices);
+            let row = vec![0u8; width * 8];
+            for _ in 0..missing {
+                writer.write_all(&row).map_err(write_error)?;
+            }
+
+            writer.finish().map_err(write_error)?;
         }
 
+        Ok(())
+    }
+
+    /// Computes the spectra of the buffered frames and writes them.
+    fn write_spectra(&mut self) -> Result<()> {
+        let amplitude = self.fft_amplitude();
+        let (fft_size, hop, window) = (self.fft_size, self.hop, self.window);
+        let (backend, deterministic) = (self.backend, self.deterministic);
+        let scaling = self.scaling();
+
+        let step = Step::new("FFT");
+        let channels = self.bins.len();
+        let finished = AtomicUsize::new(0);
+
+        let spectrogram = |(index, data): (usize, &Vec<f64>)| {
+            if cancel::is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            step.report(format_args!(
+                "channel {}/{channels}, {}%",
+                index + 1,
+                100 * finished.load(Ordering::Relaxed) / channels
+            ));
+
+            let spectrum = if deterministic {
+                portable::spectrogram(data, fft_size, hop, window, amplitude)
+            } else {
+                backend.spectrogram(data, fft_size, hop, window, amplitude)
+            };
+
+            let slices = spectrum
+                .into_iter()
+                .map(|slice| scaling.apply(slice))
+                .collect::<Vec<_>>();
+            finished.fetch_add(1, Ordering::Relaxed);
+
+            Ok(slices)
+        };
+
+        // The channels are independent, collecting keeps them in order
+        #[cfg(feature = "parallel")]
+        let spectra: Vec<_> = {
+            use rayon::prelude::*;
+            self.bins
+                .par_iter()
+                .enumerate()
+                .map(spectrogram)
+                .collect::<Result<_>>()?
+        };
+        #[cfg(not(feature = "parallel"))]
+        let spectra: Vec<_> = self
+            .bins
+            .iter()
+            .enumerate()
+            .map(spectrogram)
+            .collect::<Result<_>>()?;
+
         // Interleave spectra data
         let num_slices = spectra[0].len();
         for slice_index in 0..num_slices {
-            for channel_index in 0..self.channels {
+            for channel in &spectra {
                 if let Some(raw) = &mut self.raw {
-                    raw.results.extend(
-                        spectra[channel_index][slice_index]
-                            .iter()
-                            .flat_map(|&v| v.to_le_bytes()),
-                    );
+                    raw.results
+                        .extend(channel[slice_index].iter().flat_map(|&v| v.to_le_bytes()));
                 }
 
                 if let Some(vis) = &mut self.vis {
-                    vis.extend(spectra[channel_index][slice_index].iter().cloned());
+                    vis.extend(channel[slice_index].iter().cloned());
                 }
             }
         }
 
         // Create an image where each row is a single time slice with each channel concatenated
-        let width = self.channels * (self.fft_size / 2 + 1);
+        let width = self.width();
 
         if let Some(vis) = &self.vis {
             let height = vis.data.len() / width;
-            vis.visualize(width, height);
+            vis.visualize(width, height)?;
         }
-
-        if self.raw.is_none() {
-            return 0;
+        if cancel::is_cancelled() {
+            return Err(Error::Cancelled);
         }
 
-        let raw = self.raw.as_ref().unwrap();
-        let height = raw.results.len() / (width * 8);
-        let Ok(file) = File::create(&raw.path) else {
-            println!(
-                "FFT: Could not create output file at {}",
-                raw.path.display()
-            );
-
-            return 0;
+        let Some(raw) = self.raw.as_ref() else {
+            return Ok(());
         };
+
+        let height = raw.results.len() / (width * 8);
+        let write_error = |err| Error::io("write FFT output to", &raw.path, err);
+        let file = File::create(&raw.path).map_err(write_error)?;
         let mut w = BufWriter::new(file);
 
-        let mut encoder = Encoder::new(&mut w, width as u32, height as u32);
-        encoder.set_color(ColorType::Rgba);
-        encoder.set_depth(BitDepth::Sixteen);
+        if self.format != FftFormat::Png {
+            if self.format == FftFormat::Npy {
+                w.write_all(&self.npy_header(height)).map_err(write_error)?;
+            }
+            w.write_all(&raw.results)
+                .and_then(|_| w.flush())
+                .map_err(write_error)?;
 
-        let Ok(mut writer) = encoder.write_header() else {
-            println!("FFT: Could not write PNG header");
+            return Ok(());
+        }
 
-            return 0;
-        };
+        let writer = self
+            .encoder(&mut w, height)
+            .write_header()
+            .map_err(|err| write_error(err.into()))?;
+        let encoded = encode_rows(
+            writer,
+            &raw.results,
+            width * 8,
+            &step,
+            "output",
+            write_error,
+        );
 
-        let Ok(_) = writer.write_image_data(&raw.results) else {
-            println!("FFT: Could not write image data");
+        remove_cancelled(&raw.path, encoded)
+    }
 
-            return 0;
+    /// The settings and output files, which have to be written for their paths to be
+    /// canonicalized.
+    fn analysis(&self) -> FftAnalysis {
+        let canonical = |path: &PathBuf| {
+            path.canonicalize()
+                .ok()
+                .map(|path| path.to_string_lossy().to_string())
         };
 
-        0
+        FftAnalysis {
+            size: self.fft_size,
+            window: self.window,
+            hop: self.hop,
+            overlap: (1.0 - self.hop as f64 / self.fft_size as f64) * 100.0,
+            scale: self.scale,
+            bands: self
+                .filterbank
+                .as_ref()
+                .map(|filterbank| filterbank.num_bands()),
+            amplitude: self.amplitude,
+            format: self.format,
+            results: FftFiles {
+                output: self.raw.as_ref().and_then(|raw| canonical(&raw.path)),
+                visualization: self
+                    .vis
+                    .as_ref()
+                    .filter(|vis| vis.tiling.is_none())
+                    .and_then(|vis| canonical(&vis.path)),
+                visualization_tiles: self
+                    .vis
+                    .as_ref()
+                    .filter(|vis| vis.tiling.is_some())
+                    .and_then(|vis| canonical(&vis.index_path())),
+            },
+        }
     }
+}
 
-    fn json(&self) -> Vec<(String, serde_json::Value)> {
-        let mut map = Map::new();
+impl Analyser for FftAnalyser {
+    fn name(&self) -> &'static str {
+        "fft"
+    }
 
-        if let Some(raw) = &self.raw {
-            // We can canonicalize here because the file has already been written in finish()
-            if let Ok(path) = raw.path.canonicalize() {
-                map.insert(
-                    "output".to_string(),
-                    serde_json::Value::from(path.to_string_lossy()),
-                );
-            };
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (channel_index, sample) in frame.iter().enumerate() {
+            let bin = *sample as f64;
+            self.bins[channel_index].push(bin);
         }
 
-        if let Some(vis) = &self.vis {
-            if let Ok(vis_path) = vis.path.canonicalize() {
-                map.insert(
-                    "visualization".to_string(),
-                    serde_json::Value::from(vis_path.to_string_lossy()),
-                );
-            };
+        if let Some(stream) = self.stream.as_mut() {
+            stream.frames += 1;
+
+            if self.bins[0].len() == self.fft_size {
+                self.write_slice();
+
+                for data in self.bins.iter_mut() {
+                    data.drain(..self.hop);
+                }
+            }
         }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        if self.stream.is_some() {
+            self.finish_stream()?;
+        } else {
+            self.write_spectra()?;
+        }
+
+        Ok(AnalysisResult::new(Analysis {
+            fft: Some(self.analysis()),
+            ..Analysis::default()
+        }))
+    }
+
+    fn stop(&mut self, num_frames: usize) {
+        // The height of a streamed output is fixed, its missing rows are left zero
+        if let Some(tiling) = self.vis.as_mut().and_then(|vis| vis.tiling.as_mut()) {
+            tiling.num_frames = num_frames;
+        }
+    }
 
-        vec![(
-            "fft".to_string(),
-            serde_json::json!({ "size": self.fft_size, "results": map }),
-        )]
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<FftAnalysis>("fft", generator)]
     }
 }
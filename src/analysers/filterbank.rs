@@ -0,0 +1,102 @@
+//! Band filters for the mel and log-frequency spectrograms.
+
+use std::f64::consts::LN_10;
+
+use super::{fft::FftScale, portable};
+
+pub(crate) fn hz_to_mel(hz: f64) -> f64 {
+    2595.0 * portable::log10(1.0 + hz / 700.0)
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    700.0 * (portable::exp(mel / 2595.0 * LN_10) - 1.0)
+}
+
+/// Triangular filters that sum the bins of a spectrum into bands. The weights are computed
+/// with the portable math, so they are the same with and without `--deterministic`.
+pub struct Filterbank {
+    /// First bin and weights of each band
+    bands: Vec<(usize, Vec<f64>)>,
+}
+
+impl Filterbank {
+    /// Filters of `num_bands` bands for spectra of `fft_size / 2 + 1` bins, `None` for the
+    /// linear scale. Mel bands reach from 0 Hz to Nyquist, log bands from the first bin.
+    pub fn new(
+        scale: FftScale,
+        num_bands: usize,
+        fft_size: usize,
+        sample_rate: f64,
+    ) -> Option<Self> {
+        let nyquist = sample_rate / 2.0;
+        let bin_hz = sample_rate / fft_size as f64;
+        let num_bins = fft_size / 2 + 1;
+        let steps = (num_bands + 1) as f64;
+
+        // Lower edge, center and upper edge of every band overlap with the neighbours
+        let edges: Vec<f64> = match scale {
+            FftScale::Linear => return None,
+            FftScale::Mel => {
+                let top = hz_to_mel(nyquist);
+                (0..num_bands + 2)
+                    .map(|i| mel_to_hz(top * i as f64 / steps))
+                    .collect()
+            }
+            FftScale::Log => {
+                let span = portable::ln(nyquist / bin_hz);
+                (0..num_bands + 2)
+                    .map(|i| bin_hz * portable::exp(span * i as f64 / steps))
+                    .collect()
+            }
+        };
+
+        let bands = edges
+            .windows(3)
+            .map(|edge| {
+                let (lower, center, upper) = (edge[0], edge[1], edge[2]);
+                let weight = |bin: usize| {
+                    let hz = bin as f64 * bin_hz;
+                    if hz <= lower || hz >= upper {
+                        0.0
+                    } else if hz <= center {
+                        (hz - lower) / (center - lower)
+                    } else {
+                        (upper - hz) / (upper - center)
+                    }
+                };
+
+                let first = ((lower / bin_hz).floor() as usize).min(num_bins - 1);
+                let last = ((upper / bin_hz).ceil() as usize).min(num_bins - 1);
+                let weights: Vec<f64> = (first..=last).map(weight).collect();
+
+                // Bands narrower than a bin take the bin nearest to their center
+                if weights.iter().all(|weight| *weight == 0.0) {
+                    let nearest = ((center / bin_hz).round() as usize).min(num_bins - 1);
+                    return (nearest, vec![1.0]);
+                }
+
+                (first, weights)
+            })
+            .collect();
+
+        Some(Self { bands })
+    }
+
+    pub fn num_bands(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// Sums `spectrum` into the bands.
+    pub fn apply(&self, spectrum: &[f64]) -> Vec<f64> {
+        self.bands
+            .iter()
+            .map(|(first, weights)| {
+                weights
+                    .iter()
+                    .zip(&spectrum[*first..])
+                    .map(|(weight, value)| weight * value)
+                    .sum()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    findings::Severity,
+    inspect::{Inspection, inspect},
+    json::Analysis,
+    schema::SchemaEntry,
+};
+
+/// Chunks that may appear more than once, like a `LIST` of `INFO` next to one of `adtl`, and
+/// those that only pad the file.
+const REPEATABLE_CHUNKS: [&str; 4] = ["LIST", "JUNK", "PAD ", "FLLR"];
+
+/// Chunks that make a file ambiguous if they appear twice, because readers pick either.
+const UNIQUE_CHUNKS: [&str; 4] = ["fmt ", "data", "fact", "ds64"];
+
+/// What is wrong with the structure of a WAV file.
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FormatIssueKind {
+    /// The RIFF chunk is larger or smaller than the file
+    RiffSize,
+    /// A chunk reaches past the end of the file
+    ChunkPastEnd,
+    /// A chunk of odd size isn't followed by the pad byte at the end of the file
+    MissingPadding,
+    /// A chunk ID isn't printable, usually because a chunk before it has the wrong size or
+    /// lacks its pad byte
+    InvalidChunkId,
+    /// The data chunk ends within a frame
+    PartialFrame,
+    /// A non-PCM format has no `fact` chunk with the number of samples
+    MissingFact,
+    /// A chunk appears more than once
+    DuplicateChunk,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FormatIssue {
+    pub kind: FormatIssueKind,
+    /// `error` for corruption that readers may trip over, `warning` for deviations from the
+    /// specification that most readers accept
+    pub severity: Severity,
+    /// ID of the chunk the issue is about, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<String>,
+    /// Position of the chunk contents in the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct FormatAnalysis {
+    /// `RIFF`, `RF64` or `BW64`
+    pub container: String,
+    #[serde(rename = "riffSize")]
+    pub riff_size: u64,
+    #[serde(rename = "fileSize")]
+    pub file_size: u64,
+    pub issues: Vec<FormatIssue>,
+    /// No issue is an error
+    pub valid: bool,
+}
+
+impl FormatIssue {
+    fn new(kind: FormatIssueKind, severity: Severity, message: String) -> Self {
+        Self {
+            kind,
+            severity,
+            chunk: None,
+            offset: None,
+            message,
+        }
+    }
+
+    fn at(mut self, id: &str, offset: u64) -> Self {
+        self.chunk = Some(id.to_string());
+        self.offset = Some(offset);
+        self
+    }
+}
+
+/// Checks the RIFF structure of a WAV file against its length and the specification.
+fn check(inspection: &Inspection) -> Vec<FormatIssue> {
+    let mut issues = Vec::new();
+    let file_size = inspection.file_size;
+
+    let riff_end = inspection.riff_size.saturating_add(8);
+    if riff_end > file_size {
+        issues.push(FormatIssue::new(
+            FormatIssueKind::RiffSize,
+            Severity::Error,
+            format!(
+                "the {} chunk declares {} bytes, {} more than the file has",
+                inspection.container,
+                inspection.riff_size,
+                riff_end - file_size
+            ),
+        ));
+    } else if riff_end < file_size {
+        issues.push(FormatIssue::new(
+            FormatIssueKind::RiffSize,
+            Severity::Warning,
+            format!(
+                "{} bytes follow the end of the {} chunk",
+                file_size - riff_end,
+                inspection.container
+            ),
+        ));
+    }
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for chunk in inspection.chunks.iter() {
+        let id = chunk.id.as_str();
+        let end = chunk.offset.saturating_add(chunk.size);
+
+        if !chunk.id.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+            issues.push(
+                FormatIssue::new(
+                    FormatIssueKind::InvalidChunkId,
+                    Severity::Error,
+                    format!(
+                        "chunk ID {id:?} isn't printable, the chunks before it may be misaligned"
+                    ),
+                )
+                .at(id, chunk.offset),
+            );
+        } else if end > file_size {
+            issues.push(
+                FormatIssue::new(
+                    FormatIssueKind::ChunkPastEnd,
+                    Severity::Error,
+                    format!(
+                        "'{id}' chunk of {} bytes ends {} bytes past the end of the file",
+                        chunk.size,
+                        end - file_size
+                    ),
+                )
+                .at(id, chunk.offset),
+            );
+        } else if chunk.size & 1 == 1 && end == file_size {
+            issues.push(
+                FormatIssue::new(
+                    FormatIssueKind::MissingPadding,
+                    Severity::Warning,
+                    format!(
+                        "'{id}' chunk of {} bytes has no pad byte to an even length",
+                        chunk.size
+                    ),
+                )
+                .at(id, chunk.offset),
+            );
+        }
+
+        *counts.entry(id).or_default() += 1;
+        if counts[id] == 2 && !REPEATABLE_CHUNKS.contains(&id) {
+            let severity = if UNIQUE_CHUNKS.contains(&id) {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            issues.push(
+                FormatIssue::new(
+                    FormatIssueKind::DuplicateChunk,
+                    severity,
+                    format!("'{id}' chunk appears more than once"),
+                )
+                .at(id, chunk.offset),
+            );
+        }
+    }
+
+    if let Some(data) = inspection.data()
+        && inspection.block_align > 0
+        && data.size % inspection.block_align as u64 != 0
+    {
+        issues.push(
+            FormatIssue::new(
+                FormatIssueKind::PartialFrame,
+                Severity::Warning,
+                format!(
+                    "data chunk of {} bytes ends {} bytes into a frame of {}",
+                    data.size,
+                    data.size % inspection.block_align as u64,
+                    inspection.block_align
+                ),
+            )
+            .at("data", data.offset),
+        );
+    }
+
+    if inspection.encoding != "pcm" && !counts.contains_key("fact") {
+        issues.push(FormatIssue::new(
+            FormatIssueKind::MissingFact,
+            Severity::Warning,
+            format!(
+                "{} samples need a 'fact' chunk with the number of samples",
+                inspection.encoding
+            ),
+        ));
+    }
+
+    issues
+}
+
+/// Validates the RIFF structure of the input file: the chunk sizes against the length of the
+/// file, padding, duplicate chunks and the `fact` chunk of non-PCM formats. The structure is
+/// read from the header when the analyser is set up, the frames aren't looked at.
+pub struct FormatAnalyser {
+    inspection: Inspection,
+    issues: Vec<FormatIssue>,
+}
+
+impl FormatAnalyser {
+    pub fn new(args: &AnalyseArgs) -> Result<Self> {
+        let path = match args.input.first() {
+            Some(path) if !args.raw && args.concat.is_empty() => path,
+            _ => {
+                return Err(Error::Config(
+                    "--format-check and --strict-container need a WAV file as --input, not --raw or --concat"
+                        .to_string(),
+                ));
+            }
+        };
+
+        let inspection = inspect(path)?;
+        let issues = check(&inspection);
+
+        Ok(Self { inspection, issues })
+    }
+}
+
+impl Analyser for FormatAnalyser {
+    fn name(&self) -> &'static str {
+        "format"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, _frame: &[i32]) {}
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let errors = self
+            .issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+            .count();
+
+        Ok(AnalysisResult::new(Analysis {
+            format: Some(FormatAnalysis {
+                container: self.inspection.container.clone(),
+                riff_size: self.inspection.riff_size,
+                file_size: self.inspection.file_size,
+                issues: std::mem::take(&mut self.issues),
+                valid: errors == 0,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<FormatAnalysis>("format", generator)]
+    }
+}
@@ -0,0 +1,218 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{
+    Analyser, AnalysisResult,
+    fft::{FftAmplitude, FftWindow},
+    portable,
+};
+use crate::{Result, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Length of the spectrum slices in seconds, rounded up to a power of two of frames, long
+/// enough to tell neighbouring semitones apart in the bass.
+const SLICE_SECONDS: f64 = 0.3;
+
+/// Pitches between C2 and C7 count towards the chroma, in Hz.
+const MIN_HZ: f64 = 65.4;
+const MAX_HZ: f64 = 2093.0;
+
+/// Slices quieter than this have no meaningful chroma and are skipped, in dBFS.
+const MIN_LEVEL_DB: f64 = -60.0;
+
+/// Key profiles of Krumhansl and Kessler (1982), from the tonic up, for major and minor keys.
+const MAJOR_PROFILE: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Names of the pitch classes from C, as keys are usually written.
+const PITCH_CLASSES: [&str; 12] = [
+    "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+impl Mode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Major => "major",
+            Self::Minor => "minor",
+        }
+    }
+
+    fn profile(&self) -> &'static [f64; 12] {
+        match self {
+            Self::Major => &MAJOR_PROFILE,
+            Self::Minor => &MINOR_PROFILE,
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct KeyAnalysis {
+    /// Tonic of the key, e.g. `F#`, `null` if no slice was loud enough
+    pub key: Option<&'static str>,
+    pub mode: Option<Mode>,
+    /// Correlation of the chroma with the profile of the key, from -1 to 1
+    pub correlation: f64,
+    /// How far the key is ahead of the next best one, the difference of their correlations
+    pub confidence: f64,
+    /// Energy of the pitch classes from C to B, relative to the strongest
+    pub chroma: Vec<f64>,
+}
+
+/// Pearson correlation of `chroma` with the profile of `mode` on `tonic`.
+fn correlation(chroma: &[f64; 12], tonic: usize, mode: Mode) -> f64 {
+    let profile = mode.profile();
+    let rotated: Vec<f64> = (0..12).map(|pc| profile[(pc + 12 - tonic) % 12]).collect();
+
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+    let (chroma_mean, profile_mean) = (mean(chroma), mean(&rotated));
+
+    let mut covariance = 0.0;
+    let mut chroma_variance = 0.0;
+    let mut profile_variance = 0.0;
+    for (value, weight) in chroma.iter().zip(&rotated) {
+        covariance += (value - chroma_mean) * (weight - profile_mean);
+        chroma_variance += (value - chroma_mean).powi(2);
+        profile_variance += (weight - profile_mean).powi(2);
+    }
+
+    let norm = (chroma_variance * profile_variance).sqrt();
+    if norm > 0.0 { covariance / norm } else { 0.0 }
+}
+
+/// Estimates the key of the channel mix. The magnitudes of every slice are folded into the
+/// twelve pitch classes, and the chroma of the whole input is matched against the
+/// Krumhansl-Kessler profiles of all 24 major and minor keys.
+pub struct KeyAnalyser {
+    /// Pitch class of every bin, `None` outside the range of the chroma
+    classes: Vec<Option<usize>>,
+    chroma: [f64; 12],
+    hop: usize,
+    mix: Vec<f64>,
+    slice_size: usize,
+    slices: usize,
+    window: Vec<f64>,
+}
+
+impl KeyAnalyser {
+    pub fn new(spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let slice_size = ((sample_rate * SLICE_SECONDS) as usize).next_power_of_two();
+        let bin_hz = sample_rate / slice_size as f64;
+
+        let classes = (0..=slice_size / 2)
+            .map(|bin| {
+                let frequency = bin as f64 * bin_hz;
+                (MIN_HZ..=MAX_HZ.min(sample_rate / 2.0))
+                    .contains(&frequency)
+                    .then(|| {
+                        // Semitones from A4, A is pitch class 9
+                        let semitone = (12.0 * (frequency / 440.0).log2()).round() as i64;
+                        (semitone + 9).rem_euclid(12) as usize
+                    })
+            })
+            .collect();
+
+        Self {
+            classes,
+            chroma: [0.0; 12],
+            hop: slice_size / 2,
+            mix: Vec::with_capacity(slice_size),
+            slice_size,
+            slices: 0,
+            window: portable::window(FftWindow::Hann, slice_size),
+        }
+    }
+
+    fn analyse_slice(&mut self) {
+        let energy = self.mix.iter().map(|value| value * value).sum::<f64>();
+        let level = 10.0 * (energy / self.slice_size as f64).log10();
+        if level < MIN_LEVEL_DB {
+            return;
+        }
+
+        let magnitudes = portable::slice(
+            &self.mix,
+            &self.window,
+            self.slice_size,
+            FftAmplitude::Linear,
+        );
+        let mut chroma = [0.0; 12];
+        for (magnitude, class) in magnitudes.iter().zip(&self.classes) {
+            if let Some(class) = class {
+                chroma[*class] += magnitude;
+            }
+        }
+
+        // Every slice counts the same, loud passages don't outweigh quiet ones
+        let strongest = chroma.iter().copied().fold(0.0, f64::max);
+        if strongest > 0.0 {
+            for (sum, value) in self.chroma.iter_mut().zip(chroma) {
+                *sum += value / strongest;
+            }
+            self.slices += 1;
+        }
+    }
+}
+
+impl Analyser for KeyAnalyser {
+    fn name(&self) -> &'static str {
+        "key"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        self.mix.push(sum / frame.len().max(1) as f64);
+
+        if self.mix.len() == self.slice_size {
+            self.analyse_slice();
+            self.mix.drain(..self.hop);
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let strongest = self.chroma.iter().copied().fold(0.0, f64::max);
+        let chroma = self.chroma.map(|value| {
+            if strongest > 0.0 {
+                value / strongest
+            } else {
+                0.0
+            }
+        });
+
+        let mut keys: Vec<(usize, Mode, f64)> = (0..12)
+            .flat_map(|tonic| [Mode::Major, Mode::Minor].map(|mode| (tonic, mode)))
+            .map(|(tonic, mode)| (tonic, mode, correlation(&chroma, tonic, mode)))
+            .collect();
+        keys.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        let best = keys.first().filter(|_| self.slices > 0);
+        let runner_up = keys.get(1).map_or(0.0, |(_, _, correlation)| *correlation);
+
+        Ok(AnalysisResult::new(Analysis {
+            key: Some(KeyAnalysis {
+                key: best.map(|(tonic, _, _)| PITCH_CLASSES[*tonic]),
+                mode: best.map(|(_, mode, _)| *mode),
+                correlation: best.map_or(0.0, |(_, _, correlation)| *correlation),
+                confidence: best.map_or(0.0, |(_, _, correlation)| correlation - runner_up),
+                chroma: chroma.to_vec(),
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<KeyAnalysis>("key", generator)]
+    }
+}
@@ -1,35 +1,46 @@
-use std::vec;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    vec,
+};
 
 use ebur128::{EbuR128, Error as EbuR128Error, Mode};
+use schemars::{JsonSchema, SchemaGenerator};
 use serde::Serialize;
-use wavers::{Samples, Wav};
 
-use super::Analyser;
-use crate::{cli::Cli, debug, output, output::frame_to_time};
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    debug,
+    exclude::Exclusions,
+    findings::{Finding, Kind},
+    layout::loudness_meter,
+    output,
+    output::frame_to_time,
+    progress,
+    schema::SchemaEntry,
+    stream::StreamSpec,
+    warn,
+};
 
-#[derive(Debug, Clone)]
-pub struct SilenceState {
-    pub previous_lufs: f64,
-    pub silence_start_frame: usize,
-    pub silence_end_frame: usize,
-}
-
-impl SilenceState {
-    pub fn new() -> Self {
-        Self {
-            previous_lufs: 0.0,
-            silence_start_frame: 0,
-            silence_end_frame: 0,
-        }
-    }
+/// A silence that is still going on.
+struct CurrentSilence {
+    start: usize,
+    /// Loudness of the window that started it, LUFS-S and LUFS-I
+    start_lufs: (f64, f64),
+    /// Threshold in effect when it started
+    threshold: f64,
+    /// Whether it lasted `--silence-hold` and was reported
+    reported: bool,
 }
 
 struct InternalSegment {
     start: usize,
     end: Option<usize>,
+    threshold: f64,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct SilenceSegment {
     pub start: f32,
     pub end: f32,
@@ -40,14 +51,414 @@ pub struct SilenceSegment {
     pub end_sample: usize,
     #[serde(rename = "durationSamples")]
     pub duration_samples: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+    /// Threshold in LUFS-S in effect when the silence started, with `--silence-relative`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SilenceAnalysis {
+    pub results: Vec<SilenceSegment>,
+    /// Silence threshold in LUFS-S, with `--silence-relative` the one in effect at the end
+    pub threshold: f64,
+    /// Loudness in LUFS-S that ends a silence
+    #[serde(rename = "resumeThreshold")]
+    pub resume_threshold: f64,
+    /// Threshold in LU relative to the integrated loudness so far, from `--silence-relative`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative: Option<f64>,
+    /// Minimum duration of a reported silence in seconds
+    pub hold: f64,
+    #[serde(rename = "windowSize")]
+    pub window_size: f32,
+    /// Overlap of consecutive windows in percent
+    #[serde(rename = "windowOverlap", skip_serializing_if = "Option::is_none")]
+    pub window_overlap: Option<f64>,
+    /// Longest allowed silence at the start in seconds
+    #[serde(rename = "maxLeadingSilence", skip_serializing_if = "Option::is_none")]
+    pub max_leading_silence: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LoudnessResult {
+    pub start: f32,
+    pub end: f32,
+    /// Short-term loudness in LUFS
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub loudness: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+}
+
+/// Short-term loudness of one meter at a fixed rate.
+#[derive(Serialize, JsonSchema)]
+pub struct EnvelopeSeries {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+    /// Short-term loudness in LUFS, point `i` over the 3 seconds up to `(i + 1) * interval`
+    #[schemars(extend("items" = {"type": ["number", "null"]}))]
+    pub loudness: Vec<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LoudnessEnvelope {
+    /// Points per second, as given to `--loudness-rate`
+    pub rate: f64,
+    /// Seconds between points, `1 / rate` rounded to whole frames
+    pub interval: f32,
+    pub series: Vec<EnvelopeSeries>,
+}
+
+/// Distribution of the short-term loudness of one meter over the windows.
+#[derive(Serialize, JsonSchema)]
+pub struct LoudnessHistogram {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+    /// Width of the bins in LU, as given to `--loudness-histogram`
+    #[serde(rename = "binWidth")]
+    pub bin_width: f64,
+    /// Lower edge of the first bin in LUFS, `null` if every window was gated
+    pub low: Option<f64>,
+    /// Windows in each bin, bin `i` from `low + i * binWidth`
+    pub counts: Vec<u64>,
+    /// Windows below the absolute gate of -70 LUFS, left out of the bins and percentiles
+    pub gated: u64,
+    /// Loudness exceeded in 10% of the windows in LUFS
+    #[serde(rename = "L10")]
+    pub l10: Option<f64>,
+    /// Median loudness of the windows in LUFS
+    #[serde(rename = "L50")]
+    pub l50: Option<f64>,
+    /// Loudness exceeded in 90% of the windows in LUFS
+    #[serde(rename = "L90")]
+    pub l90: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LoudnessAnalysis {
+    pub results: Vec<LoudnessResult>,
+    #[serde(rename = "windowSize")]
+    pub window_size: f32,
+    /// Overlap of consecutive windows in percent
+    #[serde(rename = "windowOverlap", skip_serializing_if = "Option::is_none")]
+    pub window_overlap: Option<f64>,
+    /// Evenly sampled short-term loudness, with `--loudness-rate`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<LoudnessEnvelope>,
+    /// Histogram of the loudness of the windows of every meter, with `--loudness-histogram`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histograms: Option<Vec<LoudnessHistogram>>,
+}
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+/// Windows quieter than this are left out of the loudness histogram, as in the loudness
+/// range of EBU Tech 3342.
+const HISTOGRAM_GATE: f64 = -70.0;
+
+/// Windows counted by their loudness, in bins of a fixed width.
+struct Histogram {
+    width: f64,
+    /// Windows per bin, by the index of the bin from 0 LUFS
+    bins: BTreeMap<i64, u64>,
+    gated: u64,
+}
+
+impl Histogram {
+    fn add(&mut self, lufs: f64) {
+        if lufs.is_nan() || lufs < HISTOGRAM_GATE {
+            self.gated += 1;
+            return;
+        }
+
+        *self
+            .bins
+            .entry((lufs / self.width).floor() as i64)
+            .or_default() += 1;
+    }
+
+    /// Loudness below which `share` of the counted windows are, interpolated within its bin.
+    fn percentile(&self, share: f64) -> Option<f64> {
+        let total: u64 = self.bins.values().sum();
+        let target = share * total as f64;
+        let mut below = 0;
+
+        for (&bin, &count) in self.bins.iter() {
+            if (below + count) as f64 >= target {
+                let within = (target - below as f64) / count as f64;
+                return Some((bin as f64 + within) * self.width);
+            }
+            below += count;
+        }
+
+        None
+    }
+
+    fn result(&self, channel: Option<usize>) -> LoudnessHistogram {
+        let first = self.bins.keys().next().copied();
+        let last = self.bins.keys().next_back().copied();
+        let counts = first.zip(last).map_or(Vec::new(), |(first, last)| {
+            (first..=last)
+                .map(|bin| self.bins.get(&bin).copied().unwrap_or(0))
+                .collect()
+        });
+
+        LoudnessHistogram {
+            channel,
+            bin_width: self.width,
+            low: first.map(|bin| bin as f64 * self.width),
+            counts,
+            gated: self.gated,
+            l10: self.percentile(0.9),
+            l50: self.percentile(0.5),
+            l90: self.percentile(0.1),
+        }
+    }
+}
+
+/// Blocks per second the level is measured in to refine silence boundaries.
+pub const REFINE_BLOCKS_PER_SECOND: usize = 200;
+
+/// Level of short blocks of the last two windows, to place silence boundaries within the
+/// windows that crossed the threshold. The level is the loudness formula without the
+/// K-weighting, so it compares to the LUFS thresholds.
+struct BlockLevels {
+    /// Frames per block
+    block: usize,
+    frames: usize,
+    sum: f64,
+    /// Levels of the latest blocks, oldest first
+    levels: VecDeque<f64>,
+    capacity: usize,
+    /// Blocks finished so far, including those no longer kept
+    finished: usize,
+}
+
+impl BlockLevels {
+    fn new(sample_rate: i32, window: usize) -> Self {
+        let block = (sample_rate as usize / REFINE_BLOCKS_PER_SECOND).max(1);
+        let capacity = 2 * window.div_ceil(block) + 1;
+
+        Self {
+            block,
+            frames: 0,
+            sum: 0.0,
+            levels: VecDeque::with_capacity(capacity),
+            capacity,
+            finished: 0,
+        }
+    }
+
+    fn push(&mut self, samples: &[i32]) {
+        self.sum += samples
+            .iter()
+            .map(|&sample| (sample as f64 / FULL_SCALE).powi(2))
+            .sum::<f64>();
+        self.frames += 1;
+        if self.frames < self.block {
+            return;
+        }
+
+        if self.levels.len() == self.capacity {
+            self.levels.pop_front();
+        }
+        self.levels
+            .push_back(-0.691 + 10.0 * (self.sum / self.block as f64).log10());
+        self.finished += 1;
+        self.frames = 0;
+        self.sum = 0.0;
+    }
+
+    /// The latest blocks with the frame each of them starts at, latest first.
+    fn latest(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let first = self.finished - self.levels.len();
+        self.levels
+            .iter()
+            .enumerate()
+            .rev()
+            .map(move |(index, &level)| ((first + index) * self.block, level))
+    }
+
+    /// Start of the run of blocks below `lufs` the latest block ends, if it is below.
+    fn quiet_since(&self, lufs: f64) -> Option<usize> {
+        let mut start = None;
+        for (frame, level) in self.latest() {
+            if level >= lufs {
+                break;
+            }
+            start = Some(frame);
+        }
+
+        start
+    }
+
+    /// End of the latest block below `lufs`.
+    fn quiet_until(&self, lufs: f64) -> Option<usize> {
+        self.latest()
+            .find(|(_, level)| *level < lufs)
+            .map(|(frame, _)| frame + self.block)
+    }
 }
 
+/// Short-term loudness sampled every `step` frames. Unlike the windows, the meter is never
+/// reset, so each point covers the 3 seconds before it and consecutive points overlap.
+struct Envelope {
+    meter: EbuR128,
+    frame_buf: Vec<i32>,
+    points: Vec<f64>,
+}
+
+impl Envelope {
+    fn push(&mut self, samples: &[i32], step: usize) {
+        self.frame_buf.extend_from_slice(samples);
+        if self.frame_buf.len() < step * samples.len() {
+            return;
+        }
+
+        if let Err(err) = self.meter.add_frames_i32(&self.frame_buf) {
+            warn!("error adding frame to loudness envelope: {:?}", &err);
+        }
+        self.frame_buf.clear();
+        self.points
+            .push(self.meter.loudness_shortterm().unwrap_or(f64::NEG_INFINITY));
+    }
+}
+
+/// Integrated loudness of everything so far, for `--silence-relative`. Fed in blocks of
+/// `step` frames rather than frame by frame, which the meter is slow at.
+struct Programme {
+    meter: EbuR128,
+    frame_buf: Vec<i32>,
+    step: usize,
+}
+
+impl Programme {
+    fn push(&mut self, samples: &[i32]) {
+        self.frame_buf.extend_from_slice(samples);
+        if self.frame_buf.len() < self.step * samples.len() {
+            return;
+        }
+
+        if let Err(err) = self.meter.add_frames_i32(&self.frame_buf) {
+            warn!("error adding frame to programme loudness: {:?}", &err);
+        }
+        self.frame_buf.clear();
+    }
+
+    /// Integrated loudness in LUFS, `None` until a block got past the gates.
+    fn loudness(&self) -> Option<f64> {
+        self.meter
+            .loudness_global()
+            .ok()
+            .filter(|lufs| lufs.is_finite())
+    }
+}
+
+/// Silence detection with hysteresis: a silence starts below `lufs` and only ends at or
+/// above `resume_lufs`. Silences shorter than `hold` frames are dropped.
 struct Silence {
     count: usize,
+    current: Option<CurrentSilence>,
+    hold: usize,
     lufs: f64,
-    percentage: f32,
+    /// LU below the programme loudness `lufs` follows, with `--silence-relative`
+    relative: Option<f64>,
+    /// Longest allowed silence at the start in seconds
+    max_leading: Option<f64>,
+    percentage: f64,
+    previous_lufs: f64,
+    resume_lufs: f64,
     segments: Vec<InternalSegment>,
-    state: SilenceState,
+    /// Segments already returned by `take_events`
+    taken: usize,
+    /// Frames of a measurement window, a silence found in the first one is at the start
+    window: usize,
+}
+
+impl Silence {
+    /// Reports the current silence once it lasted `hold` frames at `frame`.
+    fn confirm(&mut self, label: &str, prefix: &str, frame: usize, shared: &Shared) {
+        let Some(current) = self.current.as_mut() else {
+            return;
+        };
+        if current.reported || frame - current.start < self.hold {
+            return;
+        }
+
+        current.reported = true;
+        progress::event("silence");
+        output!(
+            "[{}] SILENCE START: {}LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {}",
+            label,
+            prefix,
+            current.start_lufs.0,
+            current.start_lufs.1,
+            frame_to_time(current.start, shared.sample_rate)
+        );
+
+        self.segments.push(InternalSegment {
+            start: current.start,
+            end: None,
+            threshold: current.threshold,
+        });
+    }
+
+    /// Ends the current silence at `frame`, returns whether it was reported.
+    fn end(&mut self, label: &str, prefix: &str, frame: usize, shared: &Shared) -> bool {
+        self.confirm(label, prefix, frame, shared);
+
+        let Some(current) = self.current.take() else {
+            return false;
+        };
+        if !current.reported {
+            debug!(
+                "[{}] DEBUG        : {}silence shorter than the hold @ {}",
+                label,
+                prefix,
+                frame_to_time(current.start, shared.sample_rate)
+            );
+            return false;
+        }
+
+        self.count += frame - current.start;
+        if let Some(segment) = self.segments.last_mut() {
+            segment.end = Some(frame);
+        }
+
+        true
+    }
+
+    /// Moves the thresholds to `relative` below the programme loudness, keeping the
+    /// hysteresis. They stay at `--lufs` until the programme loudness is known.
+    fn follow(&mut self, programme: Option<f64>) {
+        if let Some(lufs) = self
+            .relative
+            .zip(programme)
+            .map(|(relative, lufs)| lufs + relative)
+        {
+            self.resume_lufs += lufs - self.lufs;
+            self.lufs = lufs;
+        }
+    }
+
+    /// Percentage of the measured frames that are silent, excluded ones don't count.
+    fn share(&self, shared: &Shared) -> f64 {
+        let measured = shared.num_frames - shared.excluded.before(shared.num_frames);
+        self.count as f64 / measured as f64 * 100.0
+    }
+
+    /// Seconds of silence at the start, if the first window is silent. The start is after
+    /// an excluded range at the beginning.
+    fn leading(&self, shared: &Shared) -> Option<f64> {
+        let first = self.segments.first()?;
+        let end = first.end.unwrap_or(shared.num_frames);
+        let start = shared.excluded.first_included();
+
+        (first.start < start + self.window)
+            .then(|| (end - start) as f64 / shared.sample_rate as f64)
+    }
 }
 
 struct Loudness {
@@ -56,43 +467,93 @@ struct Loudness {
     loudness: f64,
 }
 
-pub struct LoudnessAnalyser {
+/// Loudness and silence state of one measurement, covering either all channels or a single
+/// channel with `--per-channel`.
+struct Meter {
+    channel: Option<usize>,
     frame_buf: Vec<i32>,
     frame_buf_iter: usize,
+    /// Samples the window moves on by, less than its length with `--window-overlap`
+    hop: usize,
     loudness: EbuR128,
     loudness_windows: Option<Vec<Loudness>>,
-    num_frames: usize,
-    sample_rate: i32,
-    window_size: usize,
+    envelope: Option<Envelope>,
+    histogram: Option<Histogram>,
+    levels: Option<BlockLevels>,
+    programme: Option<Programme>,
     silence: Option<Silence>,
+    /// In an excluded range, the window starts over after it
+    excluding: bool,
+    /// End of the latest excluded range, a silence doesn't start before it
+    resumed: usize,
 }
 
-impl LoudnessAnalyser {
-    pub fn new(args: &Cli, wav: &Wav<i32>) -> Result<Self, EbuR128Error> {
-        let (_, spec) = wav.wav_spec();
-        let sample_rate = spec.fmt_chunk.sample_rate;
-        let loudness = EbuR128::new(
-            wav.n_channels().into(),
-            sample_rate as u32,
-            Mode::S | Mode::I,
-        )?;
-
-        let window_size =
-            ((sample_rate as usize * wav.n_channels() as usize) as f32 * args.window_size) as usize;
-
-        let silence = if args.silence {
+impl Meter {
+    fn new(
+        args: &AnalyseArgs,
+        spec: &StreamSpec,
+        channel: Option<usize>,
+        channels: u32,
+        envelope_step: Option<usize>,
+    ) -> Result<Self, EbuR128Error> {
+        // Each channel of --per-channel has a meter of its own
+        let meter = |mode| match channel {
+            Some(_) => EbuR128::new(1, spec.sample_rate as u32, mode),
+            None => loudness_meter(spec, mode),
+        };
+        let loudness = meter(Mode::S | Mode::I)?;
+        let buf_size =
+            ((spec.sample_rate as usize * channels as usize) as f32 * args.window_size) as usize;
+        let window_frames = buf_size / channels as usize;
+        let hop_frames = ((window_frames as f64 * (1.0 - args.window_overlap / 100.0)).round()
+            as usize)
+            .clamp(1, window_frames.max(1));
+
+        let silence = if args.detect_silence() {
             Some(Silence {
                 count: 0,
+                current: None,
+                hold: (args.silence_hold * spec.sample_rate as f64) as usize,
                 lufs: args.lufs,
-                percentage: args.silence_percentage as f32,
+                relative: args.silence_relative,
+                max_leading: args.max_leading_silence,
+                percentage: args.silence_percentage,
+                previous_lufs: 0.0,
+                resume_lufs: args.silence_resume_lufs.unwrap_or(args.lufs),
                 segments: Vec::new(),
-                state: SilenceState::new(),
+                taken: 0,
+                window: (spec.sample_rate as f32 * args.window_size) as usize,
             })
         } else {
             None
         };
 
-        let loudness_windows = if args.loudness {
+        let envelope = match envelope_step {
+            Some(step) => Some(Envelope {
+                meter: meter(Mode::S)?,
+                frame_buf: Vec::with_capacity(step * channels as usize),
+                points: Vec::new(),
+            }),
+            None => None,
+        };
+
+        let levels = (args.refine_silence && args.detect_silence())
+            .then(|| BlockLevels::new(spec.sample_rate, window_frames));
+
+        // The gating blocks of the meter move on by 100 ms
+        let programme = match args.silence_relative {
+            Some(_) => {
+                let step = (spec.sample_rate as usize / 10).max(1);
+                Some(Programme {
+                    meter: meter(Mode::I)?,
+                    frame_buf: Vec::with_capacity(step * channels as usize),
+                    step,
+                })
+            }
+            None => None,
+        };
+
+        let loudness_windows = if args.track_loudness() {
             Some(vec![Loudness {
                 start: 0,
                 end: None,
@@ -103,105 +564,204 @@ impl LoudnessAnalyser {
         };
 
         Ok(Self {
-            frame_buf: vec![0; window_size],
+            channel,
+            frame_buf: vec![0; buf_size],
             frame_buf_iter: 0,
+            hop: hop_frames * channels as usize,
             loudness,
             loudness_windows,
-            num_frames: wav.n_samples(),
-            sample_rate,
-            window_size,
+            envelope,
+            histogram: args.loudness_histogram.map(|width| Histogram {
+                width,
+                bins: BTreeMap::new(),
+                gated: 0,
+            }),
+            levels,
+            programme,
             silence,
+            excluding: false,
+            resumed: 0,
         })
     }
-}
 
-impl Analyser for LoudnessAnalyser {
-    fn analyse(&mut self, label: &str, frame_counter: usize, frame: &Samples<i32>) {
-        for sample in frame.iter() {
+    /// `CH:n ` prefix for console output in per-channel mode.
+    fn prefix(&self) -> String {
+        self.channel
+            .map_or(String::new(), |channel| format!("CH:{} ", channel))
+    }
+
+    fn push(&mut self, label: &str, frame_counter: usize, samples: &[i32], analyser: &Shared) {
+        // The envelope and block levels are by position and go on through excluded ranges
+        if let (Some(envelope), Some(step)) = (&mut self.envelope, analyser.envelope_step) {
+            envelope.push(samples, step);
+        }
+        if let Some(levels) = &mut self.levels {
+            levels.push(samples);
+        }
+
+        if analyser.excluded.contains(frame_counter) {
+            self.exclude(label, frame_counter, analyser);
+            return;
+        }
+        if self.excluding {
+            self.excluding = false;
+            self.resumed = frame_counter;
+            if let Some(window) = self.loudness_windows.as_mut().and_then(|w| w.last_mut()) {
+                window.start = frame_counter;
+            }
+        }
+
+        if let Some(programme) = &mut self.programme {
+            programme.push(samples);
+        }
+
+        for sample in samples.iter() {
             self.frame_buf[self.frame_buf_iter] = *sample;
             self.frame_buf_iter += 1;
         }
 
-        if self.frame_buf_iter >= self.window_size {
-            self.frame_buf_iter = 0;
-            self.loudness.reset();
+        if self.frame_buf_iter < self.frame_buf.len() {
+            return;
+        }
+
+        self.loudness.reset();
+
+        if let Err(err) = self.loudness.add_frames_i32(&self.frame_buf) {
+            warn!("error adding frame to loudness measurement: {:?}", &err);
+        }
+
+        // Keep the overlap with the next window
+        self.frame_buf.copy_within(self.hop.., 0);
+        self.frame_buf_iter = self.frame_buf.len() - self.hop;
 
-            if let Err(err) = self.loudness.add_frames_i32(&self.frame_buf) {
-                println!(
-                    "Warning: error adding frame to loudness measurement: {:?}",
-                    &err
-                );
+        let lufs = self
+            .loudness
+            .loudness_shortterm()
+            .unwrap_or(f64::NEG_INFINITY);
+
+        if let Some(histogram) = &mut self.histogram {
+            histogram.add(lufs);
+        }
+
+        if let Some(windows) = &mut self.loudness_windows {
+            if let Some(last_window) = windows.last_mut() {
+                last_window.end = Some(frame_counter);
+                last_window.loudness = lufs;
             }
 
-            let lufs = self
-                .loudness
-                .loudness_shortterm()
-                .unwrap_or(f64::NEG_INFINITY);
+            windows.push(Loudness {
+                start: frame_counter - self.frame_buf_iter / samples.len(),
+                end: None,
+                loudness: 0.0,
+            });
+        }
 
-            if let Some(windows) = &mut self.loudness_windows {
-                if let Some(last_window) = windows.last_mut() {
-                    last_window.end = Some(frame_counter);
-                    last_window.loudness = lufs;
-                }
+        let prefix = self.prefix();
 
-                windows.push(Loudness {
-                    start: frame_counter,
-                    end: None,
-                    loudness: 0.0,
+        if let Some(silence) = &mut self.silence {
+            let global = self.loudness.loudness_global().unwrap_or(-f64::INFINITY);
+            silence.follow(self.programme.as_ref().and_then(Programme::loudness));
+
+            if silence.current.is_none() && lufs < silence.lufs {
+                // Not before the end of the previous silence or excluded range
+                let previous = silence
+                    .segments
+                    .last()
+                    .and_then(|segment| segment.end)
+                    .unwrap_or(0)
+                    .max(self.resumed);
+                let start = self
+                    .levels
+                    .as_ref()
+                    .and_then(|levels| levels.quiet_since(silence.lufs))
+                    .map_or(frame_counter, |start| start.max(previous));
+
+                silence.current = Some(CurrentSilence {
+                    start,
+                    start_lufs: (lufs, global),
+                    threshold: silence.lufs,
+                    reported: false,
                 });
-            }
+            } else if let Some(start) = silence.current.as_ref().map(|current| current.start)
+                && lufs >= silence.resume_lufs
+            {
+                let end = self
+                    .levels
+                    .as_ref()
+                    .and_then(|levels| levels.quiet_until(silence.resume_lufs))
+                    .map_or(frame_counter, |end| end.clamp(start, frame_counter));
 
-            if let Some(silence) = &mut self.silence {
-                if lufs < silence.lufs && silence.state.previous_lufs >= silence.lufs {
-                    silence.state.silence_start_frame = frame_counter;
+                if silence.end(label, &prefix, end, analyser) {
                     output!(
-                        "[{}] SILENCE START: LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {}",
+                        "[{}] SILENCE END  : {}LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {} ({:04.3}% of total)",
                         label,
+                        prefix,
                         lufs,
-                        self.loudness.loudness_global().unwrap_or(-f64::INFINITY),
-                        frame_to_time(frame_counter, self.sample_rate)
+                        global,
+                        frame_to_time(end, analyser.sample_rate),
+                        silence.share(analyser)
                     );
-
-                    silence.segments.push(InternalSegment {
-                        start: silence.state.silence_start_frame,
-                        end: None,
-                    });
                 }
+            }
 
-                if lufs >= silence.lufs && silence.state.previous_lufs < silence.lufs {
-                    silence.state.silence_end_frame = frame_counter;
-                    silence.count +=
-                        silence.state.silence_end_frame - silence.state.silence_start_frame;
+            silence.confirm(label, &prefix, frame_counter, analyser);
+            silence.previous_lufs = lufs;
+        }
 
-                    output!(
-                        "[{}] SILENCE END  : LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {} ({:04.3}% of total)",
-                        label,
-                        lufs,
-                        self.loudness.loudness_global().unwrap_or(-f64::INFINITY),
-                        frame_to_time(frame_counter, self.sample_rate),
-                        (silence.count as f32 / self.num_frames as f32) * 100.0
-                    );
+        debug!(
+            "[{}] DEBUG        : {}LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {}",
+            label,
+            prefix,
+            lufs,
+            self.loudness.loudness_global().unwrap_or(-f64::INFINITY),
+            frame_to_time(frame_counter, analyser.sample_rate)
+        );
+    }
 
-                    if let Some(segment) = silence.segments.last_mut() {
-                        segment.end = Some(silence.state.silence_end_frame);
-                    }
-                }
+    /// Skips a frame of an excluded range at `frame`. At its start, the open silence ends and
+    /// the window in progress is dropped.
+    fn exclude(&mut self, label: &str, frame: usize, analyser: &Shared) {
+        if self.excluding {
+            return;
+        }
 
-                silence.state.previous_lufs = lufs;
-            }
+        self.excluding = true;
+        self.frame_buf_iter = 0;
+        self.end_silence(label, frame, analyser);
+    }
 
-            debug!(
-                "[{}] DEBUG        : LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {}",
+    /// Ends the open silence at `frame`, the end of the stream or the start of an excluded
+    /// range.
+    fn end_silence(&mut self, label: &str, frame: usize, analyser: &Shared) {
+        let prefix = self.prefix();
+        let Some(silence) = &mut self.silence else {
+            return;
+        };
+
+        if silence.end(label, &prefix, frame, analyser) {
+            output!(
+                "[{}] SILENCE END  : {}LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {} ({:04.3}% of total)",
                 label,
-                lufs,
+                prefix,
+                silence.previous_lufs,
                 self.loudness.loudness_global().unwrap_or(-f64::INFINITY),
-                frame_to_time(frame_counter, self.sample_rate)
+                frame_to_time(frame, analyser.sample_rate),
+                silence.share(analyser)
             );
         }
     }
 
-    fn finish(&mut self, label: &str) -> u8 {
-        if let Some(windows) = &mut self.loudness_windows
+    /// Ends the open silence, and fails `result` for too much silence.
+    fn finish(&mut self, label: &str, analyser: &Shared, result: &mut AnalysisResult) {
+        // A window that started in an excluded range at the end isn't measured
+        if self.excluding
+            && let Some(windows) = &mut self.loudness_windows
+        {
+            windows.pop();
+        }
+
+        if !self.excluding
+            && let Some(windows) = &mut self.loudness_windows
             && let Some(last_window) = windows.last_mut()
         {
             // Process any remaining samples in the buffer
@@ -210,10 +770,7 @@ impl Analyser for LoudnessAnalyser {
                 .loudness
                 .add_frames_i32(&self.frame_buf[..self.frame_buf_iter])
             {
-                println!(
-                    "Warning: error adding frame to loudness measurement: {:?}",
-                    &err
-                );
+                warn!("error adding frame to loudness measurement: {:?}", &err);
             }
 
             let lufs = self
@@ -221,92 +778,278 @@ impl Analyser for LoudnessAnalyser {
                 .loudness_shortterm()
                 .unwrap_or(f64::NEG_INFINITY);
 
-            last_window.end = Some(self.num_frames);
+            last_window.end = Some(analyser.num_frames);
             last_window.loudness = lufs;
+            if let Some(histogram) = &mut self.histogram {
+                histogram.add(lufs);
+            }
         }
 
-        if let Some(silence) = &mut self.silence {
-            if silence.state.previous_lufs < silence.lufs {
-                let end_frame = self.num_frames;
-                let count = silence.count + end_frame - silence.state.silence_start_frame;
-                output!(
-                    "[{}] SILENCE END  : LUFS-S: {:04.3}; LUFS-I: {:04.3} @ {} ({:04.3}% of total)",
-                    label,
-                    silence.state.previous_lufs,
-                    self.loudness.loudness_global().unwrap_or(-f64::INFINITY),
-                    frame_to_time(self.num_frames, self.sample_rate),
-                    (count as f32 / self.num_frames as f32) * 100.0
-                );
-
-                if let Some(segment) = silence.segments.last_mut() {
-                    segment.end = Some(end_frame);
-                }
+        self.end_silence(label, analyser.num_frames, analyser);
+        let prefix = self.prefix();
 
-                if (count as f32 / self.num_frames as f32) * 100.0 >= silence.percentage {
-                    return crate::ERR_CONTAINS_SILENCE;
-                }
-            }
+        let Some(silence) = &mut self.silence else {
+            return;
+        };
+
+        let leading = silence
+            .max_leading
+            .zip(silence.leading(analyser))
+            .filter(|(max, leading)| leading > max);
+        if let Some((max, leading)) = leading {
+            result.fail(
+                crate::ERR_CONTAINS_SILENCE,
+                format!(
+                    "{}{:.3}s of silence at the start, more than {}s",
+                    prefix, leading, max
+                ),
+            );
         }
 
-        0
+        if silence.share(analyser) >= silence.percentage {
+            result.flags |= crate::ERR_CONTAINS_SILENCE;
+        }
     }
+}
 
-    fn json(&self) -> Vec<(String, serde_json::Value)> {
-        let mut results = vec![];
+/// Stream properties the meters need.
+struct Shared {
+    num_frames: usize,
+    sample_rate: i32,
+    /// Frames between the points of the loudness envelope
+    envelope_step: Option<usize>,
+    excluded: Exclusions,
+}
+
+pub struct LoudnessAnalyser {
+    envelope_rate: Option<f64>,
+    meters: Vec<Meter>,
+    shared: Shared,
+    window_overlap: Option<f64>,
+    window_size: usize,
+}
+
+impl LoudnessAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Self> {
+        let envelope_step = match args.loudness_rate {
+            Some(rate) if rate > 0.0 && rate <= spec.sample_rate as f64 => {
+                Some((spec.sample_rate as f64 / rate).round() as usize)
+            }
+            Some(rate) => {
+                return Err(Error::Config(format!(
+                    "--loudness-rate has to be above 0 and at most the sample rate of {} Hz, got {rate}",
+                    spec.sample_rate
+                )));
+            }
+            None => None,
+        };
 
-        if let Some(windows) = &self.loudness_windows
-            && !windows.is_empty()
+        if let Some(width) = args.loudness_histogram
+            && !(width > 0.0 && width.is_finite())
         {
-            let loudness_windows: Vec<serde_json::Value> = windows
-                .iter()
-                .map(|win| {
-                    let end = win.end.unwrap_or(self.num_frames);
+            return Err(Error::Config(format!(
+                "--loudness-histogram has to be a bin width above 0 LU, got {width}"
+            )));
+        }
 
-                    serde_json::json!({
-                        "start": win.start as f32 / self.sample_rate as f32,
-                        "end": end as f32 / self.sample_rate as f32,
-                        "loudness": win.loudness,
-                    })
+        let meters = if args.per_channel {
+            (0..spec.channels as usize)
+                .map(|channel| Meter::new(args, spec, Some(channel), 1, envelope_step))
+                .collect::<Result<Vec<_>, _>>()
+        } else {
+            Meter::new(args, spec, None, spec.channels.into(), envelope_step)
+                .map(|meter| vec![meter])
+        }
+        .map_err(|err| Error::analyser("loudness", err))?;
+
+        let window_size = ((spec.sample_rate as usize * spec.channels as usize) as f32
+            * args.window_size) as usize;
+
+        Ok(Self {
+            envelope_rate: args.loudness_rate,
+            meters,
+            shared: Shared {
+                num_frames: spec.num_frames,
+                sample_rate: spec.sample_rate,
+                envelope_step,
+                excluded: Exclusions::new(&args.exclude, spec),
+            },
+            window_overlap: (args.window_overlap > 0.0).then_some(args.window_overlap),
+            window_size,
+        })
+    }
+}
+
+impl Analyser for LoudnessAnalyser {
+    fn name(&self) -> &'static str {
+        "loudness"
+    }
+
+    fn analyse(&mut self, label: &str, frame_counter: usize, frame: &[i32]) {
+        for meter in self.meters.iter_mut() {
+            match meter.channel {
+                Some(channel) => meter.push(
+                    label,
+                    frame_counter,
+                    &frame[channel..=channel],
+                    &self.shared,
+                ),
+                None => meter.push(label, frame_counter, frame, &self.shared),
+            }
+        }
+    }
+
+    fn finish(&mut self, label: &str) -> Result<AnalysisResult> {
+        let mut result = AnalysisResult::default();
+        for meter in self.meters.iter_mut() {
+            meter.finish(label, &self.shared, &mut result);
+        }
+
+        let sample_rate = self.shared.sample_rate as f32;
+        let num_frames = self.shared.num_frames;
+
+        let loudness_windows: Vec<LoudnessResult> = self
+            .meters
+            .iter()
+            .filter_map(|meter| Some((meter.channel, meter.loudness_windows.as_ref()?)))
+            .flat_map(|(channel, windows)| {
+                windows.iter().map(move |win| {
+                    let end = win.end.unwrap_or(num_frames);
+                    LoudnessResult {
+                        start: win.start as f32 / sample_rate,
+                        end: end as f32 / sample_rate,
+                        loudness: win.loudness,
+                        channel,
+                    }
                 })
-                .collect();
+            })
+            .collect();
 
-            let analysis = serde_json::json!({
-                "results": loudness_windows,
-                "windowSize": self.window_size as f32 / self.sample_rate as f32,
+        let envelope = self
+            .envelope_rate
+            .zip(self.shared.envelope_step)
+            .map(|(rate, step)| LoudnessEnvelope {
+                rate,
+                interval: step as f32 / sample_rate,
+                series: self
+                    .meters
+                    .iter()
+                    .filter_map(|meter| {
+                        Some(EnvelopeSeries {
+                            channel: meter.channel,
+                            loudness: meter.envelope.as_ref()?.points.clone(),
+                        })
+                    })
+                    .collect(),
             });
 
-            results.push(("loudness".to_string(), analysis));
+        let histograms = self
+            .meters
+            .iter()
+            .map(|meter| Some(meter.histogram.as_ref()?.result(meter.channel)))
+            .collect();
+
+        if !loudness_windows.is_empty() {
+            result.analysis.loudness = Some(LoudnessAnalysis {
+                results: loudness_windows,
+                window_size: self.window_size as f32 / sample_rate,
+                window_overlap: self.window_overlap,
+                envelope,
+                histograms,
+            });
         }
 
-        if let Some(silence) = &self.silence
-            && !silence.segments.is_empty()
-        {
-            let segments: Vec<SilenceSegment> = silence
-                .segments
-                .iter()
-                .map(|seg| {
-                    let end_frame = seg.end.unwrap_or(self.num_frames);
+        let segments: Vec<SilenceSegment> = self
+            .meters
+            .iter()
+            .filter_map(|meter| Some((meter.channel, meter.silence.as_ref()?)))
+            .flat_map(|(channel, silence)| {
+                silence.segments.iter().map(move |seg| {
+                    let end_frame = seg.end.unwrap_or(num_frames);
                     let duration_samples = end_frame - seg.start;
                     SilenceSegment {
-                        start: seg.start as f32 / self.sample_rate as f32,
-                        end: end_frame as f32 / self.sample_rate as f32,
-                        duration: duration_samples as f32 / self.sample_rate as f32,
+                        start: seg.start as f32 / sample_rate,
+                        end: end_frame as f32 / sample_rate,
+                        duration: duration_samples as f32 / sample_rate,
                         start_sample: seg.start,
                         end_sample: end_frame,
                         duration_samples,
+                        channel,
+                        threshold: silence.relative.map(|_| seg.threshold),
                     }
                 })
-                .collect();
+            })
+            .collect();
 
-            let analysis = serde_json::json!({
-                "results": segments,
-                "threshold": silence.lufs,
-                "windowSize": self.window_size as f32 / self.sample_rate as f32,
+        if let Some(silence) = self.meters.iter().find_map(|meter| meter.silence.as_ref())
+            && !segments.is_empty()
+        {
+            result.analysis.silence = Some(SilenceAnalysis {
+                results: segments,
+                threshold: silence.lufs,
+                resume_threshold: silence.resume_lufs,
+                relative: silence.relative,
+                hold: silence.hold as f64 / sample_rate as f64,
+                window_size: self.window_size as f32 / sample_rate,
+                window_overlap: self.window_overlap,
+                max_leading_silence: silence.max_leading,
             });
+        }
 
-            results.push(("silence".to_string(), analysis));
+        Ok(result)
+    }
+
+    fn stop(&mut self, num_frames: usize) {
+        self.shared.num_frames = num_frames;
+    }
+
+    fn take_events(&mut self) -> Vec<Finding> {
+        let mut events = Vec::new();
+
+        for meter in self.meters.iter_mut() {
+            let Some(silence) = meter.silence.as_mut() else {
+                continue;
+            };
+
+            // The last segment stays open until the silence ends
+            let ended: Vec<Finding> = silence.segments[silence.taken..]
+                .iter()
+                .map_while(|segment| {
+                    let end = segment.end?;
+                    Some(Finding::new(
+                        Kind::Silence,
+                        meter.channel,
+                        segment.start,
+                        end,
+                    ))
+                })
+                .collect();
+            silence.taken += ended.len();
+            events.extend(ended);
+        }
+        events.sort_by_key(|event| event.start);
+
+        events
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        // Both are left out when there is nothing to report
+        let Some(meter) = self.meters.first() else {
+            return Vec::new();
+        };
+        let mut entries = Vec::new();
+
+        if meter.loudness_windows.is_some() {
+            entries.push(SchemaEntry::optional::<LoudnessAnalysis>(
+                "loudness", generator,
+            ));
+        }
+        if meter.silence.is_some() {
+            entries.push(SchemaEntry::optional::<SilenceAnalysis>(
+                "silence", generator,
+            ));
         }
 
-        results
+        entries
     }
 }
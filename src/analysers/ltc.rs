@@ -0,0 +1,338 @@
+use std::fmt;
+
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Result, cli::AnalyseArgs, json::Analysis, output, output::frame_to_time, progress,
+    schema::SchemaEntry, stream::StreamSpec,
+};
+
+/// Bits 64..79 of an LTC frame, read LSB first.
+const SYNC_WORD: u128 = 0xBFFC;
+
+const FRAME_BITS: usize = 80;
+
+/// Bit rates covered by the decoder: 23.976 to 30 fps with about 10% varispeed.
+const MIN_BIT_RATE: f64 = 1720.0;
+const MAX_BIT_RATE: f64 = 2640.0;
+
+/// Level changes smaller than this (relative to full scale) don't count as transitions.
+const HYSTERESIS: f64 = 0.01;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub drop_frame: bool,
+}
+
+impl Timecode {
+    /// Decodes the BCD time fields of an 80-bit LTC frame, rejecting impossible values.
+    fn decode(bits: u128) -> Option<Self> {
+        let field = |start: usize, len: usize| ((bits >> start) & ((1 << len) - 1)) as u8;
+        let units = [field(0, 4), field(16, 4), field(32, 4), field(48, 4)];
+        if units.iter().any(|&unit| unit > 9) {
+            return None;
+        }
+
+        let timecode = Self {
+            frames: units[0] + 10 * field(8, 2),
+            drop_frame: field(10, 1) == 1,
+            seconds: units[1] + 10 * field(24, 3),
+            minutes: units[2] + 10 * field(40, 3),
+            hours: units[3] + 10 * field(56, 2),
+        };
+
+        if timecode.frames >= 30
+            || timecode.seconds >= 60
+            || timecode.minutes >= 60
+            || timecode.hours >= 24
+        {
+            return None;
+        }
+
+        Some(timecode)
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours,
+            self.minutes,
+            self.seconds,
+            if self.drop_frame { ';' } else { ':' },
+            self.frames
+        )
+    }
+}
+
+impl Serialize for Timecode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Consecutive decoded frames.
+#[derive(Clone)]
+struct Burst {
+    start: usize,
+    end: usize,
+    first: Timecode,
+    last: Timecode,
+    frames: usize,
+}
+
+#[derive(Clone)]
+struct ChannelState {
+    high: bool,
+    last_transition: Option<usize>,
+    /// Current bit period estimate in samples
+    period: f64,
+    pending_short: bool,
+    /// The last 80 bits, oldest in bit 0
+    bits: u128,
+    bit_count: usize,
+    burst: Option<Burst>,
+}
+
+impl ChannelState {
+    fn new(period: f64) -> Self {
+        Self {
+            high: false,
+            last_transition: None,
+            period,
+            pending_short: false,
+            bits: 0,
+            bit_count: 0,
+            burst: None,
+        }
+    }
+
+    fn push_bit(&mut self, bit: bool) {
+        self.bits = (self.bits >> 1) | ((bit as u128) << (FRAME_BITS - 1));
+        self.bit_count += 1;
+    }
+
+    fn reset(&mut self) {
+        self.pending_short = false;
+        self.bit_count = 0;
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LtcBurst {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+    pub channel: usize,
+    /// HH:MM:SS:FF, with `;` before the frames for drop-frame timecode
+    #[serde(rename = "startTimecode")]
+    #[schemars(with = "String")]
+    pub start_timecode: Timecode,
+    #[serde(rename = "endTimecode")]
+    #[schemars(with = "String")]
+    pub end_timecode: Timecode,
+    pub frames: usize,
+    #[serde(rename = "frameRate")]
+    pub frame_rate: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LtcAnalysis {
+    pub results: Vec<LtcBurst>,
+}
+
+/// Decodes biphase-mark SMPTE/EBU linear timecode per channel and reports the bursts found.
+pub struct LtcAnalyser {
+    bursts: Vec<(usize, Burst)>,
+    max_period: f64,
+    min_period: f64,
+    sample_rate: i32,
+    states: Vec<ChannelState>,
+}
+
+impl LtcAnalyser {
+    pub fn new(_args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let min_period = sample_rate / MAX_BIT_RATE;
+        let max_period = sample_rate / MIN_BIT_RATE;
+
+        Self {
+            bursts: Vec::new(),
+            max_period,
+            min_period,
+            sample_rate: spec.sample_rate,
+            states: vec![
+                ChannelState::new((min_period + max_period) / 2.0);
+                spec.channels as usize
+            ],
+        }
+    }
+
+    fn record(&mut self, label: &str, channel: usize, burst: Burst) {
+        progress::event("ltc");
+        output!(
+            "[{}] LTC          : CH:{} - {} -> {} ({} frames) {} -> {}",
+            label,
+            channel,
+            burst.first,
+            burst.last,
+            burst.frames,
+            frame_to_time(burst.start, self.sample_rate),
+            frame_to_time(burst.end, self.sample_rate)
+        );
+
+        self.bursts.push((channel, burst));
+    }
+}
+
+impl Analyser for LtcAnalyser {
+    fn name(&self) -> &'static str {
+        "ltc"
+    }
+
+    fn analyse(&mut self, label: &str, frame_counter: usize, frame: &[i32]) {
+        let mut finished = Vec::new();
+
+        for (channel, (state, sample)) in self.states.iter_mut().zip(frame.iter()).enumerate() {
+            let value = *sample as f64 / FULL_SCALE;
+            let transition = if state.high {
+                value < -HYSTERESIS
+            } else {
+                value > HYSTERESIS
+            };
+
+            if !transition {
+                continue;
+            }
+
+            state.high = !state.high;
+            let Some(last) = state.last_transition.replace(frame_counter) else {
+                continue;
+            };
+
+            let interval = (frame_counter - last) as f64;
+            let ratio = interval / state.period;
+
+            let pushed = if (0.75..1.5).contains(&ratio) {
+                // A full bit period without a mid-bit transition is a zero
+                if state.pending_short {
+                    state.reset();
+                }
+                state.push_bit(false);
+                state.period =
+                    (state.period * 0.9 + interval * 0.1).clamp(self.min_period, self.max_period);
+                true
+            } else if (0.3..0.75).contains(&ratio) {
+                // Two half periods make a one
+                state.pending_short = !state.pending_short;
+                if !state.pending_short {
+                    state.push_bit(true);
+                }
+                state.period = (state.period * 0.9 + 2.0 * interval * 0.1)
+                    .clamp(self.min_period, self.max_period);
+                !state.pending_short
+            } else {
+                state.reset();
+                false
+            };
+
+            if !pushed || state.bit_count < FRAME_BITS || (state.bits >> 64) & 0xFFFF != SYNC_WORD {
+                continue;
+            }
+
+            let Some(timecode) = Timecode::decode(state.bits) else {
+                continue;
+            };
+
+            let frame_length = (FRAME_BITS as f64 * state.period) as usize;
+            let start = frame_counter.saturating_sub(frame_length);
+
+            match state.burst.as_mut() {
+                Some(burst) if start <= burst.end + frame_length => {
+                    burst.end = frame_counter;
+                    burst.last = timecode;
+                    burst.frames += 1;
+                }
+                _ => {
+                    if let Some(burst) = state.burst.take() {
+                        finished.push((channel, burst));
+                    }
+
+                    state.burst = Some(Burst {
+                        start,
+                        end: frame_counter,
+                        first: timecode,
+                        last: timecode,
+                        frames: 1,
+                    });
+                }
+            }
+        }
+
+        for (channel, burst) in finished {
+            self.record(label, channel, burst);
+        }
+    }
+
+    fn finish(&mut self, label: &str) -> Result<AnalysisResult> {
+        for channel in 0..self.states.len() {
+            if let Some(burst) = self.states[channel].burst.take() {
+                self.record(label, channel, burst);
+            }
+        }
+
+        let bursts: Vec<LtcBurst> = self
+            .bursts
+            .iter()
+            .map(|(channel, burst)| {
+                let duration_samples = burst.end - burst.start;
+                let duration = duration_samples as f64 / self.sample_rate as f64;
+                LtcBurst {
+                    start: burst.start as f32 / self.sample_rate as f32,
+                    end: burst.end as f32 / self.sample_rate as f32,
+                    duration: duration as f32,
+                    start_sample: burst.start,
+                    end_sample: burst.end,
+                    duration_samples,
+                    channel: *channel,
+                    start_timecode: burst.first,
+                    end_timecode: burst.last,
+                    frames: burst.frames,
+                    frame_rate: if duration > 0.0 {
+                        burst.frames as f64 / duration
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+
+        Ok(AnalysisResult::new(Analysis {
+            ltc: Some(LtcAnalysis { results: bursts }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<LtcAnalysis>("ltc", generator)]
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use ebur128::{EbuR128, Mode};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Error, Result, cli::AnalyseArgs, json::Analysis, layout::loudness_meter, schema::SchemaEntry,
+    stream::StreamSpec, warn,
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeterFormat {
+    /// `time,momentary` lines with a header
+    Csv,
+    /// Raw little-endian f32 momentary values
+    F32,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MeterAnalysis {
+    pub output: String,
+    #[schemars(extend("enum" = ["csv", "f32le"]))]
+    pub format: &'static str,
+    /// Meter ticks per second
+    pub rate: f64,
+    pub count: usize,
+}
+
+/// Samples momentary loudness (400 ms window) at a fixed rate, suitable for driving an
+/// external loudness meter display.
+pub struct MeterAnalyser {
+    buffer: Vec<i32>,
+    chunk_frames: usize,
+    count: usize,
+    /// First write error, reported from `finish`
+    error: Option<io::Error>,
+    format: MeterFormat,
+    frames: usize,
+    meter: EbuR128,
+    path: PathBuf,
+    rate: f64,
+    sample_rate: i32,
+    /// `None` in a dry run, which only checks the options
+    writer: Option<BufWriter<File>>,
+}
+
+impl MeterAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec, path: PathBuf) -> Result<Self> {
+        if !(args.meter_rate > 0.0 && args.meter_rate.is_finite()) {
+            return Err(Error::Config(format!(
+                "--meter-rate has to be a positive number of Hz, got {}",
+                args.meter_rate
+            )));
+        }
+
+        let meter = loudness_meter(spec, Mode::M).map_err(|err| Error::analyser("meter", err))?;
+        let chunk_frames = ((spec.sample_rate as f64 / args.meter_rate).round() as usize).max(1);
+
+        // Values are written as they are measured, so the output doesn't grow in memory
+        let write_error = |err| Error::io("write meter output to", &path, err);
+        let writer = match args.dry_run {
+            true => None,
+            false => {
+                let mut writer = BufWriter::new(File::create(&path).map_err(write_error)?);
+                if args.meter_format == MeterFormat::Csv {
+                    writeln!(writer, "time,momentary").map_err(write_error)?;
+                }
+                Some(writer)
+            }
+        };
+
+        Ok(Self {
+            buffer: Vec::with_capacity(chunk_frames * spec.channels as usize),
+            chunk_frames,
+            count: 0,
+            error: None,
+            format: args.meter_format,
+            frames: 0,
+            meter,
+            path,
+            rate: spec.sample_rate as f64 / chunk_frames as f64,
+            sample_rate: spec.sample_rate,
+            writer,
+        })
+    }
+
+    fn measure(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+
+        if let Err(err) = self.meter.add_frames_i32(&self.buffer) {
+            warn!("error adding frame to loudness measurement: {:?}", &err);
+        }
+        self.buffer.clear();
+
+        let lufs = self.meter.loudness_momentary().unwrap_or(f64::NEG_INFINITY);
+        let time = self.frames as f64 / self.sample_rate as f64;
+        self.count += 1;
+
+        if self.error.is_none()
+            && let Err(err) = self.write(time, lufs)
+        {
+            self.error = Some(err);
+        }
+    }
+
+    fn write(&mut self, time: f64, lufs: f64) -> io::Result<()> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+
+        match self.format {
+            MeterFormat::Csv => writeln!(writer, "{:.3},{:.2}", time, lufs),
+            MeterFormat::F32 => writer.write_all(&(lufs as f32).to_le_bytes()),
+        }
+    }
+}
+
+impl Analyser for MeterAnalyser {
+    fn name(&self) -> &'static str {
+        "meter"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        self.buffer.extend_from_slice(frame);
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(self.chunk_frames) {
+            self.measure();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.measure();
+
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.writer.as_mut().map_or(Ok(()), |writer| writer.flush()),
+        }
+        .map_err(|err| Error::io("write meter output to", &self.path, err))?;
+
+        let Ok(path) = self.path.canonicalize() else {
+            return Ok(AnalysisResult::default());
+        };
+
+        let format = match self.format {
+            MeterFormat::Csv => "csv",
+            MeterFormat::F32 => "f32le",
+        };
+
+        Ok(AnalysisResult::new(Analysis {
+            meter: Some(MeterAnalysis {
+                output: path.to_string_lossy().to_string(),
+                format,
+                rate: self.rate,
+                count: self.count,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<MeterAnalysis>("meter", generator)]
+    }
+}
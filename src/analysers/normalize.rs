@@ -0,0 +1,137 @@
+use ebur128::{EbuR128, Error as EbuR128Error, Mode};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Error, Result, cli::AnalyseArgs, exclude::Exclusions, json::Analysis, layout::loudness_meter,
+    schema::SchemaEntry, stream::StreamSpec, warn,
+};
+
+/// Frames buffered before they are added to the loudness meter.
+const METER_CHUNK_FRAMES: usize = 4096;
+
+#[derive(Serialize, JsonSchema)]
+pub struct NormalizationAnalysis {
+    /// Loudness in LUFS the file would be normalized to
+    pub target: f64,
+    /// Integrated loudness in LUFS
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub integrated: f64,
+    /// Gain in dB that brings the integrated loudness to the target, absent for silence
+    pub gain: Option<f64>,
+    /// True peak before the gain in dBTP
+    #[serde(rename = "truePeak")]
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub true_peak: f64,
+    /// True peak after the gain in dBTP, absent for silence
+    #[serde(rename = "truePeakAfter")]
+    pub true_peak_after: Option<f64>,
+    /// Highest true peak in dBTP allowed after the gain
+    #[serde(rename = "peakCeiling")]
+    pub peak_ceiling: f64,
+    /// Ceiling minus the true peak after the gain in dB, negative if it would need limiting
+    pub headroom: Option<f64>,
+    /// The gain can be applied without the true peak going over the ceiling
+    pub possible: bool,
+}
+
+/// Works out whether normalizing to a loudness target would push the true peak over a
+/// ceiling, i.e. whether it needs a limiter.
+pub struct NormalizeAnalyser {
+    channels: u32,
+    interleaved: Vec<i32>,
+    meter: EbuR128,
+    peak_ceiling: f64,
+    target: f64,
+    /// Frames of `--exclude`, left out of the measurement
+    excluded: Exclusions,
+}
+
+impl NormalizeAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec, target: f64) -> Result<Self> {
+        let meter = loudness_meter(spec, Mode::I | Mode::TRUE_PEAK)
+            .map_err(|err: EbuR128Error| Error::analyser("normalization", err))?;
+
+        Ok(Self {
+            channels: spec.channels as u32,
+            interleaved: Vec::with_capacity(METER_CHUNK_FRAMES * spec.channels as usize),
+            meter,
+            peak_ceiling: args.peak_ceiling,
+            target,
+            excluded: Exclusions::new(&args.exclude, spec),
+        })
+    }
+
+    fn flush_meter(&mut self) {
+        if let Err(err) = self.meter.add_frames_i32(&self.interleaved) {
+            warn!("error adding frame to loudness measurement: {:?}", &err);
+        }
+        self.interleaved.clear();
+    }
+
+    fn true_peak(&self) -> f64 {
+        let peak = (0..self.channels)
+            .filter_map(|channel| self.meter.true_peak(channel).ok())
+            .fold(0.0, f64::max);
+
+        20.0 * peak.log10()
+    }
+}
+
+impl Analyser for NormalizeAnalyser {
+    fn name(&self) -> &'static str {
+        "normalization"
+    }
+
+    fn analyse(&mut self, _label: &str, frame_counter: usize, frame: &[i32]) {
+        if self.excluded.contains(frame_counter) {
+            return;
+        }
+        self.interleaved.extend_from_slice(frame);
+
+        if self.interleaved.len() >= self.interleaved.capacity() {
+            self.flush_meter();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.flush_meter();
+
+        let integrated = self.meter.loudness_global().unwrap_or(f64::NEG_INFINITY);
+        let true_peak = self.true_peak();
+        // Silence can't be normalized, and a gain that does nothing to it can't clip
+        let gain = integrated.is_finite().then_some(self.target - integrated);
+        let true_peak_after = gain
+            .filter(|_| true_peak.is_finite())
+            .map(|gain| true_peak + gain);
+        let headroom = true_peak_after.map(|peak| self.peak_ceiling - peak);
+        let possible = headroom.is_none_or(|headroom| headroom >= 0.0);
+
+        let mut result = AnalysisResult::new(Analysis {
+            normalization: Some(NormalizationAnalysis {
+                target: self.target,
+                integrated,
+                gain,
+                true_peak,
+                true_peak_after,
+                peak_ceiling: self.peak_ceiling,
+                headroom,
+                possible,
+            }),
+            ..Analysis::default()
+        });
+        if !possible {
+            result.flags = crate::ERR_LOUDNESS_TARGET;
+        }
+
+        Ok(result)
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<NormalizationAnalysis>(
+            "normalization",
+            generator,
+        )]
+    }
+}
@@ -0,0 +1,322 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{
+    Analyser, AnalysisResult,
+    fft::{FftAmplitude, FftWindow},
+    portable,
+};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Length of the points of the flux and novelty curves in seconds.
+const BLOCK_SECONDS: f64 = 0.5;
+
+/// Length of the spectrum slices in seconds, rounded up to a power of two of frames.
+const SLICE_SECONDS: f64 = 0.04;
+
+/// The spectrum is compared in logarithmically spaced bands between these frequencies.
+const BANDS: usize = 24;
+const MIN_HZ: f64 = 50.0;
+const MAX_HZ: f64 = 16000.0;
+
+/// Level of empty bands in dBFS, so silence has a spectrum to compare with.
+const FLOOR_DB: f64 = -90.0;
+
+/// Changes smaller than this aren't marked however much they stand out, in dB.
+const MIN_NOVELTY_DB: f64 = 3.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct NoveltyPeak {
+    pub time: f64,
+    pub sample: usize,
+    /// Mean difference of the band levels before and after, in dB
+    pub novelty: f64,
+    /// Standard deviations above the mean novelty
+    pub score: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct NoveltyAnalysis {
+    /// Where the content changes, in the order of the file
+    pub results: Vec<NoveltyPeak>,
+    /// Points per second of `flux` and `novelty`
+    pub rate: f64,
+    /// Mean spectral flux between consecutive slices in each interval, in dB
+    pub flux: Vec<f64>,
+    /// Spectral flux between the average spectra of the windows before and after the start of
+    /// each interval, in dB. `null` within a window of either end
+    pub novelty: Vec<Option<f64>>,
+    /// Seconds of audio compared on either side
+    pub window: f64,
+    /// Minimum seconds between two changes
+    #[serde(rename = "minGap")]
+    pub min_gap: f64,
+    /// Standard deviations above the mean from which a change is marked
+    pub threshold: f64,
+}
+
+/// Finds where the content of a recording changes, e.g. from music to speech or from one
+/// room to another, as hints for chapters. The band levels of the channel mix are averaged
+/// over intervals, and the novelty at each interval is the spectral flux between the
+/// averages of `--novelty-window` seconds before and after it. Peaks that stand out from
+/// the rest of the file and are `--novelty-min-gap` apart mark the changes.
+pub struct NoveltyAnalyser {
+    /// Band levels of every interval
+    blocks: Vec<[f64; BANDS]>,
+    block_frames: usize,
+    /// Interval, band level sums, flux sum and slices of the interval being filled
+    current: Option<(usize, [f64; BANDS], f64, usize)>,
+    edges: Vec<usize>,
+    flux: Vec<f64>,
+    frames: usize,
+    hop: usize,
+    min_gap: f64,
+    mix: Vec<f64>,
+    previous: Option<[f64; BANDS]>,
+    /// Power of a full-scale sine in its bin
+    reference: f64,
+    sample_rate: f64,
+    slice_size: usize,
+    threshold: f64,
+    window: f64,
+    window_function: Vec<f64>,
+}
+
+impl NoveltyAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let slice_size = ((sample_rate * SLICE_SECONDS) as usize).next_power_of_two();
+        let bin_hz = sample_rate / slice_size as f64;
+
+        // Band edges in bins, every band at least one bin wide
+        let top = MAX_HZ.min(sample_rate / 2.0);
+        let mut edges: Vec<usize> = Vec::with_capacity(BANDS + 1);
+        for band in 0..=BANDS {
+            let frequency = MIN_HZ * (top / MIN_HZ).powf(band as f64 / BANDS as f64);
+            let bin = (frequency / bin_hz).round() as usize;
+            edges.push(edges.last().map_or(bin, |last| bin.max(last + 1)));
+        }
+
+        let window_function = portable::window(FftWindow::Hann, slice_size);
+        let reference = window_function.iter().sum::<f64>().powi(2) / 4.0;
+
+        Self {
+            blocks: Vec::new(),
+            block_frames: ((sample_rate * BLOCK_SECONDS).round() as usize).max(1),
+            current: None,
+            edges,
+            flux: Vec::new(),
+            frames: 0,
+            hop: slice_size / 2,
+            min_gap: args.novelty_min_gap,
+            mix: Vec::with_capacity(slice_size),
+            previous: None,
+            reference,
+            sample_rate,
+            slice_size,
+            threshold: args.novelty_threshold,
+            window: args.novelty_window,
+            window_function,
+        }
+    }
+
+    fn analyse_slice(&mut self) {
+        let power = portable::slice(
+            &self.mix,
+            &self.window_function,
+            self.slice_size,
+            FftAmplitude::Power,
+        );
+
+        let mut bands = [FLOOR_DB; BANDS];
+        for (band, level) in bands.iter_mut().enumerate() {
+            let bins = power
+                .get(self.edges[band]..self.edges[band + 1].min(power.len()))
+                .unwrap_or_default();
+            let energy: f64 = bins.iter().sum();
+            if energy > 0.0 {
+                *level = (10.0 * portable::log10(energy / self.reference)).max(FLOOR_DB);
+            }
+        }
+
+        // Half-wave rectified: only bands that get louder count
+        let flux = self.previous.map_or(0.0, |previous| {
+            bands
+                .iter()
+                .zip(previous)
+                .map(|(level, previous)| (level - previous).max(0.0))
+                .sum::<f64>()
+                / BANDS as f64
+        });
+        self.previous = Some(bands);
+
+        // Slices count towards the interval their center falls into
+        let index = (self.frames - self.slice_size / 2) / self.block_frames;
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|(current, ..)| *current != index)
+        {
+            self.flush_block();
+        }
+
+        let (_, sums, flux_sum, slices) = self.current.get_or_insert((index, [0.0; BANDS], 0.0, 0));
+        for (sum, level) in sums.iter_mut().zip(bands) {
+            *sum += level;
+        }
+        *flux_sum += flux;
+        *slices += 1;
+    }
+
+    fn flush_block(&mut self) {
+        let Some((_, sums, flux, slices)) = self.current.take() else {
+            return;
+        };
+
+        self.blocks.push(sums.map(|sum| sum / slices as f64));
+        self.flux.push(flux / slices as f64);
+    }
+
+    /// The novelty at the start of every interval, with full windows on both sides.
+    fn novelty(&self) -> Vec<Option<f64>> {
+        let count = self.blocks.len();
+        let window = ((self.window / BLOCK_SECONDS).round() as usize).max(1);
+
+        // Sums of the band levels of the intervals before each one
+        let mut sums = vec![[0.0; BANDS]; count + 1];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for band in 0..BANDS {
+                sums[index + 1][band] = sums[index][band] + block[band];
+            }
+        }
+
+        (0..count)
+            .map(|index| {
+                if index < window || index + window > count {
+                    return None;
+                }
+
+                let difference: f64 = (0..BANDS)
+                    .map(|band| {
+                        let before = sums[index][band] - sums[index - window][band];
+                        let after = sums[index + window][band] - sums[index][band];
+                        (after - before).abs() / window as f64
+                    })
+                    .sum();
+
+                Some(difference / BANDS as f64)
+            })
+            .collect()
+    }
+
+    /// The strongest peaks of `novelty` above the threshold, at least `--novelty-min-gap`
+    /// apart.
+    fn peaks(&self, novelty: &[Option<f64>]) -> Vec<NoveltyPeak> {
+        let values: Vec<f64> = novelty.iter().flatten().copied().collect();
+        if values.is_empty() {
+            return Vec::new();
+        }
+
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let deviation = (values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / values.len() as f64)
+            .sqrt();
+        let threshold = (mean + self.threshold * deviation).max(MIN_NOVELTY_DB);
+
+        let value = |index: usize| {
+            novelty
+                .get(index)
+                .copied()
+                .flatten()
+                .unwrap_or(f64::NEG_INFINITY)
+        };
+        let mut candidates: Vec<(usize, f64)> = (0..novelty.len())
+            .map(|index| (index, value(index)))
+            .filter(|&(index, novelty)| {
+                novelty >= threshold
+                    && novelty >= value(index + 1)
+                    && index
+                        .checked_sub(1)
+                        .is_none_or(|before| novelty > value(before))
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let gap = (self.min_gap / BLOCK_SECONDS).round() as usize;
+        let mut picked: Vec<(usize, f64)> = Vec::new();
+        for (index, novelty) in candidates {
+            if picked
+                .iter()
+                .all(|(other, _)| index.abs_diff(*other) >= gap)
+            {
+                picked.push((index, novelty));
+            }
+        }
+        picked.sort_by_key(|(index, _)| *index);
+
+        picked
+            .into_iter()
+            .map(|(index, novelty)| {
+                let sample = index * self.block_frames;
+                NoveltyPeak {
+                    time: sample as f64 / self.sample_rate,
+                    sample,
+                    novelty,
+                    score: if deviation > 0.0 {
+                        (novelty - mean) / deviation
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+impl Analyser for NoveltyAnalyser {
+    fn name(&self) -> &'static str {
+        "novelty"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        self.mix.push(sum / frame.len().max(1) as f64);
+        self.frames += 1;
+
+        if self.mix.len() == self.slice_size {
+            self.analyse_slice();
+            self.mix.drain(..self.hop);
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.flush_block();
+
+        let novelty = self.novelty();
+        let results = self.peaks(&novelty);
+
+        Ok(AnalysisResult::new(Analysis {
+            novelty: Some(NoveltyAnalysis {
+                results,
+                rate: 1.0 / BLOCK_SECONDS,
+                flux: self.flux.clone(),
+                novelty,
+                window: self.window,
+                min_gap: self.min_gap,
+                threshold: self.threshold,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<NoveltyAnalysis>(
+            "novelty", generator,
+        )]
+    }
+}
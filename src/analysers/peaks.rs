@@ -1,51 +1,155 @@
-use std::{fs::File, io::BufWriter, path::PathBuf};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
 
 use aus::analysis::dbfs;
+use clap::ValueEnum;
 use png::{BitDepth, ColorType, Encoder};
-use wavers::{Samples, Wav};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
 
-use crate::{analysers::Analyser, cli::Cli};
+use crate::{
+    Error, Result,
+    analysers::{Analyser, AnalysisResult, portable},
+    cli::AnalyseArgs,
+    npy,
+    schema::SchemaEntry,
+    stream::StreamSpec,
+};
+
+/// File format of the peaks output. All formats except `png` store the channels one after
+/// another without padding.
+#[derive(ValueEnum, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PeaksFormat {
+    /// Little-endian f64s in a 16 bit RGBA PNG, each channel padded to a square (legacy)
+    Png,
+    /// NumPy array of f64s with the shape (channels, channelSize)
+    Npy,
+    /// Raw little-endian f32s
+    F32,
+    /// Raw little-endian f64s
+    F64,
+    /// JSON array of one array per channel, silence is null
+    Json,
+}
+
+impl PeaksFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Npy => "npy",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PeaksAnalysis {
+    pub output: String,
+    pub format: PeaksFormat,
+    /// Samples per peak value, each value is the largest peak of its bucket
+    #[serde(rename = "bucketSize")]
+    pub bucket_size: usize,
+    /// Peaks per channel
+    #[serde(rename = "channelSize")]
+    pub channel_size: usize,
+    /// Values per channel including the padding
+    #[serde(rename = "squareSize")]
+    pub square_size: u32,
+    pub padding: u32,
+}
 
 pub struct PeaksAnalyzer {
+    bucket: Vec<f64>,
+    bucket_frames: usize,
+    bucket_size: usize,
     channels: usize,
+    deterministic: bool,
+    format: PeaksFormat,
     path: PathBuf,
     peaks: Vec<Vec<f64>>,
 }
 
-/** Writes peaks to a .png file as little-endian raw f64s.
-Each channel is written as a square with dimensions ⌈√(sample count)⌉² and padded with f64::NEG_INFINITY. */
+/** Writes peaks to a file in the format given with `--peaks-format`.
+As PNG, each channel is written as a square with dimensions ⌈√(sample count)⌉² and padded with f64::NEG_INFINITY. */
 impl PeaksAnalyzer {
-    pub fn new(_args: &Cli, wav: &Wav<i32>, path: PathBuf) -> Self {
-        let channels = wav.n_channels() as usize;
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec, path: PathBuf) -> Self {
+        let channels = spec.channels as usize;
+        let bucket_size = args.peaks_per_second.map_or(1, |rate| {
+            ((spec.sample_rate as f64 / rate as f64).round() as usize).max(1)
+        });
 
         Self {
+            bucket: vec![f64::NEG_INFINITY; channels],
+            bucket_frames: 0,
+            bucket_size,
             channels,
+            deterministic: args.deterministic,
+            format: args.peaks_format,
             path,
             peaks: vec![vec![]; channels],
         }
     }
-}
 
-impl Analyser for PeaksAnalyzer {
-    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &Samples<i32>) {
-        for (channel, sample) in frame.iter().enumerate() {
-            self.peaks[channel].push(dbfs(*sample as f64, 1e-20));
+    fn finish_bucket(&mut self) {
+        for (peaks, peak) in self.peaks.iter_mut().zip(self.bucket.iter_mut()) {
+            peaks.push(*peak);
+            *peak = f64::NEG_INFINITY;
         }
+
+        self.bucket_frames = 0;
+    }
+
+    /// Writes the peaks in any format but PNG.
+    fn write_values(&self, w: &mut impl Write) -> std::io::Result<()> {
+        let values = self.peaks.iter().flatten();
+
+        match self.format {
+            PeaksFormat::F32 => {
+                for value in values {
+                    w.write_all(&(*value as f32).to_le_bytes())?;
+                }
+            }
+            PeaksFormat::Json => serde_json::to_writer(&mut *w, &self.peaks)?,
+            format => {
+                if format == PeaksFormat::Npy {
+                    w.write_all(&npy::header("<f8", &[self.channels, self.peaks[0].len()]))?;
+                }
+
+                for value in values {
+                    w.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+
+        w.flush()
     }
 
-    fn finish(&mut self, _label: &str) -> u8 {
+    /// Writes the peaks to the output file.
+    fn write(&mut self) -> Result<()> {
         if self.peaks.is_empty() {
-            return 0;
+            return Ok(());
         }
 
-        let Ok(file) = File::create(&self.path) else {
-            println!(
-                "Peaks: Could not create output file at {}",
-                self.path.display()
-            );
+        if self.bucket_frames > 0 {
+            self.finish_bucket();
+        }
+
+        let write_error = |err| Error::io("write peaks output to", &self.path, err);
+        let file = File::create(&self.path).map_err(write_error)?;
+
+        let mut w = BufWriter::new(file);
+
+        if self.format != PeaksFormat::Png {
+            self.write_values(&mut w).map_err(write_error)?;
 
-            return 0;
-        };
+            return Ok(());
+        }
 
         let mut results = vec![];
 
@@ -69,47 +173,87 @@ impl Analyser for PeaksAnalyzer {
         let width = sqrt.ceil() as u32;
         let height = sqrt.ceil() as u32 * self.channels as u32;
 
-        let mut w = BufWriter::new(file);
         let mut encoder = Encoder::new(&mut w, width, height);
-        encoder.set_color(ColorType::Rgba);
-        encoder.set_depth(BitDepth::Sixteen);
+        if self.deterministic {
+            let layout = format!(
+                "f64le;channels={};channelSize={};squareSize={}",
+                self.channels,
+                self.peaks[0].len(),
+                width * width
+            );
+            portable::configure_encoder(&mut encoder, &layout);
+        } else {
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_depth(BitDepth::Sixteen);
+        }
+
+        let mut writer = encoder
+            .write_header()
+            .map_err(|err| write_error(err.into()))?;
+        writer
+            .write_image_data(&results)
+            .map_err(|err| write_error(err.into()))?;
 
-        let Ok(mut writer) = encoder.write_header() else {
-            println!("Peaks: Could not write PNG header");
+        Ok(())
+    }
+}
 
-            return 0;
-        };
+impl Analyser for PeaksAnalyzer {
+    fn name(&self) -> &'static str {
+        "peaks"
+    }
 
-        let Ok(_) = writer.write_image_data(&results) else {
-            println!("Peaks: Could not write image data");
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        for (channel, sample) in frame.iter().enumerate() {
+            let value = *sample as f64;
+            let peak = if !self.deterministic {
+                dbfs(value, 1e-20)
+            } else if value.abs() < 1e-20 {
+                f64::NEG_INFINITY
+            } else {
+                portable::canonical(20.0 * portable::log10(value))
+            };
 
-            return 0;
-        };
+            self.bucket[channel] = self.bucket[channel].max(peak);
+        }
 
-        0
+        self.bucket_frames += 1;
+        if self.bucket_frames == self.bucket_size {
+            self.finish_bucket();
+        }
     }
 
-    fn json(&self) -> Vec<(String, serde_json::Value)> {
-        let mut results = vec![];
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.write()?;
 
+        let mut result = AnalysisResult::default();
         if let Ok(path) = self.path.canonicalize()
-            && self.peaks.len() > 0
+            && !self.peaks.is_empty()
         {
             let path = path.to_string_lossy().to_string();
             let channel_size = self.peaks[0].len();
-            let w = (channel_size as f64).sqrt().ceil() as u32;
-            let squared_size = w * w;
+            let squared_size = if self.format == PeaksFormat::Png {
+                let w = (channel_size as f64).sqrt().ceil() as u32;
+                w * w
+            } else {
+                channel_size as u32
+            };
             let padding = squared_size - channel_size as u32;
 
-            let json = serde_json::json!({
-                "output": path,
-                "channelSize": channel_size,
-                "squareSize": squared_size,
-                "padding": padding,
+            result.analysis.peaks = Some(PeaksAnalysis {
+                output: path,
+                format: self.format,
+                bucket_size: self.bucket_size,
+                channel_size,
+                square_size: squared_size,
+                padding,
             });
-            results.push(("peaks".to_string(), json));
         }
 
-        results
+        Ok(result)
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<PeaksAnalysis>("peaks", generator)]
     }
 }
@@ -0,0 +1,215 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Dips of the cumulative mean normalized difference below this are periods (YIN step 4).
+const YIN_THRESHOLD: f64 = 0.15;
+
+/// Windows quieter than this are unvoiced, in dBFS.
+const MIN_LEVEL_DB: f64 = -60.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct PitchPoint {
+    /// Center of the window in seconds
+    pub time: f64,
+    /// Fundamental frequency in Hz, null for unvoiced windows
+    pub frequency: Option<f64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PitchSummary {
+    pub min: f64,
+    pub median: f64,
+    pub max: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PitchAnalysis {
+    /// f0 contour, a point per window
+    pub results: Vec<PitchPoint>,
+    /// Points per second
+    pub rate: f64,
+    /// Share of the windows with a pitch in percent
+    #[serde(rename = "voicedPercentage")]
+    pub voiced_percentage: f64,
+    /// Of the voiced windows in Hz, `null` if there were none
+    pub summary: Option<PitchSummary>,
+}
+
+/// Fundamental frequency of the channel mix with the YIN estimator, in windows of two periods
+/// of `--pitch-min` at `--pitch-rate` windows per second.
+pub struct PitchAnalyser {
+    /// Difference function of the current window, reused
+    difference: Vec<f64>,
+    frames: usize,
+    hop: usize,
+    max_lag: usize,
+    min_lag: usize,
+    mix: Vec<f64>,
+    points: Vec<PitchPoint>,
+    rate: f64,
+    sample_rate: f64,
+    /// Frames left to skip when the hop is longer than a window
+    skip: usize,
+    /// Length of the window the difference function is summed over
+    window: usize,
+}
+
+impl PitchAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let max_lag = (sample_rate / args.pitch_min).ceil() as usize;
+
+        Self {
+            difference: vec![0.0; max_lag + 1],
+            frames: 0,
+            hop: ((sample_rate / args.pitch_rate) as usize).max(1),
+            max_lag,
+            min_lag: ((sample_rate / args.pitch_max).floor() as usize).max(2),
+            mix: Vec::with_capacity(2 * max_lag),
+            points: Vec::new(),
+            rate: args.pitch_rate,
+            sample_rate,
+            skip: 0,
+            window: max_lag,
+        }
+    }
+
+    fn analyse_window(&mut self) {
+        let time = (self.frames - self.mix.len() / 2) as f64 / self.sample_rate;
+        let frequency = self.estimate().map(|lag| self.sample_rate / lag);
+
+        self.points.push(PitchPoint { time, frequency });
+    }
+
+    /// The period of the window in frames, interpolated between lags, `None` if unvoiced.
+    fn estimate(&mut self) -> Option<f64> {
+        let energy = self.mix[..self.window]
+            .iter()
+            .map(|value| value * value)
+            .sum::<f64>();
+        if 10.0 * (energy / self.window as f64).log10() < MIN_LEVEL_DB {
+            return None;
+        }
+
+        // Cumulative mean normalized difference, 1 at lag 0
+        let mut running = 0.0;
+        self.difference[0] = 1.0;
+        for lag in 1..=self.max_lag {
+            let sum: f64 = (0..self.window)
+                .map(|j| {
+                    let delta = self.mix[j] - self.mix[j + lag];
+                    delta * delta
+                })
+                .sum();
+            running += sum;
+            self.difference[lag] = if running > 0.0 {
+                sum * lag as f64 / running
+            } else {
+                1.0
+            };
+        }
+
+        // The first dip below the threshold, followed down to its minimum
+        let mut lag =
+            (self.min_lag..self.max_lag).find(|lag| self.difference[*lag] < YIN_THRESHOLD)?;
+        while lag + 1 < self.max_lag && self.difference[lag + 1] < self.difference[lag] {
+            lag += 1;
+        }
+
+        let (a, b, c) = (
+            self.difference[lag - 1],
+            self.difference[lag],
+            self.difference[lag + 1],
+        );
+        let curvature = a - 2.0 * b + c;
+        let offset = if curvature > 0.0 {
+            0.5 * (a - c) / curvature
+        } else {
+            0.0
+        };
+
+        Some(lag as f64 + offset)
+    }
+
+    fn summary(&self) -> Option<PitchSummary> {
+        let mut voiced: Vec<f64> = self
+            .points
+            .iter()
+            .filter_map(|point| point.frequency)
+            .collect();
+        if voiced.is_empty() {
+            return None;
+        }
+        voiced.sort_by(f64::total_cmp);
+
+        let middle = voiced.len() / 2;
+        let median = if voiced.len().is_multiple_of(2) {
+            (voiced[middle - 1] + voiced[middle]) / 2.0
+        } else {
+            voiced[middle]
+        };
+
+        Some(PitchSummary {
+            min: voiced[0],
+            median,
+            max: voiced[voiced.len() - 1],
+        })
+    }
+
+    fn voiced_percentage(&self) -> f64 {
+        let voiced = self
+            .points
+            .iter()
+            .filter(|point| point.frequency.is_some())
+            .count();
+
+        voiced as f64 / self.points.len().max(1) as f64 * 100.0
+    }
+}
+
+impl Analyser for PitchAnalyser {
+    fn name(&self) -> &'static str {
+        "pitch"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        self.frames += 1;
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        self.mix.push(sum / frame.len().max(1) as f64);
+
+        if self.mix.len() == self.window + self.max_lag {
+            self.analyse_window();
+
+            let drained = self.hop.min(self.mix.len());
+            self.mix.drain(..drained);
+            self.skip = self.hop - drained;
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        // A trailing partial window is too short for the lowest pitch
+        Ok(AnalysisResult::new(Analysis {
+            pitch: Some(PitchAnalysis {
+                results: self.points.clone(),
+                rate: self.rate,
+                voiced_percentage: self.voiced_percentage(),
+                summary: self.summary(),
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<PitchAnalysis>("pitch", generator)]
+    }
+}
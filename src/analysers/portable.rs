@@ -0,0 +1,270 @@
+//! Math for `--deterministic` raw outputs.
+//!
+//! Everything here only uses IEEE-754 basic operations (`+ - * /`, `sqrt`, `round`), which are
+//! correctly rounded on every platform we build for, so the results are bit-identical across OS
+//! and architecture. The default path goes through the platform libm and rustfft's SIMD
+//! kernels instead, which can differ in the last bits.
+
+use std::{
+    f64::consts::{FRAC_PI_2, LN_2, LOG10_E, PI, SQRT_2, TAU},
+    io::Write,
+};
+
+use png::{BitDepth, ColorType, DeflateCompression, Encoder, Filter};
+
+use super::fft::{FftAmplitude, FftWindow};
+use crate::warn;
+
+/// Same floor as the default log spectrogram, in dB.
+pub const LOG_FLOOR: f64 = 10e-8;
+
+/// Cosine via range reduction and a fixed-length Taylor series.
+pub fn cos(x: f64) -> f64 {
+    if !x.is_finite() {
+        return f64::NAN;
+    }
+
+    let x = (x - (x / TAU).round() * TAU).abs();
+    if x > FRAC_PI_2 {
+        return -cos_reduced(PI - x);
+    }
+
+    cos_reduced(x)
+}
+
+pub fn sin(x: f64) -> f64 {
+    cos(x - FRAC_PI_2)
+}
+
+/// Cosine for 0..=π/2 as 1 - x²/(1·2) (1 - x²/(3·4) (1 - ...)), good to about 1e-17.
+fn cos_reduced(x: f64) -> f64 {
+    let x2 = x * x;
+    let mut result = 1.0;
+
+    for k in (1..=12).rev() {
+        let n = (2 * k) as f64;
+        result = 1.0 - x2 / ((n - 1.0) * n) * result;
+    }
+
+    result
+}
+
+/// Natural logarithm via exponent extraction and an atanh series on the mantissa.
+pub fn ln(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+    if x == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return x;
+    }
+
+    // Scale subnormals into the normal range
+    let (x, offset) = if x < f64::MIN_POSITIVE {
+        (x * (1u64 << 54) as f64, -54)
+    } else {
+        (x, 0)
+    };
+
+    let bits = x.to_bits();
+    let mut exponent = ((bits >> 52) & 0x7ff) as i64 - 1023 + offset;
+    let mut mantissa = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | (1023 << 52));
+
+    if mantissa > SQRT_2 {
+        mantissa /= 2.0;
+        exponent += 1;
+    }
+
+    // ln(m) = 2 atanh(s) = 2 (s + s³/3 + s⁵/5 + ...), with |s| <= 0.172
+    let s = (mantissa - 1.0) / (mantissa + 1.0);
+    let s2 = s * s;
+    let mut series = 0.0;
+    for k in (0..=12).rev() {
+        series = 1.0 / (2 * k + 1) as f64 + s2 * series;
+    }
+
+    exponent as f64 * LN_2 + 2.0 * s * series
+}
+
+pub fn log10(x: f64) -> f64 {
+    ln(x) * LOG10_E
+}
+
+/// Exponential via range reduction to |r| <= ln(2)/2 and a fixed-length Taylor series.
+pub fn exp(x: f64) -> f64 {
+    if x.is_nan() {
+        return f64::NAN;
+    }
+    if x > 709.0 {
+        return f64::INFINITY;
+    }
+    if x < -708.0 {
+        return 0.0;
+    }
+
+    let k = (x / LN_2).round();
+    let r = x - k * LN_2;
+    let mut result = 1.0;
+    for n in (1..=16).rev() {
+        result = 1.0 + r / n as f64 * result;
+    }
+
+    // 2^k, k is within the normal exponent range here
+    result * f64::from_bits(((k as i64 + 1023) as u64) << 52)
+}
+
+/// Power in dB, floored like the default log spectrogram.
+pub fn db(power: f64) -> f64 {
+    canonical((10.0 * log10(power)).max(LOG_FLOOR))
+}
+
+/// Normalizes values whose bit patterns are not fully defined by IEEE-754 (NaN payloads and
+/// signs) or that compare equal but differ in bits (negative zero).
+pub fn canonical(value: f64) -> f64 {
+    if value.is_nan() {
+        f64::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
+/// Window of `length` samples, with the same definitions as the default spectrogram.
+pub fn window(kind: FftWindow, length: usize) -> Vec<f64> {
+    let n = length as f64;
+
+    (0..length)
+        .map(|i| {
+            let x = 2.0 * PI * i as f64;
+            match kind {
+                // A single sample would be 0 / 0, it's passed as is
+                FftWindow::Hann | FftWindow::Hamming if length == 1 => 1.0,
+                FftWindow::Hann => 0.5 - 0.5 * cos(x / (n - 1.0)),
+                FftWindow::Hamming => 0.54 - 0.46 * cos(x / (n - 1.0)),
+                FftWindow::Blackman => 0.42 - 0.5 * cos(x / n) + 0.08 * cos(2.0 * x / n),
+                FftWindow::Rect => 1.0,
+            }
+        })
+        .collect()
+}
+
+/// In-place iterative radix-2 FFT. `re.len()` must be a power of two.
+fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    let bits = n.trailing_zeros();
+
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let twiddles: Vec<(f64, f64)> = (0..n / 2)
+        .map(|k| {
+            let angle = 2.0 * PI * k as f64 / n as f64;
+            (cos(angle), -sin(angle))
+        })
+        .collect();
+
+    let mut length = 2;
+    while length <= n {
+        let half = length / 2;
+        let step = n / length;
+
+        for start in (0..n).step_by(length) {
+            for k in 0..half {
+                let (w_re, w_im) = twiddles[k * step];
+                let (a, b) = (start + k, start + k + half);
+                let t_re = w_re * re[b] - w_im * im[b];
+                let t_im = w_re * im[b] + w_im * re[b];
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+
+        length *= 2;
+    }
+}
+
+/// Bit-reproducible equivalent of [`super::fft::spectrogram`], with the same framing: hops of
+/// `hop` frames and a shortened window on the zero-padded last frame.
+pub fn spectrogram(
+    data: &[f64],
+    fft_size: usize,
+    hop: usize,
+    kind: FftWindow,
+    amplitude: FftAmplitude,
+) -> Vec<Vec<f64>> {
+    assert!(fft_size.is_power_of_two());
+
+    let full_window = window(kind, fft_size);
+    let mut slices = Vec::new();
+    let mut start = 0;
+
+    loop {
+        let end = (start + fft_size).min(data.len());
+        let last = end == data.len();
+        let chunk = &data[start..end];
+        let short_window;
+        let chunk_window = if chunk.len() < fft_size {
+            short_window = window(kind, chunk.len());
+            &short_window
+        } else {
+            &full_window
+        };
+
+        slices.push(slice(chunk, chunk_window, fft_size, amplitude));
+
+        if last {
+            break;
+        }
+        start += hop;
+    }
+
+    slices
+}
+
+/// One spectrogram slice of `chunk`, zero-padded to `fft_size`, with `fft_size / 2 + 1` bins.
+pub fn slice(chunk: &[f64], window: &[f64], fft_size: usize, amplitude: FftAmplitude) -> Vec<f64> {
+    let mut re = vec![0.0; fft_size];
+    let mut im = vec![0.0; fft_size];
+    for (i, (sample, w)) in chunk.iter().zip(window.iter()).enumerate() {
+        re[i] = sample * w;
+    }
+
+    fft(&mut re, &mut im);
+
+    (0..=fft_size / 2)
+        .map(|bin| {
+            let power = re[bin] * re[bin] + im[bin] * im[bin];
+            let value = match amplitude {
+                FftAmplitude::Linear => power.sqrt(),
+                FftAmplitude::Power => power,
+                FftAmplitude::Db => db(power),
+            };
+
+            canonical(value)
+        })
+        .collect()
+}
+
+/// Pins the PNG settings of the raw outputs and records their layout in a text chunk, so the
+/// files only depend on the data and the layout description.
+pub fn configure_encoder<W: Write>(encoder: &mut Encoder<W>, layout: &str) {
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Sixteen);
+    encoder.set_deflate_compression(DeflateCompression::Level(6));
+    encoder.set_filter(Filter::NoFilter);
+
+    if let Err(err) = encoder.add_text_chunk("analwave:layout".to_string(), layout.to_string()) {
+        warn!("could not add layout to PNG header: {err}");
+    }
+}
@@ -0,0 +1,287 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Length of the level measurement hops in seconds.
+const HOP_SECONDS: f64 = 0.01;
+
+/// Hops per analysis window (3 seconds).
+const WINDOW_HOPS: usize = 300;
+
+/// Moving average length used to detrend the level curves (0.5 seconds).
+const TREND_HOPS: usize = 50;
+
+/// Modulation periods considered, in hops (10 Hz down to 1 Hz).
+const MIN_PERIOD_HOPS: usize = 10;
+const MAX_PERIOD_HOPS: usize = 100;
+
+/// Delays between a peak and the resulting gain reduction, in hops (100 to 300 ms), which
+/// skips the peak itself.
+const MIN_RELEASE_HOPS: usize = 10;
+const MAX_RELEASE_HOPS: usize = 30;
+
+/// Minimum autocorrelation of the level modulation for it to count as periodic.
+const MIN_PERIODICITY: f64 = 0.4;
+
+/// Maximum (negative) average correlation between peaks and the level shortly after them.
+const MAX_PEAK_CORRELATION: f64 = -0.1;
+
+/// Windows quieter than this on average are skipped.
+const QUIET_DB: f64 = -60.0;
+
+const FLOOR_DB: f64 = -120.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+fn to_db(value: f64) -> f64 {
+    (20.0 * value.log10()).max(FLOOR_DB)
+}
+
+/// Subtracts a centered moving average.
+fn detrend(values: &[f64]) -> Vec<f64> {
+    let half = TREND_HOPS / 2;
+
+    (0..values.len())
+        .map(|i| {
+            let range = &values[i.saturating_sub(half)..(i + half + 1).min(values.len())];
+            values[i] - range.iter().sum::<f64>() / range.len() as f64
+        })
+        .collect()
+}
+
+/// Normalized correlation of `a[t]` with `b[t + lag]`.
+fn correlation(a: &[f64], b: &[f64], lag: usize) -> f64 {
+    let n = a.len().min(b.len());
+    if lag >= n {
+        return 0.0;
+    }
+
+    let dot: f64 = (0..n - lag).map(|t| a[t] * b[t + lag]).sum();
+    let norm = (a.iter().map(|v| v * v).sum::<f64>() * b.iter().map(|v| v * v).sum::<f64>()).sqrt();
+
+    if norm > 0.0 { dot / norm } else { 0.0 }
+}
+
+fn percentile(values: &[f64], p: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+struct Detection {
+    depth: f64,
+    rate: f64,
+}
+
+/// Looks for periodic level modulation where the level drops shortly after peaks.
+fn detect(levels: &[f64], peaks: &[f64], min_depth: f64) -> Option<Detection> {
+    if levels.iter().sum::<f64>() / (levels.len() as f64) < QUIET_DB {
+        return None;
+    }
+
+    let levels = detrend(levels);
+    let peaks = detrend(peaks);
+
+    let (period, periodicity) = (MIN_PERIOD_HOPS..=MAX_PERIOD_HOPS)
+        .map(|lag| (lag, correlation(&levels, &levels, lag)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    let peak_correlation = (MIN_RELEASE_HOPS..=MAX_RELEASE_HOPS)
+        .map(|lag| correlation(&peaks, &levels, lag))
+        .sum::<f64>()
+        / (MAX_RELEASE_HOPS - MIN_RELEASE_HOPS + 1) as f64;
+
+    let depth = percentile(&levels, 0.95) - percentile(&levels, 0.05);
+
+    if periodicity >= MIN_PERIODICITY
+        && peak_correlation <= MAX_PEAK_CORRELATION
+        && depth >= min_depth
+    {
+        Some(Detection {
+            depth,
+            rate: 1.0 / (period as f64 * HOP_SECONDS),
+        })
+    } else {
+        None
+    }
+}
+
+struct InternalSegment {
+    start: usize,
+    end: usize,
+    depth: f64,
+    rate_sum: f64,
+    windows: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PumpingSegment {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+    /// Largest peak-to-trough level modulation in the segment, in dB
+    pub depth: f64,
+    /// Average modulation rate in Hz
+    pub rate: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PumpingAnalysis {
+    pub results: Vec<PumpingSegment>,
+    #[serde(rename = "minDepth")]
+    pub min_depth: f64,
+}
+
+/// Detects gain pumping from heavy limiting or ducking on the mono mix.
+pub struct PumpingAnalyser {
+    current: Option<InternalSegment>,
+    frames: usize,
+    hop_frames: usize,
+    hop_peak: f64,
+    hop_squares: f64,
+    hop_count: usize,
+    levels: Vec<f64>,
+    min_depth: f64,
+    peaks: Vec<f64>,
+    sample_rate: i32,
+    segments: Vec<InternalSegment>,
+}
+
+impl PumpingAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        Self {
+            current: None,
+            frames: 0,
+            hop_frames: ((spec.sample_rate as f64 * HOP_SECONDS) as usize).max(1),
+            hop_peak: 0.0,
+            hop_squares: 0.0,
+            hop_count: 0,
+            levels: Vec::with_capacity(WINDOW_HOPS),
+            min_depth: args.pumping_min_depth,
+            peaks: Vec::with_capacity(WINDOW_HOPS),
+            sample_rate: spec.sample_rate,
+            segments: Vec::new(),
+        }
+    }
+
+    fn finish_window(&mut self) {
+        // Too short to tell a modulation period apart
+        if self.levels.len() < 2 * MAX_PERIOD_HOPS {
+            self.close_segment();
+            return;
+        }
+
+        let window_frames = self.levels.len() * self.hop_frames;
+        let start = self.frames - window_frames;
+
+        match (
+            detect(&self.levels, &self.peaks, self.min_depth),
+            self.current.as_mut(),
+        ) {
+            (Some(detection), Some(segment)) => {
+                segment.end = self.frames;
+                segment.depth = segment.depth.max(detection.depth);
+                segment.rate_sum += detection.rate;
+                segment.windows += 1;
+            }
+            (Some(detection), None) => {
+                self.current = Some(InternalSegment {
+                    start,
+                    end: self.frames,
+                    depth: detection.depth,
+                    rate_sum: detection.rate,
+                    windows: 1,
+                });
+            }
+            (None, _) => self.close_segment(),
+        }
+
+        self.levels.clear();
+        self.peaks.clear();
+    }
+
+    fn close_segment(&mut self) {
+        if let Some(segment) = self.current.take() {
+            self.segments.push(segment);
+        }
+    }
+}
+
+impl Analyser for PumpingAnalyser {
+    fn name(&self) -> &'static str {
+        "pumping"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let mix =
+            frame.iter().map(|&s| s as f64).sum::<f64>() / frame.len().max(1) as f64 / FULL_SCALE;
+
+        self.hop_squares += mix * mix;
+        self.hop_peak = self.hop_peak.max(mix.abs());
+        self.hop_count += 1;
+        self.frames += 1;
+
+        if self.hop_count < self.hop_frames {
+            return;
+        }
+
+        self.levels
+            .push(to_db((self.hop_squares / self.hop_count as f64).sqrt()));
+        self.peaks.push(to_db(self.hop_peak));
+        self.hop_squares = 0.0;
+        self.hop_peak = 0.0;
+        self.hop_count = 0;
+
+        if self.levels.len() >= WINDOW_HOPS {
+            self.finish_window();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        // Frames of an unfinished hop are not part of any window
+        self.frames -= self.hop_count;
+        self.finish_window();
+        self.close_segment();
+
+        let segments: Vec<PumpingSegment> = self
+            .segments
+            .iter()
+            .map(|seg| {
+                let duration_samples = seg.end - seg.start;
+                PumpingSegment {
+                    start: seg.start as f32 / self.sample_rate as f32,
+                    end: seg.end as f32 / self.sample_rate as f32,
+                    duration: duration_samples as f32 / self.sample_rate as f32,
+                    start_sample: seg.start,
+                    end_sample: seg.end,
+                    duration_samples,
+                    depth: seg.depth,
+                    rate: seg.rate_sum / seg.windows as f64,
+                }
+            })
+            .collect();
+
+        Ok(AnalysisResult::new(Analysis {
+            pumping: Some(PumpingAnalysis {
+                results: segments,
+                min_depth: self.min_depth,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<PumpingAnalysis>(
+            "pumping", generator,
+        )]
+    }
+}
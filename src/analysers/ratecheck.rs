@@ -0,0 +1,295 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{
+    Analyser, AnalysisResult,
+    fft::{FftAmplitude, FftWindow},
+    portable,
+};
+use crate::{Result, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// About 0.18 Hz per bin at 48 kHz, fine enough to tell 55 Hz from 55.125 Hz.
+const FFT_SIZE: usize = 1 << 18;
+
+/// Rates a file is commonly captured at. A mislabeled file was captured at one of these and
+/// tagged with another close to it, faster or slower than 0.8 to 1.25 times.
+const STANDARD_RATES: [i32; 12] = [
+    8000, 11025, 16000, 22050, 24000, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+const MIN_RATIO: f64 = 0.8;
+const MAX_RATIO: f64 = 1.25;
+
+/// How far a tone may be from where it's expected, at least, in bins.
+const TOLERANCE_BINS: f64 = 1.5;
+
+/// Bins around a tone, relative to its frequency, whose median is the floor it has to stand
+/// out from by `PROMINENCE_DB`.
+const FLOOR_SPAN: f64 = 0.1;
+const PROMINENCE_DB: f64 = 15.0;
+
+/// Share of the slices a tone has to be found in, so a held note doesn't count as hum.
+const MIN_PRESENCE: f64 = 0.5;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+/// Tones at a fixed frequency that show where a recording was made.
+#[derive(Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ReferenceTone {
+    /// Mains hum in Europe, Asia, Africa and Australia
+    Mains50,
+    /// Mains hum in the Americas
+    Mains60,
+    /// Line frequency whine of PAL video equipment
+    PalLine,
+    /// Line frequency whine of NTSC video equipment
+    NtscLine,
+    /// Stereo pilot of FM broadcasts
+    FmPilot,
+}
+
+impl ReferenceTone {
+    const ALL: [Self; 5] = [
+        Self::Mains50,
+        Self::Mains60,
+        Self::PalLine,
+        Self::NtscLine,
+        Self::FmPilot,
+    ];
+
+    pub fn frequency(self) -> f64 {
+        match self {
+            Self::Mains50 => 50.0,
+            Self::Mains60 => 60.0,
+            Self::PalLine => 15625.0,
+            Self::NtscLine => 15734.264,
+            Self::FmPilot => 19000.0,
+        }
+    }
+
+    /// How far the tone may be from its frequency in Hz. The mains frequency drifts with the
+    /// load of the grid, the video and FM tones are derived from crystals.
+    pub fn tolerance(self) -> f64 {
+        match self {
+            Self::Mains50 | Self::Mains60 => 0.003 * self.frequency(),
+            Self::PalLine | Self::NtscLine | Self::FmPilot => 2.0,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mains50 => "50 Hz mains hum",
+            Self::Mains60 => "60 Hz mains hum",
+            Self::PalLine => "PAL line whine",
+            Self::NtscLine => "NTSC line whine",
+            Self::FmPilot => "FM pilot",
+        }
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RateEvidence {
+    pub tone: ReferenceTone,
+    /// Frequency the tone was found at in the file as tagged, in Hz
+    pub found: f64,
+    /// Mean level of the tone above the spectrum around it in dB
+    pub prominence: f64,
+    /// Share of the slices the tone was found in
+    pub presence: f64,
+    /// Sample rate the file was captured at if the tone is right
+    #[serde(rename = "sampleRate")]
+    pub sample_rate: i32,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct RateCheckAnalysis {
+    /// Sample rate of the file header
+    #[serde(rename = "declaredRate")]
+    pub declared_rate: i32,
+    /// Sample rate the reference tones point to, `null` if none was found
+    #[serde(rename = "likelyRate")]
+    pub likely_rate: Option<i32>,
+    /// The tones point to another rate than the header
+    pub mismatch: bool,
+    pub evidence: Vec<RateEvidence>,
+}
+
+/// A reference tone as it would show if the file was captured at `sample_rate`.
+struct Candidate {
+    tone: ReferenceTone,
+    sample_rate: i32,
+    frequency: f64,
+    tolerance: f64,
+    found: f64,
+    prominence: f64,
+    slices: usize,
+}
+
+/// Looks for tones at a fixed frequency, like mains hum, at the frequencies they would be
+/// shifted to if the file was captured at a different rate than its header says. The tones
+/// have to last through most of the file. The channels are mixed to mono.
+pub struct RateCheckAnalyser {
+    bin_hz: f64,
+    candidates: Vec<Candidate>,
+    declared_rate: i32,
+    mix: Vec<f64>,
+    slices: usize,
+    window: Vec<f64>,
+}
+
+impl RateCheckAnalyser {
+    pub fn new(spec: &StreamSpec) -> Self {
+        let declared_rate = spec.sample_rate;
+        let nyquist = declared_rate as f64 / 2.0;
+
+        let rates = STANDARD_RATES.into_iter().filter(|&rate| {
+            let ratio = declared_rate as f64 / rate as f64;
+            rate == declared_rate || (MIN_RATIO..=MAX_RATIO).contains(&ratio)
+        });
+
+        // A tone recorded at `rate` plays at `declared / rate` times its frequency
+        let candidates = rates
+            .flat_map(|rate| {
+                let ratio = declared_rate as f64 / rate as f64;
+                ReferenceTone::ALL.map(|tone| Candidate {
+                    tone,
+                    sample_rate: rate,
+                    frequency: tone.frequency() * ratio,
+                    tolerance: tone.tolerance() * ratio,
+                    found: 0.0,
+                    prominence: 0.0,
+                    slices: 0,
+                })
+            })
+            .filter(|candidate| candidate.frequency * (1.0 + FLOOR_SPAN) < nyquist)
+            .collect();
+
+        Self {
+            bin_hz: declared_rate as f64 / FFT_SIZE as f64,
+            candidates,
+            declared_rate,
+            mix: Vec::with_capacity(FFT_SIZE),
+            slices: 0,
+            window: portable::window(FftWindow::Hann, FFT_SIZE),
+        }
+    }
+
+    fn analyse_slice(&mut self) {
+        if self.mix.iter().all(|value| *value == 0.0) {
+            return;
+        }
+
+        let power = portable::slice(&self.mix, &self.window, FFT_SIZE, FftAmplitude::Power);
+        let bin_hz = self.bin_hz;
+        self.slices += 1;
+
+        for candidate in self.candidates.iter_mut() {
+            let tolerance = candidate.tolerance.max(TOLERANCE_BINS * bin_hz);
+            let bin = |frequency: f64| ((frequency / bin_hz).round() as usize).min(power.len() - 1);
+
+            let (low, high) = (
+                bin(candidate.frequency - tolerance),
+                bin(candidate.frequency + tolerance),
+            );
+            let Some((peak, peak_power)) = (low..=high)
+                .map(|bin| (bin, power[bin]))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+            else {
+                continue;
+            };
+
+            let span = candidate.frequency * FLOOR_SPAN;
+            let mut floor: Vec<f64> = (bin(candidate.frequency - span)..low)
+                .chain(high + 1..=bin(candidate.frequency + span))
+                .map(|bin| power[bin])
+                .collect();
+            if floor.is_empty() {
+                continue;
+            }
+            floor.sort_by(f64::total_cmp);
+            let median = floor[floor.len() / 2];
+
+            let prominence = 10.0 * (peak_power / median.max(1e-30)).log10();
+            if prominence >= PROMINENCE_DB {
+                candidate.found += peak as f64 * bin_hz;
+                candidate.prominence += prominence;
+                candidate.slices += 1;
+            }
+        }
+    }
+}
+
+impl Analyser for RateCheckAnalyser {
+    fn name(&self) -> &'static str {
+        "rate check"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        self.mix.push(sum / frame.len().max(1) as f64);
+
+        if self.mix.len() == FFT_SIZE {
+            self.analyse_slice();
+            self.mix.clear();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        // Files shorter than a slice are analysed zero-padded, with a coarser resolution
+        if self.slices == 0 && !self.mix.is_empty() {
+            self.analyse_slice();
+        }
+
+        let slices = self.slices.max(1) as f64;
+        let evidence: Vec<RateEvidence> = self
+            .candidates
+            .iter()
+            .filter(|candidate| {
+                candidate.slices > 0 && candidate.slices as f64 / slices >= MIN_PRESENCE
+            })
+            .map(|candidate| RateEvidence {
+                tone: candidate.tone,
+                found: candidate.found / candidate.slices as f64,
+                prominence: candidate.prominence / candidate.slices as f64,
+                presence: candidate.slices as f64 / slices,
+                sample_rate: candidate.sample_rate,
+            })
+            .collect();
+
+        // The rate with the most prominent tones in total, the declared one on a tie
+        let mut totals: Vec<(i32, f64)> = Vec::new();
+        for evidence in evidence.iter() {
+            match totals
+                .iter_mut()
+                .find(|(rate, _)| *rate == evidence.sample_rate)
+            {
+                Some((_, total)) => *total += evidence.prominence,
+                None => totals.push((evidence.sample_rate, evidence.prominence)),
+            }
+        }
+        let likely_rate = totals
+            .iter()
+            .max_by(|a, b| {
+                a.1.total_cmp(&b.1)
+                    .then((a.0 == self.declared_rate).cmp(&(b.0 == self.declared_rate)))
+            })
+            .map(|(rate, _)| *rate);
+
+        Ok(AnalysisResult::new(Analysis {
+            rate_check: Some(RateCheckAnalysis {
+                declared_rate: self.declared_rate,
+                likely_rate,
+                mismatch: likely_rate.is_some_and(|rate| rate != self.declared_rate),
+                evidence,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<RateCheckAnalysis>(
+            "rateCheck",
+            generator,
+        )]
+    }
+}
@@ -0,0 +1,290 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult, fft::FftAmplitude, portable};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Share of the energy below the rolloff frequency.
+const ROLLOFF: f64 = 0.85;
+
+/// Slices quieter than this have no meaningful spectrum and are skipped, in dBFS.
+const MIN_LEVEL_DB: f64 = -90.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+/// Statistics of one spectrum, frequencies in Hz.
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    centroid: f64,
+    flatness: f64,
+    rolloff: f64,
+    bandwidth: f64,
+}
+
+impl Stats {
+    fn of(power: &[f64], bin_hz: f64) -> Self {
+        let total: f64 = power.iter().sum();
+        let frequency = |bin: usize| bin as f64 * bin_hz;
+
+        let centroid = power
+            .iter()
+            .enumerate()
+            .map(|(bin, p)| frequency(bin) * p)
+            .sum::<f64>()
+            / total;
+
+        let spread = power
+            .iter()
+            .enumerate()
+            .map(|(bin, p)| (frequency(bin) - centroid).powi(2) * p)
+            .sum::<f64>()
+            / total;
+
+        let mut cumulative = 0.0;
+        let rolloff_bin = power
+            .iter()
+            .position(|p| {
+                cumulative += p;
+                cumulative >= ROLLOFF * total
+            })
+            .unwrap_or(power.len() - 1);
+
+        // Geometric over arithmetic mean, with a floor so empty bins don't zero it
+        let floor = total * 1e-12;
+        let log_mean = power.iter().map(|p| p.max(floor).ln()).sum::<f64>() / power.len() as f64;
+        let flatness = log_mean.exp() / (total / power.len() as f64);
+
+        Self {
+            centroid,
+            flatness,
+            rolloff: frequency(rolloff_bin),
+            bandwidth: spread.sqrt(),
+        }
+    }
+
+    fn map(self, other: Self, f: impl Fn(f64, f64) -> f64) -> Self {
+        Self {
+            centroid: f(self.centroid, other.centroid),
+            flatness: f(self.flatness, other.flatness),
+            rolloff: f(self.rolloff, other.rolloff),
+            bandwidth: f(self.bandwidth, other.bandwidth),
+        }
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        self.map(self, |value, _| value * factor)
+    }
+}
+
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct SpectralPoint {
+    /// Start of the interval in seconds
+    pub time: f64,
+    /// Spectral centroid in Hz
+    pub centroid: f64,
+    /// Spectral flatness from 0 (tonal) to 1 (white noise)
+    pub flatness: f64,
+    /// Frequency below which 85% of the energy lies, in Hz
+    pub rolloff: f64,
+    /// Spread of the spectrum around the centroid in Hz
+    pub bandwidth: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct StatSummary {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SpectralSummary {
+    pub centroid: StatSummary,
+    pub flatness: StatSummary,
+    pub rolloff: StatSummary,
+    pub bandwidth: StatSummary,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct SpectralAnalysis {
+    /// Mean statistics per interval, intervals without sound are left out
+    pub results: Vec<SpectralPoint>,
+    /// Intervals per second
+    pub rate: f64,
+    /// Over all slices with sound, `null` if there were none
+    pub summary: Option<SpectralSummary>,
+}
+
+/// Running sum of the statistics of several slices.
+#[derive(Clone, Copy)]
+struct Accumulator {
+    min: Stats,
+    max: Stats,
+    sum: Stats,
+    count: usize,
+}
+
+impl Accumulator {
+    fn new() -> Self {
+        Self {
+            min: Stats {
+                centroid: f64::INFINITY,
+                flatness: f64::INFINITY,
+                rolloff: f64::INFINITY,
+                bandwidth: f64::INFINITY,
+            },
+            max: Stats::default(),
+            sum: Stats::default(),
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, stats: Stats) {
+        self.min = self.min.map(stats, f64::min);
+        self.max = self.max.map(stats, f64::max);
+        self.sum = self.sum.map(stats, |sum, value| sum + value);
+        self.count += 1;
+    }
+
+    fn mean(&self) -> Stats {
+        self.sum.scale(1.0 / self.count.max(1) as f64)
+    }
+}
+
+/// Spectral centroid, flatness, rolloff and bandwidth of the channel mix, computed slice by
+/// slice with the FFT size, hop and window of the spectrogram and averaged into a time
+/// series at `--spectral-rate`.
+pub struct SpectralStatsAnalyser {
+    bin_hz: f64,
+    bucket: Option<(usize, Accumulator)>,
+    fft_size: usize,
+    frames: usize,
+    hop: usize,
+    mix: Vec<f64>,
+    points: Vec<SpectralPoint>,
+    rate: f64,
+    sample_rate: f64,
+    total: Accumulator,
+    window: Vec<f64>,
+}
+
+impl SpectralStatsAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+
+        Self {
+            bin_hz: sample_rate / args.fft_bins as f64,
+            bucket: None,
+            fft_size: args.fft_bins,
+            frames: 0,
+            hop: args.fft_hop(),
+            mix: Vec::with_capacity(args.fft_bins),
+            points: Vec::new(),
+            rate: args.spectral_rate,
+            sample_rate,
+            total: Accumulator::new(),
+            window: portable::window(args.fft_window, args.fft_bins),
+        }
+    }
+
+    fn analyse_slice(&mut self) {
+        let energy = self.mix.iter().map(|value| value * value).sum::<f64>();
+        let level = 10.0 * (energy / self.fft_size as f64).log10();
+        if level < MIN_LEVEL_DB {
+            return;
+        }
+
+        let power = portable::slice(&self.mix, &self.window, self.fft_size, FftAmplitude::Power);
+        let stats = Stats::of(&power, self.bin_hz);
+        self.total.add(stats);
+
+        // Slices count towards the interval their center falls into
+        let center = (self.frames - self.fft_size / 2) as f64 / self.sample_rate;
+        let index = (center * self.rate) as usize;
+
+        if self
+            .bucket
+            .as_ref()
+            .is_some_and(|(current, _)| *current != index)
+        {
+            self.flush_bucket();
+        }
+
+        self.bucket
+            .get_or_insert_with(|| (index, Accumulator::new()))
+            .1
+            .add(stats);
+    }
+
+    fn flush_bucket(&mut self) {
+        let Some((index, accumulator)) = self.bucket.take() else {
+            return;
+        };
+        let mean = accumulator.mean();
+
+        self.points.push(SpectralPoint {
+            time: index as f64 / self.rate,
+            centroid: mean.centroid,
+            flatness: mean.flatness,
+            rolloff: mean.rolloff,
+            bandwidth: mean.bandwidth,
+        });
+    }
+
+    fn summary(&self) -> Option<SpectralSummary> {
+        if self.total.count == 0 {
+            return None;
+        }
+
+        let mean = self.total.mean();
+        let summary = |pick: fn(&Stats) -> f64| StatSummary {
+            min: pick(&self.total.min),
+            mean: pick(&mean),
+            max: pick(&self.total.max),
+        };
+
+        Some(SpectralSummary {
+            centroid: summary(|stats| stats.centroid),
+            flatness: summary(|stats| stats.flatness),
+            rolloff: summary(|stats| stats.rolloff),
+            bandwidth: summary(|stats| stats.bandwidth),
+        })
+    }
+}
+
+impl Analyser for SpectralStatsAnalyser {
+    fn name(&self) -> &'static str {
+        "spectral stats"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        self.mix.push(sum / frame.len().max(1) as f64);
+        self.frames += 1;
+
+        if self.mix.len() == self.fft_size {
+            self.analyse_slice();
+            self.mix.drain(..self.hop);
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        // A trailing partial slice would be mostly zero padding
+        self.flush_bucket();
+
+        Ok(AnalysisResult::new(Analysis {
+            spectral: Some(SpectralAnalysis {
+                results: self.points.clone(),
+                rate: self.rate,
+                summary: self.summary(),
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<SpectralAnalysis>(
+            "spectral", generator,
+        )]
+    }
+}
@@ -0,0 +1,106 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry};
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Serialize, JsonSchema)]
+pub struct StereoAnalysis {
+    /// Phase correlation of the first two channels, from -1 (out of phase) over 0
+    /// (uncorrelated) to 1 (in phase), null if either is silent
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub correlation: f64,
+    /// Level of the mid (L+R) over the side (L-R) signal in dB, null for silence and for
+    /// identical channels
+    #[serde(rename = "midSideRatio")]
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub mid_side_ratio: f64,
+    /// Level the mono downmix (L+R)/2 loses against the mean of L and R in dB, 0 for
+    /// dual-mono, about 3 for uncorrelated channels and more the further out of phase they
+    /// are, null for silence
+    #[serde(rename = "monoDownmixLoss")]
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub mono_downmix_loss: f64,
+    /// The channels are nearly identical, the side signal is at least `dualMonoRatio` dB
+    /// below the mid signal
+    #[serde(rename = "dualMono")]
+    pub dual_mono: bool,
+    /// Loss of the mono downmix in dB above which it is warned about
+    #[serde(rename = "maxMonoLoss")]
+    pub max_mono_loss: f64,
+    #[serde(rename = "dualMonoRatio")]
+    pub dual_mono_ratio: f64,
+}
+
+fn db(ratio: f64) -> f64 {
+    10.0 * ratio.log10()
+}
+
+/// Mono compatibility of the first two channels, from the energy of both and their product
+/// over the whole file.
+pub struct StereoAnalyser {
+    dual_mono_ratio: f64,
+    left: f64,
+    max_mono_loss: f64,
+    product: f64,
+    right: f64,
+}
+
+impl StereoAnalyser {
+    pub fn new(args: &AnalyseArgs) -> Self {
+        Self {
+            dual_mono_ratio: args.dual_mono_ratio,
+            left: 0.0,
+            max_mono_loss: args.max_mono_loss,
+            product: 0.0,
+            right: 0.0,
+        }
+    }
+
+    fn mid(&self) -> f64 {
+        ((self.left + self.right + 2.0 * self.product) / 4.0).max(0.0)
+    }
+
+    fn side(&self) -> f64 {
+        ((self.left + self.right - 2.0 * self.product) / 4.0).max(0.0)
+    }
+}
+
+impl Analyser for StereoAnalyser {
+    fn name(&self) -> &'static str {
+        "stereo"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let left = frame[0] as f64 / FULL_SCALE;
+        let right = frame[1] as f64 / FULL_SCALE;
+
+        self.left += left * left;
+        self.right += right * right;
+        self.product += left * right;
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let (mid, side) = (self.mid(), self.side());
+        let mid_side_ratio = db(mid / side);
+
+        Ok(AnalysisResult::new(Analysis {
+            stereo: Some(StereoAnalysis {
+                correlation: self.product / (self.left * self.right).sqrt(),
+                mid_side_ratio,
+                mono_downmix_loss: db((self.left + self.right) / 2.0 / mid),
+                // Identical channels have no side signal, so an infinite ratio
+                dual_mono: mid > 0.0 && mid_side_ratio >= self.dual_mono_ratio,
+                max_mono_loss: self.max_mono_loss,
+                dual_mono_ratio: self.dual_mono_ratio,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<StereoAnalysis>("stereo", generator)]
+    }
+}
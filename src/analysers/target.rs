@@ -0,0 +1,119 @@
+use ebur128::{EbuR128, Error as EbuR128Error, Mode};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Error, Result, cli::AnalyseArgs, exclude::Exclusions, json::Analysis, layout::loudness_meter,
+    schema::SchemaEntry, stream::StreamSpec, warn,
+};
+
+/// Frames buffered before they are added to the loudness meter.
+const METER_CHUNK_FRAMES: usize = 4096;
+
+#[derive(Serialize, JsonSchema)]
+pub struct LoudnessTargetAnalysis {
+    /// Integrated loudness in LUFS
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub integrated: f64,
+    /// Target loudness in LUFS
+    pub target: f64,
+    /// Allowed deviation from the target in LU
+    pub tolerance: f64,
+    /// Gain in dB that brings the integrated loudness to the target, absent for silence
+    #[serde(rename = "gainToTarget")]
+    pub gain_to_target: Option<f64>,
+    #[serde(rename = "withinTolerance")]
+    pub within_tolerance: bool,
+}
+
+/// Compares the integrated loudness against a normalization target.
+pub struct LoudnessTargetAnalyser {
+    integrated: f64,
+    interleaved: Vec<i32>,
+    meter: EbuR128,
+    target: f64,
+    tolerance: f64,
+    /// Frames of `--exclude`, left out of the measurement
+    excluded: Exclusions,
+}
+
+impl LoudnessTargetAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec, target: f64) -> Result<Self> {
+        let meter = loudness_meter(spec, Mode::I)
+            .map_err(|err: EbuR128Error| Error::analyser("loudness target", err))?;
+
+        Ok(Self {
+            integrated: f64::NEG_INFINITY,
+            interleaved: Vec::with_capacity(METER_CHUNK_FRAMES * spec.channels as usize),
+            meter,
+            target,
+            tolerance: args.loudness_tolerance,
+            excluded: Exclusions::new(&args.exclude, spec),
+        })
+    }
+
+    fn flush_meter(&mut self) {
+        if let Err(err) = self.meter.add_frames_i32(&self.interleaved) {
+            warn!("error adding frame to loudness measurement: {:?}", &err);
+        }
+        self.interleaved.clear();
+    }
+
+    fn gain_to_target(&self) -> Option<f64> {
+        self.integrated
+            .is_finite()
+            .then_some(self.target - self.integrated)
+    }
+
+    /// Silence can't be normalized, so it is never within tolerance.
+    fn within_tolerance(&self) -> bool {
+        self.gain_to_target()
+            .is_some_and(|gain| gain.abs() <= self.tolerance)
+    }
+}
+
+impl Analyser for LoudnessTargetAnalyser {
+    fn name(&self) -> &'static str {
+        "loudness target"
+    }
+
+    fn analyse(&mut self, _label: &str, frame_counter: usize, frame: &[i32]) {
+        if self.excluded.contains(frame_counter) {
+            return;
+        }
+        self.interleaved.extend_from_slice(frame);
+
+        if self.interleaved.len() >= self.interleaved.capacity() {
+            self.flush_meter();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.flush_meter();
+        self.integrated = self.meter.loudness_global().unwrap_or(f64::NEG_INFINITY);
+
+        let mut result = AnalysisResult::new(Analysis {
+            loudness_target: Some(LoudnessTargetAnalysis {
+                integrated: self.integrated,
+                target: self.target,
+                tolerance: self.tolerance,
+                gain_to_target: self.gain_to_target(),
+                within_tolerance: self.within_tolerance(),
+            }),
+            ..Analysis::default()
+        });
+        if !self.within_tolerance() {
+            result.flags = crate::ERR_LOUDNESS_TARGET;
+        }
+
+        Ok(result)
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<LoudnessTargetAnalysis>(
+            "loudnessTarget",
+            generator,
+        )]
+    }
+}
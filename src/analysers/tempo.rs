@@ -0,0 +1,292 @@
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{
+    Analyser, AnalysisResult,
+    fft::{FftAmplitude, FftWindow},
+    portable,
+};
+use crate::{Result, cli::AnalyseArgs, json::Analysis, schema::SchemaEntry, stream::StreamSpec};
+
+/// Length of the spectrum slices in seconds, rounded up to a power of two of frames.
+const SLICE_SECONDS: f64 = 0.04;
+
+/// Slices per slice length, the onset envelope has a point every hop.
+const OVERLAP: usize = 4;
+
+/// Onsets above this frequency are mostly cymbals and noise, in Hz.
+const MAX_HZ: f64 = 8000.0;
+
+/// Logarithmic compression of the magnitudes, so quiet onsets count as well.
+const COMPRESSION: f64 = 1000.0;
+
+/// Seconds of the moving average subtracted from the onset envelope.
+const TREND_SECONDS: f64 = 0.5;
+
+/// Tempos are weighted by a log-normal prior around this tempo, in BPM, with a standard
+/// deviation in octaves, which settles between half and double tempo.
+const PRIOR_BPM: f64 = 120.0;
+const PRIOR_OCTAVES: f64 = 1.0;
+
+/// How strictly the beats keep to the period, as the penalty of the squared log ratio of a
+/// beat interval and the period.
+const TIGHTNESS: f64 = 100.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct Beat {
+    pub time: f64,
+    pub sample: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TempoAnalysis {
+    /// Estimated tempo in beats per minute, `null` if there were no onsets to tell it from
+    pub bpm: Option<f64>,
+    /// Autocorrelation of the onsets at the beat period, from 0 (no periodicity) to 1
+    pub confidence: f64,
+    #[serde(rename = "minBpm")]
+    pub min_bpm: f64,
+    #[serde(rename = "maxBpm")]
+    pub max_bpm: f64,
+    /// Positions of the beats at the estimated tempo, with `--beats`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beats: Option<Vec<Beat>>,
+}
+
+/// Estimates the global tempo of the channel mix. The onset envelope is the spectral flux of
+/// the log-compressed magnitudes, and the tempo the period its autocorrelation peaks at
+/// between `--tempo-min` and `--tempo-max`, weighted towards 120 BPM. With `--beats` the
+/// beats are tracked at that tempo by dynamic programming (Ellis, 2007).
+pub struct TempoAnalyser {
+    beats: bool,
+    bins: usize,
+    envelope: Vec<f64>,
+    hop: usize,
+    max_bpm: f64,
+    min_bpm: f64,
+    mix: Vec<f64>,
+    previous: Option<Vec<f64>>,
+    reference: f64,
+    sample_rate: f64,
+    slice_size: usize,
+    window: Vec<f64>,
+}
+
+impl TempoAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let slice_size = ((sample_rate * SLICE_SECONDS) as usize).next_power_of_two();
+        let window = portable::window(FftWindow::Hann, slice_size);
+        let max_hz = MAX_HZ.min(sample_rate / 2.0);
+
+        Self {
+            beats: args.beats,
+            bins: (max_hz / sample_rate * slice_size as f64) as usize + 1,
+            envelope: Vec::new(),
+            hop: slice_size / OVERLAP,
+            max_bpm: args.tempo_max,
+            min_bpm: args.tempo_min,
+            mix: Vec::with_capacity(slice_size),
+            previous: None,
+            // Magnitude of a full-scale sine in its bin
+            reference: window.iter().sum::<f64>() / 2.0,
+            sample_rate,
+            slice_size,
+            window,
+        }
+    }
+
+    /// Points of the onset envelope per second.
+    fn rate(&self) -> f64 {
+        self.sample_rate / self.hop as f64
+    }
+
+    fn analyse_slice(&mut self) {
+        let magnitudes: Vec<f64> = portable::slice(
+            &self.mix,
+            &self.window,
+            self.slice_size,
+            FftAmplitude::Linear,
+        )
+        .into_iter()
+        .take(self.bins)
+        .map(|magnitude| (COMPRESSION * magnitude / self.reference).ln_1p())
+        .collect();
+
+        // Half-wave rectified: only bins that get louder are onsets
+        let flux = self.previous.as_ref().map_or(0.0, |previous| {
+            magnitudes
+                .iter()
+                .zip(previous)
+                .map(|(magnitude, previous)| (magnitude - previous).max(0.0))
+                .sum()
+        });
+        self.envelope.push(flux);
+        self.previous = Some(magnitudes);
+    }
+
+    /// The onset envelope without its local mean, so only the onsets that stand out from
+    /// their surroundings remain.
+    fn onsets(&self) -> Vec<f64> {
+        let half = ((TREND_SECONDS * self.rate()) as usize / 2).max(1);
+        let mut sums = Vec::with_capacity(self.envelope.len() + 1);
+        sums.push(0.0);
+        for value in &self.envelope {
+            sums.push(sums.last().unwrap_or(&0.0) + value);
+        }
+
+        (0..self.envelope.len())
+            .map(|i| {
+                let (start, end) = (
+                    i.saturating_sub(half),
+                    (i + half + 1).min(self.envelope.len()),
+                );
+                let mean = (sums[end] - sums[start]) / (end - start) as f64;
+                (self.envelope[i] - mean).max(0.0).sqrt()
+            })
+            .collect()
+    }
+
+    /// The beat period in envelope points and the confidence, `None` if the onsets don't
+    /// vary or are too short for two periods of the slowest tempo.
+    fn period(&self, onsets: &[f64]) -> Option<(f64, f64)> {
+        let rate = self.rate();
+        let min_lag = ((60.0 * rate / self.max_bpm).floor() as usize).max(1);
+        let max_lag = (60.0 * rate / self.min_bpm).ceil() as usize;
+        if onsets.len() <= 2 * (max_lag + 1) {
+            return None;
+        }
+
+        // Without the mean, so an envelope without a period has a correlation around 0
+        let mean = onsets.iter().sum::<f64>() / onsets.len() as f64;
+        let centered: Vec<f64> = onsets.iter().map(|value| value - mean).collect();
+        let variance =
+            centered.iter().map(|value| value * value).sum::<f64>() / centered.len() as f64;
+        if variance <= 0.0 {
+            return None;
+        }
+
+        // Normalised so a perfectly periodic envelope has a correlation of 1 at its period
+        let correlation = |lag: usize| {
+            let count = centered.len() - lag;
+            (0..count)
+                .map(|i| centered[i] * centered[i + lag])
+                .sum::<f64>()
+                / count as f64
+                / variance
+        };
+        let bpm = |lag: f64| 60.0 * rate / lag;
+
+        let correlations: Vec<f64> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+        let at = |lag: usize| correlations[lag + 1 - min_lag];
+
+        let lag = (min_lag..=max_lag)
+            .filter(|&lag| (self.min_bpm..=self.max_bpm).contains(&bpm(lag as f64)))
+            .max_by(|&a, &b| {
+                let weighted = |lag: usize| {
+                    let octaves = (bpm(lag as f64) / PRIOR_BPM).log2() / PRIOR_OCTAVES;
+                    at(lag) * (-0.5 * octaves * octaves).exp()
+                };
+                weighted(a).total_cmp(&weighted(b))
+            })?;
+
+        // Parabolic interpolation between the neighbouring lags for a fractional period
+        let (before, peak, after) = (at(lag - 1), at(lag), at(lag + 1));
+        let curvature = before - 2.0 * peak + after;
+        let offset = if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        Some((lag as f64 + offset, peak.clamp(0.0, 1.0)))
+    }
+
+    /// The beats at `period` envelope points that best line up with the onsets.
+    fn track(&self, onsets: &[f64], period: f64) -> Vec<Beat> {
+        let earliest = (2.0 * period).round() as usize;
+        let latest = ((period / 2.0).round() as usize).max(1);
+
+        let mut scores = Vec::with_capacity(onsets.len());
+        let mut links: Vec<Option<usize>> = Vec::with_capacity(onsets.len());
+        for (t, onset) in onsets.iter().enumerate() {
+            let best = (t.saturating_sub(earliest)..t.saturating_sub(latest))
+                .map(|previous| {
+                    let ratio = ((t - previous) as f64 / period).ln();
+                    (previous, scores[previous] - TIGHTNESS * ratio * ratio)
+                })
+                .max_by(|a: &(usize, f64), b| a.1.total_cmp(&b.1));
+
+            scores.push(onset + best.map_or(0.0, |(_, score)| score));
+            links.push(best.map(|(previous, _)| previous));
+        }
+
+        // The last beat is the best one within the last period
+        let tail = onsets.len().saturating_sub(period.ceil() as usize);
+        let Some(last) = (tail..onsets.len()).max_by(|&a, &b| scores[a].total_cmp(&scores[b]))
+        else {
+            return Vec::new();
+        };
+
+        let mut points = vec![last];
+        let mut current = last;
+        while let Some(previous) = links[current] {
+            points.push(previous);
+            current = previous;
+        }
+        points.reverse();
+
+        points
+            .into_iter()
+            .map(|point| {
+                // An onset is strongest when it reaches the middle of the slice
+                let sample = point * self.hop + self.slice_size / 2;
+                Beat {
+                    time: sample as f64 / self.sample_rate,
+                    sample,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Analyser for TempoAnalyser {
+    fn name(&self) -> &'static str {
+        "tempo"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        self.mix.push(sum / frame.len().max(1) as f64);
+
+        if self.mix.len() == self.slice_size {
+            self.analyse_slice();
+            self.mix.drain(..self.hop);
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        let onsets = self.onsets();
+        let period = self.period(&onsets);
+        let beats = self
+            .beats
+            .then(|| period.map_or(Vec::new(), |(period, _)| self.track(&onsets, period)));
+
+        Ok(AnalysisResult::new(Analysis {
+            tempo: Some(TempoAnalysis {
+                bpm: period.map(|(period, _)| 60.0 * self.rate() / period),
+                confidence: period.map_or(0.0, |(_, confidence)| confidence),
+                min_bpm: self.min_bpm,
+                max_bpm: self.max_bpm,
+                beats,
+            }),
+            ..Analysis::default()
+        }))
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<TempoAnalysis>("tempo", generator)]
+    }
+}
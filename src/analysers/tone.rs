@@ -0,0 +1,294 @@
+use std::f64::consts::PI;
+
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Result, cli::AnalyseArgs, json::Analysis, output::frame_to_time, schema::SchemaEntry,
+    stream::StreamSpec,
+};
+
+/// Length of the detection blocks in seconds.
+const BLOCK_SECONDS: f64 = 0.1;
+
+/// Share of the block energy that has to sit at the detected frequency for it to count
+/// as a pure tone.
+const PURITY_THRESHOLD: f64 = 0.9;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+/// Goertzel power at an arbitrary frequency, normalized against the block energy so that a
+/// pure sine at `frequency` gives 1.0.
+pub(crate) fn goertzel_purity(samples: &[f64], frequency: f64, sample_rate: f64) -> f64 {
+    let energy: f64 = samples.iter().map(|v| v * v).sum();
+    if energy <= 0.0 {
+        return 0.0;
+    }
+
+    2.0 * goertzel_power(samples, frequency, sample_rate) / (samples.len() as f64 * energy)
+}
+
+/// Squared magnitude of the Goertzel filter output at an arbitrary frequency.
+pub(crate) fn goertzel_power(samples: &[f64], frequency: f64, sample_rate: f64) -> f64 {
+    let coeff = 2.0 * (2.0 * PI * frequency / sample_rate).cos();
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for sample in samples {
+        let s0 = sample + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+
+    s1 * s1 + s2 * s2 - coeff * s1 * s2
+}
+
+/// Frequency estimate from interpolated positive-going zero crossings.
+pub(crate) fn zero_crossing_frequency(samples: &[f64], sample_rate: f64) -> Option<f64> {
+    let mut first = None;
+    let mut last = 0.0;
+    let mut count = 0usize;
+
+    for (i, pair) in samples.windows(2).enumerate() {
+        if pair[0] < 0.0 && pair[1] >= 0.0 {
+            let position = i as f64 + pair[0] / (pair[0] - pair[1]);
+            first.get_or_insert(position);
+            last = position;
+            count += 1;
+        }
+    }
+
+    let first = first?;
+    if count < 2 || last <= first {
+        return None;
+    }
+
+    Some((count - 1) as f64 * sample_rate / (last - first))
+}
+
+struct InternalSegment {
+    start: usize,
+    end: usize,
+    /// Index into the configured frequency list
+    tone: usize,
+    frequency_sum: f64,
+    level_sum: f64,
+    blocks: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ToneSegment {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+    pub frequency: f64,
+    #[serde(rename = "measuredFrequency")]
+    pub measured_frequency: f64,
+    /// Level of the loudest channel as the peak of the sine in dBFS
+    pub level: f64,
+    pub head: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ToneAnalysis {
+    pub results: Vec<ToneSegment>,
+    pub frequencies: Vec<f64>,
+    pub tolerance: f64,
+}
+
+/// Detects sustained pure tones (line-up tones, beeps) at a configured list of frequencies.
+pub struct ToneAnalyser {
+    block_frames: usize,
+    channel_squares: Vec<f64>,
+    current: Option<InternalSegment>,
+    fail_on_tone: bool,
+    frames: usize,
+    frequencies: Vec<f64>,
+    head_frames: usize,
+    min_frames: usize,
+    mix: Vec<f64>,
+    require_tone: bool,
+    sample_rate: i32,
+    segments: Vec<InternalSegment>,
+    tolerance: f64,
+}
+
+impl ToneAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
+        let sample_rate = spec.sample_rate as f64;
+        let block_frames = ((sample_rate * BLOCK_SECONDS) as usize).max(1);
+
+        Self {
+            block_frames,
+            channel_squares: vec![0.0; spec.channels as usize],
+            current: None,
+            fail_on_tone: args.fail_on_tone,
+            frames: 0,
+            frequencies: args.tone_frequencies.clone(),
+            head_frames: (args.tone_head * sample_rate) as usize,
+            min_frames: (args.tone_min_duration * sample_rate) as usize,
+            mix: Vec::with_capacity(block_frames),
+            require_tone: args.require_tone,
+            sample_rate: spec.sample_rate,
+            segments: Vec::new(),
+            tolerance: args.tone_tolerance,
+        }
+    }
+
+    /// Matches the finished block against the configured frequencies, returning the tone
+    /// index, measured frequency and level.
+    fn detect(&self) -> Option<(usize, f64, f64)> {
+        let sample_rate = self.sample_rate as f64;
+        let measured = zero_crossing_frequency(&self.mix, sample_rate)?;
+
+        let (tone, distance) = self
+            .frequencies
+            .iter()
+            .map(|frequency| (frequency - measured).abs())
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))?;
+
+        if distance > self.tolerance
+            || goertzel_purity(&self.mix, measured, sample_rate) < PURITY_THRESHOLD
+        {
+            return None;
+        }
+
+        let rms = self
+            .channel_squares
+            .iter()
+            .fold(0.0f64, |max, sum| max.max(*sum))
+            / self.mix.len() as f64;
+        let level = 20.0 * (2.0 * rms).sqrt().log10();
+
+        Some((tone, measured, level))
+    }
+
+    fn finish_block(&mut self) {
+        if self.mix.is_empty() {
+            return;
+        }
+
+        let detected = self.detect();
+        let start = self.frames - self.mix.len();
+
+        match (self.current.as_mut(), detected) {
+            (Some(segment), Some((tone, frequency, level))) if segment.tone == tone => {
+                segment.end = self.frames;
+                segment.frequency_sum += frequency;
+                segment.level_sum += level;
+                segment.blocks += 1;
+            }
+            (_, detected) => {
+                self.close_segment();
+                self.current = detected.map(|(tone, frequency, level)| InternalSegment {
+                    start,
+                    end: self.frames,
+                    tone,
+                    frequency_sum: frequency,
+                    level_sum: level,
+                    blocks: 1,
+                });
+            }
+        }
+
+        self.mix.clear();
+        self.channel_squares.fill(0.0);
+    }
+
+    fn close_segment(&mut self) {
+        if let Some(segment) = self.current.take()
+            && segment.end - segment.start >= self.min_frames
+        {
+            self.segments.push(segment);
+        }
+    }
+
+    fn is_head(&self, segment: &InternalSegment) -> bool {
+        segment.start <= self.head_frames
+    }
+}
+
+impl Analyser for ToneAnalyser {
+    fn name(&self) -> &'static str {
+        "tones"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        let mut sum = 0.0;
+        for (square, sample) in self.channel_squares.iter_mut().zip(frame.iter()) {
+            let value = *sample as f64 / FULL_SCALE;
+            *square += value * value;
+            sum += value;
+        }
+
+        self.mix.push(sum / frame.len().max(1) as f64);
+        self.frames += 1;
+
+        if self.mix.len() >= self.block_frames {
+            self.finish_block();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        self.finish_block();
+        self.close_segment();
+
+        let segments: Vec<ToneSegment> = self
+            .segments
+            .iter()
+            .map(|seg| {
+                let duration_samples = seg.end - seg.start;
+                ToneSegment {
+                    start: seg.start as f32 / self.sample_rate as f32,
+                    end: seg.end as f32 / self.sample_rate as f32,
+                    duration: duration_samples as f32 / self.sample_rate as f32,
+                    start_sample: seg.start,
+                    end_sample: seg.end,
+                    duration_samples,
+                    frequency: self.frequencies[seg.tone],
+                    measured_frequency: seg.frequency_sum / seg.blocks as f64,
+                    level: seg.level_sum / seg.blocks as f64,
+                    head: self.is_head(seg),
+                }
+            })
+            .collect();
+
+        let mut result = AnalysisResult::new(Analysis {
+            tones: Some(ToneAnalysis {
+                results: segments,
+                frequencies: self.frequencies.clone(),
+                tolerance: self.tolerance,
+            }),
+            ..Analysis::default()
+        });
+
+        if self.fail_on_tone && self.segments.iter().any(|s| !self.is_head(s)) {
+            result.fail(
+                crate::ERR_TONE_CHECK,
+                "unexpected tone in programme".to_string(),
+            );
+        }
+
+        if self.require_tone && !self.segments.iter().any(|s| self.is_head(s)) {
+            result.fail(
+                crate::ERR_TONE_CHECK,
+                format!(
+                    "no line-up tone in the first {}",
+                    frame_to_time(self.head_frames, self.sample_rate)
+                ),
+            );
+        }
+
+        Ok(result)
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<ToneAnalysis>("tones", generator)]
+    }
+}
@@ -1,22 +1,41 @@
+use schemars::{JsonSchema, SchemaGenerator};
 use serde::Serialize;
-use wavers::{Samples, Wav};
 
-use super::Analyser;
-use crate::{cli::Cli, debug, output, output::frame_to_time};
+use super::{Analyser, AnalysisResult};
+use crate::{
+    Result,
+    cli::AnalyseArgs,
+    debug,
+    findings::{Finding, Kind},
+    output,
+    output::frame_to_time,
+    progress,
+    schema::SchemaEntry,
+    stream::StreamSpec,
+};
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+/// Runs at or above this share of full scale are clipping rather than a stuck converter.
+const CLIP_LEVEL: f64 = 0.999;
 
 #[derive(Debug, Clone)]
 pub struct DetectorState {
     pub underrun_count: usize,
     pub underrun_prev_index: usize,
+    pub stuck_value: i32,
+    pub stuck_count: usize,
 }
 
 struct InternalSegment {
     start: usize,
     end: Option<usize>,
     channel: usize,
+    /// Number of underruns merged into this one
+    runs: usize,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct UnderrunSegment {
     pub start: f32,
     pub end: f32,
@@ -28,40 +47,226 @@ pub struct UnderrunSegment {
     #[serde(rename = "durationSamples")]
     pub duration_samples: usize,
     pub channel: usize,
+    /// Number of underruns merged into this segment
+    pub runs: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct StuckSegment {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+    pub channel: usize,
+    /// The repeated sample value as a fraction of full scale
+    pub value: f64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct UnderrunAnalysis {
+    pub results: Vec<UnderrunSegment>,
+    /// Minimum number of repeated samples counted as an underrun
+    pub threshold: usize,
+    /// Underruns closer than this many samples on a channel are merged
+    #[serde(rename = "mergeGap")]
+    pub merge_gap: usize,
+    /// Number of segments after merging
+    pub count: usize,
+    /// Total duration of all segments in seconds
+    #[serde(rename = "totalDuration")]
+    pub total_duration: f32,
+    #[serde(rename = "totalDurationSamples")]
+    pub total_duration_samples: usize,
+    #[serde(rename = "maxUnderruns", skip_serializing_if = "Option::is_none")]
+    pub max_underruns: Option<usize>,
+    /// Maximum allowed total duration in seconds
+    #[serde(rename = "maxDuration", skip_serializing_if = "Option::is_none")]
+    pub max_duration: Option<f64>,
+    /// Runs of a constant non-zero sample value, only with `--stuck-sample`
+    #[serde(rename = "stuckSegments", skip_serializing_if = "Option::is_none")]
+    pub stuck_segments: Option<Vec<StuckSegment>>,
+}
+
+struct InternalStuckSegment {
+    start: usize,
+    end: usize,
+    channel: usize,
+    value: i32,
 }
 
 pub struct UnderrunAnalyser {
-    contains_underrun: bool,
+    max_duration: Option<f64>,
+    max_underruns: Option<usize>,
+    merge_gap: usize,
     num_frames: usize,
+    /// Last underrun of each channel, held back while the next one could still be merged
+    pending: Vec<Option<InternalSegment>>,
     states: Vec<DetectorState>,
     sample_rate: i32,
     samples: usize,
     segments: Vec<InternalSegment>,
+    /// Runs of a constant non-zero value, `None` without `--stuck-sample`
+    stuck: Option<Vec<InternalStuckSegment>>,
+    /// Segments and stuck runs already returned by `take_events`
+    taken: (usize, usize),
 }
 
 impl UnderrunAnalyser {
-    pub fn new(args: &Cli, wav: &Wav<i32>) -> Self {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Self {
         Self {
-            contains_underrun: false,
-            num_frames: wav.n_samples(),
+            max_duration: args.max_underrun_duration,
+            max_underruns: args.max_underruns,
+            merge_gap: args.underrun_merge_gap,
+            num_frames: spec.num_frames,
+            pending: (0..spec.channels).map(|_| None).collect(),
             states: vec![
                 DetectorState {
                     underrun_count: 0,
                     underrun_prev_index: 0,
+                    stuck_value: 0,
+                    stuck_count: 0,
                 };
-                wav.n_channels().into()
+                spec.channels.into()
             ],
-            sample_rate: wav.wav_spec().1.fmt_chunk.sample_rate,
+            sample_rate: spec.sample_rate,
             samples: args.samples,
             segments: Vec::new(),
+            stuck: args.stuck_sample.then(Vec::new),
+            taken: (0, 0),
+        }
+    }
+
+    /// Ends the run of identical samples on `channel` at `end`, and reports it if it is long
+    /// enough and neither zero, which is an underrun, nor clipping.
+    fn end_stuck(&mut self, label: &str, channel: usize, end: usize) {
+        let state = &self.states[channel];
+        let (value, count) = (state.stuck_value, state.stuck_count);
+        let Some(stuck) = self.stuck.as_mut() else {
+            return;
+        };
+
+        let level = value as f64 / FULL_SCALE;
+        if value == 0 || count < self.samples || level.abs() >= CLIP_LEVEL {
+            return;
+        }
+
+        let start = end - count;
+        progress::event("stuck");
+        output!(
+            "[{}] STUCK        : CH:{} - {} samples ({:06.3}s) at {:+.6} {} -> {}",
+            label,
+            channel,
+            count,
+            count as f32 / self.sample_rate as f32,
+            level,
+            frame_to_time(start, self.sample_rate),
+            frame_to_time(end, self.sample_rate),
+        );
+
+        stuck.push(InternalStuckSegment {
+            start,
+            end,
+            channel,
+            value,
+        });
+    }
+
+    /// Records an underrun of `channel` from `start` to `end`, merged into the pending one
+    /// if it ended less than `--underrun-merge-gap` samples before.
+    fn add(&mut self, label: &str, channel: usize, start: usize, end: usize) {
+        if let Some(pending) = self.pending[channel].as_mut()
+            && pending
+                .end
+                .is_some_and(|pending_end| start - pending_end < self.merge_gap)
+        {
+            pending.end = Some(end);
+            pending.runs += 1;
+            return;
+        }
+
+        self.flush(label, channel);
+        self.pending[channel] = Some(InternalSegment {
+            start,
+            end: Some(end),
+            channel,
+            runs: 1,
+        });
+    }
+
+    /// Reports the pending underrun of `channel`.
+    fn flush(&mut self, label: &str, channel: usize) {
+        let Some(segment) = self.pending[channel].take() else {
+            return;
+        };
+        let end = segment.end.unwrap_or(self.num_frames);
+        let length = end - segment.start;
+
+        progress::event("underrun");
+        output!(
+            "[{}] UNDERRUN     : CH:{} - {} samples ({:06.3}s) {} -> {}{}",
+            label,
+            channel,
+            length,
+            length as f32 / self.sample_rate as f32,
+            frame_to_time(segment.start, self.sample_rate),
+            frame_to_time(end, self.sample_rate),
+            if segment.runs > 1 {
+                format!(" ({} merged)", segment.runs)
+            } else {
+                String::new()
+            }
+        );
+
+        self.segments.push(segment);
+    }
+
+    fn total_samples(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|seg| seg.end.unwrap_or(self.num_frames) - seg.start)
+            .sum()
+    }
+
+    /// Whether the underruns are more than `--max-underruns` or `--max-underrun-duration`
+    /// allow, or any at all without either.
+    fn exceeded(&self) -> bool {
+        let duration = self.total_samples() as f64 / self.sample_rate as f64;
+
+        match (self.max_underruns, self.max_duration) {
+            (None, None) => !self.segments.is_empty(),
+            (max_underruns, max_duration) => {
+                max_underruns.is_some_and(|max| self.segments.len() > max)
+                    || max_duration.is_some_and(|max| duration > max)
+            }
         }
     }
 }
 
 impl Analyser for UnderrunAnalyser {
-    fn analyse(&mut self, label: &str, frame_counter: usize, frame: &Samples<i32>) {
+    fn name(&self) -> &'static str {
+        "underruns"
+    }
+
+    fn analyse(&mut self, label: &str, frame_counter: usize, frame: &[i32]) {
         for (channel_index, sample) in frame.iter().enumerate() {
             assert!(channel_index < self.states.len());
+            let state = &mut self.states[channel_index];
+            if self.stuck.is_some() {
+                if *sample == state.stuck_value {
+                    state.stuck_count += 1;
+                } else {
+                    self.end_stuck(label, channel_index, frame_counter);
+                    let state = &mut self.states[channel_index];
+                    state.stuck_value = *sample;
+                    state.stuck_count = 1;
+                }
+            }
+
             let state = &mut self.states[channel_index];
             if *sample == 0 {
                 if (frame_counter - state.underrun_prev_index) > 1 {
@@ -77,70 +282,50 @@ impl Analyser for UnderrunAnalyser {
 
                 state.underrun_prev_index = frame_counter;
             } else {
-                if state.underrun_count >= self.samples {
-                    self.contains_underrun = true;
-                    let underrun_start =
-                        frame_to_time(frame_counter - state.underrun_count, self.sample_rate);
-                    let underrun_end = frame_to_time(frame_counter, self.sample_rate);
-                    let underrun_duration = state.underrun_count as f32 / self.sample_rate as f32;
-                    output!(
-                        "[{}] UNDERRUN     : CH:{} - {} samples ({:06.3}s) {} -> {}",
-                        label,
-                        channel_index,
-                        state.underrun_count,
-                        underrun_duration,
-                        underrun_start,
-                        underrun_end
-                    );
-
-                    self.segments.push(InternalSegment {
-                        start: frame_counter - state.underrun_count,
-                        end: Some(frame_counter),
-                        channel: channel_index,
-                    });
-                }
+                let count = state.underrun_count;
                 state.underrun_count = 0;
+
+                if count >= self.samples {
+                    self.add(label, channel_index, frame_counter - count, frame_counter);
+                }
+            }
+
+            // Nothing can be merged into the pending underrun any more
+            if self.pending[channel_index]
+                .as_ref()
+                .and_then(|pending| pending.end)
+                .is_some_and(|end| frame_counter - end >= self.merge_gap)
+            {
+                self.flush(label, channel_index);
             }
         }
     }
 
-    fn finish(&mut self, label: &str) -> u8 {
-        let mut contains_underrun = self.contains_underrun;
-        for (channel_index, state) in self.states.iter().enumerate() {
-            if state.underrun_count >= self.samples {
-                contains_underrun = true;
-                let underrun_start =
-                    frame_to_time(self.num_frames - state.underrun_count, self.sample_rate);
-                let underrun_end = frame_to_time(self.num_frames, self.sample_rate);
-                let underrun_duration = state.underrun_count as f32 / self.sample_rate as f32;
-                output!(
-                    "[{}] UNDERRUN     : CH:{} - {} samples ({:06.3}s) {} -> {}",
-                    &label,
+    fn finish(&mut self, label: &str) -> Result<AnalysisResult> {
+        for channel_index in 0..self.states.len() {
+            let count = self.states[channel_index].underrun_count;
+            if count >= self.samples {
+                self.add(
+                    label,
                     channel_index,
-                    state.underrun_count,
-                    underrun_duration,
-                    underrun_start,
-                    underrun_end
+                    self.num_frames - count,
+                    self.num_frames,
                 );
-
-                self.segments.push(InternalSegment {
-                    start: self.num_frames - state.underrun_count,
-                    end: Some(self.num_frames),
-                    channel: channel_index,
-                });
             }
+
+            self.flush(label, channel_index);
+            self.end_stuck(label, channel_index, self.num_frames);
         }
 
-        if contains_underrun {
-            crate::ERR_CONTAINS_UNDERRUN
-        } else {
-            0
+        // Stuck converters aren't covered by the underrun limits
+        let stuck = self.stuck.as_ref().is_some_and(|stuck| !stuck.is_empty());
+        let mut result = AnalysisResult::default();
+        if self.exceeded() || stuck {
+            result.flags = crate::ERR_CONTAINS_UNDERRUN;
         }
-    }
 
-    fn json(&self) -> Vec<(String, serde_json::Value)> {
-        if self.segments.is_empty() {
-            return Vec::new();
+        if self.segments.is_empty() && !stuck {
+            return Ok(result);
         }
 
         let segments: Vec<UnderrunSegment> = self
@@ -157,15 +342,78 @@ impl Analyser for UnderrunAnalyser {
                     end_sample: end_frame,
                     duration_samples,
                     channel: seg.channel,
+                    runs: seg.runs,
                 }
             })
             .collect();
 
-        let analysis = serde_json::json!({
-            "results": segments,
-            "threshold": self.samples,
+        let total_samples = self.total_samples();
+
+        result.analysis.underruns = Some(UnderrunAnalysis {
+            results: segments,
+            threshold: self.samples,
+            merge_gap: self.merge_gap,
+            count: self.segments.len(),
+            total_duration: total_samples as f32 / self.sample_rate as f32,
+            total_duration_samples: total_samples,
+            max_underruns: self.max_underruns,
+            max_duration: self.max_duration,
+            stuck_segments: self.stuck.as_ref().map(|stuck| {
+                stuck
+                    .iter()
+                    .map(|seg| StuckSegment {
+                        start: seg.start as f32 / self.sample_rate as f32,
+                        end: seg.end as f32 / self.sample_rate as f32,
+                        duration: (seg.end - seg.start) as f32 / self.sample_rate as f32,
+                        start_sample: seg.start,
+                        end_sample: seg.end,
+                        duration_samples: seg.end - seg.start,
+                        channel: seg.channel,
+                        value: seg.value as f64 / FULL_SCALE,
+                    })
+                    .collect()
+            }),
         });
 
-        vec![("underruns".to_string(), analysis)]
+        Ok(result)
+    }
+
+    fn stop(&mut self, num_frames: usize) {
+        self.num_frames = num_frames;
+    }
+
+    fn take_events(&mut self) -> Vec<Finding> {
+        let stuck = self.stuck.as_deref().unwrap_or_default();
+        let mut events: Vec<Finding> = self.segments[self.taken.0..]
+            .iter()
+            .map(|segment| {
+                Finding::new(
+                    Kind::Underrun,
+                    Some(segment.channel),
+                    segment.start,
+                    segment.end.unwrap_or(self.num_frames),
+                )
+            })
+            .chain(stuck[self.taken.1..].iter().map(|segment| {
+                Finding::new(
+                    Kind::StuckSample,
+                    Some(segment.channel),
+                    segment.start,
+                    segment.end,
+                )
+            }))
+            .collect();
+        events.sort_by_key(|event| event.start);
+        self.taken = (self.segments.len(), stuck.len());
+
+        events
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        // Only written when underruns or stuck runs were found
+        vec![SchemaEntry::optional::<UnderrunAnalysis>(
+            "underruns",
+            generator,
+        )]
     }
 }
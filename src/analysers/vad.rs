@@ -0,0 +1,407 @@
+use std::{
+    collections::VecDeque,
+    f64::consts::{FRAC_1_SQRT_2, PI},
+};
+
+use ebur128::{EbuR128, Error as EbuR128Error, Mode};
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::Serialize;
+
+use super::{
+    Analyser, AnalysisResult,
+    tone::{goertzel_purity, zero_crossing_frequency},
+};
+use crate::{
+    Error, Result, cli::AnalyseArgs, json::Analysis, layout::loudness_meter, schema::SchemaEntry,
+    stream::StreamSpec, warn,
+};
+
+/// Length of the detection blocks in seconds.
+const BLOCK_SECONDS: f64 = 0.02;
+
+/// Band that holds most of the energy of speech, in Hz.
+const BAND_LOW: f64 = 100.0;
+const BAND_HIGH: f64 = 4000.0;
+
+/// Share of the block energy that has to sit in the speech band.
+const MIN_BAND_RATIO: f64 = 0.7;
+
+/// Blocks with more of their energy at a single frequency are tones, not speech.
+const MAX_PURITY: f64 = 0.9;
+
+/// Blocks quieter than this are never speech, in dBFS.
+const MIN_LEVEL_DB: f64 = -50.0;
+
+/// The noise floor is the quietest of this many previous blocks (1 second).
+const FLOOR_BLOCKS: usize = 50;
+
+/// Level above the noise floor a block needs to count as speech, in dB.
+const FLOOR_MARGIN_DB: f64 = 10.0;
+
+/// Consecutive speech blocks that start a segment (60 ms), so clicks don't count.
+const ONSET_BLOCKS: usize = 3;
+
+/// Non-speech blocks a segment is held open for (300 ms), bridging the pauses between words.
+const HANGOVER_BLOCKS: usize = 15;
+
+const FLOOR_DB: f64 = -120.0;
+
+const FULL_SCALE: f64 = 2147483648.0;
+
+/// Second order filter from the RBJ audio EQ cookbook, with a Butterworth Q.
+pub(super) struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    pub(super) fn new(frequency: f64, sample_rate: f64, highpass: bool) -> Self {
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let alpha = w0.sin() / (2.0 * FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+
+        let b = if highpass {
+            [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0]
+        } else {
+            [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0]
+        };
+
+        Self {
+            b: b.map(|value| value / a0),
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    pub(super) fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+struct InternalSegment {
+    start: usize,
+    end: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct VadSegment {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(rename = "durationSamples")]
+    pub duration_samples: usize,
+}
+
+/// Integrated loudness of the speech against that of the whole programme.
+#[derive(Serialize, JsonSchema)]
+pub struct DialogueLoudness {
+    /// Integrated loudness of the speech blocks in LUFS, null without speech
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub dialogue: f64,
+    /// Integrated loudness of the whole file in LUFS, null for silence
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub programme: f64,
+    /// Dialogue minus programme loudness in LU, null if either is
+    #[schemars(extend("type" = ["number", "null"]))]
+    pub difference: f64,
+    /// Seconds of speech blocks the dialogue loudness was measured over
+    #[serde(rename = "speechDuration")]
+    pub speech_duration: f32,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct VadAnalysis {
+    /// Speech segments
+    pub results: Vec<VadSegment>,
+    /// Total speech in seconds
+    #[serde(rename = "speechDuration")]
+    pub speech_duration: f32,
+    /// Share of the file that is speech in percent
+    #[serde(rename = "speechPercentage")]
+    pub speech_percentage: f64,
+    #[serde(
+        rename = "minSpeechPercentage",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min_speech_percentage: Option<f64>,
+    /// Dialogue-gated loudness, with --dialogue-loudness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dialogue: Option<DialogueLoudness>,
+}
+
+/// Meters for `--dialogue-loudness`: every block goes to the programme meter, and the blocks
+/// judged speech also to the dialogue meter.
+struct DialogueMeter {
+    block: Vec<i32>,
+    dialogue: EbuR128,
+    programme: EbuR128,
+    speech_frames: usize,
+}
+
+impl DialogueMeter {
+    fn new(spec: &StreamSpec, block_frames: usize) -> Result<Self> {
+        let meter = || {
+            loudness_meter(spec, Mode::I).map_err(|err: EbuR128Error| Error::analyser("vad", err))
+        };
+
+        Ok(Self {
+            block: Vec::with_capacity(block_frames * spec.channels as usize),
+            dialogue: meter()?,
+            programme: meter()?,
+            speech_frames: 0,
+        })
+    }
+
+    fn finish_block(&mut self, speech: bool, block_frames: usize) {
+        add_frames(&mut self.programme, &self.block);
+        if speech {
+            add_frames(&mut self.dialogue, &self.block);
+            self.speech_frames += block_frames;
+        }
+        self.block.clear();
+    }
+
+    fn loudness(&mut self, sample_rate: f32) -> DialogueLoudness {
+        // A trailing partial block is too short to judge, but part of the programme
+        self.finish_block(false, 0);
+
+        let dialogue = self.dialogue.loudness_global().unwrap_or(f64::NEG_INFINITY);
+        let programme = self
+            .programme
+            .loudness_global()
+            .unwrap_or(f64::NEG_INFINITY);
+        let difference = if dialogue.is_finite() && programme.is_finite() {
+            dialogue - programme
+        } else {
+            f64::NAN
+        };
+
+        DialogueLoudness {
+            dialogue,
+            programme,
+            difference,
+            speech_duration: self.speech_frames as f32 / sample_rate,
+        }
+    }
+}
+
+fn add_frames(meter: &mut EbuR128, frames: &[i32]) {
+    if let Err(err) = meter.add_frames_i32(frames) {
+        warn!("error adding frame to loudness measurement: {:?}", &err);
+    }
+}
+
+/// Voice activity detection on the channel mix, optionally with the loudness of the speech. A block is speech when it is well above the
+/// recent noise floor, most of its energy is in the speech band and it isn't a pure tone;
+/// segments need a few speech blocks to start and are held open over short pauses.
+pub struct VadAnalyser {
+    band_energy: f64,
+    block_frames: usize,
+    current: Option<InternalSegment>,
+    dialogue: Option<DialogueMeter>,
+    energy: f64,
+    frames: usize,
+    highpass: Biquad,
+    levels: VecDeque<f64>,
+    mix: Vec<f64>,
+    lowpass: Biquad,
+    min_speech_percentage: Option<f64>,
+    num_frames: usize,
+    run: usize,
+    sample_rate: i32,
+    segments: Vec<InternalSegment>,
+    silent: usize,
+}
+
+impl VadAnalyser {
+    pub fn new(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Self> {
+        let sample_rate = spec.sample_rate as f64;
+        let block_frames = ((sample_rate * BLOCK_SECONDS) as usize).max(1);
+        let dialogue = if args.dialogue_loudness {
+            Some(DialogueMeter::new(spec, block_frames)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            band_energy: 0.0,
+            block_frames,
+            current: None,
+            dialogue,
+            energy: 0.0,
+            frames: 0,
+            highpass: Biquad::new(BAND_LOW, sample_rate, true),
+            levels: VecDeque::with_capacity(FLOOR_BLOCKS + 1),
+            mix: Vec::with_capacity(block_frames),
+            // Low sample rates cut off the top of the band
+            lowpass: Biquad::new(BAND_HIGH.min(0.45 * sample_rate), sample_rate, false),
+            min_speech_percentage: args.min_speech_percentage,
+            num_frames: spec.num_frames,
+            run: 0,
+            sample_rate: spec.sample_rate,
+            segments: Vec::new(),
+            silent: 0,
+        })
+    }
+
+    fn is_speech(&self, level: f64) -> bool {
+        let floor = self.levels.iter().fold(f64::INFINITY, |min, v| min.min(*v));
+
+        level >= MIN_LEVEL_DB
+            && (self.levels.is_empty() || level >= floor + FLOOR_MARGIN_DB)
+            && self.band_energy >= MIN_BAND_RATIO * self.energy
+            && !self.is_tone()
+    }
+
+    fn is_tone(&self) -> bool {
+        let sample_rate = self.sample_rate as f64;
+
+        zero_crossing_frequency(&self.mix, sample_rate).is_some_and(|frequency| {
+            goertzel_purity(&self.mix, frequency, sample_rate) >= MAX_PURITY
+        })
+    }
+
+    fn finish_block(&mut self) {
+        let level = (10.0 * (self.energy / self.block_frames as f64).log10()).max(FLOOR_DB);
+        let speech = self.is_speech(level);
+        if let Some(dialogue) = &mut self.dialogue {
+            dialogue.finish_block(speech, self.block_frames);
+        }
+
+        self.levels.push_back(level);
+        if self.levels.len() > FLOOR_BLOCKS {
+            self.levels.pop_front();
+        }
+        self.energy = 0.0;
+        self.band_energy = 0.0;
+        self.mix.clear();
+
+        if speech {
+            self.run += 1;
+            self.silent = 0;
+
+            match self.current.as_mut() {
+                Some(segment) => segment.end = self.frames,
+                None if self.run >= ONSET_BLOCKS => {
+                    self.current = Some(InternalSegment {
+                        start: self.frames - self.run * self.block_frames,
+                        end: self.frames,
+                    });
+                }
+                None => {}
+            }
+        } else {
+            self.run = 0;
+            self.silent += 1;
+
+            if self.silent > HANGOVER_BLOCKS {
+                self.close_segment();
+            }
+        }
+    }
+
+    fn close_segment(&mut self) {
+        if let Some(segment) = self.current.take() {
+            self.segments.push(segment);
+        }
+    }
+
+    fn speech_frames(&self) -> usize {
+        self.segments.iter().map(|seg| seg.end - seg.start).sum()
+    }
+
+    fn speech_percentage(&self) -> f64 {
+        self.speech_frames() as f64 / self.num_frames.max(1) as f64 * 100.0
+    }
+}
+
+impl Analyser for VadAnalyser {
+    fn name(&self) -> &'static str {
+        "vad"
+    }
+
+    fn analyse(&mut self, _label: &str, _frame_counter: usize, frame: &[i32]) {
+        if let Some(dialogue) = &mut self.dialogue {
+            dialogue.block.extend_from_slice(frame);
+        }
+
+        let sum: f64 = frame.iter().map(|sample| *sample as f64 / FULL_SCALE).sum();
+        let value = sum / frame.len().max(1) as f64;
+        let band = self.lowpass.process(self.highpass.process(value));
+
+        self.mix.push(value);
+        self.energy += value * value;
+        self.band_energy += band * band;
+        self.frames += 1;
+
+        if self.frames.is_multiple_of(self.block_frames) {
+            self.finish_block();
+        }
+    }
+
+    fn finish(&mut self, _label: &str) -> Result<AnalysisResult> {
+        // A trailing partial block is too short to judge
+        self.close_segment();
+
+        let sample_rate = self.sample_rate as f32;
+
+        let segments: Vec<VadSegment> = self
+            .segments
+            .iter()
+            .map(|seg| {
+                let duration_samples = seg.end - seg.start;
+                VadSegment {
+                    start: seg.start as f32 / sample_rate,
+                    end: seg.end as f32 / sample_rate,
+                    duration: duration_samples as f32 / sample_rate,
+                    start_sample: seg.start,
+                    end_sample: seg.end,
+                    duration_samples,
+                }
+            })
+            .collect();
+
+        let percentage = self.speech_percentage();
+        let mut result = AnalysisResult::new(Analysis {
+            vad: Some(VadAnalysis {
+                results: segments,
+                speech_duration: self.speech_frames() as f32 / sample_rate,
+                speech_percentage: percentage,
+                min_speech_percentage: self.min_speech_percentage,
+                dialogue: self
+                    .dialogue
+                    .as_mut()
+                    .map(|dialogue| dialogue.loudness(sample_rate)),
+            }),
+            ..Analysis::default()
+        });
+
+        if let Some(min) = self.min_speech_percentage
+            && percentage < min
+        {
+            result.fail(
+                crate::ERR_CONTAINS_SILENCE,
+                format!("less than {}% speech", min),
+            );
+        }
+
+        Ok(result)
+    }
+
+    fn stop(&mut self, num_frames: usize) {
+        self.num_frames = num_frames;
+    }
+
+    fn schema(&self, generator: &mut SchemaGenerator) -> Vec<SchemaEntry> {
+        vec![SchemaEntry::required::<VadAnalysis>("vad", generator)]
+    }
+}
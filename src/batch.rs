@@ -0,0 +1,230 @@
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::to_string_pretty;
+
+use crate::{
+    EXIT_BITS, Error, Result,
+    cli::AnalyseArgs,
+    metrics::MetricsTarget,
+    output,
+    paths::{self, NAME},
+    stems::Stems,
+};
+
+/// Version of the `--batch-summary` format, bumped like the `SCHEMA_VERSION` of the JSON
+/// output of a file. 2.0.0 renamed `session` to `stems`.
+pub const BATCH_SCHEMA_VERSION: &str = "2.0.0";
+
+#[derive(Serialize)]
+pub struct BatchFile {
+    pub input: String,
+    /// Return code of the file, absent if it failed
+    #[serde(rename = "exitCode", skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<u8>,
+    /// Names of the exit code bits the file tripped
+    pub bits: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BitCount {
+    pub name: &'static str,
+    pub bit: u8,
+    pub files: usize,
+}
+
+#[derive(Serialize)]
+pub struct BatchSummary {
+    /// `BATCH_SCHEMA_VERSION`, not the one of the JSON output of the files
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: &'static str,
+    pub files: Vec<BatchFile>,
+    pub counts: Vec<BitCount>,
+    pub failed: usize,
+    #[serde(rename = "minFiles")]
+    pub min_files: u32,
+    #[serde(rename = "exitCode")]
+    pub exit_code: u8,
+    /// Cross-file checks of `--stems`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stems: Option<Stems>,
+}
+
+/// Collects the return codes of the files of a batch.
+pub struct Batch {
+    files: Vec<BatchFile>,
+    first_error: Option<u8>,
+    min_files: u32,
+}
+
+/// Analysis options for one file of a batch, with `{name}` in output paths replaced by the
+/// file name of the input.
+pub fn file_args(args: &AnalyseArgs, input: &Path) -> Result<AnalyseArgs> {
+    let name = input.file_stem().unwrap_or(input.as_os_str());
+
+    let mut file_args = args.clone();
+    file_args.input = vec![input.to_path_buf()];
+    // The batch writes one JUnit report for all files
+    file_args.junit = None;
+
+    for path in [
+        &mut file_args.json,
+        &mut file_args.report,
+        &mut file_args.loudness_graph,
+        &mut file_args.fft_file,
+        &mut file_args.fft_vis,
+        &mut file_args.peaks_file,
+        &mut file_args.meter_file,
+        &mut file_args.cue,
+        &mut file_args.chapters,
+        &mut file_args.write_cues,
+        &mut file_args.edl,
+        &mut file_args.srt,
+        &mut file_args.labels,
+        &mut file_args.issues_map,
+        &mut file_args.timeseries,
+    ]
+    .into_iter()
+    .flatten()
+    .chain(file_args.metrics.as_mut().and_then(MetricsTarget::path_mut))
+    {
+        match paths::replace_name(path, name) {
+            Some(replaced) => *path = replaced,
+            None if args.input.len() > 1 => {
+                return Err(Error::Config(format!(
+                    "Output path {} needs a {NAME} placeholder when analysing several files",
+                    path.display()
+                )));
+            }
+            None => {}
+        }
+    }
+
+    Ok(file_args)
+}
+
+impl Batch {
+    pub fn new(args: &AnalyseArgs) -> Self {
+        Self {
+            files: Vec::new(),
+            first_error: None,
+            min_files: args.batch_min_files,
+        }
+    }
+
+    pub fn add(&mut self, input: &Path, result: &Result<u8>) {
+        let file = match result {
+            Ok(code) => BatchFile {
+                input: input.display().to_string(),
+                exit_code: Some(*code),
+                bits: EXIT_BITS
+                    .iter()
+                    .filter(|(bit, _)| code & bit != 0)
+                    .map(|(_, name)| *name)
+                    .collect(),
+                error: None,
+            },
+            Err(err) => {
+                self.first_error.get_or_insert(err.exit_code());
+
+                BatchFile {
+                    input: input.display().to_string(),
+                    exit_code: None,
+                    bits: Vec::new(),
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+
+        self.files.push(file);
+    }
+
+    fn counts(&self) -> Vec<BitCount> {
+        EXIT_BITS
+            .iter()
+            .map(|&(bit, name)| BitCount {
+                name,
+                bit,
+                files: self
+                    .files
+                    .iter()
+                    .filter(|file| file.exit_code.is_some_and(|code| code & bit != 0))
+                    .count(),
+            })
+            .collect()
+    }
+
+    /// The exit code bits tripped by at least `--batch-min-files` files. If any file failed
+    /// the exit code of its error is returned instead.
+    pub fn exit_code(&self) -> u8 {
+        if let Some(code) = self.first_error {
+            return code;
+        }
+
+        self.counts()
+            .iter()
+            .filter(|count| count.files >= self.min_files as usize)
+            .fold(0, |code, count| code | count.bit)
+    }
+
+    pub fn summary(self, stems: Option<Stems>) -> BatchSummary {
+        let counts = self.counts();
+        let exit_code = match stems.as_ref() {
+            Some(stems) if self.first_error.is_none() => self.exit_code() | stems.exit_code(),
+            _ => self.exit_code(),
+        };
+
+        BatchSummary {
+            schema_version: BATCH_SCHEMA_VERSION,
+            failed: self
+                .files
+                .iter()
+                .filter(|file| file.error.is_some())
+                .count(),
+            files: self.files,
+            counts,
+            min_files: self.min_files,
+            exit_code,
+            stems,
+        }
+    }
+}
+
+impl BatchSummary {
+    pub fn print(&self) {
+        println!(
+            "[+] batch:            {} files, {} failed",
+            self.files.len(),
+            self.failed
+        );
+
+        for count in self.counts.iter() {
+            println!(
+                "[+] {:<18}{} files",
+                format!("{}:", count.name),
+                count.files
+            );
+        }
+
+        if let Some(stems) = self.stems.as_ref() {
+            stems.print();
+        }
+    }
+
+    pub fn write(&self, args: &AnalyseArgs) -> Result<()> {
+        let Some(path) = args.batch_summary.as_ref() else {
+            return Ok(());
+        };
+        let path = paths::output_path(&paths::in_out_dir(args, path), args.force);
+
+        let write_error = |err| Error::io("write batch summary to", &path, err);
+        let json = to_string_pretty(self).map_err(|err| write_error(err.into()))?;
+        std::fs::write(&path, json).map_err(write_error)?;
+
+        output!("Wrote batch summary to {}", path.display());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+use std::process::ExitCode;
+
+use clap::Parser;
+
+use analwave::{cli::PeaksVisArgs, error, visualize::visualize_peaks};
+
+/// Same as `analwave visualize-peaks`.
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(flatten)]
+    args: PeaksVisArgs,
+}
+
+fn main() -> ExitCode {
+    match visualize_peaks(&Cli::parse().args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
+}
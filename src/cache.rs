@@ -0,0 +1,189 @@
+//! Results of earlier runs kept in `--cache-dir`, so files that are analysed again with the
+//! same options aren't decoded again.
+//!
+//! Entries are stored as `<audio digest>/<options digest>.json`. The audio digest is the
+//! SHA-256 of the payload, so editing the metadata of a file keeps its entries. The options
+//! digest covers the version of analwave, the format of the input and every option that can
+//! change the results, but not the paths of the outputs or how much is printed.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    digest::{DigestAlgorithm, Digester},
+    input::Input,
+    json::{Results, to_json},
+    output,
+    output::init_messages,
+    paths, warn,
+};
+
+/// Version of analwave, results of other versions aren't reused.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    #[serde(rename = "returnCode")]
+    return_code: u8,
+    /// The `--json` output, as text so the numbers are written back with the same digits
+    json: String,
+}
+
+/// The entry of one input file in `--cache-dir`.
+pub struct Cache {
+    path: PathBuf,
+}
+
+/// Checks that the options can be answered from the cache, which only has the return code
+/// and the JSON output of every file.
+pub fn check_options(args: &AnalyseArgs) -> Result<()> {
+    if args.cache_dir.is_none() {
+        return Ok(());
+    }
+
+    let other_output = AnalyseArgs {
+        json: None,
+        out_dir: None,
+        batch_summary: None,
+        progress_file: None,
+        ..args.clone()
+    }
+    .file_output();
+    let option = [
+        ("--concat", !args.concat.is_empty()),
+        ("--status-line", args.status_line),
+        ("--timings", args.timings),
+        // The rest of the file isn't in the digest
+        ("--format-check", args.format_check || args.strict_container),
+    ]
+    .into_iter()
+    .find(|(_, used)| *used)
+    .map(|(option, _)| option)
+    .or(other_output);
+
+    match option {
+        Some(option) => Err(Error::Config(format!(
+            "--cache-dir only keeps the JSON output and the return code, it can't be combined with {option}"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// SHA-256 of `text` in lowercase hex.
+fn digest(text: &str) -> String {
+    let mut digester = Digester::new(DigestAlgorithm::Sha256);
+    digester.update(text.as_bytes());
+    digester.finish().value
+}
+
+impl Cache {
+    /// The entry of the input file at `path` in `--cache-dir`, `None` without the option.
+    /// Hashes the payload of the file, which is read once more for that.
+    pub fn open(args: &AnalyseArgs, path: &Path) -> Result<Option<Self>> {
+        let Some(dir) = &args.cache_dir else {
+            return Ok(None);
+        };
+
+        let (spec, audio) = Input::payload_digest(args, path)?;
+
+        // Everything that only changes where the outputs go or what is printed
+        let options = AnalyseArgs {
+            input: Vec::new(),
+            stems: Vec::new(),
+            json: None,
+            force: false,
+            out_dir: None,
+            name_template: paths::DEFAULT_NAME_TEMPLATE.to_string(),
+            batch_summary: None,
+            batch_min_files: 0,
+            no_progress: false,
+            progress_fd: None,
+            progress_file: None,
+            verbose: false,
+            quiet: false,
+            silent: false,
+            threads: 1,
+            timeout: None,
+            cache_dir: None,
+            no_cache: false,
+            ..args.clone()
+        };
+        let options = digest(&format!("{VERSION}\n{spec:?}\n{options:?}"));
+
+        Ok(Some(Self {
+            path: dir.join(audio.value).join(format!("{options}.json")),
+        }))
+    }
+
+    /// Writes the cached JSON output to `--json` and returns the cached return code, or
+    /// `None` if there is no entry or `--no-cache` asks for a new analysis. An entry that
+    /// can't be read is analysed again.
+    pub fn reuse(&self, args: &AnalyseArgs) -> Result<Option<u8>> {
+        if args.no_cache {
+            return Ok(None);
+        }
+
+        let Ok(text) = fs::read_to_string(&self.path) else {
+            return Ok(None);
+        };
+        let entry: Entry = match serde_json::from_str(&text) {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!("Ignoring the cache entry {}: {err}", self.path.display());
+                return Ok(None);
+            }
+        };
+
+        // Nothing is analysed, so the output is only set up for the messages
+        init_messages(args);
+        output!("Reused the cached results in {}", self.path.display());
+
+        if let Some(path) = &args.json {
+            fs::write(path, &entry.json)
+                .map_err(|err| Error::io("write JSON output to", path, err))?;
+
+            output!("Wrote JSON output to {}", path.display());
+        }
+
+        Ok(Some(entry.return_code))
+    }
+
+    /// Keeps the results of a complete analysis for the next run. The cache only saves time,
+    /// so an entry that can't be written is just a warning.
+    pub fn store(&self, return_code: u8, results: &Results) {
+        if results.partial || results.analysis.is_empty() {
+            return;
+        }
+
+        if let Err(err) = self.write(return_code, results) {
+            warn!(
+                "Could not write the cache entry {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+
+    fn write(&self, return_code: u8, results: &Results) -> Result<()> {
+        let write_error = |err| Error::io("write", &self.path, err);
+        let entry = Entry {
+            return_code,
+            json: to_json(results).map_err(|err| write_error(err.into()))?,
+        };
+        let json = serde_json::to_string(&entry).map_err(|err| write_error(err.into()))?;
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|err| Error::io("create", dir, err))?;
+        }
+
+        // Renamed into place, so a concurrent run never reads half an entry
+        let partial = self.path.with_extension("json.partial");
+        fs::write(&partial, json).map_err(|err| Error::io("write", &partial, err))?;
+        fs::rename(&partial, &self.path).map_err(write_error)
+    }
+}
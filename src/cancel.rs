@@ -0,0 +1,41 @@
+//! Cooperative cancellation of the analysis, on SIGINT or after `--timeout`. The frame loop
+//! stops at the next chunk and the analysers finish with what they have seen.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Instant,
+};
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Asks the running analysis, and any later one, to stop.
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
+
+/// Whether the analysis has to stop, because it was cancelled or `deadline` has passed.
+pub fn should_stop(deadline: Option<Instant>) -> bool {
+    is_cancelled() || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Cancels the analysis on the first SIGINT, a second one ends the process as usual.
+#[cfg(unix)]
+pub fn cancel_on_interrupt() {
+    extern "C" fn interrupted(_signal: libc::c_int) {
+        cancel();
+        // SAFETY: signal() is async-signal-safe, SIG_DFL restores the default action
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+    }
+
+    let handler = interrupted as extern "C" fn(libc::c_int);
+    // SAFETY: the handler only touches an atomic and calls signal()
+    unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+}
+
+/// Without signals there is nothing to handle.
+#[cfg(not(unix))]
+pub fn cancel_on_interrupt() {}
@@ -1,11 +1,95 @@
-use clap::Parser;
+use std::{ffi::OsString, net::SocketAddr, path::PathBuf, time::Duration};
+
+use serde_json::Value;
+
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, builder::Resettable};
+
+use crate::{
+    Error, Result,
+    analysers::{
+        fft::{Colormap, FftAmplitude, FftBackend, FftFormat, FftScale, FftWindow},
+        meter::MeterFormat,
+        peaks::PeaksFormat,
+    },
+    digest::DigestAlgorithm,
+    edl::EdlRate,
+    exclude::TimeRange,
+    generate::Signal,
+    input::{DownmixTarget, RawFormat},
+    layout::ChannelLayout,
+    metrics::MetricsTarget,
+    output::TimeFormat,
+    paths::DEFAULT_NAME_TEMPLATE,
+    presets::{Preset, preset_options},
+    visualize::PeaksStyle,
+};
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Cli {
-    /// The file to analyse
-    #[arg(short, long)]
-    pub input: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Analysis options, also accepted without the `analyse` subcommand
+    #[command(flatten)]
+    pub analyse: AnalyseArgs,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AnalyseArgs {
+    /// The file to analyse, several files are analysed as a batch
+    #[arg(short, long, num_args = 1.., required_unless_present_any = ["print_schema", "list_presets", "list_analysers", "stems", "concat"])]
+    pub input: Vec<PathBuf>,
+
+    /// Analyse the files as the stems of one session, as a batch that also checks they have
+    /// the same length and sample rate and start at the same time
+    #[arg(long, num_args = 2.., conflicts_with_all = ["input", "raw"])]
+    pub stems: Vec<PathBuf>,
+
+    /// Analyse the files as one continuous stream, e.g. a recording split into chunks. They
+    /// have to be of the same format, positions are counted from the start of the first
+    #[arg(long, num_args = 2.., conflicts_with_all = ["input", "stems", "batch_summary", "write_cues"])]
+    pub concat: Vec<PathBuf>,
+
+    /// Read the input as headerless interleaved PCM (needs --rate, --channels and --format)
+    #[arg(long, default_value_t = false, requires_all = ["rate", "channels", "format"])]
+    pub raw: bool,
+
+    /// Sample rate of raw input in Hz
+    #[arg(long, requires = "raw", value_parser = clap::value_parser!(i32).range(1..))]
+    pub rate: Option<i32>,
+
+    /// Number of channels of raw input
+    #[arg(long, requires = "raw", value_parser = clap::value_parser!(u16).range(1..))]
+    pub channels: Option<u16>,
+
+    /// Sample format of raw input
+    #[arg(long, value_enum, requires = "raw")]
+    pub format: Option<RawFormat>,
+
+    /// Resample the input to this rate in Hz before analysis
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub resample: Option<i32>,
+
+    /// Mix multichannel input down to mono or stereo before analysis (ITU-R BS.775
+    /// coefficients, the LFE is left out)
+    #[arg(long, value_enum)]
+    pub downmix: Option<DownmixTarget>,
+
+    /// Apply this gain in dB to the samples before analysis, e.g. to see whether a file would
+    /// clip after normalization. Samples past full scale are clipped
+    #[arg(long, value_name = "DB", allow_hyphen_values = true)]
+    pub gain_db: Option<f64>,
+
+    /// Defaults for a delivery spec, explicit options override them (see --list-presets)
+    #[arg(long, value_enum)]
+    pub preset: Option<Preset>,
 
     /// Detect underruns
     #[arg(short, long, default_value_t = false)]
@@ -15,6 +99,30 @@ pub struct Cli {
     #[arg(long, default_value_t = 16)]
     pub samples: usize,
 
+    /// Merge underruns on a channel that are fewer than this many samples apart
+    #[arg(long, default_value_t = 0)]
+    pub underrun_merge_gap: usize,
+
+    /// Only return the underrun error code if there are more underruns than this
+    #[arg(long)]
+    pub max_underruns: Option<usize>,
+
+    /// Only return the underrun error code if the underruns add up to more seconds than this
+    #[arg(long)]
+    pub max_underrun_duration: Option<f64>,
+
+    /// Also detect runs of a constant non-zero sample value, as left by a stuck converter
+    #[arg(long, default_value_t = false, requires = "underrun")]
+    pub stuck_sample: bool,
+
+    /// Detect discontinuities (single-sample jumps from dropped buffers)
+    #[arg(long, default_value_t = false)]
+    pub discontinuity: bool,
+
+    /// Discontinuity threshold, in dB of the prediction error above its local RMS
+    #[arg(long, default_value_t = 30.0)]
+    pub discontinuity_threshold: f64,
+
     /// Detect silence
     #[arg(short, long, default_value_t = false)]
     pub silence: bool,
@@ -24,33 +132,214 @@ pub struct Cli {
     pub lufs: f64,
 
     /// Silence percentage (returns error code if total silence is above this threshold)
-    #[arg(long, default_value_t = 99)]
-    pub silence_percentage: u16,
+    #[arg(long, default_value_t = 99.0)]
+    pub silence_percentage: f64,
+
+    /// Loudness (LUFS-S) a silence has to reach again to end (defaults to --lufs)
+    #[arg(long, allow_hyphen_values = true)]
+    pub silence_resume_lufs: Option<f64>,
+
+    /// Silence threshold in LU relative to the integrated loudness so far, e.g. -40, instead
+    /// of --lufs once the loudness is known (implies --silence)
+    #[arg(long, value_name = "LU", allow_hyphen_values = true)]
+    pub silence_relative: Option<f64>,
+
+    /// Place silence starts and ends to 5 ms by the level of the samples around the windows
+    #[arg(long, default_value_t = false)]
+    pub refine_silence: bool,
+
+    /// Minimum duration of a reported silence in seconds, shorter dips are ignored
+    #[arg(long, default_value_t = 0.0)]
+    pub silence_hold: f64,
+
+    /// Maximum silence at the start in seconds (returns error code if longer, implies --silence)
+    #[arg(long)]
+    pub max_leading_silence: Option<f64>,
+
+    /// Write a cue sheet with tracks split at silence gaps to file (implies --silence)
+    #[arg(long)]
+    pub cue: Option<PathBuf>,
+
+    /// Write chapters split at silence gaps in FFmpeg metadata format to file (implies --silence)
+    #[arg(long)]
+    pub chapters: Option<PathBuf>,
+
+    /// Copy the input WAV to file with silence, underruns and clipping found as cue points
+    #[arg(long, conflicts_with = "raw")]
+    pub write_cues: Option<PathBuf>,
+
+    /// Write silence, underruns, stuck samples and clipping found as subtitles to file, as
+    /// WebVTT for a .vtt extension and otherwise as SubRip
+    #[arg(long, value_name = "FILE")]
+    pub srt: Option<PathBuf>,
+
+    /// Write everything found as locators to an edit decision list (CMX3600), to import into
+    /// Pro Tools or Resolve
+    #[arg(long, value_name = "FILE")]
+    pub edl: Option<PathBuf>,
+
+    /// Timecode rate of --edl
+    #[arg(long, value_enum, default_value_t = EdlRate::Fps25)]
+    pub edl_rate: EdlRate,
+
+    /// Write everything found, with the content changes of --novelty, as an Audacity label
+    /// track to file
+    #[arg(long, value_name = "FILE")]
+    pub labels: Option<PathBuf>,
+
+    /// Render a PNG timeline with a row per channel, coloured where silence, underruns or
+    /// clipping were found
+    #[arg(long, value_name = "FILE")]
+    pub issues_map: Option<PathBuf>,
+
+    /// Write the loudness envelope, activity, spectral statistics, pitch, novelty and alignment
+    /// series as named arrays to file, as a Zarr store for a .zarr extension and otherwise as .npz
+    #[arg(long, value_name = "FILE")]
+    pub timeseries: Option<PathBuf>,
+
+    /// Extract every silence, underrun, stuck sample run and clipped region found to a WAV
+    /// file of its own in directory, named by the analyser and the time it starts at
+    #[arg(long, value_name = "DIR")]
+    pub export_segments: Option<PathBuf>,
+
+    /// Seconds of audio before and after every segment of --export-segments
+    #[arg(long, value_name = "SECONDS", default_value_t = 0.5)]
+    pub segment_padding: f64,
+
+    /// Minimum silence gap between tracks in seconds
+    #[arg(long, default_value_t = 2.0)]
+    pub min_gap: f64,
+
+    /// Minimum track length in seconds, shorter tracks are not split off
+    #[arg(long, default_value_t = 10.0)]
+    pub min_track_length: f64,
 
     /// No fancy progress-bar
     #[arg(long, default_value_t = false)]
     pub no_progress: bool,
 
-    /// Debug output
-    #[arg(long, default_value_t = false)]
-    pub debug: bool,
+    /// Write the progress as JSON lines to this open file descriptor, e.g. 3 with
+    /// `3>progress.jsonl`, for programs that show it themselves
+    #[arg(long, value_name = "FD", conflicts_with = "progress_file")]
+    pub progress_fd: Option<i32>,
 
-    /// Silent (no output)
-    #[arg(long, default_value_t = false)]
+    /// Write the progress as JSON lines to this file, or a named pipe
+    #[arg(long, value_name = "PATH")]
+    pub progress_file: Option<PathBuf>,
+
+    /// Format of the times in the console output
+    #[arg(long, value_enum, default_value_t = TimeFormat::Hms)]
+    pub time_format: TimeFormat,
+
+    /// Print debug messages to stderr as well
+    #[arg(short, long, visible_alias = "debug", default_value_t = false)]
+    pub verbose: bool,
+
+    /// Only print warnings and errors
+    #[arg(short, long, default_value_t = false, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Only print errors
+    #[arg(long, default_value_t = false, conflicts_with_all = ["verbose", "quiet"])]
     pub silent: bool,
 
     /// Output results as JSON to file
     #[arg(long)]
-    pub json: Option<String>,
+    pub json: Option<PathBuf>,
+
+    /// Overwrite existing output files instead of numbering new ones
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+
+    /// Write the JSON output and every output given as a relative path to directory,
+    /// which is created if needed
+    #[arg(long, value_name = "DIR")]
+    pub out_dir: Option<PathBuf>,
+
+    /// Names of the JSON, FFT and peaks outputs in --out-dir, with the placeholders {stem}
+    /// (of the input file), {date} (UTC, YYYY-MM-DD), {analyser} and {ext}
+    #[arg(long, value_name = "TEMPLATE", requires = "out_dir", default_value = DEFAULT_NAME_TEMPLATE)]
+    pub name_template: String,
+
+    /// Write a summary of a batch run as JSON to file
+    #[arg(long)]
+    pub batch_summary: Option<PathBuf>,
+
+    /// Only set an exit code bit if at least this many files of a batch tripped it
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub batch_min_files: u32,
+
+    /// Write the JSON Schema of the JSON output for the enabled analysers to file
+    #[arg(long)]
+    pub emit_schema: Option<PathBuf>,
+
+    /// Print the JSON Schema of every key the JSON output can have and exit
+    #[arg(long, default_value_t = false)]
+    pub print_schema: bool,
+
+    /// Print the presets with the options they stand for and exit
+    #[arg(long, default_value_t = false)]
+    pub list_presets: bool,
+
+    /// Print the analysers, with those of --plugin, the keys they add to the JSON output and
+    /// the options that enable them, and exit
+    #[arg(long, default_value_t = false)]
+    pub list_analysers: bool,
+
+    /// Check the options, the input headers and that the output paths can be written, print
+    /// the plan and exit without analysing
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Write a self-contained HTML report to file
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+
+    /// Write the results as Prometheus metrics to file, or with :PORT serve them (serve only)
+    #[arg(long, value_name = "PATH|:PORT")]
+    pub metrics: Option<MetricsTarget>,
+
+    /// Write a JUnit XML report with a test case per analyser to file, for CI
+    #[arg(long)]
+    pub junit: Option<PathBuf>,
+
+    /// Also write the start and end of segments in --time-format to JSON (startTime, endTime)
+    #[arg(long, default_value_t = false)]
+    pub json_time: bool,
+
+    /// Hash the PCM data of the input, without the header and metadata, and write the digest
+    /// to JSON
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    pub digest: Option<DigestAlgorithm>,
 
     /// Window size for silence / loudness in seconds
     #[arg(long, default_value_t = 1.0)]
     pub window_size: f32,
 
+    /// Overlap of consecutive silence / loudness windows in percent, for finer silence boundaries
+    #[arg(long, default_value_t = 0.0)]
+    pub window_overlap: f64,
+
     /// Track loudness to JSON (does nothing if JSON output is not enabled)
     #[arg(short, long, default_value_t = false)]
     pub loudness: bool,
 
+    /// Also track an evenly sampled short-term loudness envelope with N points per second to JSON (implies --loudness)
+    #[arg(long, value_name = "N")]
+    pub loudness_rate: Option<f64>,
+
+    /// Also count the short-term loudness windows in a histogram with bins this many LU wide, with the L10, L50 and L90 percentiles, to JSON (implies --loudness)
+    #[arg(long, value_name = "LU")]
+    pub loudness_histogram: Option<f64>,
+
+    /// Plot the short-term loudness over time with the silence threshold and regions to file, as PNG with a .png extension and otherwise as SVG (implies --loudness)
+    #[arg(long, value_name = "PATH")]
+    pub loudness_graph: Option<PathBuf>,
+
+    /// Measure loudness and silence separately for every channel
+    #[arg(long, default_value_t = false)]
+    pub per_channel: bool,
+
     /// Track FFT to file
     #[arg(short, long, default_value_t = false)]
     pub fft: bool,
@@ -59,19 +348,801 @@ pub struct Cli {
     #[arg(long, default_value_t = 2048)]
     pub fft_bins: usize,
 
-    /// FFT output file (defaults to <json_file>_fft.png)
+    /// Domain of the FFT raw output and visualization
+    #[arg(long, value_enum, default_value_t = FftAmplitude::Db)]
+    pub fft_amplitude: FftAmplitude,
+
+    /// Frequency axis of the FFT raw output and visualization
+    #[arg(long, value_enum, default_value_t = FftScale::Linear)]
+    pub fft_scale: FftScale,
+
+    /// Number of bands with --fft-scale log or mel
+    #[arg(long, default_value_t = 128)]
+    pub fft_bands: usize,
+
+    /// Window function of the FFT slices
+    #[arg(long, value_enum, default_value_t = FftWindow::Hann)]
+    pub fft_window: FftWindow,
+
+    /// Frames between the starts of consecutive FFT slices (defaults to half of --fft-bins)
+    #[arg(long, conflicts_with = "fft_overlap")]
+    pub fft_hop: Option<usize>,
+
+    /// Overlap of consecutive FFT slices in percent, sets the hop from --fft-bins
     #[arg(long)]
-    pub fft_file: Option<String>,
+    pub fft_overlap: Option<f64>,
+
+    /// FFT output file (defaults to <json_file>_fft.<format>, or the --name-template in --out-dir)
+    #[arg(long)]
+    pub fft_file: Option<PathBuf>,
+
+    /// FFT output format
+    #[arg(long, value_enum, default_value_t = FftFormat::Png)]
+    pub fft_format: FftFormat,
 
     /// Visualize the FFT output to the given file
     #[arg(long)]
-    pub fft_vis: Option<String>,
+    pub fft_vis: Option<PathBuf>,
+
+    /// Split the visualization into numbered tiles of this many slices, with an index of their time ranges
+    #[arg(long, requires = "fft_vis", value_parser = clap::value_parser!(u32).range(1..))]
+    pub fft_vis_tile_width: Option<u32>,
 
     /// Track peaks to file
     #[arg(short, long, default_value_t = false)]
     pub peaks: bool,
 
-    /// Peaks output file (defaults to <json_file>_peaks.png)
+    /// Peaks output file (defaults to <json_file>_peaks.<format>, or the --name-template in --out-dir)
+    #[arg(long)]
+    pub peaks_file: Option<PathBuf>,
+
+    /// Peaks output format
+    #[arg(long, value_enum, default_value_t = PeaksFormat::Png)]
+    pub peaks_format: PeaksFormat,
+
+    /// Store the largest peak of each bucket at this many values per second instead of every sample
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub peaks_per_second: Option<u32>,
+
+    /// Implementation the spectrogram is computed with
+    #[arg(long, value_enum, default_value_t = FftBackend::Aus)]
+    pub fft_backend: FftBackend,
+
+    /// Write FFT and peaks output that is byte-identical on every platform (needs power-of-two --fft-bins)
+    #[arg(long, default_value_t = false)]
+    pub deterministic: bool,
+
+    /// Cap all buffers to fixed sizes so memory use doesn't grow with the input length (always on in `minimal` builds)
+    #[arg(long, default_value_t = cfg!(feature = "minimal"))]
+    pub low_memory: bool,
+
+    /// Export momentary loudness at meter rate to file
+    #[arg(long)]
+    pub meter_file: Option<PathBuf>,
+
+    /// Meter export format
+    #[arg(long, value_enum, default_value_t = MeterFormat::Csv)]
+    pub meter_format: MeterFormat,
+
+    /// Meter export rate in Hz
+    #[arg(long, default_value_t = 10.0)]
+    pub meter_rate: f64,
+
+    /// Measure crest factor, peak-to-loudness ratio and DR score
+    #[arg(long, default_value_t = false)]
+    pub dynamics: bool,
+
+    /// Minimum DR score (returns error code if the overall score is below this threshold, implies --dynamics)
+    #[arg(long)]
+    pub min_dr: Option<f64>,
+
+    /// Maximum number of clipped samples (returns error code if more, implies --dynamics)
+    #[arg(long)]
+    pub max_clipped_samples: Option<u64>,
+
+    /// Target integrated loudness in LUFS, e.g. -14 for streaming or -23 for EBU R128 (returns error code if out of tolerance)
+    #[arg(long, allow_hyphen_values = true)]
+    pub target_lufs: Option<f64>,
+
+    /// Allowed deviation from --target-lufs in LU
+    #[arg(long, default_value_t = 1.0)]
+    pub loudness_tolerance: f64,
+
+    /// Check whether normalizing to this loudness in LUFS, e.g. -14, would push the true peak
+    /// over --peak-ceiling (returns error code if it would need limiting)
+    #[arg(long, value_name = "LUFS", allow_hyphen_values = true)]
+    pub simulate_normalize: Option<f64>,
+
+    /// Highest true peak in dBTP allowed after --simulate-normalize
+    #[arg(long, value_name = "DBTP", default_value_t = -1.0, allow_hyphen_values = true)]
+    pub peak_ceiling: f64,
+
+    /// Speakers of the channels, for the channel weighting of the loudness measurements
+    #[arg(long, value_enum, default_value_t = ChannelLayout::Auto)]
+    pub channel_layout: ChannelLayout,
+
+    /// Check integrated loudness (-23 LUFS +/- 0.5 LU), true peak (at most -1 dBTP) and
+    /// loudness range against EBU R128 (returns error code if any fails)
+    #[arg(long, default_value_t = false)]
+    pub ebu_r128_check: bool,
+
+    /// Largest loudness range in LU that passes --ebu-r128-check
+    #[arg(long, default_value_t = 20.0)]
+    pub max_lra: f64,
+
+    /// Compare channel levels to find dead or imbalanced channels
+    #[arg(long, default_value_t = false)]
+    pub channel_balance: bool,
+
+    /// Maximum level difference to the loudest channel in dB (returns error code if exceeded, implies --channel-balance)
+    #[arg(long)]
+    pub max_channel_imbalance_db: Option<f64>,
+
+    /// Measure the delay between channels from their cross-correlation in windows of
+    /// --window-size
+    #[arg(long, default_value_t = false)]
+    pub alignment: bool,
+
+    /// Maximum delay between channels in samples (returns error code if exceeded, implies
+    /// --alignment)
+    #[arg(long, value_name = "SAMPLES")]
+    pub max_skew_samples: Option<usize>,
+
+    /// Find out which bits of the samples are actually used, to tell the effective bit depth
+    /// of each channel and stuck bits
+    #[arg(long, default_value_t = false)]
+    pub bit_depth: bool,
+
+    /// Look for mains hum and other tones at a fixed frequency shifted by a sample rate that
+    /// doesn't match the header, e.g. a 48 kHz capture tagged as 44.1 kHz
+    #[arg(long, default_value_t = false)]
+    pub rate_check: bool,
+
+    /// Look for the fingerprints of lossy codecs (lowpass shelf, bands dropping out, pre-echo)
+    /// and score how likely the input is a transcode from a lossy format
+    #[arg(long, default_value_t = false)]
+    pub codec_check: bool,
+
+    /// Compare the number of frames the header of the input announces with those that could
+    /// be decoded, to find files that were cut off. A truncated file fails with the exit code
+    /// of a decode error (65) once the outputs are written
+    #[arg(long, default_value_t = false)]
+    pub container_check: bool,
+
+    /// Validate the RIFF structure of a WAV file: chunk sizes against the length of the file,
+    /// padding, duplicate chunks and the fact chunk of non-PCM formats
+    #[arg(long, default_value_t = false)]
+    pub format_check: bool,
+
+    /// Like --format-check, and fail if the structure has errors that readers may trip over.
+    /// The errors fail with the exit code of a decode error (65) once the outputs are written
+    #[arg(long, default_value_t = false)]
+    pub strict_container: bool,
+
+    /// Check the mono compatibility of the first two channels: phase correlation, mid/side
+    /// ratio, level loss of the mono downmix and whether they are dual-mono
+    #[arg(long, default_value_t = false)]
+    pub stereo: bool,
+
+    /// Level loss of the mono downmix in dB above which --stereo warns
+    #[arg(long, value_name = "DB", default_value_t = 6.0)]
+    pub max_mono_loss: f64,
+
+    /// Mid/side ratio in dB from which --stereo takes the channels as dual-mono
+    #[arg(long, value_name = "DB", default_value_t = 40.0)]
+    pub dual_mono_ratio: f64,
+
+    /// Estimate clock drift between channels recorded on different devices
+    #[arg(long, default_value_t = false)]
+    pub drift: bool,
+
+    /// Detect gain pumping from heavy limiting or ducking
+    #[arg(long, default_value_t = false)]
+    pub pumping: bool,
+
+    /// Minimum level modulation depth in dB reported as pumping
+    #[arg(long, default_value_t = 3.0)]
+    pub pumping_min_depth: f64,
+
+    /// Detect sustained pure tones (line-up tones, beeps)
+    #[arg(long, default_value_t = false)]
+    pub tones: bool,
+
+    /// Tone frequencies to detect in Hz
+    #[arg(long, value_delimiter = ',', default_value = "1000,997,440")]
+    pub tone_frequencies: Vec<f64>,
+
+    /// Maximum deviation from a tone frequency in Hz
+    #[arg(long, default_value_t = 5.0)]
+    pub tone_tolerance: f64,
+
+    /// Minimum tone duration in seconds
+    #[arg(long, default_value_t = 1.0)]
+    pub tone_min_duration: f64,
+
+    /// Tones starting within this many seconds count as line-up tones
+    #[arg(long, default_value_t = 2.0)]
+    pub tone_head: f64,
+
+    /// Require a line-up tone at the head (returns error code if missing, implies --tones)
+    #[arg(long, default_value_t = false)]
+    pub require_tone: bool,
+
+    /// Fail on tones after the head (returns error code if found, implies --tones)
+    #[arg(long, default_value_t = false)]
+    pub fail_on_tone: bool,
+
+    /// Decode DTMF digits
+    #[arg(long, default_value_t = false)]
+    pub dtmf: bool,
+
+    /// Decode SMPTE/EBU linear timecode (LTC)
+    #[arg(long, default_value_t = false)]
+    pub ltc: bool,
+
+    /// Detect speech with voice activity detection (VAD)
+    #[arg(long, default_value_t = false)]
+    pub vad: bool,
+
+    /// Minimum share of speech in percent (returns error code if below, implies --vad)
+    #[arg(long)]
+    pub min_speech_percentage: Option<f64>,
+
+    /// Measure the integrated loudness of the speech the VAD finds next to that of the whole file (implies --vad)
+    #[arg(long, default_value_t = false)]
+    pub dialogue_loudness: bool,
+
+    /// Compute spectral centroid, flatness, rolloff and bandwidth (uses the --fft-* settings)
+    #[arg(long, default_value_t = false)]
+    pub spectral_stats: bool,
+
+    /// Points per second of the spectral statistics time series
+    #[arg(long, default_value_t = 2.0)]
+    pub spectral_rate: f64,
+
+    /// Compute the zero-crossing rate and activity factor of every channel
+    #[arg(long, default_value_t = false)]
+    pub activity: bool,
+
+    /// Points per second of the zero-crossing rate and activity time series
+    #[arg(long, default_value_t = 10.0)]
+    pub activity_rate: f64,
+
+    /// Level in dBFS a channel has to reach to count as active
+    #[arg(long, value_name = "DBFS", default_value_t = -50.0, allow_hyphen_values = true)]
+    pub activity_threshold: f64,
+
+    /// Track the fundamental frequency (f0) of the channel mix with the YIN estimator
+    #[arg(long, default_value_t = false)]
+    pub pitch: bool,
+
+    /// Points per second of the pitch contour
+    #[arg(long, default_value_t = 10.0)]
+    pub pitch_rate: f64,
+
+    /// Lowest pitch in Hz that is tracked, sets the window length
+    #[arg(long, value_name = "HZ", default_value_t = 50.0)]
+    pub pitch_min: f64,
+
+    /// Highest pitch in Hz that is tracked
+    #[arg(long, value_name = "HZ", default_value_t = 1000.0)]
+    pub pitch_max: f64,
+
+    /// Mark where the content changes, e.g. from music to speech, from the spectral flux
+    /// between the audio before and after each point, as hints for chapters
+    #[arg(long, default_value_t = false)]
+    pub novelty: bool,
+
+    /// Seconds of audio before and after a point that --novelty compares
+    #[arg(long, value_name = "SECONDS", default_value_t = 10.0)]
+    pub novelty_window: f64,
+
+    /// Standard deviations above the mean novelty of the file from which a change is marked
+    #[arg(
+        long,
+        value_name = "SD",
+        default_value_t = 2.0,
+        allow_hyphen_values = true
+    )]
+    pub novelty_threshold: f64,
+
+    /// Minimum seconds between two changes, the stronger one is kept
+    #[arg(long, value_name = "SECONDS", default_value_t = 30.0)]
+    pub novelty_min_gap: f64,
+
+    /// Estimate the tempo of music in beats per minute from the autocorrelation of its onsets
+    #[arg(long, default_value_t = false)]
+    pub tempo: bool,
+
+    /// Slowest tempo in BPM that --tempo considers
+    #[arg(long, value_name = "BPM", default_value_t = 60.0)]
+    pub tempo_min: f64,
+
+    /// Fastest tempo in BPM that --tempo considers
+    #[arg(long, value_name = "BPM", default_value_t = 200.0)]
+    pub tempo_max: f64,
+
+    /// List the positions of the beats at the estimated tempo in the JSON output (implies
+    /// --tempo)
+    #[arg(long, default_value_t = false)]
+    pub beats: bool,
+
+    /// Estimate the musical key and mode by matching the chroma of the mix against the
+    /// Krumhansl-Kessler key profiles
+    #[arg(long, default_value_t = false)]
+    pub key: bool,
+
+    /// Load the analysers of a plugin library, built against the same version of analwave
+    /// (needs the plugins feature)
+    #[arg(long, value_name = "LIBRARY")]
+    pub plugin: Vec<PathBuf>,
+
+    /// Option for the analysers of the plugins, e.g. clicks.threshold=0.5
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_plugin_option)]
+    pub plugin_option: Vec<(String, String)>,
+
+    /// Number of worker threads to spread the analysers over
+    #[arg(long, default_value_t = 1)]
+    pub threads: usize,
+
+    /// Measure the time spent decoding and in each analyser
+    #[arg(long, default_value_t = false)]
+    pub timings: bool,
+
+    /// Rank everything found by severity and duration at the end, also to JSON
+    #[arg(long, default_value_t = false)]
+    pub summary: bool,
+
+    /// Print a final `RESULT file=... code=...` line per file with the main measurements as
+    /// key=value pairs, for shell pipelines, also with --quiet or --silent
+    #[arg(long, default_value_t = false)]
+    pub status_line: bool,
+
+    /// Stop the analysis of a file after this long, e.g. 300s, 5m or 1h, and report the
+    /// partial results
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub timeout: Option<Duration>,
+
+    /// Only analyse the last part of the file, e.g. 10m, to triage the end of long recordings
+    /// first. Positions on the console and in --json, --report, --loudness-graph and
+    /// --timeseries are relative to where the analysis started, those in --srt, --labels,
+    /// --edl, --cue, --chapters, --write-cues, --export-segments and --issues-map are in the
+    /// file
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub from_end: Option<Duration>,
+
+    /// Leave time ranges out of the loudness and silence measurements, e.g. line-up tone and
+    /// a countdown: 00:00-00:10,01:20:00-end. Times are [[HH:]MM:]SS from where the analysis
+    /// starts
+    #[arg(long, value_name = "RANGES", value_delimiter = ',')]
+    pub exclude: Vec<TimeRange>,
+
+    /// Keep the results in this directory, keyed by digests of the audio and the options, and
+    /// reuse them when a file is analysed again with the same options. Only with --json and
+    /// --batch-summary as outputs
+    #[arg(long, value_name = "DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Analyse the files again even if --cache-dir has their results, and replace them
+    #[arg(long, default_value_t = false, requires = "cache_dir")]
+    pub no_cache: bool,
+}
+
+impl AnalyseArgs {
+    /// Whether silence is detected, either for its own sake or to split tracks.
+    pub fn detect_silence(&self) -> bool {
+        self.silence
+            || self.cue.is_some()
+            || self.chapters.is_some()
+            || self.max_leading_silence.is_some()
+            || self.silence_relative.is_some()
+    }
+
+    /// Whether loudness is tracked to JSON, as windows and optionally as an envelope.
+    pub fn track_loudness(&self) -> bool {
+        self.loudness
+            || self.loudness_rate.is_some()
+            || self.loudness_histogram.is_some()
+            || self.loudness_graph.is_some()
+    }
+
+    /// The last value of the `--plugin-option` with `key`, for the analysers of plugins.
+    pub fn plugin_option(&self, key: &str) -> Option<&str> {
+        self.plugin_option
+            .iter()
+            .rev()
+            .find(|(option, _)| option == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Frames between consecutive FFT slices, from `--fft-hop` or `--fft-overlap`.
+    pub fn fft_hop(&self) -> usize {
+        match (self.fft_hop, self.fft_overlap) {
+            (Some(hop), _) => hop,
+            (None, Some(overlap)) => {
+                (self.fft_bins as f64 * (1.0 - overlap / 100.0)).round() as usize
+            }
+            (None, None) => self.fft_bins / 2,
+        }
+    }
+
+    /// The first option that writes a file, for callers that can't or shouldn't write any.
+    pub fn file_output(&self) -> Option<&'static str> {
+        [
+            ("--json", self.json.is_some()),
+            ("--report", self.report.is_some()),
+            ("--loudness-graph", self.loudness_graph.is_some()),
+            ("--metrics", self.metrics.is_some()),
+            ("--junit", self.junit.is_some()),
+            ("--emit-schema", self.emit_schema.is_some()),
+            ("--out-dir", self.out_dir.is_some()),
+            ("--batch-summary", self.batch_summary.is_some()),
+            ("--fft", self.fft),
+            ("--fft-vis", self.fft_vis.is_some()),
+            ("--peaks", self.peaks),
+            ("--meter-file", self.meter_file.is_some()),
+            ("--cue", self.cue.is_some()),
+            ("--chapters", self.chapters.is_some()),
+            ("--write-cues", self.write_cues.is_some()),
+            ("--edl", self.edl.is_some()),
+            ("--srt", self.srt.is_some()),
+            ("--labels", self.labels.is_some()),
+            ("--issues-map", self.issues_map.is_some()),
+            ("--timeseries", self.timeseries.is_some()),
+            ("--export-segments", self.export_segments.is_some()),
+            ("--progress-file", self.progress_file.is_some()),
+        ]
+        .into_iter()
+        .find(|(_, used)| *used)
+        .map(|(option, _)| option)
+    }
+
+    /// Parses the options of `analyse` for callers of the library, which pass the input
+    /// themselves, so `--input` isn't required.
+    pub fn parse_options<T: Into<OsString> + Clone>(
+        options: impl IntoIterator<Item = T>,
+    ) -> Result<Self> {
+        let command = LibraryOptions::command().mut_arg("input", |arg| {
+            arg.required_unless_present(Resettable::Reset)
+        });
+        let mut options: Vec<OsString> = options.into_iter().map(Into::into).collect();
+
+        let parse = |options: &[OsString]| command.clone().try_get_matches_from(options);
+        let preset = parse(&options).map(|matches| preset_options(&matches));
+        options.splice(0..0, preset.map_err(|err| Error::Config(err.to_string()))?);
+
+        parse(&options)
+            .and_then(|matches| LibraryOptions::from_arg_matches(&matches))
+            .map(|options| options.analyse)
+            .map_err(|err| Error::Config(err.to_string()))
+    }
+
+    /// Parses the options of `analyse` from a JSON object of long option names, with dashes or
+    /// underscores. `true` enables a flag, `false` and `null` leave an option out, arrays give
+    /// several values.
+    pub fn from_config(config: &str) -> Result<Self> {
+        let config: Value = if config.trim().is_empty() {
+            Value::Object(Default::default())
+        } else {
+            serde_json::from_str(config)
+                .map_err(|err| Error::Config(format!("Invalid configuration: {err}")))?
+        };
+        let Value::Object(config) = config else {
+            return Err(Error::Config(
+                "The configuration has to be a JSON object".to_string(),
+            ));
+        };
+
+        let mut options = Vec::new();
+        for (name, value) in config {
+            let option = format!("--{}", name.replace('_', "-"));
+            match value {
+                Value::Null | Value::Bool(false) => {}
+                Value::Bool(true) => options.push(option),
+                Value::String(value) => options.push(format!("{option}={value}")),
+                Value::Array(values) => {
+                    options.push(option);
+                    options.extend(values.iter().map(|value| match value {
+                        Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    }));
+                }
+                value => options.push(format!("{option}={value}")),
+            }
+        }
+
+        Self::parse_options(options)
+    }
+}
+
+impl Cli {
+    /// Parses the command line. The options of a `--preset` are put in front of the given
+    /// ones, leaving out those that are given explicitly.
+    pub fn parse_with_preset() -> Self {
+        let mut argv: Vec<OsString> = std::env::args_os().collect();
+        let matches = Self::command().get_matches_from(&argv);
+
+        // The analysis options follow the subcommand, if there is one
+        let (position, preset) = match matches.subcommand() {
+            Some((_, subcommand)) => (2, preset_options(subcommand)),
+            None => (1, preset_options(&matches)),
+        };
+        if preset.is_empty() {
+            return Self::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+        }
+
+        argv.splice(position..position, preset);
+        Self::parse_from(argv)
+    }
+}
+
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct LibraryOptions {
+    #[command(flatten)]
+    analyse: AnalyseArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Analyse a WAV file (the default when no command is given)
+    #[command(visible_alias = "analyze")]
+    Analyse(Box<AnalyseArgs>),
+
+    /// Render a raw FFT output file as a spectrogram image
+    #[command(visible_alias = "visualise")]
+    Visualize(VisualizeArgs),
+
+    /// Render a peaks output file as a waveform or heatmap image
+    #[command(visible_alias = "visualise-peaks")]
+    VisualizePeaks(PeaksVisArgs),
+
+    /// Print the format and metadata of a WAV file without decoding the audio
+    Inspect(InspectArgs),
+
+    /// Generate a calibrated test signal as WAV
+    Generate(GenerateArgs),
+
+    /// Render a side-by-side spectrogram/waveform comparison of two files
+    Compare(CompareArgs),
+
+    /// Analyse uploaded WAV files over HTTP
+    Serve(Box<ServeArgs>),
+}
+
+#[derive(Args, Debug)]
+pub struct VisualizeArgs {
+    /// The raw FFT file (PNG)
+    #[arg(short, long)]
+    pub input: PathBuf,
+
+    /// The output visualization file (PNG)
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Width of the spectrogram in pixels (time axis), rescaled from one pixel per slice
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Height of the spectrogram in pixels (frequency axis), rescaled from one pixel per bin
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Colours of the spectrogram
+    #[arg(long, value_enum, default_value_t = Colormap::Heat)]
+    pub colormap: Colormap,
+
+    /// Value shown in the lowest colour, lower values are clamped to it (the minimum by default)
+    #[arg(long, allow_hyphen_values = true)]
+    pub min_db: Option<f64>,
+
+    /// Value shown in the highest colour, higher values are clamped to it (the maximum by default)
+    #[arg(long, allow_hyphen_values = true)]
+    pub max_db: Option<f64>,
+
+    /// Draw time and frequency axes with tick labels and a colour scale
+    #[arg(long)]
+    pub axes: bool,
+
+    /// Sample rate of the analysed audio in Hz, to label the axes in seconds and Hz
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub sample_rate: Option<i32>,
+
+    /// Frames between slices, half the FFT size by default
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub hop: Option<u32>,
+
+    /// Number of channels in the raw file, if it doesn't record its layout
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub channels: u16,
+}
+
+#[derive(Args, Debug)]
+pub struct PeaksVisArgs {
+    /// The peaks file (PNG)
+    #[arg(short, long)]
+    pub input: PathBuf,
+
+    /// The output visualization file (PNG)
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Image width in pixels (time axis)
+    #[arg(long, default_value_t = 1200, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: u32,
+
+    /// Height of each channel in pixels
+    #[arg(long, default_value_t = 128, value_parser = clap::value_parser!(u32).range(1..))]
+    pub channel_height: u32,
+
+    /// How the peaks of a channel are drawn
+    #[arg(long, value_enum, default_value_t = PeaksStyle::Waveform)]
+    pub style: PeaksStyle,
+
+    /// Colours of the heatmap
+    #[arg(long, value_enum, default_value_t = Colormap::Heat)]
+    pub colormap: Colormap,
+
+    /// Peak level in dBFS shown in the lowest colour of the heatmap
+    #[arg(long, default_value_t = -60.0, allow_hyphen_values = true)]
+    pub floor: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct InspectArgs {
+    /// The file to inspect
+    pub input: PathBuf,
+
+    /// Output the header information as JSON to file
     #[arg(long)]
-    pub peaks_file: Option<String>,
+    pub json: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// The reference file
+    pub reference: PathBuf,
+
+    /// The processed file to compare against the reference
+    pub processed: PathBuf,
+
+    /// Output comparison image (PNG)
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Number of FFT bins
+    #[arg(long, default_value_t = 2048)]
+    pub fft_bins: usize,
+
+    /// Image width in pixels (time axis)
+    #[arg(long, default_value_t = 1200)]
+    pub width: usize,
+
+    /// Height of each spectrogram in pixels (frequency bands)
+    #[arg(long, default_value_t = 256)]
+    pub height: usize,
+
+    /// Spectral difference in dB mapped to the top of the difference strip
+    #[arg(long, default_value_t = 20.0)]
+    pub diff_range: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// The WAV file to write
+    pub output: PathBuf,
+
+    /// Signal type
+    #[arg(short, long, value_enum, default_value_t = Signal::Pink)]
+    pub signal: Signal,
+
+    /// Duration in seconds
+    #[arg(short, long, default_value_t = 10.0)]
+    pub duration: f64,
+
+    /// Sample rate
+    #[arg(long, default_value_t = 48000)]
+    pub sample_rate: u32,
+
+    /// Number of channels
+    #[arg(long, default_value_t = 2)]
+    pub channels: u16,
+
+    /// Bits per sample (16 or 32)
+    #[arg(long, default_value_t = 16, value_parser = parse_bits)]
+    pub bits: u16,
+
+    /// Integrated loudness of noise signals (LUFS)
+    #[arg(long, default_value_t = -23.0, allow_hyphen_values = true)]
+    pub lufs: f64,
+
+    /// Peak level of tones and sweeps (dBFS)
+    #[arg(long, default_value_t = -20.0, allow_hyphen_values = true)]
+    pub level: f64,
+
+    /// Tone frequency in Hz
+    #[arg(long, default_value_t = 1000.0)]
+    pub frequency: f64,
+
+    /// Sweep start frequency in Hz
+    #[arg(long, default_value_t = 20.0)]
+    pub start_frequency: f64,
+
+    /// Sweep end frequency in Hz
+    #[arg(long, default_value_t = 20000.0)]
+    pub end_frequency: f64,
+
+    /// Insert a silent gap at the end of every N seconds (requires --gap-length)
+    #[arg(long, requires = "gap_length")]
+    pub gap_every: Option<f64>,
+
+    /// Length of each silent gap in seconds
+    #[arg(long, requires = "gap_every")]
+    pub gap_length: Option<f64>,
+
+    /// Seed for noise signals
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+}
+
+/// A duration in seconds, or with an `ms`, `s`, `m` or `h` suffix.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let scale = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        unit => return Err(format!("unknown unit {unit}, use ms, s, m or h")),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|number| Duration::try_from_secs_f64(number * scale).ok())
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("{value} is not a duration, e.g. 300s"))
+}
+
+/// `KEY=VALUE` of `--plugin-option`.
+fn parse_plugin_option(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("'{value}' is not an option like key=value"))
+}
+
+fn parse_bits(value: &str) -> Result<u16, String> {
+    match value {
+        "16" => Ok(16),
+        "32" => Ok(32),
+        _ => Err("must be 16 or 32".to_string()),
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(mut_arg("input", |arg| arg.required_unless_present(Resettable::Reset)))]
+pub struct ServeArgs {
+    /// Address and port to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+
+    /// Number of queued jobs analysed at the same time
+    #[arg(long, default_value_t = 1)]
+    pub workers: usize,
+
+    /// Largest accepted upload in megabytes
+    #[arg(long, default_value_t = 1024)]
+    pub max_upload_mb: u64,
+
+    /// Also accept the path of a file on the server instead of an upload
+    #[arg(long, default_value_t = false)]
+    pub allow_paths: bool,
+
+    /// The analysers to run on every file, with the options of `analyse`
+    #[command(flatten)]
+    pub analyse: AnalyseArgs,
 }
@@ -0,0 +1,247 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use png::{BitDepth, ColorType, Encoder};
+use wavers::Wav;
+
+use crate::{
+    Error, Result,
+    analysers::fft::{heat_color, log_spectrogram},
+    cli::CompareArgs,
+};
+
+const WAVEFORM_HEIGHT: usize = 64;
+const DIFF_HEIGHT: usize = 24;
+const GAP: usize = 2;
+const BACKGROUND: [u8; 3] = [20, 20, 20];
+const NO_DATA: [u8; 3] = [60, 60, 60];
+
+/// Mono fold-down (at i32 scale, like the FFT analyser) and spectrogram of one side of
+/// the comparison.
+struct Side {
+    sample_rate: f64,
+    hop: usize,
+    samples: Vec<f64>,
+    spectrogram: Vec<Vec<f64>>,
+}
+
+impl Side {
+    fn load(path: &Path, fft_size: usize) -> Result<Self> {
+        let mut wav: Wav<i32> = Wav::from_path(path).map_err(|err| Error::wav(path, err))?;
+        let channels = wav.n_channels().max(1) as usize;
+        let sample_rate = wav.sample_rate() as f64;
+        let data = wav.read().map_err(|err| Error::wav(path, err))?;
+
+        let samples: Vec<f64> = data
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().map(|&s| s as f64).sum::<f64>() / channels as f64)
+            .collect();
+
+        let spectrogram = log_spectrogram(&samples, fft_size);
+
+        Ok(Self {
+            sample_rate,
+            hop: fft_size / 2,
+            samples,
+            spectrogram,
+        })
+    }
+
+    fn duration(&self) -> f64 {
+        self.samples.len() as f64 / self.sample_rate
+    }
+
+    /// Spectrogram slice at `time`, averaged down to `rows` bands.
+    fn bands_at(&self, time: f64, rows: usize) -> Option<Vec<f64>> {
+        let index = (time * self.sample_rate / self.hop as f64) as usize;
+        let slice = self.spectrogram.get(index)?;
+        let band_size = slice.len().div_ceil(rows).max(1);
+
+        Some(
+            slice
+                .chunks(band_size)
+                .map(|band| band.iter().sum::<f64>() / band.len() as f64)
+                .collect(),
+        )
+    }
+
+    /// Peak absolute amplitude between two points in time, relative to full scale.
+    fn peak_between(&self, start: f64, end: f64) -> Option<f64> {
+        let start = (start * self.sample_rate) as usize;
+        if start >= self.samples.len() {
+            return None;
+        }
+
+        let end = ((end * self.sample_rate) as usize).clamp(start + 1, self.samples.len());
+
+        self.samples
+            .get(start..end)
+            .map(|samples| samples.iter().fold(0.0f64, |peak, v| peak.max(v.abs())))
+            .map(|peak| peak / i32::MAX as f64)
+    }
+}
+
+pub struct Comparison {
+    /// Mean absolute spectral difference over the overlapping part, in dB.
+    pub mean_difference: f64,
+    pub max_difference: f64,
+    /// Time in seconds of the largest spectral difference.
+    pub max_difference_at: f64,
+    pub reference_duration: f64,
+    pub processed_duration: f64,
+}
+
+/// Render reference and processed waveform/spectrogram pairs on a shared time axis, with a
+/// strip showing their spectral difference in between.
+pub fn compare(args: &CompareArgs) -> Result<Comparison> {
+    // The spectrograms hop by half the window, which has to be at least one frame
+    if args.fft_bins < 2 {
+        return Err(Error::Config(format!(
+            "--fft-bins has to be at least 2, got {}",
+            args.fft_bins
+        )));
+    }
+
+    let reference = Side::load(&args.reference, args.fft_bins)?;
+    let processed = Side::load(&args.processed, args.fft_bins)?;
+
+    let duration = reference.duration().max(processed.duration());
+    let width = args.width.max(1);
+    let rows = args.height.max(1);
+    let time = |x: usize| (x as f64 + 0.5) / width as f64 * duration;
+
+    let bands: Vec<[Option<Vec<f64>>; 2]> = (0..width)
+        .map(|x| {
+            [
+                reference.bands_at(time(x), rows),
+                processed.bands_at(time(x), rows),
+            ]
+        })
+        .collect();
+
+    let (min, max) = bands
+        .iter()
+        .flatten()
+        .flatten()
+        .flatten()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+    let range = (max - min).max(f64::EPSILON);
+
+    let differences: Vec<Option<f64>> = bands
+        .iter()
+        .map(|[a, b]| {
+            let (a, b) = (a.as_ref()?, b.as_ref()?);
+            let n = a.len().min(b.len()).max(1);
+
+            Some(a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum::<f64>() / n as f64)
+        })
+        .collect();
+
+    let height = 2 * WAVEFORM_HEIGHT + 2 * rows + DIFF_HEIGHT + 4 * GAP;
+    let mut image = vec![BACKGROUND; width * height];
+    let mut put = |x: usize, y: usize, color: [u8; 3]| image[y * width + x] = color;
+
+    for x in 0..width {
+        let start = x as f64 / width as f64 * duration;
+        let end = (x + 1) as f64 / width as f64 * duration;
+
+        // Layout from top: reference waveform, reference spectrogram, difference strip,
+        // processed spectrogram, processed waveform
+        for (index, side) in [&reference, &processed].into_iter().enumerate() {
+            let wave_top = if index == 0 {
+                0
+            } else {
+                height - WAVEFORM_HEIGHT
+            };
+            let spec_top = if index == 0 {
+                WAVEFORM_HEIGHT + GAP
+            } else {
+                height - WAVEFORM_HEIGHT - GAP - rows
+            };
+
+            if let Some(peak) = side.peak_between(start, end) {
+                let half = (peak.min(1.0) * WAVEFORM_HEIGHT as f64 / 2.0) as usize;
+                let mid = wave_top + WAVEFORM_HEIGHT / 2;
+                for wy in
+                    mid.saturating_sub(half)..=(mid + half).min(wave_top + WAVEFORM_HEIGHT - 1)
+                {
+                    put(x, wy, [200, 200, 200]);
+                }
+            }
+
+            match &bands[x][index] {
+                Some(values) => {
+                    for (row, v) in values.iter().enumerate().take(rows) {
+                        // Low frequencies at the bottom
+                        let value = ((v - min) / range).clamp(0.0, 1.0).powi(2);
+                        put(x, spec_top + rows - 1 - row, heat_color(value));
+                    }
+                }
+                None => {
+                    for row in 0..rows {
+                        put(x, spec_top + row, NO_DATA);
+                    }
+                }
+            }
+        }
+
+        let diff_top = WAVEFORM_HEIGHT + rows + 2 * GAP;
+        let color = match differences[x] {
+            Some(diff) => diff_color((diff / args.diff_range).clamp(0.0, 1.0)),
+            None => NO_DATA,
+        };
+        for dy in 0..DIFF_HEIGHT {
+            put(x, diff_top + dy, color);
+        }
+    }
+
+    write_png(&args.output, width, height, &image)?;
+
+    let overlapping: Vec<(usize, f64)> = differences
+        .iter()
+        .enumerate()
+        .filter_map(|(x, diff)| diff.map(|diff| (x, diff)))
+        .collect();
+    let mean_difference =
+        overlapping.iter().map(|(_, diff)| diff).sum::<f64>() / overlapping.len().max(1) as f64;
+    let (max_x, max_difference) =
+        overlapping.iter().cloned().fold(
+            (0, 0.0),
+            |best, (x, diff)| if diff > best.1 { (x, diff) } else { best },
+        );
+
+    Ok(Comparison {
+        mean_difference,
+        max_difference,
+        max_difference_at: time(max_x),
+        reference_duration: reference.duration(),
+        processed_duration: processed.duration(),
+    })
+}
+
+/// Black -> red -> yellow ramp for the difference strip.
+fn diff_color(value: f64) -> [u8; 3] {
+    let red = (value * 2.0).min(1.0);
+    let green = (value * 2.0 - 1.0).clamp(0.0, 1.0);
+
+    [(red * 255.0) as u8, (green * 255.0) as u8, 0]
+}
+
+fn write_png(path: &Path, width: usize, height: usize, image: &[[u8; 3]]) -> Result<()> {
+    let write_error = |err| Error::io("write comparison to", path, err);
+    let file = File::create(Path::new(path)).map_err(write_error)?;
+    let mut w = BufWriter::new(file);
+
+    let mut encoder = Encoder::new(&mut w, width as u32, height as u32);
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut writer = encoder
+        .write_header()
+        .map_err(|err| write_error(err.into()))?;
+
+    writer
+        .write_image_data(image.as_flattened())
+        .map_err(|err| write_error(err.into()))
+}
@@ -0,0 +1,707 @@
+//! Console output of what the analysers found once the stream is finished. Events like the
+//! start of a silence are printed by the analysers while streaming.
+
+use crate::{
+    analysers::{
+        Analyser, AnalysisResult, activity::ActivityAnalysis, alignment::AlignmentAnalysis,
+        balance::ChannelBalanceAnalysis, bitdepth::BitDepthAnalysis, codec::CodecAnalysis,
+        compliance::ComplianceAnalysis, container::ContainerAnalysis, drift::DriftAnalysis,
+        dynamics::DynamicsAnalysis, dynamics::DynamicsResult, format::FormatAnalysis,
+        key::KeyAnalysis, loudness::LoudnessAnalysis, normalize::NormalizationAnalysis,
+        novelty::NoveltyAnalysis, pitch::PitchAnalysis, pumping::PumpingAnalysis,
+        ratecheck::RateCheckAnalysis, spectral::SpectralAnalysis, stereo::StereoAnalysis,
+        target::LoudnessTargetAnalysis, tempo::TempoAnalysis, tone::ToneAnalysis,
+        underruns::UnderrunAnalysis, vad::VadAnalysis,
+    },
+    digest::PayloadDigest,
+    input::GainInfo,
+    json::Analysis,
+    output,
+    output::frame_to_time,
+    warn,
+};
+
+/// Tag of the console lines about the failed checks of the analyser called `name`.
+fn tag(name: &str) -> String {
+    match name {
+        "loudness" => "SILENCE".to_string(),
+        "tones" => "TONE".to_string(),
+        name => name.to_uppercase(),
+    }
+}
+
+/// Prints the results of the analysers in their order, each followed by why its checks
+/// failed. `label` is the position at the end of the stream.
+pub fn print_results(
+    label: &str,
+    sample_rate: i32,
+    analysers: &[Box<dyn Analyser>],
+    results: &[AnalysisResult],
+) {
+    for (analyser, result) in analysers.iter().zip(results) {
+        print_analysis(label, sample_rate, &result.analysis);
+
+        let tag = tag(analyser.name());
+        for failure in &result.failures {
+            output!("[{}] {:<13}: {}", label, tag, failure);
+        }
+    }
+}
+
+/// Prints the gain applied to the input, with `--gain-db`.
+pub fn print_gain(label: &str, gain: &GainInfo) {
+    output!(
+        "[{}] GAIN         : {:+.2} dB, {} samples clipped",
+        label,
+        gain.db,
+        gain.clipped_samples
+    );
+}
+
+/// Prints the digest of the payload of the input, with `--digest`.
+pub fn print_digest(label: &str, digest: &PayloadDigest) {
+    output!(
+        "[{}] DIGEST       : {} {} ({} bytes)",
+        label,
+        digest.algorithm.as_str(),
+        digest.value,
+        digest.bytes
+    );
+}
+
+fn print_analysis(label: &str, sample_rate: i32, analysis: &Analysis) {
+    if let Some(underruns) = &analysis.underruns {
+        print_underruns(label, underruns);
+    }
+    if let Some(dynamics) = &analysis.dynamics {
+        print_dynamics(label, dynamics);
+    }
+    if let Some(loudness) = &analysis.loudness {
+        print_loudness_histograms(label, loudness);
+    }
+    if let Some(target) = &analysis.loudness_target {
+        print_target(label, target);
+    }
+    if let Some(compliance) = &analysis.compliance {
+        print_compliance(label, compliance);
+    }
+    if let Some(normalization) = &analysis.normalization {
+        print_normalization(label, normalization);
+    }
+    if let Some(balance) = &analysis.channel_balance {
+        print_balance(label, sample_rate, balance);
+    }
+    if let Some(alignment) = &analysis.alignment {
+        print_alignment(label, sample_rate, alignment);
+    }
+    if let Some(stereo) = &analysis.stereo {
+        print_stereo(label, stereo);
+    }
+    if let Some(codec) = &analysis.codec {
+        print_codec(label, codec);
+    }
+    if let Some(container) = &analysis.container {
+        print_container(label, container);
+    }
+    if let Some(format) = &analysis.format {
+        print_format(label, format);
+    }
+    if let Some(bit_depth) = &analysis.bit_depth {
+        print_bit_depth(label, bit_depth);
+    }
+    if let Some(rate_check) = &analysis.rate_check {
+        print_rate_check(label, rate_check);
+    }
+    if let Some(drift) = &analysis.drift {
+        print_drift(label, drift);
+    }
+    if let Some(pumping) = &analysis.pumping {
+        print_pumping(label, sample_rate, pumping);
+    }
+    if let Some(tones) = &analysis.tones {
+        print_tones(label, sample_rate, tones);
+    }
+    if let Some(vad) = &analysis.vad {
+        print_vad(label, sample_rate, vad);
+    }
+    if let Some(spectral) = &analysis.spectral {
+        print_spectral(label, spectral);
+    }
+    if let Some(pitch) = &analysis.pitch {
+        print_pitch(label, pitch);
+    }
+    if let Some(activity) = &analysis.activity {
+        print_activity(label, activity);
+    }
+    if let Some(novelty) = &analysis.novelty {
+        print_novelty(label, sample_rate, novelty);
+    }
+    if let Some(tempo) = &analysis.tempo {
+        print_tempo(label, tempo);
+    }
+    if let Some(key) = &analysis.key {
+        print_key(label, key);
+    }
+}
+
+fn print_underruns(label: &str, underruns: &UnderrunAnalysis) {
+    if !underruns.results.is_empty() {
+        output!(
+            "[{}] UNDERRUNS    : {} underruns, {:06.3}s in total",
+            label,
+            underruns.count,
+            underruns.total_duration
+        );
+    }
+
+    let stuck = underruns.stuck_segments.as_ref().map_or(0, Vec::len);
+    if stuck > 0 {
+        output!("[{}] STUCK        : {} stuck runs", label, stuck);
+    }
+}
+
+fn print_dynamics(label: &str, dynamics: &DynamicsAnalysis) {
+    let print = |name: &str, result: &DynamicsResult| {
+        output!(
+            "[{}] DYNAMICS     : {} peak {:.2} dBFS; true peak {:.2} dBTP; RMS {:.2} dBFS; crest {:.2} dB; PLR {:.2} dB; DR{}{}",
+            label,
+            name,
+            result.peak,
+            result.true_peak,
+            result.rms,
+            result.crest_factor,
+            result.plr,
+            result.dr.map_or("-".to_string(), |dr| format!("{:.0}", dr)),
+            if result.clipped_samples > 0 {
+                format!("; {} clipped", result.clipped_samples)
+            } else {
+                String::new()
+            }
+        );
+    };
+
+    for (index, result) in dynamics.channels.iter().enumerate() {
+        print(&format!("CH:{index}"), result);
+    }
+    print("ALL ", &dynamics.overall);
+}
+
+fn print_target(label: &str, target: &LoudnessTargetAnalysis) {
+    output!(
+        "[{}] TARGET       : LUFS-I {:.2}; target {:.2} LUFS +/- {:.2} LU; gain {}{}",
+        label,
+        target.integrated,
+        target.target,
+        target.tolerance,
+        target
+            .gain_to_target
+            .map_or("-".to_string(), |gain| format!("{:+.2} dB", gain)),
+        if target.within_tolerance {
+            ""
+        } else {
+            " (out of tolerance)"
+        }
+    );
+}
+
+fn print_normalization(label: &str, normalization: &NormalizationAnalysis) {
+    let db =
+        |value: Option<f64>| value.map_or("-".to_string(), |value| format!("{:+.2} dB", value));
+
+    output!(
+        "[{}] NORMALIZE    : to {:.2} LUFS with gain {}; true peak {:.2} -> {} dBTP; ceiling {:.2} dBTP; headroom {}{}",
+        label,
+        normalization.target,
+        db(normalization.gain),
+        normalization.true_peak,
+        normalization
+            .true_peak_after
+            .map_or("-".to_string(), |peak| format!("{:.2}", peak)),
+        normalization.peak_ceiling,
+        db(normalization.headroom),
+        if normalization.possible {
+            ""
+        } else {
+            " (needs limiting)"
+        }
+    );
+}
+
+fn print_compliance(label: &str, compliance: &ComplianceAnalysis) {
+    for criterion in compliance.criteria.iter() {
+        output!(
+            "[{}] COMPLIANCE   : {:<18} {:>8.2} {:<4}  {:<22} {}",
+            label,
+            criterion.name,
+            criterion.measured,
+            criterion.unit,
+            criterion.limit(),
+            criterion.verdict.as_str()
+        );
+    }
+
+    output!(
+        "[{}] COMPLIANCE   : gates at {:.2} LUFS relative, {:.2} LUFS absolute",
+        label,
+        compliance.gating.relative_threshold,
+        compliance.gating.absolute_threshold
+    );
+    output!(
+        "[{}] COMPLIANCE   : {} {}",
+        label,
+        compliance.standard,
+        compliance.verdict.as_str()
+    );
+}
+
+fn print_rate_check(label: &str, rate_check: &RateCheckAnalysis) {
+    let Some(likely) = rate_check.likely_rate else {
+        output!(
+            "[{}] RATE         : declared {} Hz; no reference tones found",
+            label,
+            rate_check.declared_rate
+        );
+        return;
+    };
+
+    let tones: Vec<String> = rate_check
+        .evidence
+        .iter()
+        .filter(|evidence| evidence.sample_rate == likely)
+        .map(|evidence| format!("{} at {:.2} Hz", evidence.tone.as_str(), evidence.found))
+        .collect();
+
+    output!(
+        "[{}] RATE         : declared {} Hz; likely {} Hz ({})",
+        label,
+        rate_check.declared_rate,
+        likely,
+        tones.join(", ")
+    );
+
+    if rate_check.mismatch {
+        warn!(
+            "the file is tagged {} Hz but looks like it was captured at {} Hz",
+            rate_check.declared_rate, likely
+        );
+    }
+}
+
+fn print_bit_depth(label: &str, bit_depth: &BitDepthAnalysis) {
+    let declared = bit_depth
+        .declared_bits
+        .map_or(String::new(), |bits| format!(" of {bits}"));
+
+    for channel in bit_depth.channels.iter() {
+        let Some(bits) = channel.effective_bits else {
+            output!("[{}] BITDEPTH     : CH:{} - silent", label, channel.channel);
+            continue;
+        };
+
+        output!(
+            "[{}] BITDEPTH     : CH:{} - {}{} bits used{}",
+            label,
+            channel.channel,
+            bits,
+            declared,
+            if channel.stuck_bits.is_empty() {
+                String::new()
+            } else {
+                format!("; stuck bits {:?}", channel.stuck_bits)
+            }
+        );
+    }
+
+    if let (Some(effective), Some(declared)) = (bit_depth.effective_bits, bit_depth.declared_bits)
+        && effective < declared as u32
+    {
+        warn!("the file declares {declared} bits per sample but only uses {effective}");
+    }
+    if bit_depth
+        .channels
+        .iter()
+        .any(|channel| !channel.stuck_bits.is_empty())
+    {
+        warn!("some bits never change, they may be stuck");
+    }
+}
+
+fn print_codec(label: &str, codec: &CodecAnalysis) {
+    let cutoff = codec.cutoff.map_or("no cutoff".to_string(), |cutoff| {
+        format!("cutoff {:.2} kHz", cutoff / 1000.0)
+    });
+
+    output!(
+        "[{}] CODEC        : transcode score {:.2}{}; {}; holes {:.2}%; pre-echo {}/{} transients",
+        label,
+        codec.score,
+        if codec.likely_transcode {
+            " (likely lossy)"
+        } else {
+            ""
+        },
+        cutoff,
+        codec.hole_rate * 100.0,
+        codec.pre_echoes,
+        codec.transients
+    );
+}
+
+fn print_container(label: &str, container: &ContainerAnalysis) {
+    let Some(declared) = container.declared_frames else {
+        output!(
+            "[{}] CONTAINER    : {} frames decoded; the header has no length",
+            label,
+            container.decoded_frames
+        );
+        return;
+    };
+
+    if container.truncated {
+        output!(
+            "[{}] CONTAINER    : {} of {} frames decoded; {} frames ({:.3} s) missing",
+            label,
+            container.decoded_frames,
+            declared,
+            container.missing_frames,
+            container.missing_duration
+        );
+    } else {
+        output!(
+            "[{}] CONTAINER    : {} of {} frames decoded",
+            label,
+            container.decoded_frames,
+            declared
+        );
+    }
+}
+
+fn print_format(label: &str, format: &FormatAnalysis) {
+    if format.issues.is_empty() {
+        output!(
+            "[{}] FORMAT       : {} structure is valid",
+            label,
+            format.container
+        );
+        return;
+    }
+
+    for issue in format.issues.iter() {
+        output!(
+            "[{}] FORMAT       : {}: {}",
+            label,
+            issue.severity.as_str(),
+            issue.message
+        );
+    }
+}
+
+fn print_stereo(label: &str, stereo: &StereoAnalysis) {
+    output!(
+        "[{}] STEREO       : correlation {:+.3}; mid/side {:.2} dB; mono downmix loss {:.2} dB",
+        label,
+        stereo.correlation,
+        stereo.mid_side_ratio,
+        stereo.mono_downmix_loss
+    );
+
+    if stereo.dual_mono {
+        warn!("the first two channels appear to be dual-mono");
+    }
+    if stereo.mono_downmix_loss > stereo.max_mono_loss {
+        warn!(
+            "the mono downmix loses {:.2} dB, more than {} dB, the channels may be out of phase",
+            stereo.mono_downmix_loss, stereo.max_mono_loss
+        );
+    }
+}
+
+fn print_alignment(label: &str, sample_rate: i32, alignment: &AlignmentAnalysis) {
+    for result in alignment.results.iter() {
+        let [a, b] = result.channels;
+        let (Some(delay), Some(max_delay), Some(correlation)) =
+            (result.delay, result.max_delay, result.correlation)
+        else {
+            output!(
+                "[{}] ALIGNMENT    : CH:{} / CH:{} - no correlation",
+                label,
+                a,
+                b
+            );
+            continue;
+        };
+
+        output!(
+            "[{}] ALIGNMENT    : CH:{} / CH:{} - CH:{} {} by {:.2} samples ({:.3} ms); max {:.2}; correlation {:.2}",
+            label,
+            a,
+            b,
+            b,
+            if delay < 0.0 { "ahead" } else { "behind" },
+            delay.abs(),
+            delay.abs() * 1000.0 / sample_rate as f64,
+            max_delay,
+            correlation
+        );
+
+        if let Some(max) = alignment.max_skew
+            && max_delay.abs().round() > max as f64
+        {
+            warn!(
+                "CH:{} and CH:{} are up to {:.2} samples apart, more than {}",
+                a,
+                b,
+                max_delay.abs(),
+                max
+            );
+        }
+    }
+}
+
+fn print_balance(label: &str, sample_rate: i32, balance: &ChannelBalanceAnalysis) {
+    for level in balance.channels.iter() {
+        output!(
+            "[{}] BALANCE      : CH:{} - RMS {:.2} dBFS; peak {:.2} dBFS; {:.2} dB below loudest{}",
+            label,
+            level.channel,
+            level.rms,
+            level.peak,
+            level.imbalance,
+            if level.dead { " (dead)" } else { "" }
+        );
+    }
+
+    for segment in balance.results.iter() {
+        let imbalance = if segment.imbalance.is_finite() {
+            format!("{:.2} dB below loudest", segment.imbalance)
+        } else {
+            "dead".to_string()
+        };
+
+        output!(
+            "[{}] BALANCE      : CH:{} - {} ({:06.3}s) {} -> {}",
+            label,
+            segment.channel,
+            imbalance,
+            segment.duration,
+            frame_to_time(segment.start_sample, sample_rate),
+            frame_to_time(segment.end_sample, sample_rate)
+        );
+    }
+}
+
+fn print_loudness_histograms(label: &str, loudness: &LoudnessAnalysis) {
+    for histogram in loudness.histograms.iter().flatten() {
+        let channel = histogram
+            .channel
+            .map_or("ALL".to_string(), |channel| format!("CH:{channel}"));
+
+        match (histogram.l10, histogram.l50, histogram.l90) {
+            (Some(l10), Some(l50), Some(l90)) => output!(
+                "[{}] LOUDNESS     : {} L10 {:.1} LUFS; L50 {:.1} LUFS; L90 {:.1} LUFS ({} windows gated)",
+                label,
+                channel,
+                l10,
+                l50,
+                l90,
+                histogram.gated
+            ),
+            _ => output!(
+                "[{}] LOUDNESS     : {} every window is below -70 LUFS",
+                label,
+                channel
+            ),
+        }
+    }
+}
+
+fn print_drift(label: &str, drift: &DriftAnalysis) {
+    for result in drift.results.iter() {
+        let [first, second] = result.channels;
+
+        match (result.ppm, result.offset_end) {
+            (Some(ppm), Some(offset)) => output!(
+                "[{}] DRIFT        : CH:{}/CH:{} - {:+.2} ppm; offset {:+.2} samples at end ({} blocks)",
+                label,
+                first,
+                second,
+                ppm,
+                offset,
+                result.blocks
+            ),
+            (None, Some(offset)) => output!(
+                "[{}] DRIFT        : CH:{}/CH:{} - offset {:+.2} samples (too short for a drift estimate)",
+                label,
+                first,
+                second,
+                offset
+            ),
+            _ => output!(
+                "[{}] DRIFT        : CH:{}/CH:{} - channels are not correlated",
+                label,
+                first,
+                second
+            ),
+        }
+    }
+}
+
+fn print_pumping(label: &str, sample_rate: i32, pumping: &PumpingAnalysis) {
+    for segment in pumping.results.iter() {
+        output!(
+            "[{}] PUMPING      : depth {:.2} dB at {:.1} Hz ({:06.3}s) {} -> {}",
+            label,
+            segment.depth,
+            segment.rate,
+            segment.duration,
+            frame_to_time(segment.start_sample, sample_rate),
+            frame_to_time(segment.end_sample, sample_rate)
+        );
+    }
+}
+
+fn print_tones(label: &str, sample_rate: i32, tones: &ToneAnalysis) {
+    for segment in tones.results.iter() {
+        output!(
+            "[{}] TONE         : {:.1} Hz ({} Hz) at {:.2} dBFS ({:06.3}s) {} -> {}{}",
+            label,
+            segment.measured_frequency,
+            segment.frequency,
+            segment.level,
+            segment.duration,
+            frame_to_time(segment.start_sample, sample_rate),
+            frame_to_time(segment.end_sample, sample_rate),
+            if segment.head { " (head)" } else { "" }
+        );
+    }
+}
+
+fn print_vad(label: &str, sample_rate: i32, vad: &VadAnalysis) {
+    for segment in vad.results.iter() {
+        output!(
+            "[{}] SPEECH       : ({:06.3}s) {} -> {}",
+            label,
+            segment.duration,
+            frame_to_time(segment.start_sample, sample_rate),
+            frame_to_time(segment.end_sample, sample_rate)
+        );
+    }
+
+    output!(
+        "[{}] VAD          : {:.3}% speech in {} segments",
+        label,
+        vad.speech_percentage,
+        vad.results.len()
+    );
+
+    if let Some(dialogue) = &vad.dialogue {
+        output!(
+            "[{}] DIALOGUE     : {:.2} LUFS dialogue; {:.2} LUFS programme; {:+.2} LU difference over {:.3}s of speech",
+            label,
+            dialogue.dialogue,
+            dialogue.programme,
+            dialogue.difference,
+            dialogue.speech_duration
+        );
+    }
+}
+
+fn print_pitch(label: &str, pitch: &PitchAnalysis) {
+    match &pitch.summary {
+        Some(summary) => output!(
+            "[{}] PITCH        : {:.3}% voiced; min {:.2} Hz, median {:.2} Hz, max {:.2} Hz",
+            label,
+            pitch.voiced_percentage,
+            summary.min,
+            summary.median,
+            summary.max
+        ),
+        None => output!("[{}] PITCH        : no voiced windows", label),
+    }
+}
+
+fn print_novelty(label: &str, sample_rate: i32, novelty: &NoveltyAnalysis) {
+    for peak in novelty.results.iter() {
+        output!(
+            "[{}] NOVELTY      : change of {:.2} dB ({:.1} sd) @ {}",
+            label,
+            peak.novelty,
+            peak.score,
+            frame_to_time(peak.sample, sample_rate)
+        );
+    }
+}
+
+fn print_tempo(label: &str, tempo: &TempoAnalysis) {
+    let beats = tempo
+        .beats
+        .as_ref()
+        .map(|beats| format!(", {} beats", beats.len()))
+        .unwrap_or_default();
+
+    match tempo.bpm {
+        Some(bpm) => output!(
+            "[{}] TEMPO        : {:.2} BPM (confidence {:.2}){}",
+            label,
+            bpm,
+            tempo.confidence,
+            beats
+        ),
+        None => output!(
+            "[{}] TEMPO        : no onsets to tell the tempo from",
+            label
+        ),
+    }
+}
+
+fn print_key(label: &str, key: &KeyAnalysis) {
+    match key.key.zip(key.mode) {
+        Some((tonic, mode)) => output!(
+            "[{}] KEY          : {} {} (correlation {:.2}, confidence {:.2})",
+            label,
+            tonic,
+            mode.as_str(),
+            key.correlation,
+            key.confidence
+        ),
+        None => output!(
+            "[{}] KEY          : nothing loud enough to tell the key from",
+            label
+        ),
+    }
+}
+
+fn print_activity(label: &str, activity: &ActivityAnalysis) {
+    for channel in activity.channels.iter() {
+        output!(
+            "[{}] ACTIVITY     : CH:{} - zero-crossing rate {:.4}; active {:.1}%",
+            label,
+            channel.channel,
+            channel.mean_zero_crossing_rate,
+            channel.activity_factor * 100.0
+        );
+    }
+}
+
+fn print_spectral(label: &str, spectral: &SpectralAnalysis) {
+    let Some(summary) = &spectral.summary else {
+        output!("[{}] SPECTRAL     : no slices with sound", label);
+        return;
+    };
+
+    for (name, stat, unit) in [
+        ("centroid", &summary.centroid, " Hz"),
+        ("flatness", &summary.flatness, ""),
+        ("rolloff", &summary.rolloff, " Hz"),
+        ("bandwidth", &summary.bandwidth, " Hz"),
+    ] {
+        output!(
+            "[{}] SPECTRAL     : {:<9} min {:.3}{unit}, mean {:.3}{unit}, max {:.3}{unit}",
+            label,
+            name,
+            stat.min,
+            stat.mean,
+            stat.max
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! A copy of the input WAV with the segments found as cue points, for `--write-cues`.
+//!
+//! Every segment becomes a cue point in a `cue ` chunk, labelled by a `labl` and given its
+//! length by an `ltxt` chunk in a `LIST` `adtl` chunk, which editors show as regions.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    findings::{Kind, findings},
+    json::Results,
+    output,
+};
+
+/// A region on the timeline, in frames of the input file.
+struct Marker {
+    start: u64,
+    length: u64,
+    label: String,
+}
+
+/// The silence, underruns, stuck samples and clipping of the results as markers, in the
+/// order they start.
+fn markers(results: &Results) -> Vec<Marker> {
+    // Segments are at the analysis rate, which differs with --resample, and start where
+    // --from-end does
+    let scale = results.sample_rate as f64 / results.analysis_sample_rate as f64;
+
+    findings(results)
+        .into_iter()
+        .filter(|finding| {
+            matches!(
+                finding.kind,
+                Kind::Silence | Kind::Underrun | Kind::StuckSample | Kind::Clipping
+            )
+        })
+        .map(|finding| Marker {
+            start: results.file_frame(finding.start) as u64,
+            length: (finding.duration() as f64 * scale).round() as u64,
+            label: match finding.channel {
+                Some(channel) => format!("{} CH:{channel}", finding.kind.label()),
+                None => finding.kind.label().to_string(),
+            },
+        })
+        .collect()
+}
+
+/// A chunk of `id` with `body`, padded to an even length.
+fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(body.len() + 9);
+    chunk.extend_from_slice(id);
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        chunk.push(0);
+    }
+
+    chunk
+}
+
+/// The `cue ` and `LIST` `adtl` chunks of the markers, which are numbered from 1.
+fn cue_chunks(markers: &[Marker]) -> Vec<u8> {
+    let mut points = (markers.len() as u32).to_le_bytes().to_vec();
+    let mut labels = b"adtl".to_vec();
+
+    for (id, marker) in (1u32..).zip(markers) {
+        // Positions are 32 bit, so markers past 2^32 frames end up at the limit
+        let start = marker.start.min(u32::MAX.into()) as u32;
+        let length = marker.length.min(u32::MAX.into()) as u32;
+
+        points.extend_from_slice(&id.to_le_bytes());
+        points.extend_from_slice(&start.to_le_bytes());
+        points.extend_from_slice(b"data");
+        // Chunk and block start, both 0 for a single uncompressed data chunk
+        points.extend_from_slice(&[0; 8]);
+        points.extend_from_slice(&start.to_le_bytes());
+
+        let mut label = id.to_le_bytes().to_vec();
+        label.extend_from_slice(marker.label.as_bytes());
+        label.push(0);
+        labels.extend(chunk(b"labl", &label));
+
+        let mut text = id.to_le_bytes().to_vec();
+        text.extend_from_slice(&length.to_le_bytes());
+        text.extend_from_slice(b"rgn ");
+        // Country, language, dialect and code page
+        text.extend_from_slice(&[0; 8]);
+        labels.extend(chunk(b"ltxt", &text));
+    }
+
+    let mut chunks = chunk(b"cue ", &points);
+    chunks.extend(chunk(b"LIST", &labels));
+
+    chunks
+}
+
+/// Copies the chunks of the WAV file `reader` to `writer`, leaving out cue points and labels
+/// an earlier run or an editor wrote, followed by `extra`.
+fn copy_chunks(
+    reader: &mut impl Read,
+    writer: &mut (impl Write + Seek),
+    extra: &[u8],
+) -> io::Result<()> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a RIFF WAVE file, cue points can't be added to RF64, RIFX, AIFF or CAF files",
+        ));
+    }
+    writer.write_all(&header)?;
+
+    let mut size: u64 = 4;
+    let mut chunk_header = [0; 8];
+    loop {
+        match reader.read_exact(&mut chunk_header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+
+        let id = &chunk_header[0..4];
+        let length = u32::from_le_bytes(chunk_header[4..8].try_into().unwrap_or_default());
+        let padded = u64::from(length) + u64::from(length % 2);
+        let mut body = reader.by_ref().take(padded);
+
+        let mut list_type = [0; 4];
+        let skip = match id {
+            b"cue " => true,
+            b"LIST" if length >= 4 => {
+                body.read_exact(&mut list_type)?;
+                &list_type == b"adtl"
+            }
+            _ => false,
+        };
+
+        if skip {
+            io::copy(&mut body, &mut io::sink())?;
+            continue;
+        }
+
+        writer.write_all(&chunk_header)?;
+        if id == b"LIST" {
+            writer.write_all(&list_type)?;
+        }
+        let copied = io::copy(&mut body, writer)? + if id == b"LIST" { 4 } else { 0 };
+        if copied < u64::from(length) {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "the {} chunk is cut short",
+                    String::from_utf8_lossy(id).trim_end()
+                ),
+            ));
+        }
+        if copied < padded {
+            writer.write_all(&[0])?;
+        }
+
+        size += 8 + padded;
+    }
+
+    writer.write_all(extra)?;
+    size += extra.len() as u64;
+
+    let size = u32::try_from(size).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the file with cue points is larger than 4 GiB",
+        )
+    })?;
+    writer.seek(SeekFrom::Start(4))?;
+    writer.write_all(&size.to_le_bytes())?;
+
+    writer.flush()
+}
+
+/// Copies the input WAV file to the `--write-cues` file with the segments of the results as
+/// cue points.
+pub fn write_cue_points(args: &AnalyseArgs, input: &Path, results: &Results) -> Result<()> {
+    let Some(path) = args.write_cues.as_ref() else {
+        return Ok(());
+    };
+
+    let canonical = |path: &Path| path.canonicalize().ok();
+    if canonical(path).is_some() && canonical(path) == canonical(input) {
+        return Err(Error::Config(format!(
+            "--write-cues can't overwrite the input {}",
+            input.display()
+        )));
+    }
+
+    let markers = markers(results);
+    let mut reader =
+        BufReader::new(File::open(input).map_err(|err| Error::io("read", input, err))?);
+    let mut writer = BufWriter::new(
+        File::create(path).map_err(|err| Error::io("write cue points to", path, err))?,
+    );
+
+    copy_chunks(&mut reader, &mut writer, &cue_chunks(&markers)).map_err(|err| {
+        match err.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => Error::decode(input, err),
+            _ => Error::io("write cue points to", path, err),
+        }
+    })?;
+
+    output!("Wrote {} cue points to {}", markers.len(), path.display());
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+//! Digests of the audio payload of the input, for `--digest`. Only the PCM data is hashed,
+//! so the digest stays the same when the header or metadata of a file are edited.
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+use md5::{Digest, Md5};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sha2::Sha256;
+
+/// Hash functions for `--digest`.
+#[derive(ValueEnum, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha256,
+    Crc32,
+}
+
+impl DigestAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha256 => "sha256",
+            Self::Crc32 => "crc32",
+        }
+    }
+}
+
+/// Digest of the audio payload as written to the JSON output.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct PayloadDigest {
+    pub algorithm: DigestAlgorithm,
+    /// Lowercase hex, the CRC-32 as 8 digits
+    pub value: String,
+    /// Number of bytes hashed: the data chunk of a WAV file, or the whole frames of raw input
+    pub bytes: u64,
+}
+
+/// Hashes the payload as it's written to it.
+pub enum Digester {
+    Md5(Md5, u64),
+    Sha256(Sha256, u64),
+    Crc32(crc32fast::Hasher, u64),
+}
+
+impl Digester {
+    pub fn new(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            DigestAlgorithm::Md5 => Self::Md5(Md5::new(), 0),
+            DigestAlgorithm::Sha256 => Self::Sha256(Sha256::new(), 0),
+            DigestAlgorithm::Crc32 => Self::Crc32(crc32fast::Hasher::new(), 0),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Self::Md5(hasher, len) => {
+                hasher.update(bytes);
+                *len += bytes.len() as u64;
+            }
+            Self::Sha256(hasher, len) => {
+                hasher.update(bytes);
+                *len += bytes.len() as u64;
+            }
+            Self::Crc32(hasher, len) => {
+                hasher.update(bytes);
+                *len += bytes.len() as u64;
+            }
+        }
+    }
+
+    pub fn finish(self) -> PayloadDigest {
+        let (algorithm, value, bytes) = match self {
+            Self::Md5(hasher, len) => (DigestAlgorithm::Md5, hex(&hasher.finalize()), len),
+            Self::Sha256(hasher, len) => (DigestAlgorithm::Sha256, hex(&hasher.finalize()), len),
+            Self::Crc32(hasher, len) => (
+                DigestAlgorithm::Crc32,
+                format!("{:08x}", hasher.finalize()),
+                len,
+            ),
+        };
+
+        PayloadDigest {
+            algorithm,
+            value,
+            bytes,
+        }
+    }
+}
+
+impl Write for Digester {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.update(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
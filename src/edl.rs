@@ -0,0 +1,128 @@
+//! The findings as an edit decision list for `--edl`, in the CMX3600 format that Pro Tools
+//! (through marker tools), Resolve and most editors import.
+//!
+//! Every finding becomes an audio event spanning it on the aux reel, followed by a `* LOC:`
+//! comment that importers turn into a locator, coloured by the severity of the finding.
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use clap::ValueEnum;
+
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    findings::{Finding, Severity, findings},
+    json::Results,
+    output,
+    output::TimeFormat,
+    warn,
+};
+
+/// CMX3600 numbers events with three digits.
+const MAX_EVENTS: usize = 999;
+
+/// Timecode rate of `--edl`.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EdlRate {
+    /// 25 fps
+    #[default]
+    #[value(name = "25")]
+    Fps25,
+    /// 29.97 fps drop-frame
+    #[value(name = "29.97")]
+    Fps2997,
+}
+
+impl EdlRate {
+    fn fps(self) -> f64 {
+        match self {
+            Self::Fps25 => 25.0,
+            Self::Fps2997 => 30_000.0 / 1001.0,
+        }
+    }
+
+    fn time_format(self) -> TimeFormat {
+        match self {
+            Self::Fps25 => TimeFormat::Smpte25,
+            Self::Fps2997 => TimeFormat::Smpte2997,
+        }
+    }
+
+    fn frame_code_mode(self) -> &'static str {
+        match self {
+            Self::Fps25 => "NON-DROP FRAME",
+            Self::Fps2997 => "DROP FRAME",
+        }
+    }
+}
+
+fn colour(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "RED",
+        Severity::Warning => "YELLOW",
+        Severity::Info => "BLUE",
+    }
+}
+
+/// The EDL of the findings of `results` for the input file `input`, up to `MAX_EVENTS`.
+fn edl(input: &Path, results: &Results, found: &[Finding], rate: EdlRate) -> String {
+    let name = input
+        .file_name()
+        .map_or("input".into(), |name| name.to_string_lossy());
+    let title = input
+        .file_stem()
+        .map_or("input".into(), |stem| stem.to_string_lossy());
+
+    let sample_rate = results.sample_rate;
+    // Events without a duration still need to span a timecode frame
+    let min_length = (sample_rate as f64 / rate.fps()).ceil() as usize;
+    let timecode = |frame: usize| rate.time_format().format(frame, sample_rate);
+
+    let mut edl = format!("TITLE: {title}\nFCM: {}\n", rate.frame_code_mode());
+
+    for (number, finding) in (1..=MAX_EVENTS).zip(found) {
+        let start = results.file_frame(finding.start);
+        let end = results.file_frame(finding.end).max(start + min_length);
+        let (start, end) = (timecode(start), timecode(end));
+        let label = match finding.channel {
+            Some(channel) => format!("{} CH:{channel}", finding.kind.label()),
+            None => finding.kind.label().to_string(),
+        };
+
+        let _ = write!(
+            edl,
+            "\n{number:03}  AX       A     C        {start} {end} {start} {end}\n\
+             * FROM CLIP NAME: {name}\n\
+             * LOC: {start} {:<7} {label}\n",
+            colour(finding.kind.severity())
+        );
+    }
+
+    edl
+}
+
+/// Writes the findings of the results to the `--edl` file.
+pub fn write_edl(args: &AnalyseArgs, input: &Path, results: &Results) -> Result<()> {
+    let Some(path) = args.edl.as_ref() else {
+        return Ok(());
+    };
+
+    let found = findings(results);
+    if found.len() > MAX_EVENTS {
+        warn!(
+            "an EDL holds {} events, the last {} findings are left out",
+            MAX_EVENTS,
+            found.len() - MAX_EVENTS
+        );
+    }
+
+    let edl = edl(input, results, &found, args.edl_rate);
+    fs::write(path, edl).map_err(|err| Error::io("write EDL to", path, err))?;
+    output!(
+        "Wrote {} locators to {}",
+        found.len().min(MAX_EVENTS),
+        path.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+use wavers::WaversError;
+
+use crate::stream::StreamError;
+
+type Source = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    /// An input or output file could not be opened, read or written.
+    #[error("Could not {operation} {}: {source}", path.display())]
+    Io {
+        operation: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// An input file was read but its contents are not valid.
+    #[error("Could not decode {}: {source}", path.display())]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: Source,
+    },
+    /// The given options can't be used together or with this input.
+    #[error("{0}")]
+    Config(String),
+    /// An analyser failed to set up or run.
+    #[error("{analyser}: {source}")]
+    Analyser {
+        analyser: &'static str,
+        #[source]
+        source: Source,
+    },
+    /// The analysis was stopped by SIGINT or `--timeout` before it produced results, or by
+    /// SIGINT while the analysers were writing their outputs.
+    #[error("The analysis was cancelled")]
+    Cancelled,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Exit codes of the error categories, following sysexits.h. They are above the range of
+/// the analyser return code bits, so the two can't be confused.
+pub const EXIT_CONFIG: u8 = 64;
+pub const EXIT_DECODE: u8 = 65;
+pub const EXIT_ANALYSER: u8 = 70;
+pub const EXIT_IO: u8 = 74;
+/// The analysis stopped early on SIGINT or `--timeout`, its outputs hold partial results.
+pub const EXIT_CANCELLED: u8 = 75;
+
+impl Error {
+    pub fn io(
+        operation: &'static str,
+        path: impl Into<PathBuf>,
+        source: impl Into<io::Error>,
+    ) -> Self {
+        Self::Io {
+            operation,
+            path: path.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn decode(path: impl Into<PathBuf>, source: impl Into<Source>) -> Self {
+        Self::Decode {
+            path: path.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Sorts a failure to read a WAV file into I/O and decoding errors.
+    pub fn wav(path: impl Into<PathBuf>, err: WaversError) -> Self {
+        match err {
+            // Malformed headers surface as invalid data or a short read
+            WaversError::IoError(source)
+                if matches!(
+                    source.kind(),
+                    io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                Self::decode(path, source)
+            }
+            WaversError::IoError(source) => Self::io("read", path, source),
+            err => Self::decode(path, err),
+        }
+    }
+
+    pub fn analyser(analyser: &'static str, source: impl Into<Source>) -> Self {
+        Self::Analyser {
+            analyser,
+            source: source.into(),
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Io { .. } => EXIT_IO,
+            Self::Decode { .. } => EXIT_DECODE,
+            Self::Config(_) => EXIT_CONFIG,
+            Self::Analyser { .. } => EXIT_ANALYSER,
+            Self::Cancelled => EXIT_CANCELLED,
+        }
+    }
+}
+
+impl From<StreamError> for Error {
+    fn from(err: StreamError) -> Self {
+        Self::analyser("stream", err)
+    }
+}
@@ -0,0 +1,147 @@
+//! `--exclude`: time ranges left out of the loudness and silence statistics, e.g. line-up
+//! tone or a countdown clock before the programme. The meters skip the frames in them, and
+//! silence percentages are of the frames that were measured.
+
+use std::{ops::Range, str::FromStr};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::stream::StreamSpec;
+
+/// A range of `--exclude` in seconds, `end` is `None` for the end of the stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+/// Seconds from `SS`, `MM:SS` or `HH:MM:SS`, with an optional fraction of the seconds.
+fn parse_time(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let (seconds, minutes) = parts.split_last()?;
+    if minutes.len() > 2 {
+        return None;
+    }
+
+    let mut total = 0.0;
+    for part in minutes {
+        total = total * 60.0 + part.parse::<u32>().ok()? as f64;
+    }
+    let seconds: f64 = seconds.parse().ok()?;
+
+    (seconds >= 0.0 && seconds.is_finite()).then_some(total * 60.0 + seconds)
+}
+
+impl FromStr for TimeRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{value}' is not a range like 00:00-00:10 or 01:20:00-end");
+
+        let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+        let start = match start.trim() {
+            "start" => 0.0,
+            start => parse_time(start).ok_or_else(invalid)?,
+        };
+        let end = match end.trim() {
+            "end" => None,
+            end => Some(parse_time(end).ok_or_else(invalid)?),
+        };
+
+        if end.is_some_and(|end| end <= start) {
+            return Err(format!("the range '{value}' ends before it starts"));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// An excluded range as written to the JSON output, clipped to the stream.
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct ExcludedRange {
+    pub start: f64,
+    pub end: f64,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+}
+
+/// The frames of the `--exclude` ranges, sorted and merged.
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions {
+    ranges: Vec<Range<usize>>,
+    sample_rate: i32,
+}
+
+impl Exclusions {
+    pub fn new(ranges: &[TimeRange], spec: &StreamSpec) -> Self {
+        let frame = |seconds: f64| {
+            ((seconds * spec.sample_rate as f64).round() as usize).min(spec.num_frames)
+        };
+
+        let mut frames: Vec<Range<usize>> = ranges
+            .iter()
+            .map(|range| frame(range.start)..range.end.map_or(spec.num_frames, frame))
+            .filter(|range| !range.is_empty())
+            .collect();
+        frames.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(frames.len());
+        for range in frames {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+
+        Self {
+            ranges: merged,
+            sample_rate: spec.sample_rate,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, frame: usize) -> bool {
+        let index = self.ranges.partition_point(|range| range.end <= frame);
+        self.ranges
+            .get(index)
+            .is_some_and(|range| range.contains(&frame))
+    }
+
+    /// Frames excluded before `frame`.
+    pub fn before(&self, frame: usize) -> usize {
+        self.ranges
+            .iter()
+            .map(|range| range.end.min(frame).saturating_sub(range.start))
+            .sum()
+    }
+
+    /// First frame that isn't excluded, where the programme starts.
+    pub fn first_included(&self) -> usize {
+        match self.ranges.first() {
+            Some(range) if range.start == 0 => range.end,
+            _ => 0,
+        }
+    }
+
+    pub fn results(&self) -> Option<Vec<ExcludedRange>> {
+        let seconds = |frame: usize| frame as f64 / self.sample_rate as f64;
+
+        (!self.is_empty()).then(|| {
+            self.ranges
+                .iter()
+                .map(|range| ExcludedRange {
+                    start: seconds(range.start),
+                    end: seconds(range.end),
+                    start_sample: range.start,
+                    end_sample: range.end,
+                })
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,115 @@
+//! C interface for calling the analysers from other languages, built with the `ffi` feature.
+//! The declarations are in `include/analwave.h`.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_int},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr,
+};
+
+use crate::{Error, Result, cli::AnalyseArgs, error::EXIT_ANALYSER, json::to_json, pipeline};
+
+thread_local! {
+    /// Error message and exit code of the last call on this thread.
+    static LAST: RefCell<(Option<CString>, u8)> = const { RefCell::new((None, 0)) };
+}
+
+fn analyse_file(path: &Path, config: &str) -> Result<(u8, String)> {
+    let args = AnalyseArgs::from_config(config)?;
+    let (exit_code, results) = pipeline::analyse_file(&args, path)?;
+    let json = to_json(&results).map_err(|err| Error::analyser("json", err))?;
+
+    Ok((exit_code, json))
+}
+
+/// The string behind `pointer`, which has to be valid UTF-8 if it isn't null.
+///
+/// # Safety
+///
+/// `pointer` has to be null or point to a NUL-terminated string.
+unsafe fn string<'a>(pointer: *const c_char, name: &str) -> Result<&'a str> {
+    if pointer.is_null() {
+        return Ok("");
+    }
+
+    // SAFETY: the caller guarantees a NUL-terminated string
+    unsafe { CStr::from_ptr(pointer) }
+        .to_str()
+        .map_err(|_| Error::Config(format!("The {name} is not valid UTF-8")))
+}
+
+/// Analyses the WAV file at `path` with the options in `config_json`, e.g.
+/// `{"underrun": true, "silence": true, "lufs": -50}`. Output files the options ask for are
+/// written as on the command line. Returns the JSON report, to be released with
+/// `analwave_free_string`, or null on errors, see `analwave_last_error`.
+///
+/// # Safety
+///
+/// `path` has to point to a NUL-terminated string, `config_json` has to be null or point to
+/// one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn analwave_analyse_file(
+    path: *const c_char,
+    config_json: *const c_char,
+) -> *mut c_char {
+    // SAFETY: both are NUL-terminated strings or null, as documented
+    let strings = unsafe { (string(path, "path"), string(config_json, "configuration")) };
+    // A panic can't unwind into the caller, it fails the call like an analyser error
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match strings {
+        (Ok(""), _) => Err(Error::Config("No input file given".to_string())),
+        (Ok(path), Ok(config)) => analyse_file(Path::new(path), config),
+        (Err(err), _) | (_, Err(err)) => Err(err),
+    }));
+
+    let (error, exit_code, report) = match result {
+        Ok(Ok((exit_code, json))) => (None, exit_code, CString::new(json).ok()),
+        Ok(Err(err)) => (CString::new(err.to_string()).ok(), err.exit_code(), None),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            let error = format!("The analysis panicked: {message}");
+            (CString::new(error).ok(), EXIT_ANALYSER, None)
+        }
+    };
+    LAST.with(|last| *last.borrow_mut() = (error, exit_code));
+
+    report.map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Return code of the last `analwave_analyse_file` on this thread: the bits of the failed
+/// checks, or the exit code of the error, as the command line tool exits with.
+#[unsafe(no_mangle)]
+pub extern "C" fn analwave_last_exit_code() -> c_int {
+    LAST.with(|last| last.borrow().1.into())
+}
+
+/// Message of the error of the last `analwave_analyse_file` on this thread, null if it
+/// succeeded. The string stays valid until the next call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn analwave_last_error() -> *const c_char {
+    LAST.with(|last| {
+        last.borrow()
+            .0
+            .as_ref()
+            .map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// Releases a report returned by `analwave_analyse_file`.
+///
+/// # Safety
+///
+/// `report` has to be null or a pointer returned by `analwave_analyse_file` that hasn't been
+/// released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn analwave_free_string(report: *mut c_char) {
+    if !report.is_null() {
+        // SAFETY: the pointer came from CString::into_raw, as documented
+        drop(unsafe { CString::from_raw(report) });
+    }
+}
@@ -0,0 +1,376 @@
+//! The segments the analysers found, in one model for outputs that cover all of them, and
+//! the `--summary` that ranks them.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{json::Results, output, output::frame_to_time};
+
+/// How much a finding matters for delivery.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth knowing, e.g. a line-up tone
+    Info,
+    /// Often intended, but worth a look, e.g. silence
+    Warning,
+    /// A fault in the audio, e.g. an underrun
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// What was found.
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Kind {
+    Underrun,
+    StuckSample,
+    Clipping,
+    Discontinuity,
+    Silence,
+    ChannelImbalance,
+    Pumping,
+    Tone,
+    Dtmf,
+    Novelty,
+}
+
+impl Kind {
+    /// Name of the kind as in the JSON output.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Underrun => "underrun",
+            Self::StuckSample => "stuckSample",
+            Self::Clipping => "clipping",
+            Self::Discontinuity => "discontinuity",
+            Self::Silence => "silence",
+            Self::ChannelImbalance => "channelImbalance",
+            Self::Pumping => "pumping",
+            Self::Tone => "tone",
+            Self::Dtmf => "dtmf",
+            Self::Novelty => "novelty",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            Self::Underrun | Self::StuckSample | Self::Clipping | Self::Discontinuity => {
+                Severity::Error
+            }
+            Self::Silence | Self::ChannelImbalance | Self::Pumping => Severity::Warning,
+            Self::Tone | Self::Dtmf | Self::Novelty => Severity::Info,
+        }
+    }
+
+    /// Name of one finding, capitalised, as in labels.
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Underrun => "Underrun",
+            Self::StuckSample => "Stuck",
+            Self::Clipping => "Clipping",
+            Self::Discontinuity => "Discontinuity",
+            Self::Silence => "Silence",
+            Self::ChannelImbalance => "Imbalance",
+            Self::Pumping => "Pumping",
+            Self::Tone => "Tone",
+            Self::Dtmf => "DTMF",
+            Self::Novelty => "Change",
+        }
+    }
+
+    /// `count` findings, e.g. "3 underruns".
+    fn counted(self, count: usize) -> String {
+        let (one, many) = match self {
+            Self::Underrun => ("underrun", "underruns"),
+            Self::StuckSample => ("stuck sample run", "stuck sample runs"),
+            Self::Clipping => ("clipped region", "clipped regions"),
+            Self::Discontinuity => ("discontinuity", "discontinuities"),
+            Self::Silence => ("silent region", "silent regions"),
+            Self::ChannelImbalance => ("imbalanced region", "imbalanced regions"),
+            Self::Pumping => ("pumping region", "pumping regions"),
+            Self::Tone => ("tone", "tones"),
+            Self::Dtmf => ("DTMF tone", "DTMF tones"),
+            Self::Novelty => ("content change", "content changes"),
+        };
+
+        format!("{count} {}", if count == 1 { one } else { many })
+    }
+}
+
+/// A segment an analyser found, in frames at the analysis sample rate. Events without a
+/// duration, like discontinuities, start and end at the same frame.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub kind: Kind,
+    pub channel: Option<usize>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Finding {
+    pub fn new(kind: Kind, channel: Option<usize>, start: usize, end: usize) -> Self {
+        Self {
+            kind,
+            channel,
+            start,
+            end,
+        }
+    }
+
+    pub fn duration(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Everything the analysers of `results` found, in the order they start.
+pub fn findings(results: &Results) -> Vec<Finding> {
+    let analysis = &results.analysis;
+    let mut findings = Vec::new();
+
+    if let Some(underruns) = &analysis.underruns {
+        findings.extend(underruns.results.iter().map(|segment| {
+            Finding::new(
+                Kind::Underrun,
+                Some(segment.channel),
+                segment.start_sample,
+                segment.end_sample,
+            )
+        }));
+        findings.extend(underruns.stuck_segments.iter().flatten().map(|segment| {
+            Finding::new(
+                Kind::StuckSample,
+                Some(segment.channel),
+                segment.start_sample,
+                segment.end_sample,
+            )
+        }));
+    }
+    if let Some(dynamics) = &analysis.dynamics {
+        findings.extend(dynamics.clipping_segments.iter().map(|segment| {
+            Finding::new(
+                Kind::Clipping,
+                Some(segment.channel),
+                segment.start_sample,
+                segment.end_sample,
+            )
+        }));
+    }
+    if let Some(discontinuities) = &analysis.discontinuities {
+        findings.extend(discontinuities.results.iter().map(|event| {
+            Finding::new(
+                Kind::Discontinuity,
+                Some(event.channel),
+                event.sample,
+                event.sample,
+            )
+        }));
+    }
+    if let Some(silence) = &analysis.silence {
+        findings.extend(silence.results.iter().map(|segment| {
+            Finding::new(
+                Kind::Silence,
+                segment.channel,
+                segment.start_sample,
+                segment.end_sample,
+            )
+        }));
+    }
+    if let Some(balance) = &analysis.channel_balance {
+        findings.extend(balance.results.iter().map(|segment| {
+            Finding::new(
+                Kind::ChannelImbalance,
+                Some(segment.channel),
+                segment.start_sample,
+                segment.end_sample,
+            )
+        }));
+    }
+    if let Some(pumping) = &analysis.pumping {
+        findings.extend(pumping.results.iter().map(|segment| {
+            Finding::new(
+                Kind::Pumping,
+                None,
+                segment.start_sample,
+                segment.end_sample,
+            )
+        }));
+    }
+    if let Some(tones) = &analysis.tones {
+        findings.extend(tones.results.iter().map(|segment| {
+            Finding::new(Kind::Tone, None, segment.start_sample, segment.end_sample)
+        }));
+    }
+    if let Some(dtmf) = &analysis.dtmf {
+        findings.extend(dtmf.results.iter().map(|segment| {
+            Finding::new(
+                Kind::Dtmf,
+                Some(segment.channel),
+                segment.start_sample,
+                segment.end_sample,
+            )
+        }));
+    }
+
+    if let Some(novelty) = &analysis.novelty {
+        findings.extend(
+            novelty
+                .results
+                .iter()
+                .map(|peak| Finding::new(Kind::Novelty, None, peak.sample, peak.sample)),
+        );
+    }
+
+    findings.sort_by_key(|finding| finding.start);
+
+    findings
+}
+
+/// The longest finding of a kind.
+#[derive(Serialize, JsonSchema)]
+pub struct WorstFinding {
+    pub start: f32,
+    pub end: f32,
+    pub duration: f32,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "endSample")]
+    pub end_sample: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<usize>,
+}
+
+/// The findings of one kind.
+#[derive(Serialize, JsonSchema)]
+pub struct SummaryEntry {
+    pub kind: Kind,
+    pub severity: Severity,
+    pub count: usize,
+    /// Seconds of all findings of the kind together
+    #[serde(rename = "totalDuration")]
+    pub total_duration: f32,
+    /// Share of the duration in percent, of all channels for findings on a channel
+    pub share: f64,
+    /// The longest finding, the first one if they have no duration
+    pub worst: WorstFinding,
+}
+
+/// The findings of all analysers by kind, the most severe and longest first.
+#[derive(Serialize, JsonSchema)]
+pub struct Summary {
+    pub results: Vec<SummaryEntry>,
+}
+
+impl Summary {
+    pub fn new(results: &Results) -> Self {
+        let rate = results.analysis_sample_rate as f32;
+        let frames = results.duration as f64 * results.analysis_sample_rate as f64;
+        let findings = findings(results);
+
+        let mut kinds: Vec<Kind> = Vec::new();
+        for finding in &findings {
+            if !kinds.contains(&finding.kind) {
+                kinds.push(finding.kind);
+            }
+        }
+
+        let mut entries: Vec<SummaryEntry> = kinds
+            .into_iter()
+            .filter_map(|kind| {
+                let of_kind: Vec<&Finding> = findings
+                    .iter()
+                    .filter(|finding| finding.kind == kind)
+                    .collect();
+                // The first of the longest
+                let worst = of_kind
+                    .iter()
+                    .rev()
+                    .max_by_key(|finding| finding.duration())?;
+                let total: usize = of_kind.iter().map(|finding| finding.duration()).sum();
+                let channels = if of_kind.iter().any(|finding| finding.channel.is_some()) {
+                    results.num_channels.into()
+                } else {
+                    1.0
+                };
+
+                Some(SummaryEntry {
+                    kind,
+                    severity: kind.severity(),
+                    count: of_kind.len(),
+                    total_duration: total as f32 / rate,
+                    share: if frames > 0.0 {
+                        (100.0 * total as f64 / (frames * channels)).min(100.0)
+                    } else {
+                        0.0
+                    },
+                    worst: WorstFinding {
+                        start: worst.start as f32 / rate,
+                        end: worst.end as f32 / rate,
+                        duration: worst.duration() as f32 / rate,
+                        start_sample: worst.start,
+                        end_sample: worst.end,
+                        channel: worst.channel,
+                    },
+                })
+            })
+            .collect();
+
+        entries.sort_by(|a, b| {
+            b.severity
+                .cmp(&a.severity)
+                .then(b.total_duration.total_cmp(&a.total_duration))
+                .then(b.count.cmp(&a.count))
+        });
+
+        Self { results: entries }
+    }
+
+    pub fn print(&self, sample_rate: i32) {
+        output!("[+] SUMMARY");
+        if self.results.is_empty() {
+            output!("    nothing found");
+        }
+
+        for entry in &self.results {
+            let worst = &entry.worst;
+            let channel = worst
+                .channel
+                .map(|channel| format!(" on CH:{channel}"))
+                .unwrap_or_default();
+            let at = frame_to_time(worst.start_sample, sample_rate);
+            let detail = if worst.duration > 0.0 {
+                format!(
+                    "worst {} @ {at}{channel}, total {:.1}%",
+                    duration(worst.duration),
+                    entry.share
+                )
+            } else {
+                format!("first @ {at}{channel}")
+            };
+
+            output!(
+                "    {:<8} {} ({})",
+                entry.severity.as_str(),
+                entry.kind.counted(entry.count),
+                detail
+            );
+        }
+    }
+}
+
+/// `seconds` in milliseconds below a second.
+fn duration(seconds: f32) -> String {
+    if seconds < 1.0 {
+        format!("{:.0} ms", seconds * 1000.0)
+    } else {
+        format!("{seconds:.1} s")
+    }
+}
@@ -0,0 +1,183 @@
+use std::{f64::consts::PI, path::Path};
+
+use clap::ValueEnum;
+use ebur128::{EbuR128, Mode};
+use wavers::{Samples, WaversError};
+
+use crate::{Error, Result, cli::GenerateArgs};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// White noise calibrated to --lufs
+    White,
+    /// Pink noise calibrated to --lufs
+    Pink,
+    /// Sine tone at --frequency and --level
+    Tone,
+    /// Exponential sine sweep from --start-frequency to --end-frequency at --level
+    Sweep,
+    /// Digital silence
+    Silence,
+}
+
+/// Rendered test signal as interleaved samples in the -1.0..=1.0 range.
+pub struct Generated {
+    pub samples: Vec<f64>,
+    /// Integrated loudness of the rendered signal, if it is measurable.
+    pub loudness: Option<f64>,
+    pub peak: f64,
+}
+
+/// Small xorshift64* generator, so signals are reproducible for a given seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    /// Uniform sample in -1.0..1.0
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let v = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d);
+
+        (v >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+/// Paul Kellet's refined pink noise filter.
+#[derive(Default, Clone)]
+struct PinkFilter {
+    b: [f64; 7],
+}
+
+impl PinkFilter {
+    fn next(&mut self, white: f64) -> f64 {
+        let b = &mut self.b;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+
+        pink * 0.11
+    }
+}
+
+fn db_to_gain(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+pub fn render(args: &GenerateArgs) -> Generated {
+    let channels = args.channels.max(1) as usize;
+    let sample_rate = args.sample_rate as f64;
+    let num_frames = (args.duration * sample_rate).round() as usize;
+    let mut samples = vec![0.0; num_frames * channels];
+
+    match args.signal {
+        Signal::White | Signal::Pink => {
+            let mut rng = Rng::new(args.seed);
+            let mut filters = vec![PinkFilter::default(); channels];
+
+            for frame in samples.chunks_exact_mut(channels) {
+                for (sample, filter) in frame.iter_mut().zip(filters.iter_mut()) {
+                    let white = rng.next();
+                    *sample = match args.signal {
+                        Signal::Pink => filter.next(white),
+                        _ => white * 0.5,
+                    };
+                }
+            }
+        }
+        Signal::Tone => {
+            let gain = db_to_gain(args.level);
+
+            for (i, frame) in samples.chunks_exact_mut(channels).enumerate() {
+                let v = gain * (2.0 * PI * args.frequency * i as f64 / sample_rate).sin();
+                frame.fill(v);
+            }
+        }
+        Signal::Sweep => {
+            let gain = db_to_gain(args.level);
+            let f0 = args.start_frequency.max(1.0);
+            let f1 = args.end_frequency.max(f0 + 1.0);
+            let rate = (f1 / f0).ln();
+
+            for (i, frame) in samples.chunks_exact_mut(channels).enumerate() {
+                let t = i as f64 / sample_rate;
+                let phase =
+                    2.0 * PI * f0 * args.duration / rate * ((t / args.duration * rate).exp() - 1.0);
+                frame.fill(gain * phase.sin());
+            }
+        }
+        Signal::Silence => {}
+    }
+
+    // Noise is calibrated to the target loudness, other signals keep their level
+    if matches!(args.signal, Signal::White | Signal::Pink)
+        && let Some(measured) = measure(&samples, channels, args.sample_rate)
+    {
+        let gain = db_to_gain(args.lufs - measured);
+        samples.iter_mut().for_each(|v| *v *= gain);
+    }
+
+    if let (Some(every), Some(length)) = (args.gap_every, args.gap_length) {
+        let every = (every * sample_rate) as usize;
+        let length = (length * sample_rate) as usize;
+
+        if every > 0 {
+            for (i, frame) in samples.chunks_exact_mut(channels).enumerate() {
+                let position = i % every;
+                if position >= every.saturating_sub(length) {
+                    frame.fill(0.0);
+                }
+            }
+        }
+    }
+
+    let loudness = measure(&samples, channels, args.sample_rate);
+    let peak = samples.iter().fold(0.0f64, |peak, v| peak.max(v.abs()));
+
+    Generated {
+        samples,
+        loudness,
+        peak,
+    }
+}
+
+fn measure(samples: &[f64], channels: usize, sample_rate: u32) -> Option<f64> {
+    let mut meter = EbuR128::new(channels as u32, sample_rate, Mode::I).ok()?;
+    meter.add_frames_f64(samples).ok()?;
+
+    meter.loudness_global().ok().filter(|lufs| lufs.is_finite())
+}
+
+pub fn write<P>(path: P, args: &GenerateArgs, samples: &[f64]) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let clamped: Vec<f64> = samples.iter().map(|v| v.clamp(-1.0, 1.0)).collect();
+    let channels = args.channels.max(1);
+    let sample_rate = args.sample_rate as i32;
+
+    let result = if args.bits == 32 {
+        let samples: Samples<i32> = Samples::from(clamped).convert();
+        wavers::write(path.as_ref(), &samples, sample_rate, channels)
+    } else {
+        let samples: Samples<i16> = Samples::from(clamped).convert();
+        wavers::write(path.as_ref(), &samples, sample_rate, channels)
+    };
+
+    result.map_err(|err| match err {
+        WaversError::IoError(source) => Error::io("write", path.as_ref(), source),
+        err => Error::Config(format!(
+            "Could not write {}: {err}",
+            path.as_ref().display()
+        )),
+    })
+}
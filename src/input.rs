@@ -0,0 +1,905 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+use wavers::Wav;
+
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    digest::{DigestAlgorithm, Digester, PayloadDigest},
+    inspect,
+    layout::speaker_count,
+    stream::StreamSpec,
+};
+
+mod aiff;
+mod caf;
+mod downmix;
+mod gain;
+mod resample;
+
+pub use downmix::{DownmixInfo, DownmixTarget};
+pub use gain::GainInfo;
+
+use downmix::Downmix;
+use gain::Gain;
+use resample::Resampler;
+
+/// Frames decoded per read from a raw input.
+const RAW_BLOCK_FRAMES: usize = 4096;
+
+/// Sample encodings of headerless PCM input.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat {
+    /// Unsigned 8 bit
+    U8,
+    /// Signed 8 bit
+    S8,
+    /// Signed 16 bit little-endian
+    S16le,
+    /// Signed 16 bit big-endian
+    S16be,
+    /// Signed 24 bit little-endian, packed in 3 bytes
+    S24le,
+    /// Signed 24 bit big-endian, packed in 3 bytes
+    S24be,
+    /// Signed 32 bit little-endian
+    S32le,
+    /// Signed 32 bit big-endian
+    S32be,
+    /// 32 bit float little-endian
+    F32le,
+    /// 32 bit float big-endian
+    F32be,
+}
+
+impl RawFormat {
+    pub fn bytes(&self) -> usize {
+        match self {
+            Self::U8 | Self::S8 => 1,
+            Self::S16le | Self::S16be => 2,
+            Self::S24le | Self::S24be => 3,
+            Self::S32le | Self::S32be | Self::F32le | Self::F32be => 4,
+        }
+    }
+
+    /// Bits per sample of the integer formats.
+    pub fn bits(&self) -> Option<u16> {
+        match self {
+            Self::F32le | Self::F32be => None,
+            _ => Some(self.bytes() as u16 * 8),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::U8 => "u8",
+            Self::S8 => "s8",
+            Self::S16le => "s16le",
+            Self::S16be => "s16be",
+            Self::S24le => "s24le",
+            Self::S24be => "s24be",
+            Self::S32le => "s32le",
+            Self::S32be => "s32be",
+            Self::F32le => "f32le",
+            Self::F32be => "f32be",
+        }
+    }
+
+    /// Converts one sample to the full i32 range, the same way WAV input is scaled.
+    fn decode(&self, b: &[u8]) -> i32 {
+        match self {
+            Self::U8 => (b[0] as i32 - 128) << 24,
+            Self::S8 => (b[0] as i8 as i32) << 24,
+            Self::S16le => (i16::from_le_bytes([b[0], b[1]]) as i32) << 16,
+            Self::S16be => (i16::from_be_bytes([b[0], b[1]]) as i32) << 16,
+            Self::S24le => i32::from_le_bytes([0, b[0], b[1], b[2]]),
+            Self::S24be => i32::from_be_bytes([b[0], b[1], b[2], 0]),
+            Self::S32le => i32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            Self::S32be => i32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+            Self::F32le => float_to_i32(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            Self::F32be => float_to_i32(f32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        }
+    }
+}
+
+fn float_to_i32(value: f32) -> i32 {
+    (value * i32::MAX as f32)
+        .clamp(i32::MIN as f32, i32::MAX as f32)
+        .round() as i32
+}
+
+/// The PCM payload of a container, at `data` in the file.
+struct Payload {
+    format: RawFormat,
+    spec: StreamSpec,
+    data: Range<u64>,
+}
+
+/// Reads headerless interleaved PCM in the format given with `--raw`, or the payload of a
+/// container wavers can't open.
+pub struct RawReader {
+    format: RawFormat,
+    path: PathBuf,
+    reader: std::io::Take<BufReader<File>>,
+    /// Position of the first frame in the file
+    offset: u64,
+    spec: StreamSpec,
+}
+
+impl RawReader {
+    pub fn open(args: &AnalyseArgs, path: &Path) -> Result<Self> {
+        let (Some(rate), Some(channels), Some(format)) = (args.rate, args.channels, args.format)
+        else {
+            return Err(Error::Config(
+                "--raw needs --rate, --channels and --format".to_string(),
+            ));
+        };
+
+        let file = File::open(path).map_err(|err| Error::io("read", path, err))?;
+        let size = file
+            .metadata()
+            .map_err(|err| Error::io("read", path, err))?
+            .len();
+        let spec = StreamSpec {
+            sample_rate: rate,
+            channels,
+            num_frames: 0,
+            channel_mask: None,
+            bits_per_sample: format.bits(),
+        };
+
+        Self::new(path, file, format, spec, 0..size)
+    }
+
+    /// Opens `path` if it is in a container other than RIFF/WAVE: RF64 or BW64, the 64-bit
+    /// variants of WAV, AIFF or AIFF-C, or CAF. With `--container-check` a truncated RIFF/WAVE
+    /// file is opened too, and the length is the one of the header rather than what the file
+    /// holds, so the check can tell the frames that are missing.
+    pub fn open_container(args: &AnalyseArgs, path: &Path) -> Result<Option<Self>> {
+        let mut header = [0; 12];
+        let mut file = File::open(path).map_err(|err| Error::io("read", path, err))?;
+        let size = file
+            .metadata()
+            .map_err(|err| Error::io("read", path, err))?
+            .len();
+        if file.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+
+        let payload = match (&header[0..4], &header[8..12]) {
+            (b"RF64" | b"BW64", _) => Self::riff(path, inspect::inspect(path)?)?,
+            // wavers fails on the first frame past the end of the file
+            (b"RIFF", b"WAVE") if args.container_check => {
+                let inspection = inspect::inspect(path)?;
+                if !inspection.truncated {
+                    return Ok(None);
+                }
+                Self::riff(path, inspection)?
+            }
+            (b"FORM", b"AIFF") => aiff::open(path, &mut file, size, false)?,
+            (b"FORM", b"AIFC") => aiff::open(path, &mut file, size, true)?,
+            (b"caff", _) => caf::open(path, &mut file, size)?,
+            _ => return Ok(None),
+        };
+
+        let data = if args.container_check {
+            payload.data
+        } else {
+            payload.data.start..payload.data.end.min(size).max(payload.data.start)
+        };
+
+        Self::new(path, file, payload.format, payload.spec, data).map(Some)
+    }
+
+    /// The payload of a WAV file as `inspection` found it, for RF64 and BW64 from their
+    /// `ds64` and data chunks.
+    fn riff(path: &Path, inspection: inspect::Inspection) -> Result<Payload> {
+        let format = match (inspection.encoding.as_str(), inspection.bits_per_sample) {
+            ("pcm", 8) => RawFormat::U8,
+            ("pcm", 16) => RawFormat::S16le,
+            ("pcm", 24) => RawFormat::S24le,
+            ("pcm", 32) => RawFormat::S32le,
+            ("float", 32) => RawFormat::F32le,
+            (encoding, bits) => {
+                return Err(Error::decode(
+                    path,
+                    format!(
+                        "{bits} bit {encoding} samples aren't supported in {} files",
+                        inspection.container
+                    ),
+                ));
+            }
+        };
+        let Some(data) = inspection.data() else {
+            return Err(Error::decode(path, "no data chunk"));
+        };
+        if inspection.channels == 0 || inspection.sample_rate == 0 {
+            return Err(Error::decode(
+                path,
+                "fmt chunk has no channels or sample rate",
+            ));
+        }
+        if inspection.block_align as usize != format.bytes() * inspection.channels as usize {
+            return Err(Error::decode(
+                path,
+                format!(
+                    "fmt chunk has a block align of {} for {} channels of {} bit samples",
+                    inspection.block_align, inspection.channels, inspection.bits_per_sample
+                ),
+            ));
+        }
+
+        Ok(Payload {
+            format,
+            spec: StreamSpec {
+                sample_rate: inspection.sample_rate as i32,
+                channels: inspection.channels,
+                num_frames: 0,
+                channel_mask: inspection.channel_mask,
+                bits_per_sample: format.bits(),
+            },
+            data: data.offset..data.offset + data.size,
+        })
+    }
+
+    /// Reads the whole frames in `bytes` of `file`, the length of `spec` is set from them. The
+    /// frames past the end of the file are left out when they are read.
+    fn new(
+        path: &Path,
+        mut file: File,
+        format: RawFormat,
+        spec: StreamSpec,
+        bytes: Range<u64>,
+    ) -> Result<Self> {
+        file.seek(SeekFrom::Start(bytes.start))
+            .map_err(|err| Error::io("read", path, err))?;
+
+        // A trailing partial frame is dropped
+        let frame_bytes = (format.bytes() * spec.channels as usize) as u64;
+        let num_frames = (bytes.end - bytes.start) / frame_bytes;
+
+        Ok(Self {
+            format,
+            path: path.to_path_buf(),
+            reader: BufReader::new(file).take(num_frames * frame_bytes),
+            offset: bytes.start,
+            spec: StreamSpec {
+                num_frames: num_frames as usize,
+                ..spec
+            },
+        })
+    }
+
+    /// Skips the first `frames` frames.
+    fn skip(&mut self, frames: usize) -> Result<()> {
+        let bytes = (frames * self.format.bytes() * self.spec.channels as usize) as u64;
+        let limit = self.reader.limit();
+
+        self.reader
+            .get_mut()
+            .seek_relative(bytes as i64)
+            .map_err(|err| Error::io("read", &self.path, err))?;
+        self.reader.set_limit(limit.saturating_sub(bytes));
+
+        Ok(())
+    }
+
+    /// Reads the frames in `range` from anywhere in the file.
+    fn read_frames(&mut self, range: Range<usize>) -> Result<Vec<i32>> {
+        let frame_bytes = self.format.bytes() * self.spec.channels as usize;
+        let mut bytes = vec![0; range.len() * frame_bytes];
+
+        let file = self.reader.get_mut();
+        file.seek(SeekFrom::Start(
+            self.offset + (range.start * frame_bytes) as u64,
+        ))
+        .and_then(|_| file.read_exact(&mut bytes))
+        .map_err(|err| Error::io("read", &self.path, err))?;
+
+        Ok(bytes
+            .chunks_exact(self.format.bytes())
+            .map(|sample| self.format.decode(sample))
+            .collect())
+    }
+
+    /// Reads the next block of whole frames into `samples`, which is left empty at the end.
+    fn read_block(&mut self, bytes: &mut Vec<u8>, samples: &mut Vec<i32>) -> Result<()> {
+        let frame_bytes = self.format.bytes() * self.spec.channels as usize;
+
+        bytes.clear();
+        (&mut self.reader)
+            .take((RAW_BLOCK_FRAMES * frame_bytes) as u64)
+            .read_to_end(bytes)
+            .map_err(|err| Error::io("read", &self.path, err))?;
+
+        samples.clear();
+        samples.extend(
+            bytes
+                .chunks_exact(self.format.bytes())
+                .map(|sample| self.format.decode(sample)),
+        );
+
+        Ok(())
+    }
+}
+
+/// Hashes the payload of the input while it's decoded, for `--digest`.
+struct PayloadDigester {
+    digester: Digester,
+    /// The data chunk of a WAV file, read next to the decoder, or those of all files of
+    /// `--concat` one after the other. Raw input is hashed from the blocks it's decoded from.
+    payload: Option<Box<dyn Read>>,
+    path: PathBuf,
+}
+
+impl PayloadDigester {
+    fn new(algorithm: DigestAlgorithm, path: impl Into<PathBuf>) -> Self {
+        Self {
+            digester: Digester::new(algorithm),
+            payload: None,
+            path: path.into(),
+        }
+    }
+
+    /// Hashes the data chunk of `wav` from `reader`, another reader of the same file, after
+    /// the data chunks added before.
+    fn with_payload(
+        mut self,
+        wav: &Wav<i32>,
+        mut reader: impl Read + Seek + 'static,
+    ) -> Result<Self> {
+        let data = wav.header().data();
+        reader
+            .seek(SeekFrom::Start(data.offset as u64 + 8))
+            .map_err(|err| Error::io("read", &self.path, err))?;
+
+        let chunk = reader.take(data.size as u64);
+        self.payload = Some(match self.payload.take() {
+            Some(payload) => Box::new(payload.chain(chunk)),
+            None => Box::new(chunk),
+        });
+
+        Ok(self)
+    }
+
+    /// Hashes the next `len` bytes of the data chunk, or the rest of it without `len`.
+    fn read(&mut self, len: Option<u64>) -> Result<()> {
+        let Some(payload) = self.payload.as_mut() else {
+            return Ok(());
+        };
+
+        let copied = match len {
+            Some(len) => io::copy(&mut payload.by_ref().take(len), &mut self.digester),
+            None => io::copy(payload, &mut self.digester),
+        };
+        copied.map_err(|err| Error::io("read", &self.path, err))?;
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<PayloadDigest> {
+        self.read(None)?;
+
+        Ok(self.digester.finish())
+    }
+}
+
+/// A WAV file in memory, shared by the decoder and the digest of its payload.
+struct SharedBytes(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Files of the same format read one after the other as one stream, for `--concat`.
+pub struct Concat {
+    parts: Vec<(PathBuf, Source)>,
+    /// Part the next frame is read from, after `--from-end` skipped the ones before
+    current: usize,
+}
+
+impl Concat {
+    /// Frames of each part.
+    fn lengths(&self) -> impl Iterator<Item = usize> + '_ {
+        self.parts
+            .iter()
+            .map(|(_, source)| source.spec().num_frames)
+    }
+}
+
+/// One of the files of `--concat` and where it is in the stream.
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct InputPart {
+    pub input: String,
+    /// Seconds into the stream the file starts at
+    pub start: f64,
+    #[serde(rename = "startSample")]
+    pub start_sample: usize,
+    #[serde(rename = "numFrames")]
+    pub num_frames: usize,
+}
+
+/// Decoder of an input file.
+pub enum Source {
+    Wav(Wav<i32>),
+    Raw(RawReader),
+    Concat(Concat),
+}
+
+impl Source {
+    pub fn spec(&self) -> StreamSpec {
+        match self {
+            Self::Wav(wav) => StreamSpec::from_wav(wav),
+            Self::Raw(raw) => raw.spec,
+            Self::Concat(concat) => StreamSpec {
+                num_frames: concat.lengths().sum(),
+                ..concat.parts[0].1.spec()
+            },
+        }
+    }
+
+    /// Skips the first `frames` frames, before any were read.
+    fn skip(&mut self, frames: usize) -> Result<()> {
+        match self {
+            Self::Wav(wav) => {
+                let samples = frames as u64 * wav.n_channels() as u64;
+                wav.seek_by_samples(samples)
+                    .map(|_| ())
+                    .map_err(|err| Error::wav("input", err))
+            }
+            Self::Raw(raw) => raw.skip(frames),
+            Self::Concat(concat) => {
+                let mut frames = frames;
+                while let Some((_, part)) = concat.parts.get_mut(concat.current) {
+                    let length = part.spec().num_frames;
+                    if frames < length {
+                        return part.skip(frames);
+                    }
+                    frames -= length;
+                    concat.current += 1;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads the frames in `range`, interleaved, from anywhere in the file.
+    fn read_frames(&mut self, range: Range<usize>) -> Result<Vec<i32>> {
+        match self {
+            Self::Wav(wav) => {
+                let channels = wav.n_channels() as usize;
+                wav.to_data()
+                    .and_then(|_| wav.seek_by_samples((range.start * channels) as u64))
+                    .and_then(|_| wav.read_samples(range.len() * channels))
+                    .map(|samples| samples.to_vec())
+                    .map_err(|err| Error::wav("input", err))
+            }
+            Self::Raw(raw) => raw.read_frames(range),
+            Self::Concat(concat) => {
+                let mut frames = Vec::new();
+                let mut start = 0;
+                for (_, part) in concat.parts.iter_mut() {
+                    let end = start + part.spec().num_frames;
+                    if range.start < end && range.end > start {
+                        let local = range.start.max(start) - start..range.end.min(end) - start;
+                        frames.extend(part.read_frames(local)?);
+                    }
+                    start = end;
+                }
+                Ok(frames)
+            }
+        }
+    }
+
+    fn for_each_frame(
+        &mut self,
+        mut digest: Option<&mut PayloadDigester>,
+        mut f: impl FnMut(&[i32]) -> Result<()>,
+    ) -> Result<()> {
+        match self {
+            Self::Wav(wav) => {
+                let frame_bytes = wav.get_fmt_chunk().block_align as u64;
+                let start = wav.current_pos().map_err(|err| Error::wav("input", err))?;
+                let frames = ((wav.max_data_pos() - start) / frame_bytes) as usize;
+                let mut pending = 0;
+                let mut read = 0;
+
+                for frame in wav.frames() {
+                    f(&frame)?;
+                    read += 1;
+
+                    if let Some(digest) = digest.as_deref_mut() {
+                        pending += 1;
+                        if pending == RAW_BLOCK_FRAMES {
+                            digest.read(Some(pending as u64 * frame_bytes))?;
+                            pending = 0;
+                        }
+                    }
+                }
+
+                // The iterator stops 8 bytes short of the end of the data chunk and rewinds to
+                // its start, the last frames are read after it
+                let rest = frames.saturating_sub(read);
+                if rest > 0 {
+                    let channels = wav.n_channels() as usize;
+                    let first = StreamSpec::from_wav(wav).num_frames - rest;
+                    let samples = wav
+                        .seek_by_samples((first * channels) as u64)
+                        .and_then(|_| wav.read_samples(rest * channels))
+                        .map_err(|err| Error::wav("input", err))?;
+                    for frame in samples.chunks_exact(channels) {
+                        f(frame)?;
+                    }
+                }
+
+                // The rest of this file is hashed before a raw part of --concat that follows
+                if let Some(digest) = digest.as_deref_mut() {
+                    digest.read(Some((pending + rest) as u64 * frame_bytes))?;
+                }
+            }
+            Self::Raw(raw) => {
+                let channels = raw.spec.channels as usize;
+                let mut bytes = Vec::new();
+                let mut samples = Vec::new();
+
+                loop {
+                    raw.read_block(&mut bytes, &mut samples)?;
+                    if samples.is_empty() {
+                        break;
+                    }
+
+                    if let Some(digest) = digest.as_deref_mut() {
+                        digest.digester.update(&bytes);
+                    }
+
+                    for frame in samples.chunks_exact(channels) {
+                        f(frame)?;
+                    }
+                }
+            }
+            Self::Concat(concat) => {
+                // As a trait object, so the recursion doesn't nest the closure type
+                let f: &mut dyn FnMut(&[i32]) -> Result<()> = &mut f;
+                for (_, part) in concat.parts[concat.current..].iter_mut() {
+                    part.for_each_frame(digest.as_deref_mut(), &mut *f)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An input file the analysers are fed from, from `--from-end` before its end, mixed down to
+/// `--downmix`, resampled to `--resample` and with `--gain-db` applied if given.
+pub struct Input {
+    source: Source,
+    start_frame: usize,
+    downmix: Option<Downmix>,
+    resampler: Option<Resampler>,
+    gain: Option<Gain>,
+    channel_mask: Option<u32>,
+    digester: Option<PayloadDigester>,
+    digest: Option<PayloadDigest>,
+}
+
+impl Input {
+    /// Opens `path` as WAV, or as raw PCM with `--raw`.
+    pub fn open(args: &AnalyseArgs, path: &Path) -> Result<Self> {
+        let mut digester = args
+            .digest
+            .map(|algorithm| PayloadDigester::new(algorithm, path));
+        let source = Self::open_source(args, path, &mut digester)?;
+
+        Self::new(args, source, digester)
+    }
+
+    /// The format of the file at `path` and a SHA-256 digest of its payload, read without
+    /// decoding it, for `--cache-dir`. Raw input and the containers other than RIFF/WAVE are
+    /// hashed whole.
+    pub fn payload_digest(args: &AnalyseArgs, path: &Path) -> Result<(StreamSpec, PayloadDigest)> {
+        let mut digester = Some(PayloadDigester::new(DigestAlgorithm::Sha256, path));
+        let spec = Self::open_source(args, path, &mut digester)?.spec();
+
+        let digest = match digester {
+            Some(digester) if digester.payload.is_some() => digester.finish()?,
+            _ => {
+                let mut file = File::open(path).map_err(|err| Error::io("read", path, err))?;
+                let mut digester = Digester::new(DigestAlgorithm::Sha256);
+                io::copy(&mut file, &mut digester).map_err(|err| Error::io("read", path, err))?;
+                digester.finish()
+            }
+        };
+
+        Ok((spec, digest))
+    }
+
+    /// Opens the files of `--concat` as one input, they have to be of the same format.
+    pub fn concat(args: &AnalyseArgs, paths: &[PathBuf]) -> Result<Self> {
+        let Some(first) = paths.first() else {
+            return Err(Error::Config("--concat needs files".to_string()));
+        };
+        let mut digester = args
+            .digest
+            .map(|algorithm| PayloadDigester::new(algorithm, first));
+
+        let mut parts: Vec<(PathBuf, Source)> = Vec::with_capacity(paths.len());
+        for path in paths {
+            let source = Self::open_source(args, path, &mut digester)?;
+            if let Some((first, expected)) = parts.first() {
+                let (expected, actual) = (expected.spec(), source.spec());
+                let format = |spec: StreamSpec| {
+                    let bits = spec
+                        .bits_per_sample
+                        .map_or("float".to_string(), |bits| format!("{bits} bit"));
+                    format!(
+                        "{} Hz, {} channels, {bits}",
+                        spec.sample_rate, spec.channels
+                    )
+                };
+
+                // Only the length may differ
+                let format_only = |spec: StreamSpec| StreamSpec {
+                    num_frames: 0,
+                    ..spec
+                };
+                if format_only(actual) != format_only(expected) {
+                    return Err(Error::Config(format!(
+                        "--concat needs files of the same format, {} is {} but {} is {}",
+                        path.display(),
+                        format(actual),
+                        first.display(),
+                        format(expected)
+                    )));
+                }
+            }
+            parts.push((path.clone(), source));
+        }
+
+        Self::new(args, Source::Concat(Concat { parts, current: 0 }), digester)
+    }
+
+    /// The decoder of `path`, with its data chunk added to `digester`.
+    fn open_source(
+        args: &AnalyseArgs,
+        path: &Path,
+        digester: &mut Option<PayloadDigester>,
+    ) -> Result<Source> {
+        if args.raw {
+            return Ok(Source::Raw(RawReader::open(args, path)?));
+        }
+        if let Some(container) = RawReader::open_container(args, path)? {
+            return Ok(Source::Raw(container));
+        }
+
+        let wav = Wav::from_path(path).map_err(|err| Error::wav(path, err))?;
+        if let Some(digest) = digester.take() {
+            let file = File::open(path).map_err(|err| Error::io("read", path, err))?;
+            *digester = Some(digest.with_payload(&wav, BufReader::new(file))?);
+        }
+
+        Ok(Source::Wav(wav))
+    }
+
+    /// Reads a WAV file that is already in memory, for targets without a filesystem.
+    pub fn from_bytes(args: &AnalyseArgs, bytes: Vec<u8>) -> Result<Self> {
+        if args.raw {
+            return Err(Error::Config(
+                "--raw is only supported for input files".to_string(),
+            ));
+        }
+
+        let bytes = Arc::new(bytes);
+        let wav = Wav::new(Box::new(Cursor::new(SharedBytes(bytes.clone()))))
+            .map_err(|err| Error::wav("input", err))?;
+
+        let digester = args
+            .digest
+            .map(|algorithm| {
+                PayloadDigester::new(algorithm, "input")
+                    .with_payload(&wav, Cursor::new(SharedBytes(bytes)))
+            })
+            .transpose()?;
+
+        Self::new(args, Source::Wav(wav), digester)
+    }
+
+    fn new(
+        args: &AnalyseArgs,
+        mut source: Source,
+        digester: Option<PayloadDigester>,
+    ) -> Result<Self> {
+        let spec = source.spec();
+
+        if args.from_end.is_some() && args.digest.is_some() {
+            return Err(Error::Config(
+                "--digest hashes the whole payload and can't be combined with --from-end"
+                    .to_string(),
+            ));
+        }
+
+        let start_frame = args.from_end.map_or(0, |duration| {
+            let frames = (duration.as_secs_f64() * spec.sample_rate as f64) as usize;
+            spec.num_frames.saturating_sub(frames)
+        });
+        if start_frame > 0 {
+            source.skip(start_frame)?;
+        }
+        let num_frames = spec.num_frames - start_frame;
+
+        let channel_mask = match args.channel_layout.mask() {
+            Some(mask) if speaker_count(mask) != spec.channels as usize => {
+                return Err(Error::Config(format!(
+                    "--channel-layout {} needs {} channels, the input has {}",
+                    args.channel_layout.as_str(),
+                    speaker_count(mask),
+                    spec.channels
+                )));
+            }
+            Some(mask) => Some(mask),
+            None => spec.channel_mask,
+        };
+
+        if args.gain_db.is_some_and(|gain| !gain.is_finite()) {
+            return Err(Error::Config("--gain-db has to be a number".to_string()));
+        }
+
+        // Input with no more channels than the downmix is analysed as it is
+        let downmix = args
+            .downmix
+            .filter(|target| spec.channels > target.channels())
+            .map(|target| Downmix::new(target, channel_mask, spec.channels as usize));
+        let channels = downmix
+            .as_ref()
+            .map_or(spec.channels, |downmix| downmix.target().channels());
+
+        let resampler = args
+            .resample
+            .filter(|&rate| rate != spec.sample_rate)
+            .map(|rate| Resampler::new(spec.sample_rate, rate, channels as usize, num_frames));
+
+        Ok(Self {
+            source,
+            start_frame,
+            downmix,
+            resampler,
+            gain: args.gain_db.map(Gain::new),
+            channel_mask,
+            digester,
+            digest: None,
+        })
+    }
+
+    /// Format of the input file, with the speakers of `--channel-layout`.
+    pub fn source_spec(&self) -> StreamSpec {
+        StreamSpec {
+            channel_mask: self.channel_mask,
+            ..self.source.spec()
+        }
+    }
+
+    /// Frame of the file the analysis starts at, after the frames `--from-end` skips.
+    pub fn start_frame(&self) -> usize {
+        self.start_frame
+    }
+
+    /// Format of the frames the analysers see.
+    pub fn spec(&self) -> StreamSpec {
+        let mut spec = self.source_spec();
+        spec.num_frames -= self.start_frame;
+
+        if let Some(downmix) = &self.downmix {
+            let target = downmix.target();
+            spec.channels = target.channels();
+            spec.channel_mask = Some(target.channel_mask());
+        }
+
+        match &self.resampler {
+            Some(resampler) => StreamSpec {
+                sample_rate: resampler.rate(),
+                num_frames: resampler.num_frames(),
+                ..spec
+            },
+            None => spec,
+        }
+    }
+
+    /// The downmix with `--downmix`, if the input has more channels.
+    pub fn downmix(&self) -> Option<DownmixInfo> {
+        self.downmix.as_ref().map(Downmix::info)
+    }
+
+    /// The gain with `--gain-db`, and how many samples it clipped so far.
+    pub fn gain(&self) -> Option<GainInfo> {
+        self.gain.as_ref().map(Gain::info)
+    }
+
+    /// The files of `--concat` with where each starts in the stream.
+    pub fn parts(&self) -> Option<Vec<InputPart>> {
+        let Source::Concat(concat) = &self.source else {
+            return None;
+        };
+        let sample_rate = self.source.spec().sample_rate as f64;
+
+        let mut start = 0;
+        let parts = concat
+            .parts
+            .iter()
+            .zip(concat.lengths())
+            .map(|((path, _), num_frames)| {
+                let part = InputPart {
+                    input: path.display().to_string(),
+                    start: start as f64 / sample_rate,
+                    start_sample: start,
+                    num_frames,
+                };
+                start += num_frames;
+                part
+            })
+            .collect();
+
+        Some(parts)
+    }
+
+    /// Digest of the payload with `--digest`, once every frame has been read.
+    pub fn digest(&self) -> Option<&PayloadDigest> {
+        self.digest.as_ref()
+    }
+
+    /// Reads the frames in `range` of the input file as they are, without downmix, resampling
+    /// or gain. The file is read from anywhere, so it's for after the analysis, e.g. to
+    /// extract what the analysers found.
+    pub fn read_frames(&mut self, range: Range<usize>) -> Result<Vec<i32>> {
+        let num_frames = self.source.spec().num_frames;
+        let range = range.start.min(num_frames)..range.end.min(num_frames);
+
+        self.source.read_frames(range)
+    }
+
+    /// Calls `f` with every interleaved frame of the input, stopping at the first error.
+    pub fn for_each_frame(&mut self, mut f: impl FnMut(&[i32]) -> Result<()>) -> Result<()> {
+        let Self {
+            source,
+            downmix,
+            resampler,
+            gain,
+            digester,
+            ..
+        } = self;
+
+        let mut emit = |frame: &[i32]| match gain.as_mut() {
+            Some(gain) => f(gain.apply(frame)),
+            None => f(frame),
+        };
+        let mut resample = |frame: &[i32]| match resampler.as_mut() {
+            Some(resampler) => resampler.push(frame, &mut emit),
+            None => emit(frame),
+        };
+        source.for_each_frame(digester.as_mut(), |frame| match downmix.as_mut() {
+            Some(downmix) => resample(downmix.mix(frame)),
+            None => resample(frame),
+        })?;
+        if let Some(resampler) = resampler {
+            resampler.finish(&mut emit)?;
+        }
+
+        self.digest = self
+            .digester
+            .take()
+            .map(PayloadDigester::finish)
+            .transpose()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+//! AIFF and AIFF-C files, as written on Macs. Their sound data is uncompressed PCM, big-endian
+//! in AIFF and in the byte order of the compression type in AIFF-C.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use super::{Payload, RawFormat};
+use crate::{Error, Result, stream::StreamSpec};
+
+/// The common chunk, with the format of the sound data.
+struct Common {
+    channels: u16,
+    frames: u32,
+    bits: u16,
+    sample_rate: f64,
+    /// Compression type of AIFF-C, `NONE` in AIFF
+    compression: [u8; 4],
+}
+
+/// An 80-bit IEEE 754 extended float, which AIFF stores the sample rate in.
+fn extended(bytes: &[u8]) -> f64 {
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff) as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap_or_default());
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+
+    let sign = if bytes[0] & 0x80 != 0 { -1.0 } else { 1.0 };
+    sign * mantissa as f64 * 2f64.powi(exponent - 16383 - 63)
+}
+
+impl Common {
+    fn parse(bytes: &[u8], compressed: bool) -> Option<Self> {
+        let compression = if compressed {
+            bytes.get(18..22)?.try_into().ok()?
+        } else {
+            *b"NONE"
+        };
+
+        Some(Self {
+            channels: u16::from_be_bytes(bytes.get(0..2)?.try_into().ok()?),
+            frames: u32::from_be_bytes(bytes.get(2..6)?.try_into().ok()?),
+            bits: u16::from_be_bytes(bytes.get(6..8)?.try_into().ok()?),
+            sample_rate: extended(bytes.get(8..18)?),
+            compression,
+        })
+    }
+
+    /// The encoding of the samples, which are stored in whole bytes.
+    fn format(&self) -> Option<RawFormat> {
+        let bytes = self.bits.div_ceil(8);
+        let format = match (&self.compression, bytes) {
+            (b"NONE" | b"twos", 1) => RawFormat::S8,
+            (b"NONE" | b"twos", 2) => RawFormat::S16be,
+            (b"NONE" | b"twos" | b"in24", 3) => RawFormat::S24be,
+            (b"NONE" | b"twos" | b"in32", 4) => RawFormat::S32be,
+            (b"sowt", 1) => RawFormat::S8,
+            (b"sowt", 2) => RawFormat::S16le,
+            (b"sowt", 3) => RawFormat::S24le,
+            (b"sowt", 4) => RawFormat::S32le,
+            (b"raw ", 1) => RawFormat::U8,
+            (b"fl32" | b"FL32", _) => RawFormat::F32be,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+}
+
+/// Reads the format and the position of the sound data of the AIFF file `file`, of `size`
+/// bytes, which is AIFF-C if `compressed`.
+pub fn open(path: &Path, file: &mut File, size: u64, compressed: bool) -> Result<Payload> {
+    let read_error = |err: io::Error| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::decode(path, "file ends within a chunk"),
+        _ => Error::io("read", path, err),
+    };
+
+    let mut common = None;
+    let mut sound = None;
+    let mut position = 12u64;
+    while position + 8 <= size {
+        let mut header = [0; 8];
+        file.seek(SeekFrom::Start(position))
+            .and_then(|_| file.read_exact(&mut header))
+            .map_err(read_error)?;
+        let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        let start = position + 8;
+
+        match &header[0..4] {
+            b"COMM" => {
+                let mut bytes = vec![0; len.min(64) as usize];
+                file.read_exact(&mut bytes).map_err(read_error)?;
+                common = Some(
+                    Common::parse(&bytes, compressed)
+                        .ok_or_else(|| Error::decode(path, "COMM chunk is too short"))?,
+                );
+            }
+            b"SSND" => {
+                // The offset is padding before the first sample, for block-aligned data
+                let mut offset = [0; 4];
+                file.read_exact(&mut offset).map_err(read_error)?;
+                let first = start + 8 + u32::from_be_bytes(offset) as u64;
+                sound = Some(first..start + len);
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even length
+        position = start + len + (len & 1);
+    }
+
+    let Some(common) = common else {
+        return Err(Error::decode(path, "no COMM chunk"));
+    };
+    let Some(sound) = sound else {
+        return Err(Error::decode(path, "no SSND chunk"));
+    };
+    let Some(format) = common.format() else {
+        return Err(Error::decode(
+            path,
+            format!(
+                "{} bit samples with compression '{}' aren't supported in AIFF files",
+                common.bits,
+                String::from_utf8_lossy(&common.compression)
+            ),
+        ));
+    };
+    if common.channels == 0 || common.sample_rate < 1.0 {
+        return Err(Error::decode(
+            path,
+            "COMM chunk has no channels or sample rate",
+        ));
+    }
+
+    let frame_bytes = (format.bytes() * common.channels as usize) as u64;
+    let end = (sound.start + common.frames as u64 * frame_bytes).min(sound.end);
+
+    Ok(Payload {
+        format,
+        spec: StreamSpec {
+            sample_rate: common.sample_rate.round() as i32,
+            channels: common.channels,
+            num_frames: 0,
+            channel_mask: None,
+            bits_per_sample: format.bits(),
+        },
+        data: sound.start..end.max(sound.start),
+    })
+}
@@ -0,0 +1,161 @@
+//! Core Audio Format files, the successor of AIFF on Macs with 64-bit chunk sizes. Only
+//! linear PCM is read, in either byte order.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use super::{Payload, RawFormat};
+use crate::{Error, Result, stream::StreamSpec};
+
+/// `mFormatFlags` of linear PCM.
+const FLOAT: u32 = 1;
+const LITTLE_ENDIAN: u32 = 2;
+
+/// Channel layout tag of a layout given as a WAVE channel mask.
+const USE_CHANNEL_BITMAP: u32 = 1 << 16;
+
+/// The audio description chunk.
+struct Description {
+    sample_rate: f64,
+    format_id: [u8; 4],
+    flags: u32,
+    bytes_per_packet: u32,
+    frames_per_packet: u32,
+    channels: u32,
+    bits: u32,
+}
+
+impl Description {
+    fn parse(bytes: &[u8; 32]) -> Self {
+        let word = |at: usize| {
+            u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+
+        Self {
+            sample_rate: f64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]),
+            format_id: [bytes[8], bytes[9], bytes[10], bytes[11]],
+            flags: word(12),
+            bytes_per_packet: word(16),
+            frames_per_packet: word(20),
+            channels: word(24),
+            bits: word(28),
+        }
+    }
+
+    /// The encoding of packed linear PCM with one frame per packet.
+    fn format(&self) -> Option<RawFormat> {
+        let packed = self.bytes_per_packet == self.channels * self.bits.div_ceil(8);
+        if &self.format_id != b"lpcm" || self.frames_per_packet != 1 || !packed {
+            return None;
+        }
+
+        let little = self.flags & LITTLE_ENDIAN != 0;
+        let format = match (self.flags & FLOAT != 0, self.bits, little) {
+            (true, 32, true) => RawFormat::F32le,
+            (true, 32, false) => RawFormat::F32be,
+            (false, 8, _) => RawFormat::S8,
+            (false, 16, true) => RawFormat::S16le,
+            (false, 16, false) => RawFormat::S16be,
+            (false, 24, true) => RawFormat::S24le,
+            (false, 24, false) => RawFormat::S24be,
+            (false, 32, true) => RawFormat::S32le,
+            (false, 32, false) => RawFormat::S32be,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+}
+
+/// Reads the format and the position of the audio data of the CAF file `file`, of `size`
+/// bytes.
+pub fn open(path: &Path, file: &mut File, size: u64) -> Result<Payload> {
+    let read_error = |err: io::Error| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::decode(path, "file ends within a chunk"),
+        _ => Error::io("read", path, err),
+    };
+
+    let mut description = None;
+    let mut channel_mask = None;
+    let mut data = None;
+    let mut position = 8u64;
+    while position + 12 <= size {
+        let mut header = [0; 12];
+        file.seek(SeekFrom::Start(position))
+            .and_then(|_| file.read_exact(&mut header))
+            .map_err(read_error)?;
+        let len = i64::from_be_bytes([
+            header[4], header[5], header[6], header[7], header[8], header[9], header[10],
+            header[11],
+        ]);
+        let start = position + 12;
+        // The data chunk may be left open while recording, up to the end of the file
+        let end = if len < 0 { size } else { start + len as u64 };
+
+        match &header[0..4] {
+            b"desc" => {
+                let mut bytes = [0; 32];
+                file.read_exact(&mut bytes).map_err(read_error)?;
+                description = Some(Description::parse(&bytes));
+            }
+            b"chan" => {
+                let mut bytes = [0; 8];
+                file.read_exact(&mut bytes).map_err(read_error)?;
+                let tag = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                let bitmap = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+                channel_mask = (tag == USE_CHANNEL_BITMAP && bitmap != 0).then_some(bitmap);
+            }
+            // The audio follows the number of edits
+            b"data" => data = Some((start + 4).min(end)..end),
+            _ => {}
+        }
+
+        position = end;
+        if len < 0 {
+            break;
+        }
+    }
+
+    let Some(description) = description else {
+        return Err(Error::decode(path, "no desc chunk"));
+    };
+    let Some(data) = data else {
+        return Err(Error::decode(path, "no data chunk"));
+    };
+    let Some(format) = description.format() else {
+        return Err(Error::decode(
+            path,
+            format!(
+                "{} bit '{}' audio isn't supported in CAF files, only packed linear PCM of 8 to 32 bit integers or 32 bit floats",
+                description.bits,
+                String::from_utf8_lossy(&description.format_id)
+            ),
+        ));
+    };
+    let channels = u16::try_from(description.channels)
+        .ok()
+        .filter(|channels| *channels > 0);
+    let (Some(channels), true) = (channels, description.sample_rate >= 1.0) else {
+        return Err(Error::decode(
+            path,
+            "desc chunk has no channels or sample rate",
+        ));
+    };
+
+    Ok(Payload {
+        format,
+        spec: StreamSpec {
+            sample_rate: description.sample_rate.round() as i32,
+            channels,
+            num_frames: 0,
+            channel_mask: channel_mask.filter(|mask| mask.count_ones() == channels as u32),
+            bits_per_sample: format.bits(),
+        },
+        data,
+    })
+}
@@ -0,0 +1,125 @@
+//! Fold-down of multichannel input to stereo or mono before the analysis, with the
+//! coefficients of ITU-R BS.775.
+
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::layout::channel_speakers;
+
+/// Channels to mix the input down to, for `--downmix`.
+#[derive(ValueEnum, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DownmixTarget {
+    Mono,
+    Stereo,
+}
+
+impl DownmixTarget {
+    pub fn channels(self) -> u16 {
+        match self {
+            Self::Mono => 1,
+            Self::Stereo => 2,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Mono => "mono",
+            Self::Stereo => "stereo",
+        }
+    }
+
+    /// Channel mask of the downmix.
+    pub fn channel_mask(self) -> u32 {
+        match self {
+            Self::Mono => 0x4,
+            Self::Stereo => 0x3,
+        }
+    }
+}
+
+/// The downmix the analysers ran on, as written to the JSON output.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct DownmixInfo {
+    pub target: DownmixTarget,
+    /// Gain of every input channel (columns) in every output channel (rows)
+    pub matrix: Vec<Vec<f64>>,
+}
+
+/// Gains of `speaker` in the left and right channel of the stereo fold-down.
+fn stereo_gains(speaker: Option<&str>) -> [f64; 2] {
+    match speaker {
+        Some("FL" | "FLC") => [1.0, 0.0],
+        Some("FR" | "FRC") => [0.0, 1.0],
+        Some("FC") => [FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+        Some("BL" | "SL" | "TFL" | "TBL") => [FRAC_1_SQRT_2, 0.0],
+        Some("BR" | "SR" | "TFR" | "TBR") => [0.0, FRAC_1_SQRT_2],
+        Some("BC" | "TC" | "TFC" | "TBC") => [0.5, 0.5],
+        // The LFE is left out, as are channels without a speaker
+        _ => [0.0, 0.0],
+    }
+}
+
+/// Mixes interleaved frames down with a fixed matrix.
+pub struct Downmix {
+    target: DownmixTarget,
+    matrix: Vec<Vec<f64>>,
+    frame: Vec<i32>,
+}
+
+impl Downmix {
+    /// Mixes `channels` channels with the speakers of `mask` down to `target`.
+    pub fn new(target: DownmixTarget, mask: Option<u32>, channels: usize) -> Self {
+        let stereo: Vec<_> = channel_speakers(mask, channels)
+            .into_iter()
+            .map(stereo_gains)
+            .collect();
+
+        let matrix = match target {
+            DownmixTarget::Stereo => (0..2)
+                .map(|side| stereo.iter().map(|gains| gains[side]).collect())
+                .collect(),
+            // The mono fold-down of BS.775 is the stereo one summed at -3 dB
+            DownmixTarget::Mono => vec![
+                stereo
+                    .iter()
+                    .map(|[left, right]| (left + right) * FRAC_1_SQRT_2)
+                    .collect(),
+            ],
+        };
+
+        Self {
+            target,
+            matrix,
+            frame: vec![0; target.channels() as usize],
+        }
+    }
+
+    pub fn target(&self) -> DownmixTarget {
+        self.target
+    }
+
+    pub fn info(&self) -> DownmixInfo {
+        DownmixInfo {
+            target: self.target,
+            matrix: self.matrix.clone(),
+        }
+    }
+
+    /// The downmix of one frame. Samples past full scale are clipped.
+    pub fn mix(&mut self, frame: &[i32]) -> &[i32] {
+        for (sample, gains) in self.frame.iter_mut().zip(&self.matrix) {
+            let value: f64 = gains
+                .iter()
+                .zip(frame)
+                .map(|(gain, &sample)| gain * sample as f64)
+                .sum();
+            *sample = value.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+        }
+
+        &self.frame
+    }
+}
@@ -0,0 +1,56 @@
+//! Gain applied to the frames right before the analysers, to see what a file would look
+//! like after normalization.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The gain the analysers ran with, as written to the JSON output.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct GainInfo {
+    /// Gain in dB
+    pub db: f64,
+    /// Samples past full scale after the gain, which were clipped
+    #[serde(rename = "clippedSamples")]
+    pub clipped_samples: u64,
+}
+
+/// Scales interleaved frames, clipping at full scale.
+pub struct Gain {
+    db: f64,
+    factor: f64,
+    clipped: u64,
+    frame: Vec<i32>,
+}
+
+impl Gain {
+    pub fn new(db: f64) -> Self {
+        Self {
+            db,
+            factor: 10f64.powf(db / 20.0),
+            clipped: 0,
+            frame: Vec::new(),
+        }
+    }
+
+    pub fn info(&self) -> GainInfo {
+        GainInfo {
+            db: self.db,
+            clipped_samples: self.clipped,
+        }
+    }
+
+    pub fn apply(&mut self, frame: &[i32]) -> &[i32] {
+        self.frame.clear();
+
+        for &sample in frame {
+            let value = (sample as f64 * self.factor).round();
+            if value > i32::MAX as f64 || value < i32::MIN as f64 {
+                self.clipped += 1;
+            }
+            self.frame
+                .push(value.clamp(i32::MIN as f64, i32::MAX as f64) as i32);
+        }
+
+        &self.frame
+    }
+}
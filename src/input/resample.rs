@@ -0,0 +1,170 @@
+use std::f64::consts::PI;
+
+use crate::Result;
+
+/// Zero crossings of the interpolation kernel on each side, at the lower of the two rates.
+const ZERO_CROSSINGS: usize = 16;
+
+/// Fractional positions the kernel is tabulated at, positions in between are interpolated.
+const PHASES: usize = 256;
+
+/// Cutoff as a fraction of the lower Nyquist frequency, leaving room for the transition band.
+const ROLLOFF: f64 = 0.95;
+
+/// Buffered input frames that are no longer needed before the buffer is compacted.
+const COMPACT_FRAMES: usize = 4096;
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn blackman(x: f64) -> f64 {
+    0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+}
+
+/// Streaming windowed-sinc sample rate converter for interleaved frames.
+///
+/// Output frame `n` sits at input position `n * from / to`, which is computed exactly in
+/// integers so long files don't drift.
+pub struct Resampler {
+    from: u64,
+    to: u64,
+    channels: usize,
+    /// Taps on each side of an output position
+    half: usize,
+    /// `PHASES + 1` kernels of `2 * half` taps, for fractional positions 0 to 1
+    table: Vec<f64>,
+    /// Interleaved input frames, the first one at input position `base`
+    buffer: Vec<f64>,
+    base: i64,
+    emitted: u64,
+    num_frames: u64,
+    weights: Vec<f64>,
+    frame: Vec<i32>,
+}
+
+impl Resampler {
+    /// Converts `num_frames` input frames from `from` Hz to `to` Hz.
+    pub fn new(from: i32, to: i32, channels: usize, num_frames: usize) -> Self {
+        let (from, to) = (from as u64, to as u64);
+        let cutoff = (to as f64 / from as f64).min(1.0) * ROLLOFF;
+        let half = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+        let taps = 2 * half;
+
+        let mut table = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let fraction = phase as f64 / PHASES as f64;
+            let kernel: Vec<f64> = (0..taps)
+                .map(|tap| {
+                    // Distance of the output position to the input frame of this tap
+                    let x = fraction + half as f64 - 1.0 - tap as f64;
+                    cutoff * sinc(cutoff * x) * blackman(x / half as f64)
+                })
+                .collect();
+
+            // Unity gain at DC for every phase
+            let sum: f64 = kernel.iter().sum();
+            table.extend(kernel.iter().map(|value| value / sum));
+        }
+
+        Self {
+            from,
+            to,
+            channels,
+            half,
+            table,
+            // The frames before the start are silence
+            buffer: vec![0.0; half * channels],
+            base: -(half as i64),
+            emitted: 0,
+            num_frames: num_frames as u64 * to / from,
+            weights: vec![0.0; taps],
+            frame: vec![0; channels],
+        }
+    }
+
+    /// Sample rate of the output.
+    pub fn rate(&self) -> i32 {
+        self.to as i32
+    }
+
+    /// Number of frames the resampled stream will have.
+    pub fn num_frames(&self) -> usize {
+        self.num_frames as usize
+    }
+
+    /// Adds one input frame and calls `emit` with every output frame it completes.
+    pub fn push(
+        &mut self,
+        frame: &[i32],
+        emit: &mut impl FnMut(&[i32]) -> Result<()>,
+    ) -> Result<()> {
+        self.buffer
+            .extend(frame.iter().map(|&sample| sample as f64));
+        self.drain(emit)
+    }
+
+    /// Flushes the output frames that depend on input past the last frame.
+    pub fn finish(&mut self, emit: &mut impl FnMut(&[i32]) -> Result<()>) -> Result<()> {
+        self.buffer
+            .extend(std::iter::repeat_n(0.0, self.half * self.channels));
+        self.drain(emit)
+    }
+
+    fn drain(&mut self, emit: &mut impl FnMut(&[i32]) -> Result<()>) -> Result<()> {
+        let taps = 2 * self.half;
+        let buffered = (self.buffer.len() / self.channels) as i64;
+
+        while self.emitted < self.num_frames {
+            let position = self.emitted * self.from;
+            let index = (position / self.to) as i64;
+
+            // First input frame of the kernel, relative to the buffer
+            let first = index - self.half as i64 + 1 - self.base;
+            if first + taps as i64 > buffered {
+                break;
+            }
+
+            let phase = (position % self.to) as f64 / self.to as f64 * PHASES as f64;
+            let row = (phase as usize).min(PHASES - 1);
+            let weight = phase - row as f64;
+            let (low, high) = (
+                &self.table[row * taps..(row + 1) * taps],
+                &self.table[(row + 1) * taps..(row + 2) * taps],
+            );
+            for (tap, value) in self.weights.iter_mut().enumerate() {
+                *value = low[tap] + (high[tap] - low[tap]) * weight;
+            }
+
+            let start = first as usize * self.channels;
+            for (channel, sample) in self.frame.iter_mut().enumerate() {
+                let value: f64 = self
+                    .weights
+                    .iter()
+                    .enumerate()
+                    .map(|(tap, weight)| {
+                        weight * self.buffer[start + tap * self.channels + channel]
+                    })
+                    .sum();
+                *sample = value.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+            }
+
+            emit(&self.frame)?;
+            self.emitted += 1;
+        }
+
+        // Drop the frames before the kernel of the next output frame
+        let next = (self.emitted * self.from / self.to) as i64 - self.half as i64 + 1;
+        let unused = (next - self.base).max(0) as usize;
+        if unused >= COMPACT_FRAMES {
+            self.buffer.drain(..unused * self.channels);
+            self.base += unused as i64;
+        }
+
+        Ok(())
+    }
+}
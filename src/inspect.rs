@@ -0,0 +1,319 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use serde::Serialize;
+use serde_json::to_string_pretty;
+
+use crate::{Error, Result, cli::InspectArgs};
+
+/// Metadata chunks larger than this are listed but not read.
+const MAX_METADATA_SIZE: u32 = 1 << 20;
+
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// Size of a chunk of an RF64/BW64 file whose real size is in the `ds64` chunk.
+const RF64_SIZE: u32 = u32::MAX;
+
+/// One chunk of the RIFF structure, in file order.
+#[derive(Serialize)]
+pub struct Chunk {
+    pub id: String,
+    /// Position of the chunk contents in the file
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// Broadcast Wave Format (`bext`) metadata.
+#[derive(Serialize)]
+pub struct Bext {
+    pub description: String,
+    pub originator: String,
+    #[serde(rename = "originatorReference")]
+    pub originator_reference: String,
+    #[serde(rename = "originationDate")]
+    pub origination_date: String,
+    #[serde(rename = "originationTime")]
+    pub origination_time: String,
+    /// Position of the first sample since midnight, in samples
+    #[serde(rename = "timeReference")]
+    pub time_reference: u64,
+}
+
+/// Format and metadata of a WAV file as read from its header.
+#[derive(Serialize)]
+pub struct Inspection {
+    /// `RIFF`, or `RF64`/`BW64` for files with 64-bit sizes that can be larger than 4 GB
+    pub container: String,
+    #[serde(rename = "sampleRate")]
+    pub sample_rate: u32,
+    pub channels: u16,
+    #[serde(rename = "bitsPerSample")]
+    pub bits_per_sample: u16,
+    /// Bytes per frame
+    #[serde(rename = "blockAlign")]
+    pub block_align: u16,
+    /// `pcm`, `float`, `alaw`, `mulaw` or the hexadecimal format code
+    pub encoding: String,
+    /// Whether the format uses `WAVE_FORMAT_EXTENSIBLE`
+    pub extensible: bool,
+    /// Speakers of the channels of an extensible format, if it has any
+    #[serde(rename = "channelMask", skip_serializing_if = "Option::is_none")]
+    pub channel_mask: Option<u32>,
+    /// Number of frames (one sample per channel)
+    #[serde(rename = "numFrames")]
+    pub num_frames: u64,
+    /// Duration in seconds
+    pub duration: f64,
+    /// Size of the audio data in bytes
+    #[serde(rename = "dataSize")]
+    pub data_size: u64,
+    #[serde(rename = "fileSize")]
+    pub file_size: u64,
+    /// Size of the RIFF chunk as the header gives it, from the `ds64` chunk for RF64/BW64.
+    /// The file should be 8 bytes longer.
+    #[serde(rename = "riffSize")]
+    pub riff_size: u64,
+    /// Whether the data chunk reaches past the end of the file
+    pub truncated: bool,
+    pub chunks: Vec<Chunk>,
+    /// Text entries of a `LIST`/`INFO` chunk, keyed by their four-character code
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub info: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bext: Option<Bext>,
+}
+
+struct Format {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    block_align: u16,
+    bits_per_sample: u16,
+    sub_format: Option<u16>,
+    channel_mask: Option<u32>,
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Text of a fixed-size, NUL-padded field.
+fn text(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+fn fourcc(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).to_string()
+}
+
+fn parse_format(bytes: &[u8]) -> Option<Format> {
+    if bytes.len() < 16 {
+        return None;
+    }
+
+    let tag = u16_at(bytes, 0);
+    let extensible = tag == WAVE_FORMAT_EXTENSIBLE && bytes.len() >= 26;
+
+    Some(Format {
+        tag,
+        channels: u16_at(bytes, 2),
+        sample_rate: u32_at(bytes, 4),
+        block_align: u16_at(bytes, 12),
+        bits_per_sample: u16_at(bytes, 14),
+        // The sub format GUID starts with the format code
+        sub_format: extensible.then(|| u16_at(bytes, 24)),
+        channel_mask: extensible
+            .then(|| u32_at(bytes, 20))
+            .filter(|&mask| mask != 0),
+    })
+}
+
+fn parse_info(bytes: &[u8], info: &mut BTreeMap<String, String>) {
+    if bytes.len() < 4 || &bytes[..4] != b"INFO" {
+        return;
+    }
+
+    let mut position = 4;
+    while position + 8 <= bytes.len() {
+        let size = u32_at(bytes, position + 4) as usize;
+        let start = position + 8;
+        let end = (start + size).min(bytes.len());
+
+        info.insert(
+            fourcc(&bytes[position..position + 4]),
+            text(&bytes[start..end]),
+        );
+        position = start + size + (size & 1);
+    }
+}
+
+fn parse_bext(bytes: &[u8]) -> Option<Bext> {
+    if bytes.len() < 346 {
+        return None;
+    }
+
+    Some(Bext {
+        description: text(&bytes[0..256]),
+        originator: text(&bytes[256..288]),
+        originator_reference: text(&bytes[288..320]),
+        origination_date: text(&bytes[320..330]),
+        origination_time: text(&bytes[330..338]),
+        time_reference: u32_at(bytes, 338) as u64 | (u32_at(bytes, 342) as u64) << 32,
+    })
+}
+
+fn encoding(tag: u16) -> String {
+    match tag {
+        0x0001 => "pcm".to_string(),
+        0x0003 => "float".to_string(),
+        0x0006 => "alaw".to_string(),
+        0x0007 => "mulaw".to_string(),
+        tag => format!("0x{tag:04x}"),
+    }
+}
+
+/// Walks the RIFF chunks of `path`, or those of an RF64/BW64 file with the sizes of its
+/// `ds64` chunk. Only the format and metadata chunks are read, the audio data itself is
+/// skipped.
+pub fn inspect(path: &Path) -> Result<Inspection> {
+    let read_error = |err: io::Error| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::decode(path, "file ends within a chunk header"),
+        _ => Error::io("read", path, err),
+    };
+
+    let file = File::open(path).map_err(read_error)?;
+    let file_size = file.metadata().map_err(read_error)?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header).map_err(read_error)?;
+    let container = fourcc(&header[0..4]);
+    if !matches!(container.as_str(), "RIFF" | "RF64" | "BW64") || &header[8..12] != b"WAVE" {
+        return Err(Error::decode(path, "not a RIFF/WAVE file"));
+    }
+
+    let mut chunks = Vec::new();
+    let mut format = None;
+    let mut data = None;
+    // Sizes of the RIFF and data chunks from the ds64 chunk, which comes first
+    let mut riff_size_64 = None;
+    let mut data_size_64 = None;
+    let mut info = BTreeMap::new();
+    let mut bext = None;
+    let mut position = 12u64;
+
+    while position + 8 <= file_size {
+        let mut chunk_header = [0u8; 8];
+        reader
+            .seek(SeekFrom::Start(position))
+            .and_then(|_| reader.read_exact(&mut chunk_header))
+            .map_err(read_error)?;
+
+        let id = fourcc(&chunk_header[0..4]);
+        let size = match (id.as_str(), u32_at(&chunk_header, 4), data_size_64) {
+            ("data", RF64_SIZE, Some(size)) => size,
+            (_, size, _) => size as u64,
+        };
+        let offset = position + 8;
+
+        let contents = match id.as_str() {
+            "fmt " | "LIST" | "bext" | "ds64" if size <= MAX_METADATA_SIZE as u64 => {
+                let mut bytes = Vec::new();
+                (&mut reader)
+                    .take(size)
+                    .read_to_end(&mut bytes)
+                    .map_err(read_error)?;
+                bytes
+            }
+            _ => Vec::new(),
+        };
+
+        match id.as_str() {
+            "fmt " => format = parse_format(&contents),
+            "data" => data = Some((offset, size)),
+            "LIST" => parse_info(&contents, &mut info),
+            "bext" => bext = parse_bext(&contents),
+            // Sizes of the RIFF and data chunks and the number of frames, 64 bit each
+            "ds64" if contents.len() >= 16 => {
+                riff_size_64 = Some(u64_at(&contents, 0));
+                data_size_64 = Some(u64_at(&contents, 8));
+            }
+            _ => {}
+        }
+
+        chunks.push(Chunk { id, offset, size });
+        position = offset + size + (size & 1);
+    }
+
+    let Some(format) = format else {
+        return Err(Error::decode(path, "no valid fmt chunk"));
+    };
+    let Some((data_offset, data_size)) = data else {
+        return Err(Error::decode(path, "no data chunk"));
+    };
+
+    let riff_size = match (u32_at(&header, 4), riff_size_64) {
+        (RF64_SIZE, Some(size)) => size,
+        (size, _) => size as u64,
+    };
+    let num_frames = if format.block_align > 0 {
+        data_size / format.block_align as u64
+    } else {
+        0
+    };
+
+    Ok(Inspection {
+        container,
+        sample_rate: format.sample_rate,
+        channels: format.channels,
+        bits_per_sample: format.bits_per_sample,
+        block_align: format.block_align,
+        encoding: encoding(format.sub_format.unwrap_or(format.tag)),
+        extensible: format.tag == WAVE_FORMAT_EXTENSIBLE,
+        channel_mask: format.channel_mask,
+        num_frames,
+        duration: num_frames as f64 / format.sample_rate.max(1) as f64,
+        data_size,
+        file_size,
+        riff_size,
+        truncated: data_offset + data_size > file_size,
+        chunks,
+        info,
+        bext,
+    })
+}
+
+impl Inspection {
+    /// The data chunk, there always is one.
+    pub fn data(&self) -> Option<&Chunk> {
+        self.chunks.iter().find(|chunk| chunk.id == "data")
+    }
+}
+
+pub fn write_inspection(args: &InspectArgs, inspection: &Inspection) -> Result<()> {
+    let Some(path) = args.json.as_ref() else {
+        return Ok(());
+    };
+
+    let write_error = |err| Error::io("write JSON output to", path, err);
+    let json = to_string_pretty(inspection).map_err(|err| write_error(err.into()))?;
+    std::fs::write(path, json).map_err(write_error)?;
+
+    println!("Wrote JSON output to {}", path.display());
+
+    Ok(())
+}
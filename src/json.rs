@@ -1,52 +1,427 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
 use serde::Serialize;
-use serde_json::{Map, Value, to_string_pretty};
-use wavers::Wav;
+use serde_json::{Value, to_string_pretty, to_value};
+
+use crate::{
+    Error, Result,
+    analysers::{
+        Analyser, AnalysisResult, activity::ActivityAnalysis, alignment::AlignmentAnalysis,
+        balance::ChannelBalanceAnalysis, bitdepth::BitDepthAnalysis, codec::CodecAnalysis,
+        compliance::ComplianceAnalysis, container::ContainerAnalysis,
+        discontinuity::DiscontinuityAnalysis, drift::DriftAnalysis, dtmf::DtmfAnalysis,
+        dynamics::DynamicsAnalysis, fft::FftAnalysis, format::FormatAnalysis, key::KeyAnalysis,
+        loudness::LoudnessAnalysis, loudness::SilenceAnalysis, ltc::LtcAnalysis,
+        meter::MeterAnalysis, normalize::NormalizationAnalysis, novelty::NoveltyAnalysis,
+        peaks::PeaksAnalysis, pitch::PitchAnalysis, pumping::PumpingAnalysis,
+        ratecheck::RateCheckAnalysis, spectral::SpectralAnalysis, stereo::StereoAnalysis,
+        target::LoudnessTargetAnalysis, tempo::TempoAnalysis, tone::ToneAnalysis,
+        underruns::UnderrunAnalysis, vad::VadAnalysis,
+    },
+    cli::AnalyseArgs,
+    digest::PayloadDigest,
+    exclude::ExcludedRange,
+    findings::Summary,
+    input::{DownmixInfo, GainInfo, Input, InputPart},
+    layout::LayoutInfo,
+    output,
+    output::TimeFormat,
+    performance::Performance,
+    stream::StreamSpec,
+    timeseries::TimeSeriesIndex,
+};
 
-use crate::{analysers::Analyser, cli::Cli, output};
+/// Version of the JSON output format. The minor version is bumped when keys are added, the
+/// major version when keys are removed, renamed or change their type or meaning.
+pub const SCHEMA_VERSION: &str = "2.0.0";
 
-#[derive(Serialize)]
-struct JsonOutput {
-    analysis: Map<String, Value>,
-    duration: f32,
-    num_channels: u16,
-    num_samples: usize,
-    sample_rate: i32,
+/// Results of the enabled analysers, keyed by analysis.
+#[derive(Serialize, JsonSchema, Default)]
+pub struct Analysis {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<LoudnessAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub silence: Option<SilenceAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub underruns: Option<UnderrunAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discontinuities: Option<DiscontinuityAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fft: Option<FftAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peaks: Option<PeaksAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamics: Option<DynamicsAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meter: Option<MeterAnalysis>,
+    #[serde(rename = "loudnessTarget", skip_serializing_if = "Option::is_none")]
+    pub loudness_target: Option<LoudnessTargetAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compliance: Option<ComplianceAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalization: Option<NormalizationAnalysis>,
+    #[serde(rename = "channelBalance", skip_serializing_if = "Option::is_none")]
+    pub channel_balance: Option<ChannelBalanceAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stereo: Option<StereoAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<CodecAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<ContainerAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<FormatAnalysis>,
+    #[serde(rename = "bitDepth", skip_serializing_if = "Option::is_none")]
+    pub bit_depth: Option<BitDepthAnalysis>,
+    #[serde(rename = "rateCheck", skip_serializing_if = "Option::is_none")]
+    pub rate_check: Option<RateCheckAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alignment: Option<AlignmentAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drift: Option<DriftAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pumping: Option<PumpingAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tones: Option<ToneAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dtmf: Option<DtmfAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ltc: Option<LtcAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vad: Option<VadAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spectral: Option<SpectralAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<PitchAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub activity: Option<ActivityAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub novelty: Option<NoveltyAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tempo: Option<TempoAnalysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<KeyAnalysis>,
+    /// Results of the analysers of `--plugin` libraries, under the keys they registered
+    #[serde(flatten)]
+    pub plugins: BTreeMap<String, Value>,
 }
 
-pub fn write_json(args: &Cli, wav: &Wav<i32>, analysers: &Vec<Box<dyn Analyser>>) {
-    let Some(path) = args.json.as_ref() else {
-        return;
-    };
+impl Analysis {
+    pub fn is_empty(&self) -> bool {
+        self.loudness.is_none()
+            && self.silence.is_none()
+            && self.underruns.is_none()
+            && self.discontinuities.is_none()
+            && self.fft.is_none()
+            && self.peaks.is_none()
+            && self.dynamics.is_none()
+            && self.meter.is_none()
+            && self.loudness_target.is_none()
+            && self.compliance.is_none()
+            && self.normalization.is_none()
+            && self.channel_balance.is_none()
+            && self.stereo.is_none()
+            && self.codec.is_none()
+            && self.container.is_none()
+            && self.format.is_none()
+            && self.bit_depth.is_none()
+            && self.rate_check.is_none()
+            && self.alignment.is_none()
+            && self.drift.is_none()
+            && self.pumping.is_none()
+            && self.tones.is_none()
+            && self.dtmf.is_none()
+            && self.ltc.is_none()
+            && self.vad.is_none()
+            && self.spectral.is_none()
+            && self.pitch.is_none()
+            && self.activity.is_none()
+            && self.novelty.is_none()
+            && self.tempo.is_none()
+            && self.key.is_none()
+            && self.plugins.is_empty()
+    }
 
-    let mut analysis = Map::new();
+    /// Adds the entries set in `other`.
+    pub fn merge(&mut self, other: Self) {
+        let Self {
+            loudness,
+            silence,
+            underruns,
+            discontinuities,
+            fft,
+            peaks,
+            dynamics,
+            meter,
+            loudness_target,
+            compliance,
+            normalization,
+            channel_balance,
+            stereo,
+            codec,
+            container,
+            format,
+            bit_depth,
+            rate_check,
+            alignment,
+            drift,
+            pumping,
+            tones,
+            dtmf,
+            ltc,
+            vad,
+            spectral,
+            pitch,
+            activity,
+            novelty,
+            tempo,
+            key,
+            plugins,
+        } = other;
+
+        self.loudness = loudness.or(self.loudness.take());
+        self.silence = silence.or(self.silence.take());
+        self.underruns = underruns.or(self.underruns.take());
+        self.discontinuities = discontinuities.or(self.discontinuities.take());
+        self.fft = fft.or(self.fft.take());
+        self.peaks = peaks.or(self.peaks.take());
+        self.dynamics = dynamics.or(self.dynamics.take());
+        self.meter = meter.or(self.meter.take());
+        self.loudness_target = loudness_target.or(self.loudness_target.take());
+        self.compliance = compliance.or(self.compliance.take());
+        self.normalization = normalization.or(self.normalization.take());
+        self.channel_balance = channel_balance.or(self.channel_balance.take());
+        self.stereo = stereo.or(self.stereo.take());
+        self.codec = codec.or(self.codec.take());
+        self.container = container.or(self.container.take());
+        self.format = format.or(self.format.take());
+        self.bit_depth = bit_depth.or(self.bit_depth.take());
+        self.rate_check = rate_check.or(self.rate_check.take());
+        self.alignment = alignment.or(self.alignment.take());
+        self.drift = drift.or(self.drift.take());
+        self.pumping = pumping.or(self.pumping.take());
+        self.tones = tones.or(self.tones.take());
+        self.dtmf = dtmf.or(self.dtmf.take());
+        self.ltc = ltc.or(self.ltc.take());
+        self.vad = vad.or(self.vad.take());
+        self.spectral = spectral.or(self.spectral.take());
+        self.pitch = pitch.or(self.pitch.take());
+        self.activity = activity.or(self.activity.take());
+        self.novelty = novelty.or(self.novelty.take());
+        self.tempo = tempo.or(self.tempo.take());
+        self.key = key.or(self.key.take());
+        self.plugins.extend(plugins);
+    }
+}
+
+/// Results of a run as written to the JSON output, also used to render the HTML report.
+#[derive(Serialize, JsonSchema)]
+pub struct Results {
+    /// Version of the output format, major.minor.patch
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: &'static str,
+    /// Version of analwave that wrote the output
+    #[serde(rename = "toolVersion")]
+    pub tool_version: &'static str,
+    pub analysis: Analysis,
+    pub duration: f32,
+    pub num_channels: u16,
+    pub num_samples: usize,
+    /// Sample rate of the input file
+    pub sample_rate: i32,
+    /// Sample rate the analysers ran at, differs from `sample_rate` with `--resample`.
+    /// Sample positions in the analysis are at this rate.
+    pub analysis_sample_rate: i32,
+    /// Speakers of the channels the loudness was weighted by, from the channel mask of the
+    /// file or `--channel-layout`. Absent if unknown
+    #[serde(rename = "channelLayout", skip_serializing_if = "Option::is_none")]
+    pub channel_layout: Option<LayoutInfo>,
+    /// The analysers ran on a downmix of the input, with `--downmix`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downmix: Option<DownmixInfo>,
+    /// The analysers ran with this gain applied, with `--gain-db`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain: Option<GainInfo>,
+    /// Digest of the PCM data of the input file, with `--digest`. Left out of partial results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<PayloadDigest>,
+    /// The files read as one stream with `--concat`, positions in the results are in that
+    /// stream
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parts: Option<Vec<InputPart>>,
+    /// Ranges left out of the loudness and silence measurements, with `--exclude`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excluded: Option<Vec<ExcludedRange>>,
+    /// Time spent decoding and in each analyser, with `--timings`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub performance: Option<Performance>,
+    /// Findings of all analysers ranked by severity, with `--summary`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    /// Return code of each analyser, for outputs that report them one by one
+    #[serde(skip)]
+    pub checks: Vec<Check>,
+    /// The analysis stopped early, on SIGINT or `--timeout`, and the results only cover the
+    /// start of the file
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    /// Seconds of the file analysed before it stopped, only for partial results
+    #[serde(rename = "analysedDuration", skip_serializing_if = "Option::is_none")]
+    pub analysed_duration: Option<f32>,
+    /// Frame of the file the analysis started at with `--from-end`, at the sample rate of
+    /// the file. Positions in the results are relative to it, the outputs that are lined up
+    /// with the file, like `--srt`, `--labels`, `--edl` and the cue points, have the positions
+    /// in the file, which are the positions in the results plus this
+    #[serde(rename = "analysisStart", skip_serializing_if = "Option::is_none")]
+    pub analysis_start: Option<usize>,
+    /// Arrays of the time series file, with `--timeseries`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeseries: Option<TimeSeriesIndex>,
+    /// Format segment times are also written in, with `--json-time`
+    #[serde(skip)]
+    pub time_format: Option<TimeFormat>,
+}
 
-    for analyser in analysers.iter() {
-        for (key, value) in analyser.json() {
-            analysis.insert(key, value);
+impl Results {
+    /// Frame of the input file a position in the analysis is at, undoing `--resample` and
+    /// `--from-end`.
+    pub fn file_frame(&self, frame: usize) -> usize {
+        let sample_rate = self.sample_rate as f64;
+        let scale = sample_rate / self.analysis_sample_rate as f64;
+
+        self.analysis_start.unwrap_or(0) + (frame as f64 * scale).round() as usize
+    }
+}
+
+/// An analyser and the return code bits it set.
+#[derive(Debug, Clone, Copy)]
+pub struct Check {
+    pub analyser: &'static str,
+    pub exit_code: u8,
+}
+
+/// The results of the analysers, in the order of `analysers`, as one JSON output.
+pub fn collect_results(
+    input: &Input,
+    analysers: &[Box<dyn Analyser>],
+    results: Vec<AnalysisResult>,
+) -> Results {
+    // The analysers ran on `input.spec()`, after --resample and --downmix
+    let spec = input.source_spec();
+
+    Results {
+        duration: spec.num_frames as f32 / spec.sample_rate as f32,
+        num_channels: spec.channels,
+        num_samples: spec.num_frames * spec.channels as usize,
+        sample_rate: spec.sample_rate,
+        channel_layout: LayoutInfo::new(&spec),
+        downmix: input.downmix(),
+        gain: input.gain(),
+        digest: input.digest().cloned(),
+        parts: input.parts(),
+        analysis_start: (input.start_frame() > 0).then(|| input.start_frame()),
+        ..stream_results(&input.spec(), analysers, results)
+    }
+}
+
+/// The results of the analysers of a stream of `spec`, that wasn't read from a file.
+pub fn stream_results(
+    spec: &StreamSpec,
+    analysers: &[Box<dyn Analyser>],
+    results: Vec<AnalysisResult>,
+) -> Results {
+    let mut analysis = Analysis::default();
+    let mut checks = Vec::with_capacity(results.len());
+
+    for (analyser, result) in analysers.iter().zip(results) {
+        analysis.merge(result.analysis);
+        checks.push(Check {
+            analyser: analyser.name(),
+            exit_code: result.flags,
+        });
+    }
+
+    Results {
+        schema_version: SCHEMA_VERSION,
+        tool_version: env!("CARGO_PKG_VERSION"),
+        analysis,
+        duration: spec.num_frames as f32 / spec.sample_rate as f32,
+        num_channels: spec.channels,
+        num_samples: spec.num_frames * spec.channels as usize,
+        sample_rate: spec.sample_rate,
+        analysis_sample_rate: spec.sample_rate,
+        channel_layout: LayoutInfo::new(spec),
+        downmix: None,
+        gain: None,
+        digest: None,
+        parts: None,
+        excluded: None,
+        performance: None,
+        summary: None,
+        partial: false,
+        analysed_duration: None,
+        analysis_start: None,
+        timeseries: None,
+        time_format: None,
+        checks,
+    }
+}
+
+/// Adds `startTime` and `endTime` to every object in `value` with `startSample` and
+/// `endSample`.
+fn add_times(value: &mut Value, format: TimeFormat, sample_rate: i32) {
+    match value {
+        Value::Object(object) => {
+            for (sample, time) in [("startSample", "startTime"), ("endSample", "endTime")] {
+                if let Some(frame) = object.get(sample).and_then(Value::as_u64) {
+                    let formatted = format.format(frame as usize, sample_rate);
+                    object.insert(time.to_string(), Value::String(formatted));
+                }
+            }
+            for value in object.values_mut() {
+                add_times(value, format, sample_rate);
+            }
         }
+        Value::Array(values) => {
+            for value in values {
+                add_times(value, format, sample_rate);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The results as a JSON value, with segment times in the `--json-time` format.
+pub fn to_json_value(results: &Results) -> serde_json::Result<Value> {
+    let mut value = to_value(results)?;
+    if let Some(format) = results.time_format {
+        add_times(&mut value, format, results.analysis_sample_rate);
     }
 
-    if analysis.is_empty() {
-        // Shouldn't happen
-        return;
+    Ok(value)
+}
+
+/// The results as they are written to `--json`.
+pub fn to_json(results: &Results) -> serde_json::Result<String> {
+    // Going through a Value widens f32 to f64 first, which keeps the digits of earlier versions
+    to_json_value(results).and_then(|value| to_string_pretty(&value))
+}
+
+pub fn write_json(args: &AnalyseArgs, results: &Results) -> Result<()> {
+    let Some(path) = args.json.as_ref() else {
+        return Ok(());
+    };
+
+    // The digest of --digest is an output of its own, also when no analyser found anything
+    if results.analysis.is_empty() && results.digest.is_none() {
+        return Ok(());
     }
 
-    let (_, spec) = wav.wav_spec();
-    let sample_rate = spec.fmt_chunk.sample_rate;
-    let num_samples = wav.n_samples();
+    let write_error = |err| Error::io("write JSON output to", path, err);
+    let json = to_json(results).map_err(|err| write_error(err.into()))?;
+    std::fs::write(path, json).map_err(write_error)?;
 
-    std::fs::write(
-        path,
-        to_string_pretty(&JsonOutput {
-            analysis,
-            duration: num_samples as f32 / sample_rate as f32,
-            num_channels: wav.n_channels(),
-            num_samples,
-            sample_rate,
-        })
-        .unwrap(),
-    )
-    .expect("Could not write JSON output to file");
+    output!("Wrote JSON output to {}", path.display());
 
-    output!("Wrote JSON output to {}", path);
+    Ok(())
 }
@@ -0,0 +1,377 @@
+//! JUnit XML output for CI, with a test suite per file and a test case per analyser.
+
+use std::{fmt::Write, fs, path::Path};
+
+use crate::{
+    EXIT_BITS, Error, Result,
+    analysers::compliance::Verdict,
+    cli::AnalyseArgs,
+    json::{Analysis, Results},
+    output,
+};
+
+/// Why a test case didn't pass.
+enum Outcome {
+    Passed,
+    /// The analyser set return code bits, with the segments it found
+    Failed {
+        bits: String,
+        details: Vec<String>,
+    },
+    /// The file couldn't be analysed
+    Error(String),
+}
+
+struct TestCase {
+    name: String,
+    outcome: Outcome,
+}
+
+/// The test cases of one file.
+pub struct TestSuite {
+    file: String,
+    cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    /// The suite of the analysis of `file`, with a single erroring case if it failed.
+    pub fn new(file: &Path, result: &Result<(u8, Results)>) -> Self {
+        let cases = match result {
+            Ok((_, results)) => results
+                .checks
+                .iter()
+                .map(|check| TestCase {
+                    name: check.analyser.to_string(),
+                    outcome: match check.exit_code {
+                        0 => Outcome::Passed,
+                        code => Outcome::Failed {
+                            bits: bit_names(code),
+                            details: details(check.analyser, results),
+                        },
+                    },
+                })
+                .collect(),
+            Err(err) => vec![TestCase {
+                name: "analysis".to_string(),
+                outcome: Outcome::Error(err.to_string()),
+            }],
+        };
+
+        Self {
+            file: file.display().to_string(),
+            cases,
+        }
+    }
+
+    fn count(&self, f: impl Fn(&Outcome) -> bool) -> usize {
+        self.cases.iter().filter(|case| f(&case.outcome)).count()
+    }
+
+    fn failures(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed { .. }))
+    }
+
+    fn errors(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Error(_)))
+    }
+
+    fn render(&self, xml: &mut String) {
+        let file = escape(&self.file);
+        let _ = writeln!(
+            xml,
+            r#"  <testsuite name="{}" tests="{}" failures="{}" errors="{}">"#,
+            file,
+            self.cases.len(),
+            self.failures(),
+            self.errors()
+        );
+
+        for case in &self.cases {
+            let _ = write!(
+                xml,
+                r#"    <testcase name="{}" classname="{}""#,
+                escape(&case.name),
+                file
+            );
+
+            match &case.outcome {
+                Outcome::Passed => xml.push_str("/>\n"),
+                Outcome::Failed { bits, details } => {
+                    let _ = writeln!(
+                        xml,
+                        ">\n      <failure message=\"{0}\" type=\"{0}\">{1}</failure>\n    </testcase>",
+                        escape(bits),
+                        escape(&details.join("\n"))
+                    );
+                }
+                Outcome::Error(message) => {
+                    let _ = writeln!(
+                        xml,
+                        ">\n      <error message=\"{}\"/>\n    </testcase>",
+                        escape(message)
+                    );
+                }
+            }
+        }
+
+        xml.push_str("  </testsuite>\n");
+    }
+}
+
+/// Names of the return code bits set in `code`.
+fn bit_names(code: u8) -> String {
+    EXIT_BITS
+        .iter()
+        .filter(|(bit, _)| code & bit != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// What the analyser called `name` found, one line each.
+fn details(name: &str, results: &Results) -> Vec<String> {
+    let Analysis {
+        silence,
+        underruns,
+        discontinuities,
+        dynamics,
+        loudness_target,
+        compliance,
+        normalization,
+        channel_balance,
+        tones,
+        vad,
+        ..
+    } = &results.analysis;
+
+    match name {
+        "underruns" => underruns
+            .iter()
+            .flat_map(|underruns| {
+                let runs = underruns.results.iter().map(|segment| {
+                    format!(
+                        "Underrun on CH:{} from {:.3}s to {:.3}s ({} samples)",
+                        segment.channel, segment.start, segment.end, segment.duration_samples
+                    )
+                });
+                let stuck = underruns.stuck_segments.iter().flatten().map(|segment| {
+                    format!(
+                        "Stuck at {:+.6} on CH:{} from {:.3}s to {:.3}s ({} samples)",
+                        segment.value,
+                        segment.channel,
+                        segment.start,
+                        segment.end,
+                        segment.duration_samples
+                    )
+                });
+
+                runs.chain(stuck).collect::<Vec<_>>()
+            })
+            .collect(),
+        "discontinuities" => discontinuities
+            .iter()
+            .flat_map(|discontinuities| &discontinuities.results)
+            .map(|event| {
+                format!(
+                    "Discontinuity on CH:{} at {:.3}s, a step of {:.1} dBFS",
+                    event.channel, event.time, event.step
+                )
+            })
+            .collect(),
+        "loudness" => {
+            let segments = silence.iter().flat_map(|silence| &silence.results);
+            let silent: f32 = segments.clone().map(|segment| segment.duration).sum();
+            let mut lines = vec![format!(
+                "{:.3}% of {:.3}s silent",
+                100.0 * silent / results.duration.max(f32::MIN_POSITIVE),
+                results.duration
+            )];
+            let leading = silence.as_ref().and_then(|silence| {
+                let first = silence.results.first()?;
+                let max = silence.max_leading_silence?;
+                (first.start < silence.window_size && f64::from(first.end) > max).then(|| {
+                    format!(
+                        "{:.3}s of silence at the start, more than {max}s",
+                        first.end
+                    )
+                })
+            });
+            lines.extend(leading);
+            lines.extend(segments.map(|segment| {
+                let channel = segment
+                    .channel
+                    .map(|channel| format!(" on CH:{channel}"))
+                    .unwrap_or_default();
+                format!(
+                    "Silence{} from {:.3}s to {:.3}s",
+                    channel, segment.start, segment.end
+                )
+            }));
+
+            lines
+        }
+        "dynamics" => dynamics
+            .iter()
+            .flat_map(|dynamics| {
+                let overall = &dynamics.overall;
+                let dr = overall
+                    .dr
+                    .zip(dynamics.min_dr)
+                    .filter(|(dr, min)| dr < min)
+                    .map(|(dr, min)| format!("DR{dr:.0} is below the minimum of DR{min}"));
+                let clipped = dynamics
+                    .max_clipped_samples
+                    .filter(|max| overall.clipped_samples > *max)
+                    .map(|max| {
+                        format!(
+                            "{} clipped samples, more than the maximum of {max}",
+                            overall.clipped_samples
+                        )
+                    });
+
+                dr.into_iter().chain(clipped)
+            })
+            .collect(),
+        "loudness target" => loudness_target
+            .iter()
+            .map(|target| {
+                format!(
+                    "Integrated loudness of {:.1} LUFS is outside {} LUFS ± {} LU",
+                    target.integrated, target.target, target.tolerance
+                )
+            })
+            .collect(),
+        "compliance" => compliance
+            .iter()
+            .flat_map(|compliance| {
+                compliance
+                    .criteria
+                    .iter()
+                    .filter(|criterion| criterion.verdict == Verdict::Fail)
+                    .map(|criterion| {
+                        format!(
+                            "{} {} of {:.1} {} is not {}",
+                            compliance.standard,
+                            criterion.name,
+                            criterion.measured,
+                            criterion.unit,
+                            criterion.limit()
+                        )
+                    })
+            })
+            .collect(),
+        "normalization" => normalization
+            .iter()
+            .filter(|normalization| !normalization.possible)
+            .map(|normalization| {
+                format!(
+                    "Normalizing to {} LUFS puts the true peak at {:.1} dBTP, above the ceiling of {} dBTP",
+                    normalization.target,
+                    normalization.true_peak_after.unwrap_or(f64::NAN),
+                    normalization.peak_ceiling
+                )
+            })
+            .collect(),
+        "channel balance" => channel_balance
+            .iter()
+            .flat_map(|balance| {
+                balance
+                    .channels
+                    .iter()
+                    .filter(|level| {
+                        balance
+                            .max_imbalance
+                            .is_some_and(|max| level.imbalance > max)
+                    })
+                    .map(|level| {
+                        format!(
+                            "CH:{} is {:.1} dB below the loudest channel{}",
+                            level.channel,
+                            level.imbalance,
+                            if level.dead { " (dead)" } else { "" }
+                        )
+                    })
+            })
+            .collect(),
+        "tones" => tones
+            .iter()
+            .flat_map(|tones| &tones.results)
+            .map(|tone| {
+                format!(
+                    "{} Hz tone from {:.3}s to {:.3}s{}",
+                    tone.frequency,
+                    tone.start,
+                    tone.end,
+                    if tone.head { " (head)" } else { "" }
+                )
+            })
+            .chain(
+                tones
+                    .iter()
+                    .filter(|tones| !tones.results.iter().any(|tone| tone.head))
+                    .map(|_| "No line-up tone at the head".to_string()),
+            )
+            .collect(),
+        "vad" => vad
+            .iter()
+            .filter_map(|vad| {
+                Some(format!(
+                    "{:.1}% speech is below the minimum of {}%",
+                    vad.speech_percentage, vad.min_speech_percentage?
+                ))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `text` for XML attributes and content. Control characters XML doesn't allow are dropped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' | '\t' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+pub fn render_junit(suites: &[TestSuite]) -> String {
+    let tests: usize = suites.iter().map(|suite| suite.cases.len()).sum();
+    let failures: usize = suites.iter().map(TestSuite::failures).sum();
+    let errors: usize = suites.iter().map(TestSuite::errors).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        r#"<testsuites name="analwave" tests="{tests}" failures="{failures}" errors="{errors}">"#
+    );
+    for suite in suites {
+        suite.render(&mut xml);
+    }
+    xml.push_str("</testsuites>\n");
+
+    xml
+}
+
+/// Writes the suites to the `--junit` file.
+pub fn write_junit(args: &AnalyseArgs, suites: &[TestSuite]) -> Result<()> {
+    let Some(path) = args.junit.as_ref() else {
+        return Ok(());
+    };
+
+    fs::write(path, render_junit(suites))
+        .map_err(|err| Error::io("write JUnit report to", path, err))?;
+
+    output!("Wrote JUnit report to {}", path.display());
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+//! Everything found as an Audacity label track for `--labels`, to import with File > Import >
+//! Labels and step through the findings and content changes along the waveform.
+//!
+//! Each line is the start and end in seconds and the text, separated by tabs. Events without
+//! a duration, like discontinuities or the changes of `--novelty`, are point labels.
+
+use std::{fmt::Write as _, fs};
+
+use crate::{Error, Result, cli::AnalyseArgs, findings::findings, json::Results, output};
+
+/// Writes the findings of the results to the `--labels` file.
+pub fn write_labels(args: &AnalyseArgs, results: &Results) -> Result<()> {
+    let Some(path) = args.labels.as_ref() else {
+        return Ok(());
+    };
+
+    let sample_rate = results.sample_rate as f64;
+    let found = findings(results);
+
+    let mut labels = String::new();
+    for finding in &found {
+        let start = results.file_frame(finding.start) as f64 / sample_rate;
+        let end = results.file_frame(finding.end) as f64 / sample_rate;
+        let text = match finding.channel {
+            Some(channel) => format!("{} CH:{channel}", finding.kind.label()),
+            None => finding.kind.label().to_string(),
+        };
+
+        let _ = writeln!(labels, "{start:.6}\t{end:.6}\t{text}");
+    }
+
+    fs::write(path, labels).map_err(|err| Error::io("write labels to", path, err))?;
+    output!("Wrote {} labels to {}", found.len(), path.display());
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+//! Speaker layouts of multichannel input, which decide how each channel is weighted in the
+//! BS.1770 loudness. Layouts are kept as WAVE_FORMAT_EXTENSIBLE channel masks, where the
+//! channels are assigned to the set bits in order.
+
+use clap::ValueEnum;
+use ebur128::{Channel, EbuR128, Error as EbuR128Error, Mode};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::stream::StreamSpec;
+
+/// Speakers of the channel mask bits, from the lowest.
+const SPEAKERS: [&str; 18] = [
+    "FL", "FR", "FC", "LFE", "BL", "BR", "FLC", "FRC", "BC", "SL", "SR", "TC", "TFL", "TFC", "TFR",
+    "TBL", "TBC", "TBR",
+];
+
+const BACK: u32 = 0x30;
+const SIDE: u32 = 0x600;
+
+/// Speaker layouts for `--channel-layout`.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelLayout {
+    /// The channel mask of WAVE_FORMAT_EXTENSIBLE files, otherwise L R C LFE Ls Rs for the
+    /// first six channels
+    #[default]
+    Auto,
+    /// C
+    Mono,
+    /// L R
+    Stereo,
+    /// L R C LFE Ls Rs, with the surrounds as back speakers (FL FR FC LFE BL BR)
+    #[value(name = "5.1")]
+    Surround51,
+    /// L R C LFE Ls Rs, with the surrounds as side speakers (FL FR FC LFE SL SR)
+    #[value(name = "5.1-side")]
+    Surround51Side,
+    /// L R C LFE Lb Rb Ls Rs (FL FR FC LFE BL BR SL SR)
+    #[value(name = "7.1")]
+    Surround71,
+}
+
+impl ChannelLayout {
+    /// Channel mask of the layout, `None` for `auto`.
+    pub fn mask(self) -> Option<u32> {
+        match self {
+            Self::Auto => None,
+            Self::Mono => Some(0x4),
+            Self::Stereo => Some(0x3),
+            Self::Surround51 => Some(0x3f),
+            Self::Surround51Side => Some(0x60f),
+            Self::Surround71 => Some(0x63f),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Mono => "mono",
+            Self::Stereo => "stereo",
+            Self::Surround51 => "5.1",
+            Self::Surround51Side => "5.1-side",
+            Self::Surround71 => "7.1",
+        }
+    }
+}
+
+/// The speaker layout of the input as written to the JSON output.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct LayoutInfo {
+    /// Name of a known layout, e.g. 5.1 or 7.1, or custom
+    pub name: &'static str,
+    /// WAVE_FORMAT_EXTENSIBLE channel mask
+    pub mask: u32,
+    /// Speaker of every channel, e.g. FL or LFE, null for channels without one
+    pub speakers: Vec<Option<&'static str>>,
+}
+
+impl LayoutInfo {
+    /// The layout of `spec`, `None` if its channel mask is unknown.
+    pub fn new(spec: &StreamSpec) -> Option<Self> {
+        let mask = spec.channel_mask?;
+        let name = ChannelLayout::value_variants()
+            .iter()
+            .find(|layout| layout.mask() == Some(mask))
+            .map_or("custom", |layout| layout.as_str());
+
+        Some(Self {
+            name,
+            mask,
+            speakers: channel_speakers(Some(mask), spec.channels as usize),
+        })
+    }
+}
+
+/// Speaker of each of `channels` channels with `mask`, as ebur128 assigns them without one.
+pub fn channel_speakers(mask: Option<u32>, channels: usize) -> Vec<Option<&'static str>> {
+    // ebur128 takes four channels as L R Ls Rs, five as L R C Ls Rs and otherwise the first
+    // six as L R C LFE Ls Rs
+    let default = match channels {
+        4 => 0x33,
+        5 => 0x37,
+        _ => 0x3f,
+    };
+    let mut speakers: Vec<_> = speaker_bits(mask.unwrap_or(default))
+        .map(|bit| Some(SPEAKERS[bit]))
+        .take(channels)
+        .collect();
+    speakers.resize(channels, None);
+
+    speakers
+}
+
+/// Set bits of `mask` that stand for a speaker.
+fn speaker_bits(mask: u32) -> impl Iterator<Item = usize> {
+    (0..SPEAKERS.len()).filter(move |bit| mask & (1 << bit) != 0)
+}
+
+/// Number of speakers in `mask`.
+pub fn speaker_count(mask: u32) -> usize {
+    speaker_bits(mask).count()
+}
+
+/// How ebur128 weights the speaker of `bit`. With back and side speakers, as in 7.1, the
+/// side ones are the surrounds of BS.1770 and the back ones count at unity, otherwise
+/// either pair is the surrounds. The LFE is left out.
+fn speaker_channel(bit: usize, mask: u32) -> Channel {
+    let both = mask & BACK != 0 && mask & SIDE != 0;
+
+    match bit {
+        0 => Channel::Left,
+        1 => Channel::Right,
+        2 => Channel::Center,
+        4 if both => Channel::Mp135,
+        5 if both => Channel::Mm135,
+        4 => Channel::LeftSurround,
+        5 => Channel::RightSurround,
+        6 => Channel::MpSC,
+        7 => Channel::MmSC,
+        8 => Channel::Mp180,
+        9 if both => Channel::Mp090,
+        10 if both => Channel::Mm090,
+        9 => Channel::LeftSurround,
+        10 => Channel::RightSurround,
+        11 => Channel::Tp000,
+        12 => Channel::Up030,
+        13 => Channel::Up000,
+        14 => Channel::Um030,
+        15 => Channel::Up135,
+        16 => Channel::Up180,
+        17 => Channel::Um135,
+        _ => Channel::Unused,
+    }
+}
+
+/// A loudness meter for all channels of `spec`, with the channels weighted by its layout.
+/// Without a channel mask, ebur128 takes the first six channels as L R C LFE Ls Rs.
+pub fn loudness_meter(spec: &StreamSpec, mode: Mode) -> Result<EbuR128, EbuR128Error> {
+    let mut meter = EbuR128::new(spec.channels as u32, spec.sample_rate as u32, mode)?;
+
+    if let Some(mask) = spec.channel_mask {
+        let mut map: Vec<_> = speaker_bits(mask)
+            .map(|bit| speaker_channel(bit, mask))
+            .take(spec.channels as usize)
+            .collect();
+        map.resize(spec.channels as usize, Channel::Unused);
+        meter.set_channel_map(&map)?;
+    }
+
+    Ok(meter)
+}
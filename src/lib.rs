@@ -1,7 +1,69 @@
-pub mod analysers;
-pub mod cli;
-pub mod json;
-pub mod output;
-
-const ERR_CONTAINS_UNDERRUN: u8 = 0b0001;
-const ERR_CONTAINS_SILENCE: u8 = 0b0010;
+pub mod analysers;
+pub mod batch;
+pub mod cache;
+pub mod cancel;
+pub mod cli;
+pub mod compare;
+pub mod console;
+pub mod cue_points;
+pub mod digest;
+pub mod edl;
+pub mod error;
+pub mod exclude;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod findings;
+pub mod generate;
+pub mod input;
+pub mod inspect;
+pub mod json;
+pub mod junit;
+pub mod labels;
+pub mod layout;
+pub mod metrics;
+pub mod npy;
+pub mod output;
+pub mod paths;
+pub mod performance;
+pub mod pipeline;
+pub mod plan;
+#[cfg(all(unix, feature = "plugins"))]
+pub mod plugins;
+pub mod presets;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod registry;
+pub mod report;
+pub mod schema;
+pub mod segments;
+pub mod serve;
+pub mod session;
+pub mod status;
+pub mod stems;
+pub mod stream;
+pub mod subtitles;
+pub mod timeseries;
+pub mod tracks;
+pub mod visualize;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use error::{Error, Result};
+
+const ERR_CONTAINS_UNDERRUN: u8 = 0b0001;
+const ERR_CONTAINS_SILENCE: u8 = 0b0010;
+const ERR_LOW_DYNAMIC_RANGE: u8 = 0b0100;
+const ERR_TONE_CHECK: u8 = 0b1000;
+const ERR_CHANNEL_IMBALANCE: u8 = 0b1_0000;
+const ERR_LOUDNESS_TARGET: u8 = 0b10_0000;
+
+/// Exit code bits with the names they are reported under in batch summaries.
+pub const EXIT_BITS: [(u8, &str); 6] = [
+    (ERR_CONTAINS_UNDERRUN, "underrun"),
+    (ERR_CONTAINS_SILENCE, "silence"),
+    (ERR_LOW_DYNAMIC_RANGE, "lowDynamicRange"),
+    (ERR_TONE_CHECK, "tone"),
+    (ERR_CHANNEL_IMBALANCE, "channelImbalance"),
+    (ERR_LOUDNESS_TARGET, "loudnessTarget"),
+];
@@ -1,146 +1,337 @@
-use clap::Parser;
-use std::path::PathBuf;
+use std::path::Path;
 use std::process::ExitCode;
-use wavers::{Wav, WaversResult};
-
-use analwave::analysers::{
-    Analyser, fft::FftAnalyser, loudness::LoudnessAnalyser, peaks::PeaksAnalyzer,
-    underruns::UnderrunAnalyser,
-};
-use analwave::cli::Cli;
-use analwave::output;
-use analwave::output::{fmt_frame, init_output};
-
-use analwave::json::write_json;
-
-/// Set png output path to either the provided PNG file path,
-/// or derive it from the JSON output path.
-fn calculate_png_path(
-    json: &Option<String>,
-    file: &Option<String>,
-    suffix: &str,
-) -> Option<PathBuf> {
-    if let Some(file) = file {
-        Some(PathBuf::from(file))
-    } else if let Some(json) = json {
-        let mut path = PathBuf::from(json);
-        let name = path.file_stem().unwrap().to_string_lossy();
-        path.set_file_name(format!("{name}_{suffix}.png"));
-
-        Some(path)
-    } else {
-        None
-    }
+
+use analwave::batch::{self, Batch};
+use analwave::cache::{self, Cache};
+use analwave::cli::{AnalyseArgs, Cli, Command, CompareArgs, GenerateArgs, InspectArgs};
+use analwave::error::EXIT_CANCELLED;
+use analwave::input::Input;
+use analwave::output::{Level, init_output};
+use analwave::paths;
+use analwave::pipeline;
+use analwave::plan;
+use analwave::presets::print_presets;
+use analwave::progress;
+use analwave::registry::{Registry, print_analysers};
+use analwave::serve;
+use analwave::status::print_status_line;
+use analwave::stems::Stems;
+use analwave::visualize::{visualize, visualize_peaks};
+use analwave::{Error, Result, cancel, compare, error, generate, inspect, warn};
+
+use analwave::json::Results;
+use analwave::junit::{TestSuite, write_junit};
+use analwave::schema::print_schema;
+
+fn analyse(args: &AnalyseArgs, path: &Path, input: &mut Input) -> Result<(u8, Results)> {
+    let spec = input.spec();
+    let analysers = pipeline::analysers(args, &spec)?;
+
+    init_output(args, spec.num_frames as u64);
+    pipeline::print_settings(args, input, &analysers);
+
+    pipeline::analyse(args, path, input, analysers)
 }
 
-fn analyse(args: &Cli, wav: &mut Wav<i32>) -> Result<u8, ()> {
-    let mut return_code = 0;
+fn generate(args: &GenerateArgs) -> Result<()> {
+    let generated = generate::render(args);
 
-    let mut analysers: Vec<Box<dyn Analyser>> = vec![];
+    println!("[+] signal:      {:?}", args.signal);
+    println!("[+] sample rate: {}", args.sample_rate);
+    println!("[+] channels:    {}", args.channels);
+    println!("[+] duration:    {} seconds", args.duration);
 
-    if args.silence || args.loudness {
-        analysers.push(Box::new(
-            LoudnessAnalyser::new(args, wav).expect("Could not initialize EbuR128"),
-        ));
+    if let Some(loudness) = generated.loudness {
+        println!("[+] loudness:    {:.2} LUFS-I", loudness);
     }
+    println!("[+] peak:        {:.2} dBFS", 20.0 * generated.peak.log10());
 
-    if args.underrun {
-        analysers.push(Box::new(UnderrunAnalyser::new(args, wav)));
+    if generated.peak > 1.0 {
+        warn!("signal exceeds full scale and will be clipped");
     }
 
-    if args.fft || args.fft_vis.is_some() {
-        let mut path = None;
-        if args.fft {
-            path = calculate_png_path(&args.json, &args.fft_file, "fft");
-        }
+    generate::write(&args.output, args, &generated.samples)?;
 
-        if args.fft && path.is_none() {
-            println!(
-                "FFT output was enabled but no path could be determined, please provide --fft-file or --json"
-            );
-            return Err(());
-        } else {
-            analysers.push(Box::new(FftAnalyser::new(args, wav, path)));
-        }
-    }
+    println!("Wrote {}", args.output.display());
 
-    if args.peaks {
-        let mut path = None;
-        if args.peaks {
-            path = calculate_png_path(&args.json, &args.peaks_file, "peaks");
-        }
+    Ok(())
+}
+
+fn compare(args: &CompareArgs) -> Result<()> {
+    let comparison = compare::compare(args)?;
+
+    println!(
+        "[+] reference:       {} ({:.3} seconds)",
+        args.reference.display(),
+        comparison.reference_duration
+    );
+    println!(
+        "[+] processed:       {} ({:.3} seconds)",
+        args.processed.display(),
+        comparison.processed_duration
+    );
+    println!("[+] mean difference: {:.2} dB", comparison.mean_difference);
+    println!(
+        "[+] max difference:  {:.2} dB @ {:.3} seconds",
+        comparison.max_difference, comparison.max_difference_at
+    );
+    println!("Wrote comparison to {}", args.output.display());
+
+    Ok(())
+}
+
+fn inspect(args: &InspectArgs) -> Result<()> {
+    let inspection = inspect::inspect(&args.input)?;
 
-        if let Some(path) = path {
-            analysers.push(Box::new(PeaksAnalyzer::new(args, wav, path)));
+    println!("[+] container:       {}", inspection.container);
+    println!("[+] sample rate:     {}", inspection.sample_rate);
+    println!("[+] channels:        {}", inspection.channels);
+    println!("[+] bits per sample: {}", inspection.bits_per_sample);
+    println!(
+        "[+] encoding:        {}{}",
+        inspection.encoding,
+        if inspection.extensible {
+            " (extensible)"
         } else {
-            println!(
-                "Peaks output was enabled but no path could be determined, please provide --peaks-file or --json"
-            );
-            return Err(());
+            ""
         }
+    );
+    println!("[+] frames:          {}", inspection.num_frames);
+    println!("[+] duration:        {:.3} seconds", inspection.duration);
+    println!(
+        "[+] data size:       {} bytes{}",
+        inspection.data_size,
+        if inspection.truncated {
+            " (truncated)"
+        } else {
+            ""
+        }
+    );
+
+    for chunk in inspection.chunks.iter() {
+        println!(
+            "[+] chunk:           '{}' {} bytes at {}",
+            chunk.id, chunk.size, chunk.offset
+        );
+    }
+
+    for (key, value) in inspection.info.iter() {
+        println!("[+] {:<17}{}", format!("{}:", key), value);
     }
 
-    if analysers.is_empty() {
-        println!("No detection is active, exiting.");
-        return Err(());
+    if let Some(bext) = inspection.bext.as_ref() {
+        println!("[+] description:     {}", bext.description);
+        println!("[+] originator:      {}", bext.originator);
+        println!(
+            "[+] origination:     {} {}",
+            bext.origination_date, bext.origination_time
+        );
+        println!("[+] time reference:  {}", bext.time_reference);
     }
 
-    let (_, spec) = wav.wav_spec();
-    init_output(&args, wav.n_samples() as u64);
+    inspect::write_inspection(args, &inspection)
+}
 
-    output!("[+] sample rate:        {}", &spec.fmt_chunk.sample_rate);
-    output!("[+] channels:           {}", wav.n_channels());
-    output!("[+] total samples:      {}", wav.n_samples());
+fn analyse_file(args: &AnalyseArgs) -> Result<u8> {
+    if args.print_schema {
+        return print_schema().map(|_| 0);
+    }
 
-    if args.silence {
-        output!("[+] silence threshold:  {} LUFS-S", &args.lufs);
-        output!("[+] silence window:     {} seconds", &args.window_size);
+    if args.list_presets {
+        print_presets();
+        return Ok(0);
     }
 
-    if args.underrun {
-        output!("[+] underrun threshold: {} samples", &args.samples);
+    if args.list_analysers {
+        print_analysers(&Registry::new(args)?);
+        return Ok(0);
     }
 
-    if args.fft || args.fft_vis.is_some() {
-        output!("[+] FFT bins:           {}", &args.fft_bins);
+    if args.dry_run {
+        return plan::dry_run(args).map(|_| 0);
     }
 
-    let digits = wav.n_samples().to_string().len();
-    let num_frames = wav.n_samples();
-    let frames = wav.frames();
+    cache::check_options(args)?;
+    progress::open(args)?;
 
-    for (frame_counter, frame) in frames.enumerate() {
-        let frame_label = fmt_frame(frame_counter, digits);
-        output::inc();
+    // Stop cleanly on Ctrl-C, with the outputs of what was analysed until then
+    cancel::cancel_on_interrupt();
+    paths::create_out_dir(args)?;
 
-        for analyser in analysers.iter_mut() {
-            analyser.analyse(&frame_label, frame_counter, &frame);
-        }
+    if !args.stems.is_empty() {
+        return analyse_stems(args);
     }
 
-    let frame_label = fmt_frame(num_frames, digits);
+    if !args.concat.is_empty() {
+        return analyse_concat(args);
+    }
+
+    let input = match args.input.as_slice() {
+        [] => return Err(Error::Config("No input file given".to_string())),
+        [input] if args.batch_summary.is_none() => input,
+        _ => return analyse_batch(args, None),
+    };
 
-    for analyser in analysers.iter_mut() {
-        return_code |= analyser.finish(&frame_label);
+    let args = paths::resolve_outputs(args);
+    let cache = Cache::open(&args, input)?;
+    if let Some(return_code) = cache
+        .as_ref()
+        .map_or(Ok(None), |cache| cache.reuse(&args))?
+    {
+        return Ok(return_code);
     }
 
-    output::finish();
+    analyse_input(&args, input, Input::open(&args, input), cache.as_ref())
+}
+
+fn analyse_concat(args: &AnalyseArgs) -> Result<u8> {
+    // The outputs are named after the first file
+    let first = &args.concat[0];
+    let args = paths::resolve_outputs(&AnalyseArgs {
+        input: vec![first.clone()],
+        ..args.clone()
+    });
+    analyse_input(&args, first, Input::concat(&args, &args.concat), None)
+}
+
+/// Analyses one opened input, reported as `name`, and keeps the results in `cache`.
+fn analyse_input(
+    args: &AnalyseArgs,
+    name: &Path,
+    input: Result<Input>,
+    cache: Option<&Cache>,
+) -> Result<u8> {
+    let result = input.and_then(|mut input| analyse(args, name, &mut input));
+    let junit = write_junit(args, &[TestSuite::new(name, &result)]);
+    print_status_line(args, name, &result);
 
-    write_json(args, wav, &analysers);
+    let (return_code, results) = result?;
+    junit?;
+
+    if let Some(cache) = cache {
+        cache.store(return_code, &results);
+    }
+
+    if results.partial {
+        return Ok(EXIT_CANCELLED);
+    }
 
     Ok(return_code)
 }
 
-fn main() -> ExitCode {
-    let args = Cli::parse();
-    let Ok(mut wav): WaversResult<Wav<i32>> = Wav::from_path(&args.input) else {
-        println!("Could not open file: {}", args.input);
-        return ExitCode::from(1);
-    };
+fn analyse_stems(args: &AnalyseArgs) -> Result<u8> {
+    let stems = Stems::inspect(&args.stems)?;
 
-    let Ok(code) = analyse(&args, &mut wav) else {
-        return ExitCode::from(1);
-    };
+    analyse_batch(
+        &AnalyseArgs {
+            input: args.stems.clone(),
+            ..args.clone()
+        },
+        Some(stems),
+    )
+}
+
+fn analyse_batch(args: &AnalyseArgs, stems: Option<Stems>) -> Result<u8> {
+    // Check the output paths before the first file
+    let files = args
+        .input
+        .iter()
+        .map(|input| batch::file_args(args, input))
+        .collect::<Result<Vec<_>>>()?;
+    let mut batch = Batch::new(args);
+    let mut suites = Vec::new();
+    let mut partial = false;
+
+    for (index, file_args) in files.iter().enumerate() {
+        let input = &args.input[index];
+        if Level::from_args(args) >= Level::Info {
+            println!("[{}/{}] {}", index + 1, files.len(), input.display());
+        }
+
+        let file_args = paths::resolve_outputs(file_args);
+        let (cache, reused) = match Cache::open(&file_args, input) {
+            Ok(cache) => {
+                let reused = cache
+                    .as_ref()
+                    .map_or(Ok(None), |cache| cache.reuse(&file_args));
+                (cache, reused)
+            }
+            Err(err) => (None, Err(err)),
+        };
+
+        if let Ok(Some(return_code)) = reused {
+            batch.add(input, &Ok(return_code));
+        } else {
+            let result = reused
+                .and_then(|_| Input::open(args, input))
+                .and_then(|mut file| analyse(&file_args, input, &mut file));
+            if let Err(err) = &result {
+                error!("{err}");
+            }
+            print_status_line(&file_args, input, &result);
+
+            if let (Some(cache), Ok((return_code, results))) = (&cache, &result) {
+                cache.store(*return_code, results);
+            }
+            partial |= result.as_ref().is_ok_and(|(_, results)| results.partial);
+            suites.push(TestSuite::new(input, &result));
+            batch.add(input, &result.map(|(return_code, _)| return_code));
+        }
+
+        // The remaining files are skipped on Ctrl-C, a --timeout only stops the file
+        if cancel::is_cancelled() && index + 1 < files.len() {
+            warn!("cancelled, {} files not analysed", files.len() - index - 1);
+            break;
+        }
+    }
+
+    let summary = batch.summary(stems);
+    if Level::from_args(args) >= Level::Info {
+        summary.print();
+    }
+    summary.write(args)?;
+
+    // One report for the whole batch
+    let junit = args
+        .junit
+        .as_deref()
+        .map(|path| paths::output_path(&paths::in_out_dir(args, path), args.force));
+    write_junit(
+        &AnalyseArgs {
+            junit,
+            ..args.clone()
+        },
+        &suites,
+    )?;
+
+    if partial {
+        return Ok(EXIT_CANCELLED);
+    }
+
+    Ok(summary.exit_code)
+}
+
+fn run(args: &Cli) -> Result<u8> {
+    match &args.command {
+        Some(Command::Analyse(analyse_args)) => analyse_file(analyse_args),
+        Some(Command::Visualize(visualize_args)) => visualize(visualize_args).map(|_| 0),
+        Some(Command::VisualizePeaks(peaks_args)) => visualize_peaks(peaks_args).map(|_| 0),
+        Some(Command::Inspect(inspect_args)) => inspect(inspect_args).map(|_| 0),
+        Some(Command::Generate(generate_args)) => generate(generate_args).map(|_| 0),
+        Some(Command::Compare(compare_args)) => compare(compare_args).map(|_| 0),
+        Some(Command::Serve(serve_args)) => serve::serve(serve_args).map(|_| 0),
+        None => analyse_file(&args.analyse),
+    }
+}
 
-    ExitCode::from(code)
+fn main() -> ExitCode {
+    let args = Cli::parse_with_preset();
+
+    match run(&args) {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            error!("{err}");
+            ExitCode::from(err.exit_code())
+        }
+    }
 }
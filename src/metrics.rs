@@ -0,0 +1,340 @@
+//! Analysis results as metrics in the Prometheus text format, for `--metrics`.
+
+use std::{fmt::Write, fs, path::Path, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{Error, Result, cli::AnalyseArgs, json::Results, output};
+
+/// Where `--metrics` goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetricsTarget {
+    /// A file rewritten after every analysis, e.g. for the textfile collector of node_exporter
+    File(PathBuf),
+    /// A port `analwave serve` answers `GET /metrics` on, written `:PORT`
+    Port(u16),
+}
+
+impl FromStr for MetricsTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_prefix(':') {
+            Some(port) => port
+                .parse()
+                .map(Self::Port)
+                .map_err(|_| format!("'{port}' is not a port")),
+            None => Ok(Self::File(PathBuf::from(value))),
+        }
+    }
+}
+
+impl MetricsTarget {
+    pub fn path_mut(&mut self) -> Option<&mut PathBuf> {
+        match self {
+            Self::File(path) => Some(path),
+            Self::Port(_) => None,
+        }
+    }
+}
+
+/// The measurements of one analysis that are exported, `None` where the analyser measuring
+/// them didn't run.
+#[derive(Debug, Clone)]
+pub struct FileMetrics {
+    /// Integrated loudness in LUFS, from `--dynamics` or `--target-lufs`
+    pub loudness: Option<f64>,
+    /// Share of the duration that is silent, from `--silence`
+    pub silence_ratio: Option<f64>,
+    /// Number of underruns, from `--underrun`
+    pub underruns: Option<usize>,
+    /// Number of clipped samples, from `--dynamics`
+    pub clipped: Option<u64>,
+    /// Duration of the input in seconds
+    pub duration: f64,
+    /// Time the analysis took in seconds
+    pub processing: f64,
+    pub exit_code: u8,
+}
+
+impl FileMetrics {
+    pub fn new(args: &AnalyseArgs, results: &Results, exit_code: u8, processing: Duration) -> Self {
+        let analysis = &results.analysis;
+        let duration = results.duration as f64;
+
+        let silence_ratio = args.detect_silence().then(|| {
+            let silent = analysis.silence.as_ref().map_or(0, |silence| {
+                silence
+                    .results
+                    .iter()
+                    .map(|segment| segment.duration_samples)
+                    .sum::<usize>()
+            });
+            // Segments are at the analysis rate, and per channel they add up to the duration
+            // of all channels
+            let channels = if args.per_channel {
+                results.num_channels.into()
+            } else {
+                1.0
+            };
+            // Of the frames that were measured, --exclude ranges aren't
+            let excluded: usize = results
+                .excluded
+                .iter()
+                .flatten()
+                .map(|range| range.end_sample - range.start_sample)
+                .sum();
+            let frames =
+                (duration * results.analysis_sample_rate as f64 - excluded as f64) * channels;
+
+            if frames > 0.0 {
+                (silent as f64 / frames).min(1.0)
+            } else {
+                0.0
+            }
+        });
+
+        Self {
+            loudness: analysis
+                .dynamics
+                .as_ref()
+                .map(|dynamics| dynamics.overall.loudness)
+                .or(analysis
+                    .loudness_target
+                    .as_ref()
+                    .map(|target| target.integrated)),
+            silence_ratio,
+            underruns: args.underrun.then(|| {
+                analysis
+                    .underruns
+                    .as_ref()
+                    .map_or(0, |underruns| underruns.count)
+            }),
+            clipped: analysis
+                .dynamics
+                .as_ref()
+                .map(|dynamics| dynamics.overall.clipped_samples),
+            duration,
+            processing: processing.as_secs_f64(),
+            exit_code,
+        }
+    }
+}
+
+/// Totals of all analyses of `analwave serve`.
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    done: u64,
+    failed: u64,
+    underruns: u64,
+    clipped: u64,
+    duration: f64,
+    processing: f64,
+    last: Option<FileMetrics>,
+}
+
+impl ServerMetrics {
+    pub fn add(&mut self, metrics: FileMetrics) {
+        self.done += 1;
+        self.underruns += metrics.underruns.unwrap_or(0) as u64;
+        self.clipped += metrics.clipped.unwrap_or(0);
+        self.duration += metrics.duration;
+        self.processing += metrics.processing;
+        self.last = Some(metrics);
+    }
+
+    pub fn add_failure(&mut self) {
+        self.failed += 1;
+    }
+
+    /// The metrics, with the number of `queued` and `running` jobs.
+    pub fn render(&self, queued: usize, running: usize) -> String {
+        let mut text = Text::default();
+
+        text.metric(
+            "analwave_analyses_total",
+            "counter",
+            "Analyses by result",
+            &[
+                ("result=\"done\"".to_string(), Some(self.done as f64)),
+                ("result=\"failed\"".to_string(), Some(self.failed as f64)),
+            ],
+        );
+        text.metric(
+            "analwave_jobs",
+            "gauge",
+            "Jobs by status",
+            &[
+                ("status=\"queued\"".to_string(), Some(queued as f64)),
+                ("status=\"running\"".to_string(), Some(running as f64)),
+            ],
+        );
+        text.counter(
+            "analwave_underruns_total",
+            "Underruns found",
+            self.underruns as f64,
+        );
+        text.counter(
+            "analwave_clipped_samples_total",
+            "Clipped samples found",
+            self.clipped as f64,
+        );
+        text.counter(
+            "analwave_audio_duration_seconds_total",
+            "Duration of the analysed audio",
+            self.duration,
+        );
+        text.counter(
+            "analwave_processing_duration_seconds_total",
+            "Time spent analysing",
+            self.processing,
+        );
+
+        if let Some(last) = &self.last {
+            text.file("analwave_last", "", last);
+        }
+
+        text.0
+    }
+}
+
+/// Metrics text, written one metric family at a time.
+#[derive(Default)]
+struct Text(String);
+
+impl Text {
+    /// A metric family with one sample per label set, samples without a value are left out.
+    fn metric(&mut self, name: &str, kind: &str, help: &str, samples: &[(String, Option<f64>)]) {
+        if samples.iter().all(|(_, value)| value.is_none()) {
+            return;
+        }
+
+        let _ = writeln!(self.0, "# HELP {name} {help}");
+        let _ = writeln!(self.0, "# TYPE {name} {kind}");
+        for (labels, value) in samples {
+            let Some(value) = value else {
+                continue;
+            };
+
+            match labels.as_str() {
+                "" => {
+                    let _ = writeln!(self.0, "{name} {}", format_value(*value));
+                }
+                labels => {
+                    let _ = writeln!(self.0, "{name}{{{labels}}} {}", format_value(*value));
+                }
+            }
+        }
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: f64) {
+        self.metric(name, "counter", help, &[(String::new(), Some(value))]);
+    }
+
+    /// The gauges of one analysis, named `<prefix>_...`.
+    fn file(&mut self, prefix: &str, labels: &str, metrics: &FileMetrics) {
+        let gauges = [
+            (
+                "integrated_loudness_lufs",
+                "Integrated loudness in LUFS",
+                metrics.loudness,
+            ),
+            (
+                "silence_ratio",
+                "Share of the duration that is silent",
+                metrics.silence_ratio,
+            ),
+            (
+                "underruns",
+                "Number of underruns",
+                metrics.underruns.map(|count| count as f64),
+            ),
+            (
+                "clipped_samples",
+                "Number of samples at 0.999 of full scale or above",
+                metrics.clipped.map(|count| count as f64),
+            ),
+            (
+                "audio_duration_seconds",
+                "Duration of the audio",
+                Some(metrics.duration),
+            ),
+            (
+                "processing_duration_seconds",
+                "Time the analysis took",
+                Some(metrics.processing),
+            ),
+            (
+                "exit_code",
+                "Return code bits of the checks",
+                Some(metrics.exit_code.into()),
+            ),
+        ];
+
+        for (name, help, value) in gauges {
+            self.metric(
+                &format!("{prefix}_{name}"),
+                "gauge",
+                help,
+                &[(labels.to_string(), value)],
+            );
+        }
+    }
+}
+
+/// A sample value, with the spelling Prometheus expects for the special values.
+fn format_value(value: f64) -> String {
+    match value {
+        value if value.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "+Inf".to_string(),
+        f64::NEG_INFINITY => "-Inf".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The metrics of the analysis of `file`.
+pub fn render(file: &str, metrics: &FileMetrics) -> String {
+    let mut text = Text::default();
+    text.file(
+        "analwave",
+        &format!("file=\"{}\"", escape_label(file)),
+        metrics,
+    );
+
+    text.0
+}
+
+/// Writes `text` to `path` through a temporary file, so collectors never read half of it.
+pub fn write(path: &Path, text: &str) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+
+    fs::write(&temporary, text)
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|err| Error::io("write metrics to", path, err))
+}
+
+/// Writes the metrics of an analysis of `file` to the `--metrics` file.
+pub fn write_metrics(
+    args: &AnalyseArgs,
+    file: &Path,
+    results: &Results,
+    exit_code: u8,
+    processing: Duration,
+) -> Result<()> {
+    let Some(MetricsTarget::File(path)) = &args.metrics else {
+        return Ok(());
+    };
+
+    let metrics = FileMetrics::new(args, results, exit_code, processing);
+    write(path, &render(&file.display().to_string(), &metrics))?;
+
+    output!("Wrote metrics to {}", path.display());
+
+    Ok(())
+}
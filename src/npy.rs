@@ -0,0 +1,32 @@
+//! Writer for the header of NumPy `.npy` files (format version 1.0), so raw outputs can be
+//! loaded with `numpy.load`.
+
+const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+/// Header of a C-order array of `shape` with the element type `descr`, e.g. `<f8` for
+/// little-endian f64. The array data follows it directly.
+pub fn header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [length] => format!("({length},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|length| length.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+
+    // The data starts 64-byte aligned, the header ends with a newline
+    let length = MAGIC.len() + 2 + dict.len() + 1;
+    dict.push_str(&" ".repeat(length.next_multiple_of(64) - length));
+    dict.push('\n');
+
+    let mut header = MAGIC.to_vec();
+    header.extend((dict.len() as u16).to_le_bytes());
+    header.extend(dict.as_bytes());
+
+    header
+}
@@ -1,102 +1,348 @@
-use std::sync::OnceLock;
+use std::{
+    fmt,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
 
-use crate::cli::Cli;
+use crate::{
+    cli::AnalyseArgs,
+    progress::{self, Phase},
+};
+use clap::ValueEnum;
 use indicatif::{ProgressBar, ProgressStyle};
 
 pub static OUTPUT: OnceLock<Output> = OnceLock::new();
 
-pub fn init_output(args: &Cli, num_frames: u64) {
-    let output = Output::new(args, num_frames);
-    OUTPUT.set(output).unwrap();
+pub fn init_output(args: &AnalyseArgs, num_frames: u64) {
+    progress::start(args.input.first().map(|input| input.as_path()), num_frames);
+
+    match OUTPUT.get() {
+        // Later files of a batch get a fresh progress bar
+        Some(output) => output.restart(num_frames),
+        None => OUTPUT.set(Output::new(args, num_frames)).unwrap(),
+    }
+}
+
+/// Sets the output up for messages without a progress bar, for a file that isn't analysed.
+/// The next [`init_output`] shows the progress again.
+pub fn init_messages(args: &AnalyseArgs) {
+    let output = OUTPUT.get_or_init(|| Output::new(args, 0));
+    if let Ok(mut pb) = output.progress_bar.lock() {
+        *pb = None;
+    }
+}
+
+/// Severity of a message, from most to least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    /// Least important level shown with `--silent`, `--quiet` or `--verbose`.
+    pub fn from_args(args: &AnalyseArgs) -> Self {
+        if args.silent {
+            Self::Error
+        } else if args.quiet {
+            Self::Warn
+        } else if args.verbose {
+            Self::Debug
+        } else {
+            Self::Info
+        }
+    }
+
+    fn prefix(&self) -> &'static str {
+        match self {
+            Self::Error => "Error: ",
+            Self::Warn => "Warning: ",
+            Self::Info | Self::Debug => "",
+        }
+    }
 }
 
+/// Results on stdout, at the info level.
 #[macro_export]
 macro_rules! output {
     ($($arg:tt)*) => {
         if let Some(output) = $crate::output::OUTPUT.get() {
-            if output.enabled() {
+            if output.enabled($crate::output::Level::Info) {
                 println!($($arg)*);
             }
         }
     };
 }
 
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::output::log($crate::output::Level::Error, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::output::log($crate::output::Level::Warn, format_args!($($arg)*))
+    };
+}
+
 #[macro_export]
 macro_rules! debug {
     ($($arg:tt)*) => {
-        if let Some(output) = $crate::output::OUTPUT.get() {
-            if output.debug {
-                println!($($arg)*);
-            }
-        }
+        $crate::output::log($crate::output::Level::Debug, format_args!($($arg)*))
     };
 }
 
-pub fn inc() {
+/// Writes a message to stderr, so stdout only has results. Errors and warnings are also shown
+/// before the output is set up, and in the commands that don't set it up.
+pub fn log(level: Level, args: fmt::Arguments) {
+    match OUTPUT.get() {
+        Some(output) if output.enabled(level) => {
+            output.suspend(|| eprintln!("{}{}", level.prefix(), args))
+        }
+        None if level <= Level::Warn => eprintln!("{}{}", level.prefix(), args),
+        _ => {}
+    }
+}
+
+pub fn inc(delta: u64) {
     if let Some(output) = OUTPUT.get() {
-        output.inc();
+        output.inc(delta);
     }
+    progress::advance(delta);
+}
+
+/// Switches the progress bar to the finish phase, after the last frame was analysed, where
+/// it shows the steps of the analysers until [`finish`].
+pub fn finishing() {
+    if let Some(output) = OUTPUT.get() {
+        output.finishing();
+    }
+    progress::phase(Phase::Finishing);
 }
 
 pub fn finish() {
     if let Some(output) = OUTPUT.get() {
         output.finish();
     }
+    progress::phase(Phase::Done);
+}
+
+/// A long step of an analyser in the finish phase, like computing the spectrogram of a long
+/// recording. What it reports is shown next to the progress bar until it is dropped.
+pub struct Step {
+    name: &'static str,
+}
+
+impl Step {
+    pub fn new(name: &'static str) -> Self {
+        Self { name }
+    }
+
+    /// Shows `status` for the step, e.g. `channel 2/8, 40%`.
+    pub fn report(&self, status: impl fmt::Display) {
+        if let Some(output) = OUTPUT.get() {
+            output.set_step(self.name, Some(status.to_string()));
+        }
+    }
+}
+
+impl Drop for Step {
+    fn drop(&mut self) {
+        if let Some(output) = OUTPUT.get() {
+            output.set_step(self.name, None);
+        }
+    }
 }
 
 pub fn fmt_frame(frame: usize, digits: usize) -> String {
     format!("{:0width$}", frame, width = digits)
 }
 
+/// How positions in the input are written, for `--time-format`.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// Seconds with millisecond precision, e.g. 83.250
+    Seconds,
+    /// Hours, minutes and seconds, e.g. 00:01:23.250
+    #[default]
+    Hms,
+    /// SMPTE timecode at 25 fps, e.g. 00:01:23:06
+    Smpte25,
+    /// SMPTE drop-frame timecode at 29.97 fps, e.g. 00:01:23;07
+    Smpte2997,
+    /// Frames at the analysis sample rate, e.g. 3996000
+    Samples,
+}
+
+impl TimeFormat {
+    pub fn format(self, frame: usize, sample_rate: i32) -> String {
+        let seconds = frame as f64 / sample_rate as f64;
+
+        match self {
+            Self::Seconds => format!("{seconds:.3}"),
+            Self::Hms => {
+                let seconds = seconds as f32;
+                let hours = (seconds / 3600.0).floor();
+                let minutes = ((seconds % 3600.0) / 60.0).floor();
+                let secs = seconds % 60.0;
+                format!("{:02.0}:{:02.0}:{:06.3}", hours, minutes, secs)
+            }
+            Self::Smpte25 => {
+                let frames = (frame as u64 * 25) / sample_rate as u64;
+                timecode(frames, 25, ':')
+            }
+            Self::Smpte2997 => {
+                let frames = (frame as u64 * 30_000) / (sample_rate as u64 * 1001);
+                // Frame numbers 0 and 1 are skipped at the start of every minute except
+                // every tenth, so the timecode keeps up with the clock
+                let tens = frames / 17_982;
+                let rest = frames % 17_982;
+                let dropped = 18 * tens + if rest < 2 { 0 } else { 2 * ((rest - 2) / 1798) };
+                timecode(frames + dropped, 30, ';')
+            }
+            Self::Samples => frame.to_string(),
+        }
+    }
+}
+
+/// `hh:mm:ss` and `frames` timecode frames at `rate` fps, hours may go past 23.
+fn timecode(frames: u64, rate: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:02}",
+        frames / (3600 * rate),
+        frames / (60 * rate) % 60,
+        frames / rate % 60,
+        frames % rate
+    )
+}
+
+/// `frame` in the `--time-format` of the output.
 pub fn frame_to_time(frame: usize, sample_rate: i32) -> String {
-    let seconds = frame as f32 / sample_rate as f32;
-    let hours = (seconds / 3600.0).floor();
-    let minutes = ((seconds % 3600.0) / 60.0).floor();
-    let secs = seconds % 60.0;
-    format!("{:02.0}:{:02.0}:{:06.3}", hours, minutes, secs)
+    OUTPUT
+        .get()
+        .map_or_else(TimeFormat::default, |output| output.time_format)
+        .format(frame, sample_rate)
 }
 
 #[derive(Debug)]
 pub struct Output {
-    pub progress_bar: Option<ProgressBar>,
-    pub silent: bool,
-    pub debug: bool,
+    pub progress_bar: Mutex<Option<ProgressBar>>,
+    /// Status of the running steps of the finish phase, by analyser
+    steps: Mutex<Vec<(&'static str, String)>>,
+    pub progress: bool,
+    pub level: Level,
+    pub time_format: TimeFormat,
+}
+
+fn progress_bar(num_frames: u64) -> ProgressBar {
+    let pb = ProgressBar::new(num_frames);
+    pb.set_style(
+        ProgressStyle::with_template(
+            "[{elapsed_precise}] [{wide_bar:.yellow/green}] {percent_precise}% ({pos}/{len})",
+        )
+        .unwrap()
+        .progress_chars("#>-"),
+    );
+
+    pb
+}
+
+/// The progress bar once all frames are analysed. The analysers can take a while to finish,
+/// so the clock keeps ticking and their steps are shown instead of the frames.
+fn finishing_style() -> ProgressStyle {
+    ProgressStyle::with_template("[{elapsed_precise}] {spinner:.yellow} finishing {wide_msg}")
+        .unwrap()
 }
 
 impl Output {
-    pub fn new(args: &Cli, num_frames: u64) -> Self {
-        let progress_bar = if args.no_progress || args.silent {
-            None
-        } else {
-            Some(ProgressBar::new(num_frames))
-        };
+    pub fn new(args: &AnalyseArgs, num_frames: u64) -> Self {
+        let level = Level::from_args(args);
+        let progress = !args.no_progress && level >= Level::Info;
 
-        if let Some(pb) = &progress_bar {
-            pb.set_style(ProgressStyle::with_template("[{elapsed_precise}] [{wide_bar:.yellow/green}] {percent_precise}% ({pos}/{len})")
-                .unwrap()
-                .progress_chars("#>-"));
+        Self {
+            progress_bar: Mutex::new(progress.then(|| progress_bar(num_frames))),
+            steps: Mutex::new(Vec::new()),
+            progress,
+            level,
+            time_format: args.time_format,
         }
+    }
 
-        Self {
-            progress_bar,
-            silent: args.silent,
-            debug: args.debug,
+    pub fn restart(&self, num_frames: u64) {
+        if let Ok(mut pb) = self.progress_bar.lock() {
+            *pb = self.progress.then(|| progress_bar(num_frames));
         }
     }
 
-    pub fn inc(&self) {
-        if let Some(pb) = &self.progress_bar {
-            pb.inc(1);
+    pub fn inc(&self, delta: u64) {
+        if let Ok(pb) = self.progress_bar.lock()
+            && let Some(pb) = pb.as_ref()
+        {
+            pb.inc(delta);
+        }
+    }
+
+    pub fn finishing(&self) {
+        if let Ok(pb) = self.progress_bar.lock()
+            && let Some(pb) = pb.as_ref()
+        {
+            pb.set_style(finishing_style());
+            pb.enable_steady_tick(Duration::from_millis(100));
         }
     }
 
     pub fn finish(&self) {
-        if let Some(pb) = &self.progress_bar {
-            pb.finish();
+        if let Ok(mut steps) = self.steps.lock() {
+            steps.clear();
+        }
+        if let Ok(pb) = self.progress_bar.lock()
+            && let Some(pb) = pb.as_ref()
+        {
+            pb.disable_steady_tick();
+            pb.finish_with_message("done");
         }
     }
 
-    pub fn enabled(&self) -> bool {
-        !self.silent
+    /// Sets the status of the step of `name`, or removes it, and shows those of all steps.
+    fn set_step(&self, name: &'static str, status: Option<String>) {
+        let Ok(mut steps) = self.steps.lock() else {
+            return;
+        };
+        steps.retain(|(step, _)| *step != name);
+        if let Some(status) = status {
+            steps.push((name, status));
+        }
+        progress::steps(&steps);
+
+        let message = steps
+            .iter()
+            .map(|(step, status)| format!("{step} {status}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Ok(pb) = self.progress_bar.lock()
+            && let Some(pb) = pb.as_ref()
+        {
+            pb.set_message(message);
+        }
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    /// Runs `f` with the progress bar hidden, so it isn't drawn over.
+    fn suspend(&self, f: impl FnOnce()) {
+        if let Ok(pb) = self.progress_bar.lock()
+            && let Some(pb) = pb.as_ref()
+        {
+            pb.suspend(f);
+        } else {
+            f();
+        }
     }
 }
@@ -0,0 +1,417 @@
+//! Derivation of output paths from the options.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{Error, Result, cli::AnalyseArgs, metrics::MetricsTarget, warn};
+
+/// Placeholder for the input file name in output paths of a batch.
+pub const NAME: &str = "{name}";
+
+/// Default of `--name-template`.
+pub const DEFAULT_NAME_TEMPLATE: &str = "{stem}_{analyser}.{ext}";
+
+/// Today's date in UTC as YYYY-MM-DD.
+fn today() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+
+    civil_date(seconds)
+}
+
+/// The UTC date `seconds` after the Unix epoch as YYYY-MM-DD.
+fn civil_date(seconds: u64) -> String {
+    // Days since 1970-01-01 to the proleptic Gregorian calendar, in eras of 400 years
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// The `--name-template` with its placeholders filled in for the `analyser` output of the
+/// input of `args`.
+fn template_name(args: &AnalyseArgs, analyser: &str, extension: &str) -> String {
+    let stem = args
+        .input
+        .first()
+        .and_then(|input| input.file_stem())
+        .map_or("input".into(), |stem| stem.to_string_lossy());
+
+    args.name_template
+        .replace("{stem}", &stem)
+        .replace("{date}", &today())
+        .replace("{analyser}", analyser)
+        .replace("{ext}", extension)
+}
+
+/// `path` in `--out-dir` if it is relative and the option is given.
+pub fn in_out_dir(args: &AnalyseArgs, path: &Path) -> PathBuf {
+    match &args.out_dir {
+        Some(dir) => dir.join(path),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Creates `--out-dir` with its parents, if given.
+pub fn create_out_dir(args: &AnalyseArgs) -> Result<()> {
+    match &args.out_dir {
+        Some(dir) => fs::create_dir_all(dir).map_err(|err| Error::io("create", dir, err)),
+        None => Ok(()),
+    }
+}
+
+/// `path` with the `{name}` placeholder replaced by `name`, or `None` if it has none.
+/// Components that aren't valid UTF-8 are kept as they are.
+pub fn replace_name(path: &Path, name: &OsStr) -> Option<PathBuf> {
+    let mut found = false;
+
+    let replaced = path
+        .components()
+        .map(|component| {
+            let component = component.as_os_str();
+            let Some(text) = component.to_str().filter(|text| text.contains(NAME)) else {
+                return component.to_os_string();
+            };
+            found = true;
+
+            let mut replaced = OsString::new();
+            for (index, part) in text.split(NAME).enumerate() {
+                if index > 0 {
+                    replaced.push(name);
+                }
+                replaced.push(part);
+            }
+            replaced
+        })
+        .collect();
+
+    found.then_some(replaced)
+}
+
+/// `file` if given, otherwise `<json_stem>_<suffix>.<extension>` next to the JSON output.
+pub fn derive_output_path(
+    json: Option<&Path>,
+    file: Option<&Path>,
+    suffix: &str,
+    extension: &str,
+) -> Option<PathBuf> {
+    if let Some(file) = file {
+        return Some(file.to_path_buf());
+    }
+
+    let json = json?;
+    let mut name = json.file_stem()?.to_os_string();
+    name.push(format!("_{suffix}.{extension}"));
+
+    Some(json.with_file_name(name))
+}
+
+/// `path` if nothing exists there yet or `force` is set, otherwise the first free
+/// `<stem>-<n>.<extension>` next to it.
+pub fn free_path(path: &Path, force: bool) -> PathBuf {
+    if force || !path.exists() {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default();
+    let extension = path.extension();
+
+    (1..)
+        .map(|number| {
+            let mut name = stem.to_os_string();
+            name.push(format!("-{number}"));
+            if let Some(extension) = extension {
+                name.push(".");
+                name.push(extension);
+            }
+            path.with_file_name(name)
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// [`free_path`], with a warning if it isn't `path`.
+pub fn output_path(path: &Path, force: bool) -> PathBuf {
+    let free = free_path(path, force);
+    if free != path {
+        warn!(
+            "{} exists, writing to {} instead (--force overwrites)",
+            path.display(),
+            free.display()
+        );
+    }
+
+    free
+}
+
+/// Options with every relative output path moved into `--out-dir`, where the JSON output and
+/// enabled raw outputs without a path are named by `--name-template`.
+fn place_in_out_dir(args: &mut AnalyseArgs) {
+    let Some(dir) = args.out_dir.clone() else {
+        return;
+    };
+    let templated = |args: &AnalyseArgs, analyser, extension| {
+        PathBuf::from(template_name(args, analyser, extension))
+    };
+
+    if args.json.is_none() {
+        args.json = Some(templated(args, "analysis", "json"));
+    }
+    if args.fft && args.fft_file.is_none() {
+        args.fft_file = Some(templated(args, "fft", args.fft_format.extension()));
+    }
+    if args.peaks && args.peaks_file.is_none() {
+        args.peaks_file = Some(templated(args, "peaks", args.peaks_format.extension()));
+    }
+
+    let metrics = match &mut args.metrics {
+        Some(MetricsTarget::File(path)) => Some(path),
+        _ => None,
+    };
+    for path in [
+        args.json.as_mut(),
+        args.report.as_mut(),
+        args.loudness_graph.as_mut(),
+        metrics,
+        args.junit.as_mut(),
+        args.emit_schema.as_mut(),
+        args.fft_file.as_mut(),
+        args.fft_vis.as_mut(),
+        args.peaks_file.as_mut(),
+        args.meter_file.as_mut(),
+        args.cue.as_mut(),
+        args.chapters.as_mut(),
+        args.write_cues.as_mut(),
+        args.edl.as_mut(),
+        args.srt.as_mut(),
+        args.labels.as_mut(),
+        args.issues_map.as_mut(),
+        args.timeseries.as_mut(),
+        args.export_segments.as_mut(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *path = dir.join(&*path);
+    }
+}
+
+/// Options with the outputs placed in `--out-dir`, the raw output paths derived from `--json`
+/// where they weren't given, and every output path moved to a free one unless `--force` is
+/// set.
+pub fn resolve_outputs(args: &AnalyseArgs) -> AnalyseArgs {
+    let mut args = args.clone();
+    place_in_out_dir(&mut args);
+    let force = args.force;
+    let resolve = |path: &mut Option<PathBuf>| {
+        if let Some(path) = path {
+            *path = output_path(path, force);
+        }
+    };
+
+    // The JSON output first, so derived paths follow its name
+    resolve(&mut args.json);
+
+    if args.fft {
+        args.fft_file = derive_output_path(
+            args.json.as_deref(),
+            args.fft_file.as_deref(),
+            "fft",
+            args.fft_format.extension(),
+        );
+    }
+
+    if args.peaks {
+        args.peaks_file = derive_output_path(
+            args.json.as_deref(),
+            args.peaks_file.as_deref(),
+            "peaks",
+            args.peaks_format.extension(),
+        );
+    }
+
+    for path in [
+        &mut args.report,
+        &mut args.loudness_graph,
+        &mut args.junit,
+        &mut args.emit_schema,
+        &mut args.fft_file,
+        &mut args.fft_vis,
+        &mut args.peaks_file,
+        &mut args.meter_file,
+        &mut args.cue,
+        &mut args.chapters,
+        &mut args.write_cues,
+        &mut args.edl,
+        &mut args.srt,
+        &mut args.labels,
+        &mut args.issues_map,
+        &mut args.timeseries,
+    ] {
+        resolve(path);
+    }
+
+    args
+}
+
+/// The output files and directories of the options with the option that writes each,
+/// after [`resolve_outputs`]. The `--batch-summary` of a batch isn't one of them.
+pub fn output_paths(args: &AnalyseArgs) -> Vec<(&'static str, &Path)> {
+    let metrics = match &args.metrics {
+        Some(MetricsTarget::File(path)) => Some(path),
+        _ => None,
+    };
+
+    [
+        ("--json", args.json.as_ref()),
+        ("--report", args.report.as_ref()),
+        ("--loudness-graph", args.loudness_graph.as_ref()),
+        ("--metrics", metrics),
+        ("--junit", args.junit.as_ref()),
+        ("--emit-schema", args.emit_schema.as_ref()),
+        ("--fft-file", args.fft_file.as_ref().filter(|_| args.fft)),
+        ("--fft-vis", args.fft_vis.as_ref()),
+        (
+            "--peaks-file",
+            args.peaks_file.as_ref().filter(|_| args.peaks),
+        ),
+        ("--meter-file", args.meter_file.as_ref()),
+        ("--cue", args.cue.as_ref()),
+        ("--chapters", args.chapters.as_ref()),
+        ("--write-cues", args.write_cues.as_ref()),
+        ("--edl", args.edl.as_ref()),
+        ("--srt", args.srt.as_ref()),
+        ("--labels", args.labels.as_ref()),
+        ("--issues-map", args.issues_map.as_ref()),
+        ("--timeseries", args.timeseries.as_ref()),
+        ("--export-segments", args.export_segments.as_ref()),
+    ]
+    .into_iter()
+    .filter_map(|(option, path)| Some((option, path?.as_path())))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for the test `name` in the system temp directory.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("analwave-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn derive_output_path_prefers_the_given_file() {
+        let path = derive_output_path(
+            Some(Path::new("out/take.json")),
+            Some(Path::new("spectrum.png")),
+            "fft",
+            "png",
+        );
+        assert_eq!(path, Some(PathBuf::from("spectrum.png")));
+    }
+
+    #[test]
+    fn derive_output_path_follows_the_json_output() {
+        let path = derive_output_path(Some(Path::new("out/take.json")), None, "fft", "npy");
+        assert_eq!(path, Some(PathBuf::from("out/take_fft.npy")));
+
+        assert_eq!(derive_output_path(None, None, "peaks", "png"), None);
+    }
+
+    #[test]
+    fn replace_name_fills_every_placeholder() {
+        let path = replace_name(Path::new("out/{name}/{name}_fft.png"), OsStr::new("take1"));
+        assert_eq!(path, Some(PathBuf::from("out/take1/take1_fft.png")));
+    }
+
+    #[test]
+    fn replace_name_without_placeholder() {
+        assert_eq!(
+            replace_name(Path::new("out/summary.json"), OsStr::new("take1")),
+            None
+        );
+    }
+
+    #[test]
+    fn free_path_numbers_existing_files() {
+        let dir = scratch_dir("free-path");
+        let path = dir.join("out.json");
+        assert_eq!(free_path(&path, false), path);
+
+        fs::write(&path, "{}").unwrap();
+        assert_eq!(free_path(&path, false), dir.join("out-1.json"));
+
+        fs::write(dir.join("out-1.json"), "{}").unwrap();
+        assert_eq!(free_path(&path, false), dir.join("out-2.json"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn free_path_without_extension() {
+        let dir = scratch_dir("free-path-extension");
+        let path = dir.join("cues");
+        fs::write(&path, "").unwrap();
+        assert_eq!(free_path(&path, false), dir.join("cues-1"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn free_path_with_force_overwrites() {
+        let dir = scratch_dir("free-path-force");
+        let path = dir.join("out.json");
+        fs::write(&path, "{}").unwrap();
+        assert_eq!(free_path(&path, true), path);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn civil_date_of_known_days() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(951_782_400), "2000-02-29");
+        assert_eq!(civil_date(951_868_800), "2000-03-01");
+        assert_eq!(civil_date(1_709_164_800), "2024-02-29");
+        assert_eq!(civil_date(1_735_689_599), "2024-12-31");
+        assert_eq!(civil_date(1_735_689_600), "2025-01-01");
+        // 2100 isn't a leap year
+        assert_eq!(civil_date(4_107_456_000), "2100-02-28");
+        assert_eq!(civil_date(4_107_542_400), "2100-03-01");
+    }
+
+    #[test]
+    fn template_name_fills_the_placeholders() {
+        let mut args = AnalyseArgs::from_config("{}").unwrap();
+        args.input = vec![PathBuf::from("takes/take1.wav")];
+        args.name_template = "{stem}-{analyser}.{ext}".to_string();
+        assert_eq!(template_name(&args, "fft", "png"), "take1-fft.png");
+
+        args.name_template = "{date}.{ext}".to_string();
+        assert_eq!(
+            template_name(&args, "analysis", "json"),
+            format!("{}.json", today())
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Wall time spent decoding and in each analyser, for `--timings`.
+
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::{output, stream::Stream};
+
+#[derive(Serialize, JsonSchema)]
+pub struct Timing {
+    pub name: String,
+    /// Wall time in seconds
+    pub seconds: f64,
+    /// Samples of all channels processed per second of wall time
+    #[serde(rename = "samplesPerSecond")]
+    pub samples_per_second: f64,
+}
+
+impl Timing {
+    fn new(name: &str, time: Duration, samples: usize) -> Self {
+        let seconds = time.as_secs_f64();
+
+        Self {
+            name: name.to_string(),
+            seconds,
+            samples_per_second: if seconds > 0.0 {
+                samples as f64 / seconds
+            } else {
+                0.0
+            },
+        }
+    }
+
+    fn print(&self) {
+        print_timing(&self.name, self.seconds, self.samples_per_second);
+    }
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Performance {
+    /// Wall time of the whole analysis in seconds
+    pub seconds: f64,
+    /// Samples of all channels analysed per second of wall time
+    #[serde(rename = "samplesPerSecond")]
+    pub samples_per_second: f64,
+    /// Reading, converting and resampling the input
+    pub decoding: Timing,
+    /// Time in each analyser, including finishing it. Analysers on worker threads run in
+    /// parallel, so these can add up to more than the whole analysis.
+    pub analysers: Vec<Timing>,
+}
+
+impl Performance {
+    /// Timings of a finished stream, `None` if they weren't enabled.
+    pub fn new(stream: &Stream, total: Duration, decoding: Duration) -> Option<Self> {
+        let timings = stream.timings()?;
+        let samples = stream.position() * stream.spec().channels as usize;
+        let whole = Timing::new("total", total, samples);
+
+        Some(Self {
+            seconds: whole.seconds,
+            samples_per_second: whole.samples_per_second,
+            decoding: Timing::new("decoding", decoding, samples),
+            analysers: stream
+                .analysers()
+                .iter()
+                .zip(timings)
+                .map(|(analyser, time)| Timing::new(analyser.name(), *time, samples))
+                .collect(),
+        })
+    }
+
+    pub fn print(&self) {
+        print_timing("total time", self.seconds, self.samples_per_second);
+        self.decoding.print();
+
+        for timing in self.analysers.iter() {
+            timing.print();
+        }
+    }
+}
+
+fn print_timing(name: &str, seconds: f64, samples_per_second: f64) {
+    output!(
+        "[+] {:<20}{:.3} s, {:.2} M samples/s",
+        format!("{}:", name),
+        seconds,
+        samples_per_second / 1e6
+    );
+}
@@ -0,0 +1,601 @@
+//! The analysis of one input, from setting up the analysers to the results.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::analysers::fft::{FftBackend, FftScale};
+use crate::analysers::{Analyser, loudness::REFINE_BLOCKS_PER_SECOND};
+use crate::cue_points::write_cue_points;
+use crate::edl::write_edl;
+use crate::exclude::Exclusions;
+use crate::findings::{Severity, Summary};
+use crate::json::{Results, collect_results, write_json};
+use crate::junit::{TestSuite, write_junit};
+use crate::labels::write_labels;
+use crate::layout::LayoutInfo;
+use crate::metrics::{MetricsTarget, write_metrics};
+use crate::registry::Registry;
+use crate::report::{write_issues_map, write_loudness_graph, write_report};
+use crate::schema::write_schema;
+use crate::segments::write_segments;
+use crate::stream::{Stream, StreamSpec};
+use crate::subtitles::write_subtitles;
+use crate::timeseries::write_timeseries;
+use crate::tracks::write_tracks;
+use crate::{
+    Error, Result, cancel, cli::AnalyseArgs, console, input::Input, output, output::frame_to_time,
+    paths, performance::Performance, warn,
+};
+
+/// Frames collected from the input before they are pushed to the stream.
+const CHUNK_FRAMES: usize = 4096;
+
+fn push_chunk(stream: &mut Stream, chunk: &mut Vec<i32>, pushing: &mut Duration) -> Result<()> {
+    let start = timer(stream);
+    let frames = stream.push(chunk)?;
+    *pushing += elapsed(start);
+
+    output::inc(frames as u64);
+    chunk.clear();
+
+    Ok(())
+}
+
+/// Feeds the input to the stream until it ends, the analysis is cancelled or `deadline` has
+/// passed. Returns the return code, the time spent decoding, which is everything outside the
+/// stream, or zero without timings, and whether the stream was stopped early.
+fn stream_frames(
+    stream: &mut Stream,
+    input: &mut Input,
+    deadline: Option<Instant>,
+) -> Result<(u8, Duration, bool)> {
+    stream.begin(input.spec())?;
+
+    let chunk_len = CHUNK_FRAMES * input.spec().channels as usize;
+    let mut chunk = Vec::with_capacity(chunk_len);
+    let mut pushing = Duration::ZERO;
+    let start = timer(stream);
+
+    let fed = input.for_each_frame(|frame| {
+        chunk.extend_from_slice(frame);
+
+        if chunk.len() >= chunk_len {
+            push_chunk(stream, &mut chunk, &mut pushing)?;
+
+            if cancel::should_stop(deadline) {
+                return Err(Error::Cancelled);
+            }
+        }
+
+        Ok(())
+    });
+    let stopped = matches!(fed, Err(Error::Cancelled));
+    if !stopped {
+        fed?;
+    }
+    push_chunk(stream, &mut chunk, &mut pushing)?;
+
+    let decoding = elapsed(start).saturating_sub(pushing);
+    output::finishing();
+    let return_code = if stopped {
+        stream.stop()?
+    } else {
+        stream.finish()?
+    };
+
+    Ok((return_code, decoding, stopped))
+}
+
+/// Starts a clock if the stream is timed. Clocks aren't available on every target, e.g. in
+/// the browser, so nothing is measured unless `--timings` asks for it.
+fn timer(stream: &Stream) -> Option<Instant> {
+    stream.timings().map(|_| Instant::now())
+}
+
+fn elapsed(start: Option<Instant>) -> Duration {
+    start.map_or(Duration::ZERO, |start| start.elapsed())
+}
+
+/// Checks the options and sets up the analysers they enable for a stream of `spec`.
+pub fn analysers(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Vec<Box<dyn Analyser>>> {
+    if (args.cue.is_some() || args.chapters.is_some()) && args.per_channel {
+        return Err(Error::Config(
+            "--cue and --chapters split on silence of all channels and can't be used with --per-channel"
+                .to_string(),
+        ));
+    }
+
+    if matches!(args.metrics, Some(MetricsTarget::Port(_))) {
+        return Err(Error::Config(
+            "--metrics :PORT serves the metrics and needs analwave serve, give a file instead"
+                .to_string(),
+        ));
+    }
+
+    if args.write_cues.is_some()
+        && !(args.detect_silence()
+            || args.underrun
+            || args.dynamics
+            || args.min_dr.is_some()
+            || args.max_clipped_samples.is_some())
+    {
+        return Err(Error::Config(
+            "--write-cues needs --silence, --underrun or --dynamics to find segments".to_string(),
+        ));
+    }
+
+    if args
+        .silence_resume_lufs
+        .is_some_and(|resume| resume < args.lufs)
+    {
+        return Err(Error::Config(
+            "--silence-resume-lufs has to be at least --lufs".to_string(),
+        ));
+    }
+
+    if args.name_template.contains(['/', '\\']) {
+        return Err(Error::Config(
+            "--name-template names files in --out-dir and can't have directories".to_string(),
+        ));
+    }
+
+    if args
+        .silence_relative
+        .is_some_and(|relative| !(relative < 0.0 && relative.is_finite()))
+    {
+        return Err(Error::Config(
+            "--silence-relative has to be below 0 LU".to_string(),
+        ));
+    }
+
+    if !(0.0..100.0).contains(&args.window_overlap) {
+        return Err(Error::Config(format!(
+            "--window-overlap has to be at least 0 and below 100 percent, got {}",
+            args.window_overlap
+        )));
+    }
+
+    let hop = args.fft_hop();
+    if (args.fft || args.fft_vis.is_some() || args.spectral_stats)
+        && (hop == 0 || hop > args.fft_bins)
+    {
+        return Err(Error::Config(format!(
+            "The FFT hop has to be between 1 and {} frames (--fft-bins), got {}",
+            args.fft_bins, hop
+        )));
+    }
+
+    // Nothing would be left to measure, and the outputs would be skipped as empty
+    let excluded = Exclusions::new(&args.exclude, spec);
+    if !excluded.is_empty() && excluded.first_included() >= spec.num_frames {
+        return Err(Error::Config(
+            "--exclude covers the whole input, nothing is left to analyse".to_string(),
+        ));
+    }
+
+    let analysers = Registry::new(args)?.analysers(args, spec)?;
+
+    if analysers.is_empty() {
+        return Err(Error::Config(
+            "No detection is active, exiting.".to_string(),
+        ));
+    }
+
+    Ok(analysers)
+}
+
+/// Prints the format of the input and the settings of the enabled analysers.
+pub fn print_settings(args: &AnalyseArgs, input: &Input, analysers: &[Box<dyn Analyser>]) {
+    let spec = input.spec();
+
+    output!("[+] sample rate:        {}", &spec.sample_rate);
+    output!("[+] channels:           {}", spec.channels);
+    output!(
+        "[+] total samples:      {}",
+        spec.num_frames * spec.channels as usize
+    );
+
+    if let Some(parts) = input.parts() {
+        output!("[+] concat:             {} files", parts.len());
+    }
+
+    let source = input.source_spec();
+    if source.sample_rate != spec.sample_rate {
+        output!("[+] resampled from:     {} Hz", source.sample_rate);
+    }
+
+    if input.start_frame() > 0 {
+        output!(
+            "[+] from end:           {} to {}",
+            frame_to_time(input.start_frame(), source.sample_rate),
+            frame_to_time(source.num_frames, source.sample_rate)
+        );
+    }
+
+    if let Some(excluded) = Exclusions::new(&args.exclude, &spec).results() {
+        let ranges: Vec<_> = excluded
+            .iter()
+            .map(|range| {
+                format!(
+                    "{}-{}",
+                    frame_to_time(range.start_sample, spec.sample_rate),
+                    frame_to_time(range.end_sample, spec.sample_rate)
+                )
+            })
+            .collect();
+        output!("[+] excluded:           {}", ranges.join(", "));
+    }
+
+    if let Some(layout) = LayoutInfo::new(&source) {
+        let speakers: Vec<_> = layout
+            .speakers
+            .iter()
+            .map(|speaker| speaker.unwrap_or("-"))
+            .collect();
+        output!(
+            "[+] channel layout:     {} ({})",
+            layout.name,
+            speakers.join(" ")
+        );
+    }
+
+    if let Some(downmix) = input.downmix() {
+        output!(
+            "[+] downmix:            {} channels to {}",
+            source.channels,
+            downmix.target.as_str()
+        );
+    }
+
+    if let Some(gain) = args.gain_db {
+        output!("[+] gain:               {:+} dB", gain);
+    }
+
+    if let Some(algorithm) = args.digest {
+        output!("[+] digest:             {}", algorithm.as_str());
+    }
+
+    if let Some(preset) = args.preset {
+        output!("[+] preset:             {}", preset.name());
+    }
+
+    if let Some(format) = args.format.filter(|_| args.raw) {
+        output!("[+] raw format:         {}", format.as_str());
+    }
+
+    if args.detect_silence() {
+        output!("[+] silence threshold:  {} LUFS-S", &args.lufs);
+        if let Some(relative) = args.silence_relative {
+            output!(
+                "[+] relative silence:   {} LU below the integrated loudness",
+                -relative
+            );
+        }
+        output!("[+] silence window:     {} seconds", &args.window_size);
+
+        if args.window_overlap > 0.0 {
+            output!("[+] window overlap:     {}%", args.window_overlap);
+        }
+
+        if args.refine_silence {
+            output!(
+                "[+] silence boundaries: refined to {} ms",
+                1000 / REFINE_BLOCKS_PER_SECOND
+            );
+        }
+
+        if let Some(resume) = args.silence_resume_lufs {
+            output!("[+] silence resume:     {} LUFS-S", resume);
+        }
+
+        if args.silence_hold > 0.0 {
+            output!("[+] silence hold:       {} seconds", args.silence_hold);
+        }
+
+        if let Some(max) = args.max_leading_silence {
+            output!("[+] leading silence:    at most {} seconds", max);
+        }
+    }
+
+    if let Some(width) = args.loudness_histogram {
+        output!("[+] loudness histogram: {} LU bins", width);
+    }
+
+    if args.underrun {
+        output!("[+] underrun threshold: {} samples", &args.samples);
+    }
+
+    if args.discontinuity {
+        output!(
+            "[+] step threshold:     {} dB",
+            &args.discontinuity_threshold
+        );
+    }
+
+    if args.fft || args.fft_vis.is_some() {
+        output!("[+] FFT bins:           {}", &args.fft_bins);
+        output!("[+] FFT amplitude:      {}", args.fft_amplitude.as_str());
+        output!(
+            "[+] FFT window:         {}, hop {} frames",
+            args.fft_window.as_str(),
+            args.fft_hop()
+        );
+        if args.fft_backend != FftBackend::Aus {
+            output!("[+] FFT backend:        {}", args.fft_backend.as_str());
+        }
+        if args.fft_scale != FftScale::Linear {
+            output!(
+                "[+] FFT scale:          {}, {} bands",
+                args.fft_scale.as_str(),
+                args.fft_bands
+            );
+        }
+    }
+
+    if args.spectral_stats && !(args.fft || args.fft_vis.is_some()) {
+        output!(
+            "[+] spectral window:    {} frames, {}, hop {} frames",
+            args.fft_bins,
+            args.fft_window.as_str(),
+            args.fft_hop()
+        );
+    }
+
+    if args.activity {
+        output!(
+            "[+] activity:           above {} dBFS, {} points per second",
+            args.activity_threshold,
+            args.activity_rate
+        );
+    }
+
+    if args.pitch {
+        output!(
+            "[+] pitch range:        {} to {} Hz, {} points per second",
+            args.pitch_min,
+            args.pitch_max,
+            args.pitch_rate
+        );
+    }
+
+    if args.novelty {
+        output!(
+            "[+] novelty:            {} s windows, {} sd above the mean, {} s apart",
+            args.novelty_window,
+            args.novelty_threshold,
+            args.novelty_min_gap
+        );
+    }
+
+    if args.tempo || args.beats {
+        output!(
+            "[+] tempo range:        {} to {} BPM",
+            args.tempo_min,
+            args.tempo_max
+        );
+    }
+
+    if let Some(rate) = args.peaks_per_second.filter(|_| args.peaks) {
+        output!("[+] peaks per second:   {}", rate);
+    }
+
+    if args.meter_file.is_some() {
+        output!("[+] meter rate:         {} Hz", &args.meter_rate);
+    }
+
+    if args.tones || args.require_tone || args.fail_on_tone {
+        output!(
+            "[+] tone frequencies:   {:?} Hz (+/- {} Hz)",
+            &args.tone_frequencies,
+            &args.tone_tolerance
+        );
+    }
+
+    if let Some(target) = args.target_lufs {
+        output!(
+            "[+] loudness target:    {} LUFS (+/- {} LU)",
+            target,
+            args.loudness_tolerance
+        );
+    }
+
+    if let Some(target) = args.simulate_normalize {
+        output!(
+            "[+] normalize to:       {} LUFS, true peak ceiling {} dBTP",
+            target,
+            args.peak_ceiling
+        );
+    }
+
+    if args.ebu_r128_check {
+        output!("[+] EBU R128 check:     max LRA {} LU", args.max_lra);
+    }
+
+    if let Some(max) = args.max_channel_imbalance_db {
+        output!("[+] max imbalance:      {} dB", max);
+    }
+
+    if let Some(max) = args.max_skew_samples {
+        output!("[+] max skew:           {} samples", max);
+    }
+
+    if let Some(min) = args.min_speech_percentage {
+        output!("[+] minimum speech:     {}%", min);
+    }
+
+    if let Some(min_dr) = args.min_dr {
+        output!("[+] minimum DR:         {}", min_dr);
+    }
+
+    if let Some(max) = args.max_clipped_samples {
+        output!("[+] max clipped:        {} samples", max);
+    }
+
+    if args.low_memory {
+        output!("[+] low memory:         on");
+    }
+
+    if args.threads > 1 {
+        output!(
+            "[+] threads:            {}",
+            args.threads.min(analysers.len())
+        );
+    }
+}
+
+/// Streams the input through the analysers and collects their results, returns them with the
+/// combined return code.
+pub fn run(
+    args: &AnalyseArgs,
+    input: &mut Input,
+    analysers: Vec<Box<dyn Analyser>>,
+) -> Result<(u8, Results)> {
+    let mut stream = Stream::new(input.spec(), analysers)
+        .with_threads(args.threads)
+        .with_timings(args.timings);
+    let start = timer(&stream);
+    let deadline = args.timeout.map(|timeout| Instant::now() + timeout);
+    let result = stream_frames(&mut stream, input, deadline);
+    let total = elapsed(start);
+
+    output::finish();
+    let stopped = result.as_ref().is_ok_and(|(.., stopped)| *stopped);
+    if stopped {
+        let sample_rate = input.spec().sample_rate;
+        warn!(
+            "stopped after {} of {}, the results only cover the frames analysed until then",
+            frame_to_time(stream.position(), sample_rate),
+            frame_to_time(input.spec().num_frames, sample_rate)
+        );
+    }
+    console::print_results(
+        &stream.label(),
+        input.spec().sample_rate,
+        stream.analysers(),
+        stream.results(),
+    );
+    if let Some(gain) = input.gain() {
+        console::print_gain(&stream.label(), &gain);
+    }
+    if let Some(digest) = input.digest() {
+        console::print_digest(&stream.label(), digest);
+    }
+
+    let (return_code, decoding, _) = result?;
+    let performance = Performance::new(&stream, total, decoding);
+
+    if let Some(performance) = &performance {
+        performance.print();
+    }
+
+    let analysis = stream.take_results();
+    let mut results = collect_results(input, stream.analysers(), analysis);
+    results.performance = performance;
+    if stopped {
+        results.partial = true;
+        results.analysed_duration =
+            Some(stream.position() as f32 / results.analysis_sample_rate as f32);
+    }
+    if args.summary {
+        let summary = Summary::new(&results);
+        summary.print(results.analysis_sample_rate);
+        results.summary = Some(summary);
+    }
+    results.time_format = args.json_time.then_some(args.time_format);
+    results.excluded = Exclusions::new(&args.exclude, &input.spec()).results();
+
+    Ok((return_code, results))
+}
+
+/// Writes the schema, runs the analysers on `input`, reported as `path`, and writes the output
+/// files the options ask for. The command line tool sets up the console before, the JUnit report
+/// is up to the callers as it also covers inputs that fail to open.
+pub fn analyse(
+    args: &AnalyseArgs,
+    path: &Path,
+    input: &mut Input,
+    analysers: Vec<Box<dyn Analyser>>,
+) -> Result<(u8, Results)> {
+    write_schema(args, &analysers)?;
+
+    let start = Instant::now();
+    let (return_code, mut results) = run(args, input, analysers)?;
+    let processing = start.elapsed();
+    write_timeseries(args, &mut results)?;
+    write_json(args, &results)?;
+    write_metrics(args, path, &results, return_code, processing)?;
+    write_report(args, &results)?;
+    write_loudness_graph(args, &results)?;
+    write_issues_map(args, &results)?;
+    write_tracks(args, &results)?;
+    write_subtitles(args, &results)?;
+    write_labels(args, &results)?;
+    write_cue_points(args, path, &results)?;
+    write_edl(args, path, &results)?;
+    write_segments(args, path, input, &results)?;
+    check_input(args, path, &results)?;
+
+    Ok((return_code, results))
+}
+
+/// Fails a file that `--container-check` found cut off, or whose WAV structure has errors with
+/// `--strict-container`, with a decode error once its outputs are written, as there is no bit
+/// of the return code left for them.
+pub fn check_input(args: &AnalyseArgs, path: &Path, results: &Results) -> Result<()> {
+    if let Some(container) = &results.analysis.container
+        && container.truncated
+    {
+        return Err(Error::decode(
+            path,
+            format!(
+                "the payload ends {} frames before the length in the header",
+                container.missing_frames
+            ),
+        ));
+    }
+
+    if let Some(format) = &results.analysis.format
+        && args.strict_container
+        && !format.valid
+    {
+        let errors = format
+            .issues
+            .iter()
+            .filter(|issue| issue.severity == Severity::Error)
+            .count();
+        return Err(Error::decode(
+            path,
+            format!("{errors} structural errors in the WAV container"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Analyses the file at `path` and writes the output files the options ask for, as the
+/// command line tool does, but without printing anything. For callers of the library that
+/// analyse one file at a time.
+pub fn analyse_file(args: &AnalyseArgs, path: &Path) -> Result<(u8, Results)> {
+    if !args.input.is_empty() || args.batch_summary.is_some() {
+        return Err(Error::Config(
+            "--input and --batch-summary are only available on the command line".to_string(),
+        ));
+    }
+
+    // The input names the outputs of --name-template
+    let args = paths::resolve_outputs(&AnalyseArgs {
+        input: vec![path.to_path_buf()],
+        ..args.clone()
+    });
+    paths::create_out_dir(&args)?;
+
+    let result = Input::open(&args, path).and_then(|mut input| {
+        let analysers = analysers(&args, &input.spec())?;
+        analyse(&args, path, &mut input, analysers)
+    });
+    write_junit(&args, &[TestSuite::new(path, &result)])?;
+
+    result
+}
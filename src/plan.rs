@@ -0,0 +1,167 @@
+//! `--dry-run`: the options are checked, the input headers read and the output paths resolved
+//! as for an analysis, and the plan printed instead of running it.
+
+use std::{
+    fs::{self, File},
+    path::Path,
+};
+
+use crate::{
+    Error, Result, batch, cli::AnalyseArgs, error, input::Input, output, output::init_output,
+    paths, pipeline, stems::Stems,
+};
+
+/// The directory an output at `path` is written to, the output itself if it is a directory
+/// (`--export-segments`, a Zarr store). If that directory is `created` with its parents, as
+/// those and `--out-dir` are, the closest one that exists.
+fn output_directory(path: &Path, directory: bool, created: bool) -> Result<&Path> {
+    // A relative path without a directory is in the working directory
+    fn dir(dir: &Path) -> &Path {
+        match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        }
+    }
+
+    let target = match directory {
+        true => Some(path),
+        false => path.parent(),
+    };
+    let found = match created {
+        true => target.and_then(|target| target.ancestors().map(dir).find(|dir| dir.exists())),
+        false => target.map(dir).filter(|dir| dir.exists()),
+    };
+
+    found
+        .filter(|found| found.is_dir())
+        .ok_or_else(|| Error::Config(format!("the directory of {} doesn't exist", path.display())))
+}
+
+/// Checks that the output at `path` can be written, by creating and removing a file next to
+/// it. Permissions alone don't tell, e.g. on a read-only mount.
+fn check_output(path: &Path, directory: bool, created: bool) -> Result<()> {
+    let dir = output_directory(path, directory, created)?;
+    let probe = dir.join(format!(".analwave-dry-run-{}", std::process::id()));
+
+    File::create(&probe).map_err(|err| Error::io("write to", dir, err))?;
+    fs::remove_file(&probe).map_err(|err| Error::io("remove", &probe, err))
+}
+
+/// Whether `path` is in `--out-dir`, which is created before the analysis.
+fn in_out_dir(args: &AnalyseArgs, path: &Path) -> bool {
+    args.out_dir
+        .as_deref()
+        .is_some_and(|dir| path.starts_with(dir))
+}
+
+/// Prints the plan for one input, returns the number of outputs that can't be written.
+fn plan_file(args: &AnalyseArgs, path: &Path) -> Result<usize> {
+    let args = paths::resolve_outputs(args);
+    let input = match args.concat.is_empty() {
+        true => Input::open(&args, path)?,
+        false => Input::concat(&args, &args.concat)?,
+    };
+    let analysers = pipeline::analysers(&args, &input.spec())?;
+
+    match input.parts() {
+        Some(parts) => {
+            for part in parts {
+                output!("[+] input:              {}", part.input);
+            }
+        }
+        None => output!("[+] input:              {}", path.display()),
+    }
+    pipeline::print_settings(&args, &input, &analysers);
+
+    let names: Vec<_> = analysers.iter().map(|analyser| analyser.name()).collect();
+    output!("[+] analysers:          {}", names.join(", "));
+
+    let mut failed = 0;
+    for (option, output) in paths::output_paths(&args) {
+        let directory = option == "--export-segments"
+            || output
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("zarr"));
+        let created = directory || in_out_dir(&args, output);
+        output!("[+] {:<20}{}", format!("{option}:"), output.display());
+
+        if let Err(err) = check_output(output, directory, created) {
+            error!("{option} {}: {err}", output.display());
+            failed += 1;
+        }
+    }
+
+    Ok(failed)
+}
+
+/// Checks everything the analysis with `args` would need up front and prints what it would
+/// read, run and write, without analysing anything. Fails if any of it wouldn't work.
+pub fn dry_run(args: &AnalyseArgs) -> Result<()> {
+    // The plan has nothing to show progress for
+    init_output(
+        &AnalyseArgs {
+            no_progress: true,
+            ..args.clone()
+        },
+        0,
+    );
+
+    // The files of --concat are one input, with outputs named after the first
+    let concat = AnalyseArgs {
+        input: args.concat.first().cloned().into_iter().collect(),
+        ..args.clone()
+    };
+    let (args, inputs) = if !args.stems.is_empty() {
+        Stems::inspect(&args.stems)?.print();
+        (args, &args.stems)
+    } else if !args.concat.is_empty() {
+        (&concat, &concat.input)
+    } else {
+        (args, &args.input)
+    };
+    let batch = inputs.len() > 1 || args.batch_summary.is_some();
+
+    // Problems are counted, so the plan shows all of them
+    let mut failed = 0;
+    for input in inputs {
+        let plan = match batch {
+            true => {
+                batch::file_args(args, input).and_then(|file_args| plan_file(&file_args, input))
+            }
+            false => plan_file(args, input),
+        };
+        match plan {
+            Ok(outputs) => failed += outputs,
+            Err(err) => {
+                error!("{}: {err}", input.display());
+                failed += 1;
+            }
+        }
+    }
+
+    // A batch writes these once for all files
+    let batch_outputs = [
+        ("--batch-summary", args.batch_summary.as_ref()),
+        ("--junit", args.junit.as_ref()),
+    ];
+    for (option, path) in batch_outputs.into_iter().filter(|_| batch) {
+        let Some(path) = path.map(|path| paths::in_out_dir(args, path)) else {
+            continue;
+        };
+        output!("[+] {:<20}{}", format!("{option}:"), path.display());
+        if let Err(err) = check_output(&path, false, in_out_dir(args, &path)) {
+            error!("{option} {}: {err}", path.display());
+            failed += 1;
+        }
+    }
+    output::finish();
+
+    if failed > 0 {
+        return Err(Error::Config(
+            "the dry run failed, see the errors above".to_string(),
+        ));
+    }
+    output!("Dry run, nothing was analysed or written");
+
+    Ok(())
+}
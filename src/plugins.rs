@@ -0,0 +1,122 @@
+//! Analysers loaded from shared libraries with `--plugin`, for site-specific detectors that
+//! don't belong in analwave itself.
+//!
+//! A plugin is a `cdylib` crate that depends on the `analwave` library and declares its
+//! registration function with [`declare_plugin!`](crate::declare_plugin):
+//!
+//! ```ignore
+//! fn register(registry: &mut analwave::registry::Registry) {
+//!     registry.register(Registration { name: "clicks", .. });
+//! }
+//!
+//! analwave::declare_plugin!(register);
+//! ```
+//!
+//! Analysers are passed across the library boundary as Rust trait objects, which have no
+//! stable layout, so a plugin has to be built with the same compiler and the same version of
+//! analwave. The version of the interface is part of the name of the registration function,
+//! and the version of analwave is checked before it is called.
+
+use std::{
+    ffi::{CStr, CString, c_void},
+    os::unix::ffi::OsStrExt,
+    path::Path,
+};
+
+use crate::{Error, Result, registry::Registry};
+
+/// Version of the plugin interface, bumped when [`Registry`] or the [`Analyser`] trait
+/// change.
+///
+/// [`Analyser`]: crate::analysers::Analyser
+pub const API_VERSION: u32 = 1;
+
+/// Name of the registration function of a plugin of [`API_VERSION`].
+pub const REGISTER_SYMBOL: &CStr = c"analwave_register_v1";
+
+/// Name of the version of analwave a plugin was built with.
+pub const VERSION_SYMBOL: &CStr = c"ANALWAVE_PLUGIN_VERSION";
+
+/// Version of analwave, which plugins have to be built with.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Exports the registration function `$register`, a `fn(&mut Registry)`, with the version of
+/// analwave the plugin is built with.
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:path) => {
+        #[unsafe(no_mangle)]
+        pub static ANALWAVE_PLUGIN_VERSION: &str = $crate::plugins::VERSION;
+
+        #[unsafe(no_mangle)]
+        pub fn analwave_register_v1(registry: &mut $crate::registry::Registry) {
+            $register(registry)
+        }
+    };
+}
+
+/// The last error of the dynamic loader.
+fn loader_error() -> String {
+    // SAFETY: dlerror() returns null or a string valid until the next call of the loader
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        return "unknown error".to_string();
+    }
+
+    // SAFETY: checked for null above
+    unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Looks up `symbol` in the library of `handle`, null if it has none.
+fn symbol(handle: *mut c_void, symbol: &CStr) -> *mut c_void {
+    // SAFETY: handle was returned by dlopen() and symbol is a C string
+    unsafe { libc::dlsym(handle, symbol.as_ptr()) }
+}
+
+/// Loads the plugin at `path` and adds its analysers to `registry`. The library stays loaded
+/// until the process ends, as the analysers run its code.
+pub fn load(path: &Path, registry: &mut Registry) -> Result<()> {
+    let invalid = |reason: String| {
+        Error::Config(format!(
+            "Could not load the plugin {}: {reason}",
+            path.display()
+        ))
+    };
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| invalid("the path has a null byte".to_string()))?;
+    // SAFETY: loading runs the initialisers of the library, which is trusted like any
+    // other code the user runs
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(invalid(loader_error()));
+    }
+
+    let register = symbol(handle, REGISTER_SYMBOL);
+    let version = symbol(handle, VERSION_SYMBOL);
+    if register.is_null() || version.is_null() {
+        return Err(invalid(format!(
+            "it is not an analwave plugin of interface version {API_VERSION}"
+        )));
+    }
+
+    // SAFETY: declare_plugin! exports the version as a &'static str
+    let version = unsafe { *(version as *const &str) };
+    if version != VERSION {
+        return Err(invalid(format!(
+            "it was built for analwave {version}, this is {VERSION}"
+        )));
+    }
+
+    // SAFETY: declare_plugin! exports the registration function with this signature
+    let register: fn(&mut Registry) = unsafe { std::mem::transmute(register) };
+    register(registry);
+
+    if let Some(name) = registry.conflict() {
+        return Err(invalid(format!("'{name}' is already registered")));
+    }
+
+    Ok(())
+}
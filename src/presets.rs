@@ -0,0 +1,94 @@
+//! Bundles of options for common delivery specs, for `--preset`.
+
+use std::ffi::OsString;
+
+use clap::{ArgMatches, ValueEnum, parser::ValueSource};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Podcast,
+    BroadcastEbu,
+    MusicStreaming,
+    Archive,
+}
+
+impl Preset {
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Podcast => "Spoken word for podcast platforms",
+            Self::BroadcastEbu => "Programme delivery to EBU R 128",
+            Self::MusicStreaming => "Music masters for streaming services",
+            Self::Archive => "Transfers for archiving, checked for capture faults",
+        }
+    }
+
+    /// The options the preset stands for, as they would be given on the command line.
+    pub fn options(self) -> &'static [&'static str] {
+        match self {
+            Self::Podcast => &[
+                "--target-lufs=-16",
+                "--loudness-tolerance=1",
+                "--silence",
+                "--lufs=-50",
+                "--max-leading-silence=2",
+                "--max-clipped-samples=0",
+            ],
+            Self::BroadcastEbu => &[
+                "--target-lufs=-23",
+                "--loudness-tolerance=0.5",
+                "--silence",
+                "--lufs=-60",
+                "--max-leading-silence=5",
+                "--max-clipped-samples=0",
+            ],
+            Self::MusicStreaming => &[
+                "--target-lufs=-14",
+                "--loudness-tolerance=1",
+                "--silence",
+                "--lufs=-70",
+                "--max-leading-silence=2",
+                "--max-clipped-samples=10",
+            ],
+            Self::Archive => &[
+                "--underrun",
+                "--stuck-sample",
+                "--discontinuity",
+                "--silence",
+                "--lufs=-70",
+                "--max-clipped-samples=0",
+            ],
+        }
+    }
+
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// The options of the `--preset` in `matches` that weren't given explicitly, to be parsed
+/// before the given ones.
+pub fn preset_options(matches: &ArgMatches) -> Vec<OsString> {
+    let Ok(Some(preset)) = matches.try_get_one::<Preset>("preset") else {
+        return Vec::new();
+    };
+
+    preset
+        .options()
+        .iter()
+        .filter(|option| {
+            let name = option.trim_start_matches('-');
+            let id = name.split('=').next().unwrap_or(name).replace('-', "_");
+            matches.value_source(&id) != Some(ValueSource::CommandLine)
+        })
+        .map(OsString::from)
+        .collect()
+}
+
+pub fn print_presets() {
+    for preset in Preset::value_variants() {
+        println!("{:<16} {}", preset.name(), preset.description());
+        println!("{:<16} {}", "", preset.options().join(" "));
+    }
+}
@@ -0,0 +1,222 @@
+//! Progress as JSON lines on a side channel, `--progress-fd` or `--progress-file`, for
+//! programs that wrap the command line tool and show the progress themselves.
+//!
+//! Every line is a [`Record`]. One is written when a file starts, when its last frame is
+//! analysed, when it's done, and in between at most every [`INTERVAL`].
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{LineWriter, Write},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{Error, Result, cli::AnalyseArgs, warn};
+
+/// Least time between two records of the same phase.
+pub const INTERVAL: Duration = Duration::from_millis(500);
+
+static PROGRESS: OnceLock<Mutex<Progress>> = OnceLock::new();
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Frames are decoded and analysed
+    Analysing,
+    /// The analysers finish after the last frame, see `steps`
+    Finishing,
+    Done,
+}
+
+#[derive(Serialize)]
+pub struct Record<'a> {
+    pub file: &'a str,
+    pub phase: Phase,
+    /// Frames analysed so far
+    pub frame: u64,
+    /// Frames of the file, 0 if unknown
+    pub frames: u64,
+    /// `null` if the number of frames is unknown
+    pub percent: Option<f64>,
+    /// Seconds since the file started
+    pub elapsed: f64,
+    /// Seconds until the last frame is analysed, at the speed so far
+    pub eta: Option<f64>,
+    /// Events reported on the console so far, like silences and underruns, by kind
+    pub events: &'a BTreeMap<&'static str, u64>,
+    /// Status of the running steps of the analysers in the finish phase
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub steps: BTreeMap<&'static str, &'a str>,
+}
+
+struct Progress {
+    /// `None` once writing failed, e.g. because the reader went away
+    writer: Option<LineWriter<File>>,
+    file: String,
+    phase: Phase,
+    frame: u64,
+    frames: u64,
+    start: Instant,
+    written: Instant,
+    events: BTreeMap<&'static str, u64>,
+    steps: Vec<(&'static str, String)>,
+}
+
+/// Opens the channel given by `--progress-fd` or `--progress-file`, if any.
+pub fn open(args: &AnalyseArgs) -> Result<()> {
+    let file = match (args.progress_fd, &args.progress_file) {
+        (Some(fd), _) => from_fd(fd)?,
+        (None, Some(path)) => File::create(path).map_err(|err| Error::io("create", path, err))?,
+        (None, None) => return Ok(()),
+    };
+
+    let now = Instant::now();
+    let _ = PROGRESS.set(Mutex::new(Progress {
+        writer: Some(LineWriter::new(file)),
+        file: String::new(),
+        phase: Phase::Done,
+        frame: 0,
+        frames: 0,
+        start: now,
+        written: now,
+        events: BTreeMap::new(),
+        steps: Vec::new(),
+    }));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn from_fd(fd: i32) -> Result<File> {
+    use std::os::fd::FromRawFd;
+
+    // SAFETY: fcntl() only queries the flags of the descriptor
+    if fd < 0 || unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+        return Err(Error::Config(format!(
+            "--progress-fd {fd} is not an open file descriptor"
+        )));
+    }
+
+    // SAFETY: the descriptor is open and the parent process handed it to us for this
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn from_fd(_fd: i32) -> Result<File> {
+    Err(Error::Config(
+        "--progress-fd is only available on Unix, use --progress-file".to_string(),
+    ))
+}
+
+/// Runs `f` on the progress, if there is a channel.
+fn with_progress(f: impl FnOnce(&mut Progress)) {
+    if let Some(progress) = PROGRESS.get()
+        && let Ok(mut progress) = progress.lock()
+    {
+        f(&mut progress);
+    }
+}
+
+/// Starts the records of the input `file` of `frames` frames.
+pub fn start(file: Option<&Path>, frames: u64) {
+    with_progress(|progress| {
+        let now = Instant::now();
+        progress.file = file.map_or_else(String::new, |file| file.display().to_string());
+        progress.phase = Phase::Analysing;
+        progress.frame = 0;
+        progress.frames = frames;
+        progress.start = now;
+        progress.events.clear();
+        progress.steps.clear();
+        progress.write(now);
+    });
+}
+
+/// Counts `delta` more frames as analysed.
+pub fn advance(delta: u64) {
+    with_progress(|progress| {
+        progress.frame += delta;
+        progress.write_due();
+    });
+}
+
+/// Counts an event of `kind` reported on the console, e.g. `silence`.
+pub fn event(kind: &'static str) {
+    with_progress(|progress| {
+        *progress.events.entry(kind).or_default() += 1;
+    });
+}
+
+/// Moves on to `phase`, which is always written.
+pub fn phase(phase: Phase) {
+    with_progress(|progress| {
+        if progress.phase == phase {
+            return;
+        }
+        progress.phase = phase;
+        if phase == Phase::Done {
+            progress.steps.clear();
+        }
+        progress.write(Instant::now());
+    });
+}
+
+/// The status of the running steps of the finish phase.
+pub fn steps(steps: &[(&'static str, String)]) {
+    with_progress(|progress| {
+        progress.steps = steps.to_vec();
+        progress.write_due();
+    });
+}
+
+impl Progress {
+    fn write_due(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.written) >= INTERVAL {
+            self.write(now);
+        }
+    }
+
+    fn write(&mut self, now: Instant) {
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+
+        let elapsed = now.duration_since(self.start).as_secs_f64();
+        let known = self.frames > 0;
+        let record = Record {
+            file: &self.file,
+            phase: self.phase,
+            frame: self.frame,
+            frames: self.frames,
+            percent: known.then(|| 100.0 * self.frame as f64 / self.frames as f64),
+            elapsed,
+            eta: match self.phase {
+                Phase::Analysing if known && self.frame > 0 => Some(
+                    elapsed * self.frames.saturating_sub(self.frame) as f64 / self.frame as f64,
+                ),
+                Phase::Analysing => None,
+                Phase::Finishing | Phase::Done => Some(0.0),
+            },
+            events: &self.events,
+            steps: self
+                .steps
+                .iter()
+                .map(|(name, status)| (*name, status.as_str()))
+                .collect(),
+        };
+
+        let written = serde_json::to_writer(&mut *writer, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"));
+        self.written = now;
+        if let Err(err) = written {
+            self.writer = None;
+            warn!("Stopped writing progress records: {err}");
+        }
+    }
+}
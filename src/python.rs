@@ -0,0 +1,143 @@
+//! The `pyanalwave` Python module, built with the `python` feature.
+
+use std::path::PathBuf;
+
+use pyo3::{
+    exceptions::{PyOSError, PyRuntimeError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+use crate::{Error, cli::AnalyseArgs, json::to_json, pipeline};
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io { .. } => PyOSError::new_err(err.to_string()),
+            Error::Decode { .. } | Error::Config(_) => PyValueError::new_err(err.to_string()),
+            Error::Analyser { .. } | Error::Cancelled => PyRuntimeError::new_err(err.to_string()),
+        }
+    }
+}
+
+/// Runs the analysis and returns the JSON report as a dict, with the return code the command
+/// line tool would exit with under `exitCode`.
+fn report<'py>(
+    py: Python<'py>,
+    path: PathBuf,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let json = py.import("json")?;
+    let config: String = match options {
+        Some(options) => json.call_method1("dumps", (options,))?.extract()?,
+        None => String::new(),
+    };
+    let args = AnalyseArgs::from_config(&config)?;
+
+    let (exit_code, results) = py.detach(|| pipeline::analyse_file(&args, &path))?;
+    let results = to_json(&results).map_err(|err| Error::analyser("json", err))?;
+
+    let report = json
+        .call_method1("loads", (results,))?
+        .downcast_into::<PyDict>()?;
+    report.set_item("exitCode", exit_code)?;
+
+    Ok(report)
+}
+
+/// Analyses a WAV file with the options of `analwave analyse` as keyword arguments, e.g.
+/// `analyse("take.wav", underrun=True, silence=True, lufs=-50)`. Returns the JSON report as a
+/// dict, with the return code bits under `exitCode`.
+#[pyfunction]
+#[pyo3(signature = (path, **options))]
+fn analyse<'py>(
+    py: Python<'py>,
+    path: PathBuf,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    report(py, path, options)
+}
+
+/// A class per analyser, holding its options. `analyse(path)` runs only this analyser and
+/// returns its part of the report, `None` if it left it out because it found nothing.
+macro_rules! analyser_class {
+    ($class:ident, $option:literal, $key:literal) => {
+        #[doc = concat!("The `--", $option, "` analyser, with its options as keyword arguments.")]
+        #[pyclass(module = "pyanalwave")]
+        struct $class {
+            options: Py<PyDict>,
+        }
+
+        #[pymethods]
+        impl $class {
+            #[new]
+            #[pyo3(signature = (**options))]
+            fn new(py: Python<'_>, options: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+                let options = match options {
+                    Some(options) => options.copy()?,
+                    None => PyDict::new(py),
+                };
+                options.set_item($option, true)?;
+
+                Ok(Self {
+                    options: options.unbind(),
+                })
+            }
+
+            fn analyse<'py>(
+                &self,
+                py: Python<'py>,
+                path: PathBuf,
+            ) -> PyResult<Option<Bound<'py, PyAny>>> {
+                let report = report(py, path, Some(self.options.bind(py)))?;
+                let Some(analysis) = report.get_item("analysis")? else {
+                    return Ok(None);
+                };
+
+                analysis.downcast_into::<PyDict>()?.get_item($key)
+            }
+
+            fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+                Ok(format!(
+                    "{}({})",
+                    stringify!($class),
+                    self.options.bind(py).repr()?
+                ))
+            }
+        }
+    };
+}
+
+analyser_class!(Loudness, "loudness", "loudness");
+analyser_class!(Silence, "silence", "silence");
+analyser_class!(Underruns, "underrun", "underruns");
+analyser_class!(Discontinuities, "discontinuity", "discontinuities");
+analyser_class!(Dynamics, "dynamics", "dynamics");
+analyser_class!(ChannelBalance, "channel-balance", "channelBalance");
+analyser_class!(Drift, "drift", "drift");
+analyser_class!(Pumping, "pumping", "pumping");
+analyser_class!(Tones, "tones", "tones");
+analyser_class!(Dtmf, "dtmf", "dtmf");
+analyser_class!(Ltc, "ltc", "ltc");
+analyser_class!(Vad, "vad", "vad");
+analyser_class!(SpectralStats, "spectral-stats", "spectral");
+
+#[pymodule]
+fn pyanalwave(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(analyse, module)?)?;
+    module.add_class::<Loudness>()?;
+    module.add_class::<Silence>()?;
+    module.add_class::<Underruns>()?;
+    module.add_class::<Discontinuities>()?;
+    module.add_class::<Dynamics>()?;
+    module.add_class::<ChannelBalance>()?;
+    module.add_class::<Drift>()?;
+    module.add_class::<Pumping>()?;
+    module.add_class::<Tones>()?;
+    module.add_class::<Dtmf>()?;
+    module.add_class::<Ltc>()?;
+    module.add_class::<Vad>()?;
+    module.add_class::<SpectralStats>()?;
+
+    Ok(())
+}
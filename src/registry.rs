@@ -0,0 +1,571 @@
+//! The analysers, described by the options that enable them and the keys they add to the JSON
+//! output, so they are set up from the options generically. Analysers of `--plugin`
+//! libraries register the same way, see [`crate::plugins`].
+
+use std::path::PathBuf;
+
+use crate::analysers::fft::{FftAnalyser, FftBackend};
+use crate::analysers::{
+    Analyser, activity::ActivityAnalyser, alignment::AlignmentAnalyser,
+    balance::ChannelBalanceAnalyser, bitdepth::BitDepthAnalyser, codec::CodecAnalyser,
+    compliance::ComplianceAnalyser, container::ContainerAnalyser,
+    discontinuity::DiscontinuityAnalyser, drift::DriftAnalyser, dtmf::DtmfAnalyser,
+    dynamics::DynamicsAnalyser, format::FormatAnalyser, key::KeyAnalyser,
+    loudness::LoudnessAnalyser, ltc::LtcAnalyser, meter::MeterAnalyser,
+    normalize::NormalizeAnalyser, novelty::NoveltyAnalyser, peaks::PeaksAnalyzer,
+    pitch::PitchAnalyser, pumping::PumpingAnalyser, ratecheck::RateCheckAnalyser,
+    spectral::SpectralStatsAnalyser, stereo::StereoAnalyser, target::LoudnessTargetAnalyser,
+    tempo::TempoAnalyser, tone::ToneAnalyser, underruns::UnderrunAnalyser, vad::VadAnalyser,
+};
+use crate::{Error, Result, cli::AnalyseArgs, output, stream::StreamSpec};
+
+/// Checks the options an analyser needs and sets it up for a stream.
+pub type Build = fn(&AnalyseArgs, &StreamSpec) -> Result<Box<dyn Analyser>>;
+
+/// How an analyser is enabled and set up.
+#[derive(Clone, Copy)]
+pub struct Registration {
+    /// Name of the analyser, as in the timings
+    pub name: &'static str,
+    /// The options that enable it
+    pub flags: &'static [&'static str],
+    /// Keys it adds to the `analysis` object of the JSON output
+    pub keys: &'static [&'static str],
+    pub enabled: fn(&AnalyseArgs) -> bool,
+    pub build: Build,
+}
+
+/// The analysers that can run, in the order they are set up.
+pub struct Registry {
+    registrations: Vec<Registration>,
+}
+
+impl Registry {
+    /// The built-in analysers and those of the `--plugin` libraries.
+    pub fn new(args: &AnalyseArgs) -> Result<Self> {
+        let mut registry = Self {
+            registrations: BUILTIN.to_vec(),
+        };
+        registry.load_plugins(&args.plugin)?;
+
+        Ok(registry)
+    }
+
+    #[cfg(all(unix, feature = "plugins"))]
+    fn load_plugins(&mut self, paths: &[PathBuf]) -> Result<()> {
+        for path in paths {
+            crate::plugins::load(path, self)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(all(unix, feature = "plugins")))]
+    fn load_plugins(&mut self, paths: &[PathBuf]) -> Result<()> {
+        match paths.first() {
+            Some(path) => Err(Error::Config(format!(
+                "--plugin {} needs analwave built with the plugins feature on a Unix system",
+                path.display()
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds an analyser, after the built-in ones. Called by plugins from their registration
+    /// function.
+    pub fn register(&mut self, registration: Registration) {
+        self.registrations.push(registration);
+    }
+
+    pub fn registrations(&self) -> &[Registration] {
+        &self.registrations
+    }
+
+    /// The name or key of an analyser that is registered twice, which would mix up their
+    /// results.
+    pub fn conflict(&self) -> Option<&'static str> {
+        let (mut names, mut keys) = (Vec::new(), Vec::new());
+
+        for registration in &self.registrations {
+            if names.contains(&registration.name) {
+                return Some(registration.name);
+            }
+            names.push(registration.name);
+
+            for key in registration.keys {
+                if keys.contains(key) {
+                    return Some(key);
+                }
+                keys.push(key);
+            }
+        }
+
+        None
+    }
+
+    /// Sets up the analysers the options enable for a stream of `spec`.
+    pub fn analysers(
+        &self,
+        args: &AnalyseArgs,
+        spec: &StreamSpec,
+    ) -> Result<Vec<Box<dyn Analyser>>> {
+        self.registrations
+            .iter()
+            .filter(|registration| (registration.enabled)(args))
+            .map(|registration| (registration.build)(args, spec))
+            .collect()
+    }
+}
+
+/// Prints the analysers with the keys they write and the options that enable them.
+pub fn print_analysers(registry: &Registry) {
+    for registration in registry.registrations() {
+        println!(
+            "{:<16} {:<24} {}",
+            registration.name,
+            registration.keys.join(", "),
+            registration.flags.join(" ")
+        );
+    }
+}
+
+const BUILTIN: [Registration; 30] = [
+    Registration {
+        name: "loudness",
+        flags: &[
+            "--silence",
+            "--cue",
+            "--chapters",
+            "--max-leading-silence",
+            "--silence-relative",
+            "--loudness",
+            "--loudness-rate",
+            "--loudness-histogram",
+            "--loudness-graph",
+        ],
+        keys: &["loudness", "silence"],
+        enabled: |args| args.detect_silence() || args.track_loudness(),
+        build: loudness,
+    },
+    Registration {
+        name: "underruns",
+        flags: &["--underrun"],
+        keys: &["underruns"],
+        enabled: |args| args.underrun,
+        build: |args, spec| Ok(Box::new(UnderrunAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "discontinuities",
+        flags: &["--discontinuity"],
+        keys: &["discontinuities"],
+        enabled: |args| args.discontinuity,
+        build: |args, spec| Ok(Box::new(DiscontinuityAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "fft",
+        flags: &["--fft", "--fft-vis"],
+        keys: &["fft"],
+        enabled: |args| args.fft || args.fft_vis.is_some(),
+        build: fft,
+    },
+    Registration {
+        name: "peaks",
+        flags: &["--peaks"],
+        keys: &["peaks"],
+        enabled: |args| args.peaks,
+        build: peaks,
+    },
+    Registration {
+        name: "dynamics",
+        flags: &["--dynamics", "--min-dr", "--max-clipped-samples"],
+        keys: &["dynamics"],
+        enabled: |args| {
+            args.dynamics || args.min_dr.is_some() || args.max_clipped_samples.is_some()
+        },
+        build: |args, spec| Ok(Box::new(DynamicsAnalyser::new(args, spec)?)),
+    },
+    Registration {
+        name: "meter",
+        flags: &["--meter-file"],
+        keys: &["meter"],
+        enabled: |args| args.meter_file.is_some(),
+        build: |args, spec| {
+            let path = args.meter_file.clone().unwrap_or_default();
+            Ok(Box::new(MeterAnalyser::new(args, spec, path)?))
+        },
+    },
+    Registration {
+        name: "loudnesstarget",
+        flags: &["--target-lufs"],
+        keys: &["loudnessTarget"],
+        enabled: |args| args.target_lufs.is_some(),
+        build: |args, spec| {
+            let target = args.target_lufs.unwrap_or_default();
+            Ok(Box::new(LoudnessTargetAnalyser::new(args, spec, target)?))
+        },
+    },
+    Registration {
+        name: "normalization",
+        flags: &["--simulate-normalize"],
+        keys: &["normalization"],
+        enabled: |args| args.simulate_normalize.is_some(),
+        build: normalize,
+    },
+    Registration {
+        name: "compliance",
+        flags: &["--ebu-r128-check"],
+        keys: &["compliance"],
+        enabled: |args| args.ebu_r128_check,
+        build: compliance,
+    },
+    Registration {
+        name: "bitdepth",
+        flags: &["--bit-depth"],
+        keys: &["bitDepth"],
+        enabled: |args| args.bit_depth,
+        build: bit_depth,
+    },
+    Registration {
+        name: "ratecheck",
+        flags: &["--rate-check"],
+        keys: &["rateCheck"],
+        enabled: |args| args.rate_check,
+        build: rate_check,
+    },
+    Registration {
+        name: "codec",
+        flags: &["--codec-check"],
+        keys: &["codec"],
+        enabled: |args| args.codec_check,
+        build: |_, spec| Ok(Box::new(CodecAnalyser::new(spec))),
+    },
+    Registration {
+        name: "container",
+        flags: &["--container-check"],
+        keys: &["container"],
+        enabled: |args| args.container_check,
+        build: |_, spec| Ok(Box::new(ContainerAnalyser::new(spec))),
+    },
+    Registration {
+        name: "format",
+        flags: &["--format-check", "--strict-container"],
+        keys: &["format"],
+        enabled: |args| args.format_check || args.strict_container,
+        build: |args, _| Ok(Box::new(FormatAnalyser::new(args)?)),
+    },
+    Registration {
+        name: "stereo",
+        flags: &["--stereo"],
+        keys: &["stereo"],
+        enabled: |args| args.stereo,
+        build: stereo,
+    },
+    Registration {
+        name: "alignment",
+        flags: &["--alignment", "--max-skew-samples"],
+        keys: &["alignment"],
+        enabled: |args| args.alignment || args.max_skew_samples.is_some(),
+        build: alignment,
+    },
+    Registration {
+        name: "channelbalance",
+        flags: &["--channel-balance", "--max-channel-imbalance-db"],
+        keys: &["channelBalance"],
+        enabled: |args| args.channel_balance || args.max_channel_imbalance_db.is_some(),
+        build: |args, spec| Ok(Box::new(ChannelBalanceAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "drift",
+        flags: &["--drift"],
+        keys: &["drift"],
+        enabled: |args| args.drift,
+        build: |args, spec| Ok(Box::new(DriftAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "pumping",
+        flags: &["--pumping"],
+        keys: &["pumping"],
+        enabled: |args| args.pumping,
+        build: |args, spec| Ok(Box::new(PumpingAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "tones",
+        flags: &["--tones", "--require-tone", "--fail-on-tone"],
+        keys: &["tones"],
+        enabled: |args| args.tones || args.require_tone || args.fail_on_tone,
+        build: |args, spec| Ok(Box::new(ToneAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "dtmf",
+        flags: &["--dtmf"],
+        keys: &["dtmf"],
+        enabled: |args| args.dtmf,
+        build: |args, spec| Ok(Box::new(DtmfAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "ltc",
+        flags: &["--ltc"],
+        keys: &["ltc"],
+        enabled: |args| args.ltc,
+        build: |args, spec| Ok(Box::new(LtcAnalyser::new(args, spec))),
+    },
+    Registration {
+        name: "vad",
+        flags: &["--vad", "--min-speech-percentage", "--dialogue-loudness"],
+        keys: &["vad"],
+        enabled: |args| args.vad || args.min_speech_percentage.is_some() || args.dialogue_loudness,
+        build: |args, spec| Ok(Box::new(VadAnalyser::new(args, spec)?)),
+    },
+    Registration {
+        name: "spectralstats",
+        flags: &["--spectral-stats"],
+        keys: &["spectral"],
+        enabled: |args| args.spectral_stats,
+        build: spectral_stats,
+    },
+    Registration {
+        name: "activity",
+        flags: &["--activity"],
+        keys: &["activity"],
+        enabled: |args| args.activity,
+        build: activity,
+    },
+    Registration {
+        name: "pitch",
+        flags: &["--pitch"],
+        keys: &["pitch"],
+        enabled: |args| args.pitch,
+        build: pitch,
+    },
+    Registration {
+        name: "novelty",
+        flags: &["--novelty"],
+        keys: &["novelty"],
+        enabled: |args| args.novelty,
+        build: novelty,
+    },
+    Registration {
+        name: "tempo",
+        flags: &["--tempo", "--beats"],
+        keys: &["tempo"],
+        enabled: |args| args.tempo || args.beats,
+        build: tempo,
+    },
+    Registration {
+        name: "key",
+        flags: &["--key"],
+        keys: &["key"],
+        enabled: |args| args.key,
+        build: |_, spec| Ok(Box::new(KeyAnalyser::new(spec))),
+    },
+];
+
+fn loudness(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    let analyser = LoudnessAnalyser::new(args, spec)?;
+
+    if let Some(rate) = args.loudness_rate {
+        output!("[+] loudness envelope:  {} points per second", rate);
+    }
+
+    Ok(Box::new(analyser))
+}
+
+fn fft(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.deterministic && !args.fft_bins.is_power_of_two() {
+        return Err(Error::Config(
+            "--deterministic needs a power-of-two number of FFT bins".to_string(),
+        ));
+    }
+
+    if args.fft_backend == FftBackend::Fast {
+        if !cfg!(feature = "fast-fft") {
+            return Err(Error::Config(
+                "--fft-backend fast needs analwave built with the fast-fft feature".to_string(),
+            ));
+        }
+
+        if args.deterministic {
+            return Err(Error::Config(
+                "--deterministic computes the spectrogram portably and can't be used with --fft-backend fast"
+                    .to_string(),
+            ));
+        }
+    }
+
+    if args.low_memory && !args.fft_bins.is_power_of_two() {
+        return Err(Error::Config(
+            "--low-memory needs a power-of-two number of FFT bins".to_string(),
+        ));
+    }
+
+    if args.fft_bands == 0 {
+        return Err(Error::Config(
+            "--fft-bands has to be at least 1".to_string(),
+        ));
+    }
+
+    if args.low_memory && args.fft_vis.is_some() {
+        return Err(Error::Config(
+            "--fft-vis needs the whole spectrogram in memory and can't be used with --low-memory"
+                .to_string(),
+        ));
+    }
+
+    let path = args.fft_file.clone().filter(|_| args.fft);
+
+    if args.fft && path.is_none() {
+        return Err(Error::Config(
+            "FFT output was enabled but no path could be determined, please provide --fft-file or --json"
+                .to_string(),
+        ));
+    }
+
+    Ok(Box::new(FftAnalyser::new(args, spec, path)?))
+}
+
+fn peaks(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.low_memory {
+        return Err(Error::Config(
+            "--peaks needs every sample in memory and can't be used with --low-memory".to_string(),
+        ));
+    }
+
+    let Some(path) = args.peaks_file.clone() else {
+        return Err(Error::Config(
+            "Peaks output was enabled but no path could be determined, please provide --peaks-file or --json"
+                .to_string(),
+        ));
+    };
+
+    Ok(Box::new(PeaksAnalyzer::new(args, spec, path)))
+}
+
+fn normalize(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    let target = args.simulate_normalize.unwrap_or_default();
+    if !target.is_finite() || !args.peak_ceiling.is_finite() {
+        return Err(Error::Config(
+            "--simulate-normalize and --peak-ceiling have to be numbers".to_string(),
+        ));
+    }
+
+    Ok(Box::new(NormalizeAnalyser::new(args, spec, target)?))
+}
+
+fn compliance(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.max_lra < 0.0 {
+        return Err(Error::Config("--max-lra can't be negative".to_string()));
+    }
+
+    Ok(Box::new(ComplianceAnalyser::new(args, spec)?))
+}
+
+fn bit_depth(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.resample.is_some() || args.downmix.is_some() || args.gain_db.is_some() {
+        return Err(Error::Config(
+            "--bit-depth looks at the samples as stored and can't be combined with --resample, --downmix or --gain-db"
+                .to_string(),
+        ));
+    }
+
+    Ok(Box::new(BitDepthAnalyser::new(spec)))
+}
+
+fn rate_check(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.resample.is_some() {
+        return Err(Error::Config(
+            "--rate-check compares against the rate of the file and can't be combined with --resample"
+                .to_string(),
+        ));
+    }
+
+    Ok(Box::new(RateCheckAnalyser::new(spec)))
+}
+
+fn stereo(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if spec.channels < 2 {
+        return Err(Error::Config(
+            "--stereo needs at least two channels".to_string(),
+        ));
+    }
+
+    Ok(Box::new(StereoAnalyser::new(args)))
+}
+
+fn alignment(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if spec.channels < 2 {
+        return Err(Error::Config(
+            "--alignment needs at least two channels".to_string(),
+        ));
+    }
+
+    Ok(Box::new(AlignmentAnalyser::new(args, spec)))
+}
+
+fn spectral_stats(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if !args.fft_bins.is_power_of_two() {
+        return Err(Error::Config(
+            "--spectral-stats needs a power-of-two number of FFT bins".to_string(),
+        ));
+    }
+
+    if args.spectral_rate <= 0.0 {
+        return Err(Error::Config(
+            "--spectral-rate has to be positive".to_string(),
+        ));
+    }
+
+    Ok(Box::new(SpectralStatsAnalyser::new(args, spec)))
+}
+
+fn activity(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.activity_rate <= 0.0 || args.activity_rate > spec.sample_rate as f64 {
+        return Err(Error::Config(format!(
+            "--activity-rate has to be above 0 and at most the sample rate of {} Hz",
+            spec.sample_rate
+        )));
+    }
+
+    Ok(Box::new(ActivityAnalyser::new(args, spec)))
+}
+
+fn pitch(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.pitch_rate <= 0.0 {
+        return Err(Error::Config("--pitch-rate has to be positive".to_string()));
+    }
+
+    let nyquist = spec.sample_rate as f64 / 2.0;
+    if !(args.pitch_min > 0.0 && args.pitch_min < args.pitch_max && args.pitch_max < nyquist) {
+        return Err(Error::Config(format!(
+            "--pitch-min and --pitch-max have to be a range of frequencies from above 0 to below {nyquist} Hz"
+        )));
+    }
+
+    Ok(Box::new(PitchAnalyser::new(args, spec)))
+}
+
+fn novelty(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if args.novelty_window <= 0.0 {
+        return Err(Error::Config(
+            "--novelty-window has to be positive".to_string(),
+        ));
+    }
+
+    if args.novelty_min_gap < 0.0 || !args.novelty_threshold.is_finite() {
+        return Err(Error::Config(
+            "--novelty-min-gap can't be negative and --novelty-threshold has to be a number"
+                .to_string(),
+        ));
+    }
+
+    Ok(Box::new(NoveltyAnalyser::new(args, spec)))
+}
+
+fn tempo(args: &AnalyseArgs, spec: &StreamSpec) -> Result<Box<dyn Analyser>> {
+    if !(args.tempo_min > 0.0 && args.tempo_min < args.tempo_max) {
+        return Err(Error::Config(
+            "--tempo-min and --tempo-max have to be a range of tempos above 0 BPM".to_string(),
+        ));
+    }
+
+    Ok(Box::new(TempoAnalyser::new(args, spec)))
+}
@@ -0,0 +1,409 @@
+use std::{fmt::Write, fs, fs::File, io::BufReader, path::Path};
+
+use crate::{
+    Error, Result,
+    analysers::{
+        loudness::{LoudnessAnalysis, SilenceAnalysis},
+        peaks::{PeaksAnalysis, PeaksFormat},
+    },
+    cli::AnalyseArgs,
+    findings::{Kind, findings},
+    json::Results,
+    output,
+    visualize::{issues_map_png, loudness_graph_png},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+pub(crate) mod charts;
+mod template;
+
+use charts::{LoudnessWindow, loudness_chart, waveform_chart};
+use template::{escape, render};
+
+const TEMPLATE: &str = include_str!("report/template.html");
+
+/// Render a self-contained HTML report of the results, with charts as inline SVG
+/// and images embedded as base64 data URIs.
+pub fn write_report(args: &AnalyseArgs, results: &Results) -> Result<()> {
+    let Some(path) = args.report.as_ref() else {
+        return Ok(());
+    };
+
+    let input = args
+        .input
+        .first()
+        .map(|input| input.display().to_string())
+        .unwrap_or_default();
+
+    fs::write(path, render_report(&input, results))
+        .map_err(|err| Error::io("write HTML report to", path, err))?;
+
+    output!("Wrote HTML report to {}", path.display());
+
+    Ok(())
+}
+
+pub fn render_report(input: &str, results: &Results) -> String {
+    let analysis = &results.analysis;
+    let duration = results.duration as f64;
+    let mut sections = String::new();
+
+    if let Some(loudness) = &analysis.loudness {
+        sections.push_str(&loudness_section(
+            loudness,
+            analysis.silence.as_ref(),
+            duration,
+        ));
+    }
+
+    if let Some(silence) = &analysis.silence {
+        let rows: Vec<Row> = silence
+            .results
+            .iter()
+            .map(|seg| Row {
+                channel: None,
+                start: seg.start,
+                end: seg.end,
+                duration: seg.duration,
+                start_sample: seg.start_sample,
+                end_sample: seg.end_sample,
+            })
+            .collect();
+
+        sections.push_str(&segment_section(
+            "Silence",
+            &rows,
+            &format!("threshold {:?} LUFS-S", silence.threshold),
+            false,
+        ));
+    }
+
+    if let Some(underruns) = &analysis.underruns {
+        let rows: Vec<Row> = underruns
+            .results
+            .iter()
+            .map(|seg| Row {
+                channel: Some(seg.channel),
+                start: seg.start,
+                end: seg.end,
+                duration: seg.duration,
+                start_sample: seg.start_sample,
+                end_sample: seg.end_sample,
+            })
+            .collect();
+
+        sections.push_str(&segment_section(
+            "Underruns",
+            &rows,
+            &format!("threshold {} samples", underruns.threshold),
+            true,
+        ));
+    }
+
+    if let Some(path) = analysis
+        .fft
+        .as_ref()
+        .and_then(|fft| fft.results.visualization.as_ref())
+    {
+        sections.push_str("<h2>Spectrogram</h2>");
+        sections.push_str(&image(Path::new(path)));
+    }
+
+    if let Some(peaks) = &analysis.peaks {
+        sections.push_str("<h2>Waveform</h2>");
+        sections.push_str(&waveform(peaks, results.num_channels as usize));
+    }
+
+    let name = Path::new(input).file_name().map_or_else(
+        || input.to_string(),
+        |name| name.to_string_lossy().to_string(),
+    );
+
+    render(
+        TEMPLATE,
+        &[
+            ("title", &escape(&name)),
+            ("metadata", &metadata_table(input, results)),
+            ("sections", &sections),
+            ("version", env!("CARGO_PKG_VERSION")),
+        ],
+    )
+}
+
+fn sample_rate(results: &Results) -> String {
+    if results.analysis_sample_rate == results.sample_rate {
+        format!("{} Hz", results.sample_rate)
+    } else {
+        format!(
+            "{} Hz (analysed at {} Hz)",
+            results.sample_rate, results.analysis_sample_rate
+        )
+    }
+}
+
+fn metadata_table(input: &str, results: &Results) -> String {
+    let mut html = String::from("<table>");
+    let rows = [
+        ("File", escape(input)),
+        ("Sample rate", sample_rate(results)),
+        ("Channels", results.num_channels.to_string()),
+        ("Samples", results.num_samples.to_string()),
+        ("Duration", format!("{:.3} s", results.duration)),
+    ];
+
+    for (label, value) in rows {
+        let _ = write!(html, "<tr><th>{label}</th><td>{value}</td></tr>");
+    }
+    html.push_str("</table>");
+
+    html
+}
+
+/// Plot the loudness windows to the file of `--loudness-graph`, as PNG for a `.png`
+/// extension and otherwise as SVG.
+pub fn write_loudness_graph(args: &AnalyseArgs, results: &Results) -> Result<()> {
+    let Some(path) = args.loudness_graph.as_ref() else {
+        return Ok(());
+    };
+    let Some(loudness) = &results.analysis.loudness else {
+        return Ok(());
+    };
+
+    let silence = results.analysis.silence.as_ref();
+    let windows = loudness_windows(loudness);
+    let threshold = silence.map_or(args.lufs, |silence| silence.threshold);
+    let regions = silence_regions(silence);
+    let duration = results.duration as f64;
+
+    let is_png = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+    if is_png {
+        loudness_graph_png(path, &windows, duration, threshold, &regions)?;
+    } else {
+        let svg = loudness_chart(&windows, duration, Some(threshold), &regions);
+        fs::write(path, svg).map_err(|err| Error::io("write loudness graph to", path, err))?;
+    }
+
+    output!("Wrote loudness graph to {}", path.display());
+
+    Ok(())
+}
+
+/// Render where silence, underruns and clipping were found over the channels of the file.
+pub fn write_issues_map(args: &AnalyseArgs, results: &Results) -> Result<()> {
+    let Some(path) = args.issues_map.as_ref() else {
+        return Ok(());
+    };
+
+    let sample_rate = results.sample_rate as f64;
+    let issues: Vec<_> = findings(results)
+        .into_iter()
+        .filter(|finding| {
+            matches!(
+                finding.kind,
+                Kind::Silence | Kind::Underrun | Kind::Clipping
+            )
+        })
+        .map(|finding| {
+            (
+                finding.kind,
+                finding.channel,
+                results.file_frame(finding.start) as f64 / sample_rate,
+                results.file_frame(finding.end) as f64 / sample_rate,
+            )
+        })
+        .collect();
+    // Findings are on the channels of the downmix, if there is one
+    let channels = results
+        .downmix
+        .as_ref()
+        .map_or(results.num_channels as usize, |downmix| {
+            downmix.matrix.len()
+        });
+
+    issues_map_png(path, channels, results.duration as f64, &issues)?;
+    output!("Wrote issues map to {}", path.display());
+
+    Ok(())
+}
+
+fn loudness_windows(loudness: &LoudnessAnalysis) -> Vec<LoudnessWindow> {
+    loudness
+        .results
+        .iter()
+        .map(|win| LoudnessWindow {
+            start: win.start as f64,
+            end: win.end as f64,
+            // NaN (serialized as null) is drawn like silence
+            loudness: if win.loudness.is_nan() {
+                f64::NEG_INFINITY
+            } else {
+                win.loudness
+            },
+        })
+        .collect()
+}
+
+fn silence_regions(silence: Option<&SilenceAnalysis>) -> Vec<(f64, f64)> {
+    silence
+        .map(|silence| {
+            silence
+                .results
+                .iter()
+                .map(|seg| (seg.start as f64, seg.end as f64))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn loudness_section(
+    loudness: &LoudnessAnalysis,
+    silence: Option<&SilenceAnalysis>,
+    duration: f64,
+) -> String {
+    let windows = loudness_windows(loudness);
+    let threshold = silence.map(|silence| silence.threshold);
+    let regions = silence_regions(silence);
+
+    format!(
+        "<h2>Loudness</h2><p class=\"muted\">Short-term loudness (LUFS-S), window {:?} s</p>{}",
+        loudness.window_size,
+        loudness_chart(&windows, duration, threshold, &regions)
+    )
+}
+
+/// One line of a segment table.
+struct Row {
+    channel: Option<usize>,
+    start: f32,
+    end: f32,
+    duration: f32,
+    start_sample: usize,
+    end_sample: usize,
+}
+
+fn segment_section(title: &str, results: &[Row], summary: &str, channels: bool) -> String {
+    let mut html = format!(
+        "<h2>{title}</h2><p class=\"muted\">{} segments, {summary}</p>",
+        results.len()
+    );
+
+    if results.is_empty() {
+        return html;
+    }
+
+    html.push_str("<table><tr>");
+    if channels {
+        html.push_str("<th>Channel</th>");
+    }
+    html.push_str("<th>Start (s)</th><th>End (s)</th><th>Duration (s)</th><th>Start sample</th><th>End sample</th></tr>");
+
+    for segment in results {
+        html.push_str("<tr>");
+        if channels {
+            let channel = segment
+                .channel
+                .map_or_else(|| "-".to_string(), |channel| channel.to_string());
+            let _ = write!(html, "<td class=\"num\">{channel}</td>");
+        }
+        for value in [segment.start, segment.end, segment.duration] {
+            let _ = write!(html, "<td class=\"num\">{:.3}</td>", value as f64);
+        }
+        for value in [segment.start_sample, segment.end_sample] {
+            let _ = write!(html, "<td class=\"num\">{value}</td>");
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</table>");
+
+    html
+}
+
+fn image(path: &Path) -> String {
+    let Ok(bytes) = fs::read(path) else {
+        return format!(
+            "<p class=\"muted\">Could not read {}</p>",
+            escape(&path.to_string_lossy())
+        );
+    };
+
+    format!(
+        "<img alt=\"{}\" src=\"data:image/png;base64,{}\">",
+        escape(&path.to_string_lossy()),
+        STANDARD.encode(bytes)
+    )
+}
+
+/// Decode the raw peaks dump (see `PeaksAnalyzer`) back into per-channel dBFS values.
+fn waveform(peaks: &PeaksAnalysis, num_channels: usize) -> String {
+    let Some(values) = read_peaks(peaks) else {
+        return format!(
+            "<p class=\"muted\">Could not read {}</p>",
+            escape(&peaks.output)
+        );
+    };
+
+    let channels: Vec<Vec<f64>> = (0..num_channels)
+        .filter_map(|channel| {
+            let start = channel * peaks.square_size as usize;
+            values
+                .get(start..start + peaks.channel_size)
+                .map(|v| v.to_vec())
+        })
+        .collect();
+
+    waveform_chart(&channels)
+}
+
+fn read_raw_f64(path: &Path) -> Option<Vec<f64>> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path).ok()?));
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()?];
+    let info = reader.next_frame(&mut buf).ok()?;
+
+    Some(
+        buf[..info.buffer_size()]
+            .chunks_exact(8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect(),
+    )
+}
+
+/// Values of the peaks output in file order, silence as `-inf`.
+fn read_peaks(peaks: &PeaksAnalysis) -> Option<Vec<f64>> {
+    let path = Path::new(&peaks.output);
+    if peaks.format == PeaksFormat::Png {
+        return read_raw_f64(path);
+    }
+
+    let bytes = fs::read(path).ok()?;
+    let values = match peaks.format {
+        PeaksFormat::Json => serde_json::from_slice::<Vec<Vec<Option<f64>>>>(&bytes)
+            .ok()?
+            .into_iter()
+            .flatten()
+            .map(|value| value.unwrap_or(f64::NEG_INFINITY))
+            .collect(),
+        PeaksFormat::F32 => bytes
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()) as f64)
+            .collect(),
+        format => {
+            // The NumPy header length follows the magic string and version
+            let start = if format == PeaksFormat::Npy {
+                10 + u16::from_le_bytes(bytes.get(8..10)?.try_into().unwrap()) as usize
+            } else {
+                0
+            };
+
+            bytes
+                .get(start..)?
+                .chunks_exact(8)
+                .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+                .collect()
+        }
+    };
+
+    Some(values)
+}
@@ -0,0 +1,153 @@
+use std::fmt::Write;
+
+const WIDTH: f64 = 1000.0;
+const LOUDNESS_HEIGHT: f64 = 200.0;
+const LANE_HEIGHT: f64 = 80.0;
+const FLOOR_LUFS: f64 = -70.0;
+const FLOOR_DBFS: f64 = -60.0;
+const WAVEFORM_BUCKETS: usize = 1000;
+
+pub struct LoudnessWindow {
+    pub start: f64,
+    pub end: f64,
+    pub loudness: f64,
+}
+
+/// Lowest loudness on the axis, below the silence threshold.
+pub fn loudness_floor(threshold: Option<f64>) -> f64 {
+    threshold.map_or(FLOOR_LUFS, |t| t.min(FLOOR_LUFS) - 10.0)
+}
+
+/// Stepped short-term loudness over time, with silence regions shaded and the
+/// silence threshold drawn as a dashed line.
+pub fn loudness_chart(
+    windows: &[LoudnessWindow],
+    duration: f64,
+    threshold: Option<f64>,
+    silence: &[(f64, f64)],
+) -> String {
+    let floor = loudness_floor(threshold);
+    let x = |t: f64| (t / duration.max(f64::EPSILON)).clamp(0.0, 1.0) * WIDTH;
+    let y = |lufs: f64| ((lufs.max(floor) / floor).clamp(0.0, 1.0)) * LOUDNESS_HEIGHT;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-40 -10 {} {}">"#,
+        WIDTH + 50.0,
+        LOUDNESS_HEIGHT + 30.0
+    );
+
+    for (start, end) in silence {
+        let _ = write!(
+            svg,
+            r##"<rect x="{:.1}" y="0" width="{:.1}" height="{}" fill="#f4c7c3"/>"##,
+            x(*start),
+            (x(*end) - x(*start)).max(0.5),
+            LOUDNESS_HEIGHT
+        );
+    }
+
+    for lufs in (floor as i32..=0).rev().step_by(10) {
+        let _ = write!(
+            svg,
+            r##"<line x1="0" x2="{WIDTH}" y1="{0:.1}" y2="{0:.1}" stroke="#ddd"/><text x="-5" y="{0:.1}" font-size="10" text-anchor="end" dominant-baseline="middle">{lufs}</text>"##,
+            y(lufs as f64)
+        );
+    }
+
+    for tick in 0..=4 {
+        let t = duration * tick as f64 / 4.0;
+        let _ = write!(
+            svg,
+            r#"<text x="{:.1}" y="{}" font-size="10" text-anchor="middle">{:.1}s</text>"#,
+            x(t),
+            LOUDNESS_HEIGHT + 15.0,
+            t
+        );
+    }
+
+    let points: Vec<String> = windows
+        .iter()
+        .flat_map(|w| {
+            [
+                format!("{:.1},{:.1}", x(w.start), y(w.loudness)),
+                format!("{:.1},{:.1}", x(w.end), y(w.loudness)),
+            ]
+        })
+        .collect();
+
+    let _ = write!(
+        svg,
+        r##"<polyline points="{}" fill="none" stroke="#1565c0" stroke-width="1.5"/>"##,
+        points.join(" ")
+    );
+
+    if let Some(threshold) = threshold {
+        let _ = write!(
+            svg,
+            r##"<line x1="0" x2="{WIDTH}" y1="{0:.1}" y2="{0:.1}" stroke="#c62828" stroke-dasharray="6 4"/>"##,
+            y(threshold)
+        );
+    }
+
+    svg.push_str("</svg>");
+
+    svg
+}
+
+/// Envelope of per-sample dBFS values, one lane per channel.
+pub fn waveform_chart(channels: &[Vec<f64>]) -> String {
+    let height = LANE_HEIGHT * channels.len() as f64;
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {WIDTH} {height}">"#
+    );
+
+    for (index, peaks) in channels.iter().enumerate() {
+        if peaks.is_empty() {
+            continue;
+        }
+
+        let mid = LANE_HEIGHT * (index as f64 + 0.5);
+        let buckets = WAVEFORM_BUCKETS.min(peaks.len());
+        let bucket_size = peaks.len().div_ceil(buckets);
+
+        let amplitudes: Vec<f64> = peaks
+            .chunks(bucket_size)
+            .map(|bucket| {
+                let max = bucket.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                ((max - FLOOR_DBFS) / -FLOOR_DBFS).clamp(0.0, 1.0)
+            })
+            .collect();
+
+        let step = WIDTH / amplitudes.len() as f64;
+        let upper = amplitudes
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (i as f64 * step, mid - a * LANE_HEIGHT * 0.45));
+        let lower = amplitudes
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, a)| (i as f64 * step, mid + a * LANE_HEIGHT * 0.45));
+
+        let points: Vec<String> = upper
+            .chain(lower)
+            .map(|(x, y)| format!("{x:.1},{y:.1}"))
+            .collect();
+
+        let _ = write!(
+            svg,
+            r##"<line x1="0" x2="{WIDTH}" y1="{mid:.1}" y2="{mid:.1}" stroke="#ccc"/><polygon points="{}" fill="#2e7d32"/><text x="4" y="{:.1}" font-size="10">CH {index}</text>"##,
+            points.join(" "),
+            mid - LANE_HEIGHT * 0.5 + 12.0
+        );
+    }
+
+    svg.push_str("</svg>");
+
+    svg
+}
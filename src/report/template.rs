@@ -0,0 +1,44 @@
+/// Replace every `{{name}}` placeholder in `template` with its value.
+/// Values are inserted verbatim, escape them with [`escape`] where needed.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut html = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        html.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+
+        let Some(end) = rest.find("}}") else {
+            html.push_str("{{");
+            break;
+        };
+
+        let name = rest[..end].trim();
+        if let Some((_, value)) = values.iter().find(|(key, _)| *key == name) {
+            html.push_str(value);
+        }
+
+        rest = &rest[end + 2..];
+    }
+
+    html.push_str(rest);
+
+    html
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
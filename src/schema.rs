@@ -0,0 +1,144 @@
+use schemars::{
+    JsonSchema, Schema, SchemaGenerator, generate::SchemaSettings, transform::RecursiveTransform,
+};
+use serde_json::{Map, Value, to_string_pretty};
+
+use crate::{
+    Error, Result,
+    analysers::Analyser,
+    cli::AnalyseArgs,
+    json::{Results, SCHEMA_VERSION},
+    output,
+};
+
+/// Schema of one key an analyser adds to the `analysis` object of the JSON output.
+pub struct SchemaEntry {
+    pub key: String,
+    pub schema: Schema,
+    /// Whether the key is always present, some analysers leave it out when they found nothing.
+    pub required: bool,
+}
+
+impl SchemaEntry {
+    pub fn required<T: JsonSchema>(key: &str, generator: &mut SchemaGenerator) -> Self {
+        Self {
+            key: key.to_string(),
+            schema: generator.subschema_for::<T>(),
+            required: true,
+        }
+    }
+
+    pub fn optional<T: JsonSchema>(key: &str, generator: &mut SchemaGenerator) -> Self {
+        Self {
+            required: false,
+            ..Self::required::<T>(key, generator)
+        }
+    }
+}
+
+/// Objects in the output only ever have the keys listed in their schema. Segments can also
+/// have their times in the `--time-format`, with `--json-time`.
+fn close_object(schema: &mut Schema) {
+    let Some(properties) = schema.get_mut("properties").and_then(Value::as_object_mut) else {
+        return;
+    };
+
+    for (sample, time) in [("startSample", "startTime"), ("endSample", "endTime")] {
+        if properties.contains_key(sample) {
+            properties.insert(
+                time.to_string(),
+                serde_json::json!({
+                    "description": format!("{sample} in the --time-format, with --json-time"),
+                    "type": "string",
+                }),
+            );
+        }
+    }
+    schema.insert("additionalProperties".to_string(), Value::Bool(false));
+}
+
+fn generator() -> SchemaGenerator {
+    // Describe the documents we write, so keys that are always serialized are required
+    SchemaSettings::draft2020_12()
+        .for_serialize()
+        .with_transform(RecursiveTransform(close_object))
+        .into_generator()
+}
+
+/// Pins `schemaVersion` so documents of another version don't validate.
+fn pin_version(schema: &mut Schema) {
+    if let Some(Value::Object(version)) = schema
+        .get_mut("properties")
+        .and_then(|properties| properties.get_mut("schemaVersion"))
+    {
+        version.insert("const".to_string(), Value::from(SCHEMA_VERSION));
+    }
+}
+
+/// JSON Schema of every key the JSON output can have, whichever analysers are enabled.
+pub fn full_schema() -> Schema {
+    let mut schema = generator().into_root_schema_for::<Results>();
+    pin_version(&mut schema);
+
+    schema
+}
+
+/// JSON Schema of the output of a run with exactly these analysers.
+pub fn report_schema(analysers: &[Box<dyn Analyser>]) -> Schema {
+    let mut generator = generator();
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+
+    for analyser in analysers.iter() {
+        for entry in analyser.schema(&mut generator) {
+            if entry.required {
+                required.push(Value::from(entry.key.clone()));
+            }
+            properties.insert(entry.key, entry.schema.to_value());
+        }
+    }
+
+    let mut schema = generator.into_root_schema_for::<Results>();
+    pin_version(&mut schema);
+    let analysis = serde_json::json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    });
+
+    if let Some(root) = schema.as_object_mut() {
+        if let Some(Value::Object(root_properties)) = root.get_mut("properties") {
+            root_properties.insert("analysis".to_string(), analysis);
+        }
+        // The generic definition is replaced by the one above
+        if let Some(Value::Object(definitions)) = root.get_mut("$defs") {
+            definitions.remove("Analysis");
+        }
+    }
+
+    schema
+}
+
+pub fn write_schema(args: &AnalyseArgs, analysers: &[Box<dyn Analyser>]) -> Result<()> {
+    let Some(path) = args.emit_schema.as_ref() else {
+        return Ok(());
+    };
+
+    let write_error = |err| Error::io("write JSON schema to", path, err);
+    let schema =
+        to_string_pretty(&report_schema(analysers)).map_err(|err| write_error(err.into()))?;
+    std::fs::write(path, schema).map_err(write_error)?;
+
+    output!("Wrote JSON schema to {}", path.display());
+
+    Ok(())
+}
+
+pub fn print_schema() -> Result<()> {
+    let schema = to_string_pretty(&full_schema())
+        .map_err(|err| Error::io("write JSON schema to", "stdout", err))?;
+    println!("{schema}");
+
+    Ok(())
+}
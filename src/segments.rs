@@ -0,0 +1,97 @@
+//! Extraction of what the analysers found, for `--export-segments`: every silence, underrun,
+//! stuck sample run and clipped region is copied from the input to a WAV file of its own.
+
+use std::{fs, path::Path};
+
+use wavers::WaversError;
+
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    findings::{Kind, findings},
+    input::Input,
+    json::Results,
+    output,
+};
+
+/// Writes a WAV file with the audio of every finding, `--segment-padding` seconds longer on
+/// both sides, to the directory of `--export-segments`.
+pub fn write_segments(
+    args: &AnalyseArgs,
+    file: &Path,
+    input: &mut Input,
+    results: &Results,
+) -> Result<()> {
+    let Some(dir) = args.export_segments.as_ref() else {
+        return Ok(());
+    };
+    if args.segment_padding < 0.0 || !args.segment_padding.is_finite() {
+        return Err(Error::Config(
+            "--segment-padding has to be 0 or more seconds".to_string(),
+        ));
+    }
+
+    fs::create_dir_all(dir).map_err(|err| Error::io("create", dir, err))?;
+
+    let spec = input.source_spec();
+    let stem = file
+        .file_stem()
+        .map_or("input".into(), |stem| stem.to_string_lossy());
+    // Findings are at the analysis rate and start at --from-end, the input is read as it is
+    let scale = results.sample_rate as f64 / results.analysis_sample_rate as f64;
+    let to_frame = |frame: usize| input.start_frame() + (frame as f64 * scale).round() as usize;
+    let padding = (args.segment_padding * spec.sample_rate as f64) as usize;
+
+    let segments: Vec<_> = findings(results)
+        .into_iter()
+        .filter(|finding| {
+            matches!(
+                finding.kind,
+                Kind::Silence | Kind::Underrun | Kind::StuckSample | Kind::Clipping
+            )
+        })
+        .map(|finding| {
+            (
+                finding.kind,
+                finding.channel,
+                to_frame(finding.start),
+                to_frame(finding.end),
+            )
+        })
+        .collect();
+
+    for (kind, channel, start, end) in &segments {
+        let channel = channel.map_or(String::new(), |channel| format!("-ch{channel}"));
+        let name = format!(
+            "{stem}-{}{channel}-{}.wav",
+            kind.as_str(),
+            file_time(*start, spec.sample_rate)
+        );
+        let path = dir.join(name);
+
+        let frames = input.read_frames(start.saturating_sub(padding)..end + padding)?;
+        wavers::write(&path, &frames, spec.sample_rate, spec.channels).map_err(
+            |err| match err {
+                WaversError::IoError(source) => Error::io("write segment to", &path, source),
+                err => Error::Config(format!("Could not write {}: {err}", path.display())),
+            },
+        )?;
+    }
+
+    output!("Wrote {} segments to {}", segments.len(), dir.display());
+
+    Ok(())
+}
+
+/// `HH-MM-SS.mmm` of `frame`, for file names.
+fn file_time(frame: usize, sample_rate: i32) -> String {
+    let millis = frame as u64 * 1000 / sample_rate.max(1) as u64;
+
+    format!(
+        "{:02}-{:02}-{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
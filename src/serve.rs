@@ -0,0 +1,600 @@
+//! HTTP server that analyses uploaded WAV files, for `analwave serve`.
+//!
+//! - `GET /healthz` reports that the server is up, with the number of queued and running jobs
+//! - `POST /analyse` analyses the request and answers with the report
+//! - `POST /jobs` queues the request and answers with the job id right away
+//! - `GET /jobs/<id>` answers with the state of a job, and its report once it is done
+//! - `DELETE /jobs/<id>` forgets a finished job
+//! - `GET /metrics` answers with the totals of all analyses in the Prometheus text format
+//!
+//! The body of a request is either the WAV file, or with `--allow-paths` a JSON object
+//! `{"path": "..."}` naming a file on the server.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json, to_value};
+
+use crate::{
+    Error, Result,
+    cli::{AnalyseArgs, ServeArgs},
+    error,
+    error::EXIT_ANALYSER,
+    input::Input,
+    json::{Results, to_json_value},
+    metrics::{self, FileMetrics, MetricsTarget, ServerMetrics},
+    output,
+    output::init_messages,
+    pipeline, warn,
+};
+
+/// Longest accepted request line or header.
+const MAX_LINE_BYTES: u64 = 8192;
+
+/// Most headers accepted in one request.
+const MAX_HEADERS: usize = 100;
+
+/// What a request asks to analyse.
+enum Source {
+    Upload(Vec<u8>),
+    Path(PathBuf),
+}
+
+#[derive(Deserialize)]
+struct PathRequest {
+    path: PathBuf,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+struct Job {
+    id: u64,
+    status: Status,
+    /// Return code bits of a finished analysis, or the exit code of its error
+    #[serde(rename = "exitCode", skip_serializing_if = "Option::is_none")]
+    exit_code: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    report: Option<Value>,
+}
+
+impl Job {
+    fn finish(&mut self, result: Result<(u8, Value)>) {
+        match result {
+            Ok((exit_code, report)) => {
+                self.status = Status::Done;
+                self.exit_code = Some(exit_code);
+                self.report = Some(report);
+            }
+            Err(err) => {
+                self.status = Status::Failed;
+                self.exit_code = Some(err.exit_code());
+                self.error = Some(err.to_string());
+            }
+        }
+    }
+}
+
+/// Jobs by id, and the queue the workers take them from.
+struct Jobs {
+    jobs: Mutex<HashMap<u64, Job>>,
+    next_id: Mutex<u64>,
+    queue: Mutex<Sender<(u64, Source)>>,
+}
+
+impl Jobs {
+    fn submit(&self, source: Source) -> Job {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        let job = Job {
+            id,
+            status: Status::Queued,
+            exit_code: None,
+            error: None,
+            report: None,
+        };
+
+        self.jobs.lock().unwrap().insert(id, job.clone());
+        // The workers only stop with the server
+        let _ = self.queue.lock().unwrap().send((id, source));
+
+        job
+    }
+
+    fn get(&self, id: u64) -> Option<Job> {
+        self.jobs.lock().unwrap().get(&id).cloned()
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            f(job);
+        }
+    }
+
+    /// Removes a finished job and returns it.
+    fn remove(&self, id: u64) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        match jobs.get(&id).map(|job| job.status) {
+            Some(Status::Done | Status::Failed) => jobs.remove(&id),
+            _ => None,
+        }
+    }
+
+    fn count(&self, status: Status) -> usize {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| job.status == status)
+            .count()
+    }
+}
+
+fn worker(server: Arc<Server>, queue: Arc<Mutex<Receiver<(u64, Source)>>>) {
+    loop {
+        let Ok((id, source)) = queue.lock().unwrap().recv() else {
+            return;
+        };
+
+        server.jobs.update(id, |job| job.status = Status::Running);
+        let result = server.analyse(source);
+        if let Err(err) = &result {
+            error!("[job {id}] {err}");
+        }
+        server.jobs.update(id, |job| job.finish(result));
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    content_type: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+
+    fn metrics(text: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: text,
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::new(status, json!({ "error": message.into() }))
+    }
+
+    /// An analysis error, with the exit code the command line tool would exit with.
+    fn failed(err: &Error) -> Self {
+        let status = if err.exit_code() == EXIT_ANALYSER {
+            500
+        } else {
+            400
+        };
+
+        Self::new(
+            status,
+            json!({ "error": err.to_string(), "exitCode": err.exit_code() }),
+        )
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            202 => "Accepted",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// One line of the request head, without the line break.
+fn read_line(reader: &mut impl BufRead) -> std::io::Result<String> {
+    let mut line = String::new();
+    reader.take(MAX_LINE_BYTES).read_line(&mut line)?;
+
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Reads a request, or returns the response that rejects it.
+fn read_request(stream: &TcpStream, max_body: u64) -> Result<Request, Response> {
+    let bad_request = |_| Response::error(400, "Malformed request");
+    let mut reader = BufReader::new(stream);
+
+    let request_line = read_line(&mut reader).map_err(bad_request)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(Response::error(400, "Malformed request line"));
+    };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut content_length = None;
+    let mut content_type = String::new();
+    for _ in 0..MAX_HEADERS {
+        let line = read_line(&mut reader).map_err(bad_request)?;
+        if line.is_empty() {
+            break;
+        }
+
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(Response::error(400, "Malformed header"));
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => {
+                content_length = Some(
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| Response::error(400, "Invalid Content-Length"))?,
+                )
+            }
+            "content-type" => content_type = value.to_ascii_lowercase(),
+            "transfer-encoding" => {
+                return Err(Response::error(411, "Chunked uploads are not supported"));
+            }
+            _ => {}
+        }
+    }
+
+    let needs_body = method == "POST";
+    let length = match content_length {
+        Some(length) if length > max_body => {
+            return Err(Response::error(
+                413,
+                "Upload is larger than --max-upload-mb",
+            ));
+        }
+        Some(length) => length,
+        None if needs_body => return Err(Response::error(411, "Content-Length is required")),
+        None => 0,
+    };
+
+    let mut body = Vec::with_capacity(length as usize);
+    reader
+        .take(length)
+        .read_to_end(&mut body)
+        .map_err(bad_request)?;
+    if (body.len() as u64) < length {
+        return Err(Response::error(
+            400,
+            "Request body is shorter than Content-Length",
+        ));
+    }
+
+    Ok(Request {
+        method: method.to_string(),
+        path,
+        content_type,
+        body,
+    })
+}
+
+fn write_response(mut stream: &TcpStream, response: &Response) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+
+    stream.flush()
+}
+
+struct Server {
+    allow_paths: bool,
+    args: AnalyseArgs,
+    jobs: Jobs,
+    max_body: u64,
+    metrics: Mutex<ServerMetrics>,
+    /// File the metrics are rewritten to after every analysis
+    metrics_file: Option<PathBuf>,
+}
+
+impl Server {
+    /// Runs the configured analysers on one file, returns the return code and the report.
+    fn analyse(&self, source: Source) -> Result<(u8, Value)> {
+        let start = Instant::now();
+        let result = self.run(source);
+
+        let mut totals = self.metrics.lock().unwrap();
+        match &result {
+            Ok((exit_code, results)) => totals.add(FileMetrics::new(
+                &self.args,
+                results,
+                *exit_code,
+                start.elapsed(),
+            )),
+            Err(_) => totals.add_failure(),
+        }
+
+        if let Some(path) = &self.metrics_file {
+            let text = totals.render(
+                self.jobs.count(Status::Queued),
+                self.jobs.count(Status::Running),
+            );
+            if let Err(err) = metrics::write(path, &text) {
+                warn!("{err}");
+            }
+        }
+        drop(totals);
+
+        let (exit_code, results) = result?;
+        let report = to_json_value(&results).map_err(|err| Error::analyser("json", err))?;
+
+        Ok((exit_code, report))
+    }
+
+    fn run(&self, source: Source) -> Result<(u8, Results)> {
+        // Uploads are named like in the errors of their decoding
+        let (mut input, path) = match source {
+            Source::Upload(bytes) => (
+                Input::from_bytes(&self.args, bytes)?,
+                PathBuf::from("input"),
+            ),
+            Source::Path(path) => (Input::open(&self.args, &path)?, path),
+        };
+        let analysers = pipeline::analysers(&self.args, &input.spec())?;
+
+        let (exit_code, results) = pipeline::run(&self.args, &mut input, analysers)?;
+        pipeline::check_input(&self.args, &path, &results)?;
+
+        Ok((exit_code, results))
+    }
+
+    fn metrics(&self) -> Response {
+        Response::metrics(self.metrics.lock().unwrap().render(
+            self.jobs.count(Status::Queued),
+            self.jobs.count(Status::Running),
+        ))
+    }
+
+    /// The file a request asks to analyse.
+    fn source(&self, request: Request) -> Result<Source, Response> {
+        if !request.content_type.starts_with("application/json") {
+            return Ok(Source::Upload(request.body));
+        }
+
+        if !self.allow_paths {
+            return Err(Response::error(
+                400,
+                "Paths are only accepted with --allow-paths, upload the WAV file instead",
+            ));
+        }
+
+        serde_json::from_slice::<PathRequest>(&request.body)
+            .map(|request| Source::Path(request.path))
+            .map_err(|err| Response::error(400, format!("Invalid request: {err}")))
+    }
+
+    fn job(&self, method: &str, id: &str) -> Response {
+        let Ok(id) = id.parse::<u64>() else {
+            return Response::error(404, "No such job");
+        };
+
+        let job = match method {
+            "GET" => self.jobs.get(id),
+            "DELETE" => match self.jobs.get(id) {
+                Some(job) if matches!(job.status, Status::Queued | Status::Running) => {
+                    return Response::error(409, "The job hasn't finished yet");
+                }
+                _ => self.jobs.remove(id),
+            },
+            _ => return Response::error(405, "Use GET or DELETE"),
+        };
+
+        match job {
+            Some(job) => Response::new(200, to_value(job).unwrap_or_default()),
+            None => Response::error(404, "No such job"),
+        }
+    }
+
+    fn handle(&self, request: Request) -> Response {
+        let method = request.method.clone();
+        let path = request.path.clone();
+
+        match (method.as_str(), path.as_str()) {
+            ("GET", "/healthz") => Response::new(
+                200,
+                json!({
+                    "status": "ok",
+                    "queued": self.jobs.count(Status::Queued),
+                    "running": self.jobs.count(Status::Running),
+                }),
+            ),
+            ("POST", "/analyse") => {
+                let source = match self.source(request) {
+                    Ok(source) => source,
+                    Err(response) => return response,
+                };
+
+                match self.analyse(source) {
+                    Ok((exit_code, report)) => Response::new(
+                        200,
+                        json!({ "status": Status::Done, "exitCode": exit_code, "report": report }),
+                    ),
+                    Err(err) => Response::failed(&err),
+                }
+            }
+            ("GET", "/metrics") => self.metrics(),
+            ("POST", "/jobs") => match self.source(request) {
+                Ok(source) => {
+                    Response::new(202, to_value(self.jobs.submit(source)).unwrap_or_default())
+                }
+                Err(response) => response,
+            },
+            (_, "/healthz" | "/analyse" | "/jobs" | "/metrics") => {
+                Response::error(405, "Method not allowed")
+            }
+            (method, path) => match path.strip_prefix("/jobs/") {
+                Some(id) => self.job(method, id),
+                None => Response::error(404, "Not found"),
+            },
+        }
+    }
+
+    /// Answers one connection, with only `GET /metrics` on the `--metrics` port.
+    fn connection(&self, stream: TcpStream, metrics_only: bool) {
+        let response = match read_request(&stream, self.max_body) {
+            Ok(request) => {
+                let line = format!("{} {}", request.method, request.path);
+                let response = match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/metrics") => self.metrics(),
+                    _ if metrics_only => Response::error(404, "Not found"),
+                    _ => self.handle(request),
+                };
+                output!("{} {}", line, response.status);
+                response
+            }
+            Err(response) => response,
+        };
+
+        // The client may have gone already
+        let _ = write_response(&stream, &response);
+    }
+}
+
+/// Serves the analysis until the process is stopped.
+pub fn serve(args: &ServeArgs) -> Result<()> {
+    // The metrics are the server's, the analyses only get the other options
+    let mut analyse_args = args.analyse.clone();
+    let (metrics_port, metrics_file) = match analyse_args.metrics.take() {
+        Some(MetricsTarget::Port(port)) => (Some(port), None),
+        Some(MetricsTarget::File(path)) => (None, Some(path)),
+        None => (None, None),
+    };
+    // The requests are analysed without a progress bar, the output is only set up for the
+    // messages, so --quiet and --silent apply to the server too
+    init_messages(&analyse_args);
+
+    if let Some(option) = analyse_args.file_output() {
+        return Err(Error::Config(format!(
+            "{option} writes a file and can't be used with serve"
+        )));
+    }
+
+    if !analyse_args.input.is_empty() {
+        return Err(Error::Config(
+            "serve takes its input from the requests, not --input".to_string(),
+        ));
+    }
+
+    if args.workers == 0 {
+        return Err(Error::Config("--workers has to be at least 1".to_string()));
+    }
+
+    // Check the options once, so a bad configuration doesn't fail every request
+    let spec = crate::stream::StreamSpec {
+        sample_rate: 48000,
+        channels: 2,
+        num_frames: 0,
+        channel_mask: None,
+        bits_per_sample: None,
+    };
+    pipeline::analysers(&analyse_args, &spec)?;
+
+    let listener = bind(args.listen)?;
+    let metrics_listener = metrics_port
+        .map(|port| bind(SocketAddr::new(args.listen.ip(), port)))
+        .transpose()?;
+
+    let (sender, receiver) = mpsc::channel();
+    let queue = Arc::new(Mutex::new(receiver));
+    let server = Arc::new(Server {
+        allow_paths: args.allow_paths,
+        args: analyse_args,
+        jobs: Jobs {
+            jobs: Mutex::new(HashMap::new()),
+            next_id: Mutex::new(0),
+            queue: Mutex::new(sender),
+        },
+        max_body: args.max_upload_mb * 1024 * 1024,
+        metrics: Mutex::new(ServerMetrics::default()),
+        metrics_file,
+    });
+
+    for _ in 0..args.workers {
+        let (server, queue) = (server.clone(), queue.clone());
+        thread::spawn(move || worker(server, queue));
+    }
+
+    output!("[+] listening on:       http://{}", args.listen);
+    output!("[+] workers:            {}", args.workers);
+
+    if let Some(listener) = metrics_listener {
+        if let Ok(address) = listener.local_addr() {
+            output!("[+] metrics on:         http://{address}/metrics");
+        }
+
+        let server = server.clone();
+        thread::spawn(move || accept(server, listener, true));
+    }
+    if let Some(path) = &server.metrics_file {
+        output!("[+] metrics file:       {}", path.display());
+    }
+
+    accept(server, listener, false);
+
+    Ok(())
+}
+
+fn bind(address: SocketAddr) -> Result<TcpListener> {
+    TcpListener::bind(address).map_err(|err| Error::io("listen on", address.to_string(), err))
+}
+
+/// Answers the connections to `listener` until the process is stopped.
+fn accept(server: Arc<Server>, listener: TcpListener, metrics_only: bool) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let server = server.clone();
+        thread::spawn(move || server.connection(stream, metrics_only));
+    }
+}
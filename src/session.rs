@@ -0,0 +1,133 @@
+//! Analysis of audio that isn't read from a file, e.g. from the ring buffer of a capture
+//! application, for as long as it runs.
+//!
+//! A [`Session`] runs the analysers the options enable on whatever is fed to it, returns the
+//! segments found so far after every chunk and the results once it is finalized:
+//!
+//! ```no_run
+//! use analwave::{cli::AnalyseArgs, session::Session};
+//!
+//! let args = AnalyseArgs::parse_options(["--underrun", "--silence"])?;
+//! let mut session = Session::new(&args, 48000, 2)?;
+//!
+//! for chunk in [vec![0; 9600], vec![1 << 20; 9600]] {
+//!     for event in session.feed(&chunk)? {
+//!         println!("{} on {:?} at {}", event.kind.label(), event.channel, event.start);
+//!     }
+//! }
+//!
+//! let (return_code, results) = session.finalize()?;
+//! # Ok::<(), analwave::Error>(())
+//! ```
+
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    findings::{Finding, Summary},
+    json::{Results, stream_results},
+    layout::speaker_count,
+    output::TimeFormat,
+    pipeline,
+    stream::{Stream, StreamSpec},
+};
+
+/// Push API for live analysis: feed interleaved samples scaled to `i32`, poll the segments
+/// found and finalize for the results.
+///
+/// The length of the stream isn't known up front, so the options that need the whole file
+/// (output files, `--resample`, `--downmix`, `--gain-db` and `--from-end`) aren't available,
+/// and the analysers run on the thread that feeds them.
+pub struct Session {
+    stream: Stream,
+    summary: bool,
+    time_format: Option<TimeFormat>,
+}
+
+impl Session {
+    /// Sets up the analysers `args` enables for `channels` channels at `sample_rate`.
+    pub fn new(args: &AnalyseArgs, sample_rate: i32, channels: u16) -> Result<Self> {
+        if !args.input.is_empty() || !args.stems.is_empty() || args.batch_summary.is_some() {
+            return Err(Error::Config(
+                "--input, --stems and --batch-summary are only available on the command line"
+                    .to_string(),
+            ));
+        }
+
+        let whole_file = [
+            ("--resample", args.resample.is_some()),
+            ("--downmix", args.downmix.is_some()),
+            ("--gain-db", args.gain_db.is_some()),
+            ("--from-end", args.from_end.is_some()),
+        ]
+        .into_iter()
+        .find(|(_, used)| *used)
+        .map(|(option, _)| option)
+        .or(args.file_output());
+        if let Some(option) = whole_file {
+            return Err(Error::Config(format!(
+                "{option} needs the whole input and isn't available in a session"
+            )));
+        }
+
+        let channel_mask = args.channel_layout.mask();
+        if let Some(mask) = channel_mask
+            && speaker_count(mask) != channels as usize
+        {
+            return Err(Error::Config(format!(
+                "--channel-layout {} needs {} channels, the session has {}",
+                args.channel_layout.as_str(),
+                speaker_count(mask),
+                channels
+            )));
+        }
+
+        // Runs until it is finalized, the analysers are told how far it got then
+        let spec = StreamSpec {
+            sample_rate,
+            channels,
+            num_frames: usize::MAX,
+            channel_mask,
+            bits_per_sample: None,
+        };
+        let mut stream = Stream::new(spec, pipeline::analysers(args, &spec)?);
+        stream.begin(spec)?;
+
+        Ok(Self {
+            stream,
+            summary: args.summary,
+            time_format: args.json_time.then_some(args.time_format),
+        })
+    }
+
+    /// Analyses interleaved frames and returns the segments that ended since the last call,
+    /// in frames since the start of the session.
+    pub fn feed(&mut self, samples: &[i32]) -> Result<Vec<Finding>> {
+        self.stream.push(samples)?;
+
+        Ok(self.stream.take_events())
+    }
+
+    /// Number of frames fed so far.
+    pub fn position(&self) -> usize {
+        self.stream.position()
+    }
+
+    /// Ends the analysis at the frames fed so far and returns the results with the combined
+    /// return code, as the JSON output of a file of that length would have them.
+    pub fn finalize(mut self) -> Result<(u8, Results)> {
+        let return_code = self.stream.stop()?;
+        let spec = StreamSpec {
+            num_frames: self.stream.position(),
+            ..*self.stream.spec()
+        };
+
+        let analysis = self.stream.take_results();
+        let mut results = stream_results(&spec, self.stream.analysers(), analysis);
+        if self.summary {
+            results.summary = Some(Summary::new(&results));
+        }
+        results.time_format = self.time_format;
+
+        Ok((return_code, results))
+    }
+}
@@ -0,0 +1,66 @@
+//! The single line `--status-line` prints for every file, `RESULT` and then `key=value` fields
+//! in logfmt, so shell pipelines can grep for it instead of parsing the log or the JSON output.
+
+use std::{fmt::Write, path::Path, time::Duration};
+
+use crate::{Result, cli::AnalyseArgs, json::Results, metrics::FileMetrics};
+
+/// Prints the status line of `file` with `--status-line`, also with `--quiet` or `--silent`.
+pub fn print_status_line(args: &AnalyseArgs, file: &Path, result: &Result<(u8, Results)>) {
+    if args.status_line {
+        println!("{}", status_line(args, file, result));
+    }
+}
+
+/// `RESULT file=x.wav code=3 silence=4.2% underruns=7 lufs_i=-21.3`, with the measurements
+/// of the analysers that ran, or the error the analysis failed with.
+pub fn status_line(args: &AnalyseArgs, file: &Path, result: &Result<(u8, Results)>) -> String {
+    let mut line = format!("RESULT file={}", value(&file.display().to_string()));
+
+    match result {
+        Ok((exit_code, results)) => {
+            let metrics = FileMetrics::new(args, results, *exit_code, Duration::ZERO);
+
+            let _ = write!(line, " code={exit_code}");
+            if let Some(ratio) = metrics.silence_ratio {
+                let _ = write!(line, " silence={:.1}%", ratio * 100.0);
+            }
+            if let Some(count) = metrics.underruns {
+                let _ = write!(line, " underruns={count}");
+            }
+            if let Some(loudness) = metrics.loudness {
+                let _ = write!(line, " lufs_i={loudness:.1}");
+            }
+            if let Some(clipped) = metrics.clipped {
+                let _ = write!(line, " clipped={clipped}");
+            }
+            if results.partial {
+                line.push_str(" partial=true");
+            }
+        }
+        Err(err) => {
+            let _ = write!(
+                line,
+                " code={} error={}",
+                err.exit_code(),
+                value(&err.to_string())
+            );
+        }
+    }
+
+    line
+}
+
+/// `text` as a logfmt value, quoted if it has spaces, quotes or `=` in it.
+fn value(text: &str) -> String {
+    if !text.is_empty() && !text.contains([' ', '"', '=', '\t', '\n']) {
+        return text.to_string();
+    }
+
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
@@ -0,0 +1,128 @@
+//! Cross-file checks for `--stems`, the files of one multitrack session that are meant to
+//! line up sample for sample when imported side by side.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::{Result, inspect::inspect, warn};
+
+#[derive(Serialize)]
+pub struct Stem {
+    pub input: String,
+    #[serde(rename = "sampleRate")]
+    pub sample_rate: u32,
+    pub channels: u16,
+    #[serde(rename = "bitsPerSample")]
+    pub bits_per_sample: u16,
+    #[serde(rename = "numFrames")]
+    pub num_frames: u64,
+    /// Duration in seconds
+    pub duration: f64,
+    /// Position of the first sample in the timeline of the session in samples, from the
+    /// `bext` chunk, `null` if the file has none
+    #[serde(rename = "timeReference")]
+    pub time_reference: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct Stems {
+    pub stems: Vec<Stem>,
+    /// All stems have the same number of frames
+    #[serde(rename = "equalLengths")]
+    pub equal_lengths: bool,
+    #[serde(rename = "equalSampleRates")]
+    pub equal_sample_rates: bool,
+    /// All stems start at the same time reference, files without one start at zero
+    #[serde(rename = "alignedStarts")]
+    pub aligned_starts: bool,
+    /// Frames between the shortest and the longest stem
+    #[serde(rename = "lengthDifference")]
+    pub length_difference: u64,
+}
+
+fn all_equal<T: PartialEq>(mut values: impl Iterator<Item = T>) -> bool {
+    let first = values.next();
+    values.all(|value| Some(value) == first)
+}
+
+impl Stems {
+    /// Reads the headers of the stems and compares them.
+    pub fn inspect(stems: &[PathBuf]) -> Result<Self> {
+        let stems = stems
+            .iter()
+            .map(|path| {
+                let inspection = inspect(path)?;
+
+                Ok(Stem {
+                    input: path.display().to_string(),
+                    sample_rate: inspection.sample_rate,
+                    channels: inspection.channels,
+                    bits_per_sample: inspection.bits_per_sample,
+                    num_frames: inspection.num_frames,
+                    duration: inspection.duration,
+                    time_reference: inspection.bext.map(|bext| bext.time_reference),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let frames = stems.iter().map(|stem| stem.num_frames);
+        let length_difference = frames.clone().max().unwrap_or(0) - frames.min().unwrap_or(0);
+
+        Ok(Self {
+            equal_lengths: length_difference == 0,
+            equal_sample_rates: all_equal(stems.iter().map(|stem| stem.sample_rate)),
+            aligned_starts: all_equal(stems.iter().map(|stem| stem.time_reference.unwrap_or(0))),
+            length_difference,
+            stems,
+        })
+    }
+
+    pub fn consistent(&self) -> bool {
+        self.equal_lengths && self.equal_sample_rates && self.aligned_starts
+    }
+
+    /// The exit code bit for a session whose stems don't line up.
+    pub fn exit_code(&self) -> u8 {
+        if self.consistent() {
+            0
+        } else {
+            crate::ERR_CHANNEL_IMBALANCE
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "[+] session:          {} stems, {}",
+            self.stems.len(),
+            if self.consistent() {
+                "consistent"
+            } else {
+                "inconsistent"
+            }
+        );
+
+        if !self.equal_sample_rates {
+            let rates: Vec<String> = self
+                .stems
+                .iter()
+                .map(|stem| stem.sample_rate.to_string())
+                .collect();
+            warn!("stems have different sample rates: {}", rates.join(", "));
+        }
+        if !self.equal_lengths {
+            warn!(
+                "stems differ in length by up to {} frames",
+                self.length_difference
+            );
+        }
+        if !self.aligned_starts {
+            let starts: Vec<String> = self
+                .stems
+                .iter()
+                .map(|stem| stem.time_reference.unwrap_or(0).to_string())
+                .collect();
+            warn!("stems start at different times: {}", starts.join(", "));
+        }
+    }
+}
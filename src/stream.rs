@@ -0,0 +1,416 @@
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use wavers::{FormatCode, Wav};
+
+use crate::{
+    Result,
+    analysers::{Analyser, AnalysisResult},
+    findings::Finding,
+    output::fmt_frame,
+};
+
+mod workers;
+
+use workers::Workers;
+
+/// Format of the interleaved sample stream fed to the analysers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSpec {
+    pub sample_rate: i32,
+    pub channels: u16,
+    /// Number of frames (one sample per channel) the stream will deliver.
+    pub num_frames: usize,
+    /// Speakers of the channels as a WAVE_FORMAT_EXTENSIBLE channel mask, if known.
+    pub channel_mask: Option<u32>,
+    /// Bits per sample the file declares for integer PCM, `None` for floats or if unknown.
+    /// The samples are always scaled to `i32`.
+    pub bits_per_sample: Option<u16>,
+}
+
+impl StreamSpec {
+    pub fn from_wav(wav: &Wav<i32>) -> Self {
+        let channels = wav.n_channels();
+        let fmt = wav.get_fmt_chunk();
+        let channel_mask = (fmt.format == FormatCode::WAVE_FORMAT_EXTENSIBLE)
+            .then(|| fmt.ext_fmt_chunk.channel_mask())
+            .filter(|&mask| mask != 0);
+
+        Self {
+            sample_rate: wav.sample_rate(),
+            channels,
+            num_frames: wav.n_samples() / channels.max(1) as usize,
+            channel_mask,
+            bits_per_sample: (wav.format().1 != FormatCode::WAV_FORMAT_IEEE_FLOAT)
+                .then_some(fmt.bits_per_sample),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+    /// `push` or `finish` was called before `begin`.
+    NotStarted,
+    /// `begin` was called on a stream that is already running.
+    AlreadyStarted,
+    /// The stream was used after `finish`.
+    Finished,
+    /// The spec has no channels or a non-positive sample rate.
+    InvalidSpec(StreamSpec),
+    /// The producer's spec differs from the one the analysers were configured for.
+    SpecMismatch {
+        expected: StreamSpec,
+        actual: StreamSpec,
+    },
+    /// A pushed chunk does not contain a whole number of frames.
+    PartialFrame { channels: u16, len: usize },
+    /// More frames were pushed than the spec announced.
+    TooManyFrames { expected: usize, actual: usize },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotStarted => write!(f, "stream has not been started with begin()"),
+            Self::AlreadyStarted => write!(f, "stream has already been started"),
+            Self::Finished => write!(f, "stream has already been finished"),
+            Self::InvalidSpec(spec) => write!(
+                f,
+                "invalid stream spec: {} channels @ {} Hz",
+                spec.channels, spec.sample_rate
+            ),
+            Self::SpecMismatch { expected, actual } => write!(
+                f,
+                "stream spec mismatch: expected {} channels @ {} Hz ({} frames), got {} channels @ {} Hz ({} frames)",
+                expected.channels,
+                expected.sample_rate,
+                expected.num_frames,
+                actual.channels,
+                actual.sample_rate,
+                actual.num_frames
+            ),
+            Self::PartialFrame { channels, len } => write!(
+                f,
+                "chunk of {len} samples is not a multiple of {channels} channels"
+            ),
+            Self::TooManyFrames { expected, actual } => write!(
+                f,
+                "pushed {actual} frames but the stream was announced with {expected}"
+            ),
+        }
+    }
+}
+
+impl Error for StreamError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Streaming,
+    Finished,
+}
+
+/// Push API driving a set of analysers with interleaved frames.
+///
+/// The producer negotiates its format with [`Stream::begin`], then pushes chunks of whole
+/// frames with [`Stream::push`] and collects the return code from [`Stream::finish`], with
+/// the results of each analyser in [`Stream::results`].
+///
+/// With more than one thread the analysers are spread over worker threads that receive
+/// shared blocks of frames through bounded channels, so the stream runs at the speed of
+/// the slowest worker rather than the sum of all analysers.
+///
+/// With timings enabled each analyser runs over a whole pushed chunk before the next one
+/// starts, so console messages of different analysers within a chunk aren't in time order.
+pub struct Stream {
+    spec: StreamSpec,
+    analysers: Vec<Box<dyn Analyser>>,
+    threads: usize,
+    workers: Option<Workers>,
+    state: State,
+    frame_counter: usize,
+    digits: usize,
+    timings: Option<Vec<Duration>>,
+    results: Vec<AnalysisResult>,
+}
+
+impl Stream {
+    pub fn new(spec: StreamSpec, analysers: Vec<Box<dyn Analyser>>) -> Self {
+        Self {
+            spec,
+            analysers,
+            threads: 1,
+            workers: None,
+            state: State::Idle,
+            frame_counter: 0,
+            digits: spec.num_frames.to_string().len(),
+            timings: None,
+            results: Vec::new(),
+        }
+    }
+
+    /// Run the analysers on up to `threads` worker threads.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Measure the time spent in each analyser.
+    pub fn with_timings(mut self, enabled: bool) -> Self {
+        self.timings = enabled.then(|| vec![Duration::ZERO; self.analysers.len()]);
+        self
+    }
+
+    pub fn spec(&self) -> &StreamSpec {
+        &self.spec
+    }
+
+    /// The analysers of this stream, empty while they are owned by worker threads.
+    pub fn analysers(&self) -> &[Box<dyn Analyser>] {
+        &self.analysers
+    }
+
+    /// Time spent in each analyser, in the order of [`Stream::analysers`], if timings are
+    /// enabled.
+    pub fn timings(&self) -> Option<&[Duration]> {
+        self.timings.as_deref()
+    }
+
+    /// Results of each analyser, in the order of [`Stream::analysers`], once the stream is
+    /// finished. Analysers that failed have empty results.
+    pub fn results(&self) -> &[AnalysisResult] {
+        &self.results
+    }
+
+    /// Takes the results out of the finished stream.
+    pub fn take_results(&mut self) -> Vec<AnalysisResult> {
+        std::mem::take(&mut self.results)
+    }
+
+    /// Segments the analysers found that ended since the last call, in the order they start.
+    /// Always empty while the analysers run on worker threads.
+    pub fn take_events(&mut self) -> Vec<Finding> {
+        let mut events: Vec<Finding> = self
+            .analysers
+            .iter_mut()
+            .flat_map(|analyser| analyser.take_events())
+            .collect();
+        events.sort_by_key(|event| event.start);
+
+        events
+    }
+
+    /// Label of the current position, as in the console messages of the analysers.
+    pub fn label(&self) -> String {
+        fmt_frame(self.frame_counter, self.digits)
+    }
+
+    /// Number of frames pushed so far.
+    pub fn position(&self) -> usize {
+        self.frame_counter
+    }
+
+    pub fn begin(&mut self, spec: StreamSpec) -> Result<(), StreamError> {
+        match self.state {
+            State::Idle => {}
+            State::Streaming => return Err(StreamError::AlreadyStarted),
+            State::Finished => return Err(StreamError::Finished),
+        }
+
+        if spec.channels == 0 || spec.sample_rate <= 0 {
+            return Err(StreamError::InvalidSpec(spec));
+        }
+
+        if spec != self.spec {
+            return Err(StreamError::SpecMismatch {
+                expected: self.spec,
+                actual: spec,
+            });
+        }
+
+        if self.threads > 1 && self.analysers.len() > 1 {
+            self.workers = Some(Workers::spawn(
+                std::mem::take(&mut self.analysers),
+                self.threads,
+                spec.channels as usize,
+                self.digits,
+                self.timings.is_some(),
+            ));
+        }
+
+        self.state = State::Streaming;
+
+        Ok(())
+    }
+
+    /// Push one or more interleaved frames, returns the number of frames consumed.
+    pub fn push(&mut self, samples: &[i32]) -> Result<usize, StreamError> {
+        self.check_streaming()?;
+
+        let channels = self.spec.channels as usize;
+        if !samples.len().is_multiple_of(channels) {
+            return Err(StreamError::PartialFrame {
+                channels: self.spec.channels,
+                len: samples.len(),
+            });
+        }
+
+        let num_frames = samples.len() / channels;
+        if self.frame_counter + num_frames > self.spec.num_frames {
+            return Err(StreamError::TooManyFrames {
+                expected: self.spec.num_frames,
+                actual: self.frame_counter + num_frames,
+            });
+        }
+
+        if let Some(workers) = &mut self.workers {
+            workers.push(self.frame_counter, samples);
+            self.frame_counter += num_frames;
+
+            return Ok(num_frames);
+        }
+
+        if let Some(timings) = &mut self.timings {
+            let labels = frame_labels(self.frame_counter, num_frames, self.digits);
+
+            for (analyser, time) in self.analysers.iter_mut().zip(timings.iter_mut()) {
+                *time += analyse_timed(
+                    analyser.as_mut(),
+                    &labels,
+                    self.frame_counter,
+                    samples,
+                    channels,
+                );
+            }
+
+            self.frame_counter += num_frames;
+
+            return Ok(num_frames);
+        }
+
+        for frame in samples.chunks_exact(channels) {
+            let frame_label = fmt_frame(self.frame_counter, self.digits);
+
+            for analyser in self.analysers.iter_mut() {
+                analyser.analyse(&frame_label, self.frame_counter, frame);
+            }
+
+            self.frame_counter += 1;
+        }
+
+        Ok(num_frames)
+    }
+
+    /// Finish all analysers. A stream may end before all announced frames were pushed,
+    /// the analysers still report against the announced length.
+    ///
+    /// Every analyser is finished even if an earlier one fails, the first error is returned.
+    pub fn finish(&mut self) -> Result<u8> {
+        self.finish_at(None)
+    }
+
+    /// Finish all analysers at the frames pushed so far, for a stream that was stopped early.
+    /// Unlike [`Stream::finish`] the analysers report up to there instead of the announced
+    /// length.
+    pub fn stop(&mut self) -> Result<u8> {
+        self.finish_at(Some(self.frame_counter))
+    }
+
+    fn finish_at(&mut self, stopped: Option<usize>) -> Result<u8> {
+        self.check_streaming()?;
+
+        self.state = State::Finished;
+
+        if let Some(workers) = self.workers.take() {
+            let (analysers, timings, results, result) = workers.finish(stopped.is_some());
+            self.analysers = analysers;
+            self.results = results;
+            if self.timings.is_some() {
+                self.timings = Some(timings);
+            }
+
+            return result;
+        }
+
+        let frame_label = fmt_frame(self.frame_counter, self.digits);
+        let mut result = Ok(0);
+
+        for (index, analyser) in self.analysers.iter_mut().enumerate() {
+            // Only read the clock when timing, not every target has one
+            let start = self.timings.is_some().then(Instant::now);
+            if let Some(num_frames) = stopped {
+                analyser.stop(num_frames);
+            }
+            let finished = analyser.finish(&frame_label);
+            self.results.push(merge_result(&mut result, finished));
+
+            if let Some((time, start)) = self
+                .timings
+                .as_mut()
+                .and_then(|timings| timings.get_mut(index))
+                .zip(start)
+            {
+                *time += start.elapsed();
+            }
+        }
+
+        result
+    }
+
+    fn check_streaming(&self) -> Result<(), StreamError> {
+        match self.state {
+            State::Idle => Err(StreamError::NotStarted),
+            State::Streaming => Ok(()),
+            State::Finished => Err(StreamError::Finished),
+        }
+    }
+}
+
+/// Labels of `num_frames` frames starting at `start`.
+fn frame_labels(start: usize, num_frames: usize, digits: usize) -> Vec<String> {
+    (start..start + num_frames)
+        .map(|frame| fmt_frame(frame, digits))
+        .collect()
+}
+
+/// Runs one analyser over a chunk of whole frames starting at `start`, returns the time it
+/// took. Timing every frame would cost more than some analysers spend on it.
+fn analyse_timed(
+    analyser: &mut dyn Analyser,
+    labels: &[String],
+    start: usize,
+    samples: &[i32],
+    channels: usize,
+) -> Duration {
+    let begin = Instant::now();
+
+    for (offset, (frame, label)) in samples.chunks_exact(channels).zip(labels).enumerate() {
+        analyser.analyse(label, start + offset, frame);
+    }
+
+    begin.elapsed()
+}
+
+/// Adds the return code of one analyser to `result`, keeping the first error, and returns
+/// its results.
+fn merge_result(result: &mut Result<u8>, other: Result<AnalysisResult>) -> AnalysisResult {
+    match other {
+        Ok(other) => {
+            if let Ok(return_code) = result {
+                *return_code |= other.flags;
+            }
+
+            other
+        }
+        Err(err) => {
+            if result.is_ok() {
+                *result = Err(err);
+            }
+
+            AnalysisResult::default()
+        }
+    }
+}
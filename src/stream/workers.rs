@@ -0,0 +1,237 @@
+use std::{
+    panic,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{SyncSender, sync_channel},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use super::{analyse_timed, frame_labels, merge_result};
+use crate::{
+    Result,
+    analysers::{Analyser, AnalysisResult},
+    output::fmt_frame,
+};
+
+/// Frames per block handed to the worker threads.
+const BLOCK_FRAMES: usize = 4096;
+
+/// Blocks a worker may have queued before `push` waits for it to catch up.
+const QUEUE_DEPTH: usize = 4;
+
+struct Block {
+    start: usize,
+    samples: Vec<i32>,
+}
+
+/// Analysers owned by one worker, tagged with their position in the original list, the time
+/// spent in them and their results.
+type Group = Vec<(usize, Box<dyn Analyser>, Duration, AnalysisResult)>;
+
+/// The analysers in their original order, with the time spent in each, their results and
+/// the combined return code or the first error.
+type Finished = (
+    Vec<Box<dyn Analyser>>,
+    Vec<Duration>,
+    Vec<AnalysisResult>,
+    Result<u8>,
+);
+
+struct Worker {
+    sender: SyncSender<Arc<Block>>,
+    handle: JoinHandle<(Group, Result<u8>)>,
+}
+
+/// Runs groups of analysers on their own threads, fed with shared blocks of frames.
+pub struct Workers {
+    workers: Vec<Worker>,
+    channels: usize,
+    pending: Vec<i32>,
+    pending_start: usize,
+    /// Set when the stream was stopped early
+    stopped: Arc<AtomicBool>,
+}
+
+impl Workers {
+    pub fn spawn(
+        analysers: Vec<Box<dyn Analyser>>,
+        threads: usize,
+        channels: usize,
+        digits: usize,
+        timed: bool,
+    ) -> Self {
+        let num_workers = threads.min(analysers.len()).max(1);
+        let mut groups: Vec<Group> = (0..num_workers).map(|_| Vec::new()).collect();
+
+        for (index, analyser) in analysers.into_iter().enumerate() {
+            groups[index % num_workers].push((
+                index,
+                analyser,
+                Duration::ZERO,
+                AnalysisResult::default(),
+            ));
+        }
+
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let workers = groups
+            .into_iter()
+            .map(|group| {
+                let (sender, receiver) = sync_channel::<Arc<Block>>(QUEUE_DEPTH);
+                let stopped = stopped.clone();
+
+                let handle = thread::spawn(move || {
+                    let mut group = group;
+                    let mut end = 0;
+
+                    for block in receiver {
+                        let num_frames = block.samples.len() / channels;
+
+                        if timed {
+                            let labels = frame_labels(block.start, num_frames, digits);
+
+                            for (_, analyser, time, _) in group.iter_mut() {
+                                *time += analyse_timed(
+                                    analyser.as_mut(),
+                                    &labels,
+                                    block.start,
+                                    &block.samples,
+                                    channels,
+                                );
+                            }
+
+                            end = block.start + num_frames;
+                            continue;
+                        }
+
+                        for (offset, frame) in block.samples.chunks_exact(channels).enumerate() {
+                            let frame_counter = block.start + offset;
+                            let frame_label = fmt_frame(frame_counter, digits);
+
+                            for (_, analyser, _, _) in group.iter_mut() {
+                                analyser.analyse(&frame_label, frame_counter, frame);
+                            }
+                        }
+
+                        end = block.start + num_frames;
+                    }
+
+                    // The channel closes once the stream is finished
+                    let frame_label = fmt_frame(end, digits);
+                    let mut result = Ok(0);
+
+                    for (_, analyser, time, analysis) in group.iter_mut() {
+                        let start = Instant::now();
+                        if stopped.load(Ordering::Relaxed) {
+                            analyser.stop(end);
+                        }
+                        let finished = analyser.finish(&frame_label);
+                        *time += start.elapsed();
+
+                        *analysis = merge_result(&mut result, finished);
+                    }
+
+                    (group, result)
+                });
+
+                Worker { sender, handle }
+            })
+            .collect();
+
+        Self {
+            workers,
+            channels,
+            pending: Vec::with_capacity(BLOCK_FRAMES * channels),
+            pending_start: 0,
+            stopped,
+        }
+    }
+
+    /// Queue whole interleaved frames starting at `frame_counter`.
+    pub fn push(&mut self, frame_counter: usize, samples: &[i32]) {
+        if self.pending.is_empty() {
+            self.pending_start = frame_counter;
+        }
+
+        let block_len = BLOCK_FRAMES * self.channels;
+        let mut samples = samples;
+
+        while !samples.is_empty() {
+            let take = (block_len - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+
+            if self.pending.len() == block_len {
+                self.flush();
+            }
+        }
+    }
+
+    /// Send the remaining frames, wait for every worker to finish its analysers and
+    /// return them in their original order together with the time spent in each, their
+    /// results, and the combined return code or the first error of an analyser. If
+    /// `stopped`, the analysers report up to the last frame instead of the announced length.
+    pub fn finish(mut self, stopped: bool) -> Finished {
+        self.flush();
+        // Read by the workers once their channel is closed below
+        self.stopped.store(stopped, Ordering::Relaxed);
+
+        let mut analysers: Group = Vec::new();
+        let mut result = Ok(0);
+
+        for Worker { sender, handle } in self.workers {
+            drop(sender);
+
+            let (group, group_result) = handle
+                .join()
+                .unwrap_or_else(|payload| panic::resume_unwind(payload));
+            match (&mut result, group_result) {
+                (Ok(code), Ok(group_code)) => *code |= group_code,
+                (Ok(_), Err(err)) => result = Err(err),
+                _ => {}
+            }
+            analysers.extend(group);
+        }
+        analysers.sort_by_key(|(index, ..)| *index);
+
+        let mut finished = (Vec::new(), Vec::new(), Vec::new(), result);
+        for (_, analyser, time, analysis) in analysers {
+            finished.0.push(analyser);
+            finished.1.push(time);
+            finished.2.push(analysis);
+        }
+
+        finished
+    }
+
+    fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let start = self.pending_start;
+        let block = Arc::new(Block {
+            start,
+            samples: std::mem::replace(
+                &mut self.pending,
+                Vec::with_capacity(BLOCK_FRAMES * self.channels),
+            ),
+        });
+        self.pending_start = start + block.samples.len() / self.channels;
+
+        let hung_up = self
+            .workers
+            .iter()
+            .position(|worker| worker.sender.send(block.clone()).is_err());
+
+        // A worker only hangs up when one of its analysers panicked
+        if let Some(index) = hung_up
+            && let Err(payload) = self.workers.swap_remove(index).handle.join()
+        {
+            panic::resume_unwind(payload);
+        }
+    }
+}
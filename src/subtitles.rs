@@ -0,0 +1,99 @@
+//! Silence, underruns, stuck samples and clipping as subtitles for `--srt`, so reviewers can
+//! scrub the media in any player with the findings overlaid. A `.vtt` file is written as
+//! WebVTT, any other as SubRip.
+
+use std::{fmt::Write as _, fs};
+
+use crate::{
+    Error, Result,
+    cli::AnalyseArgs,
+    findings::{Kind, findings},
+    json::Results,
+    output,
+};
+
+/// Findings shorter than this are shown for this long, so a player gets to show them.
+const MIN_CUE_SECONDS: f64 = 1.0;
+
+/// `seconds` as `hh:mm:ss` and milliseconds, hours may go past 99.
+fn timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// The text of a cue, e.g. "Underrun on CH:0 (0.250s)".
+fn text(kind: Kind, channel: Option<usize>, duration: f64) -> String {
+    let what = match kind {
+        Kind::StuckSample => "Stuck samples",
+        kind => kind.label(),
+    };
+    let channel = channel.map_or(String::new(), |channel| format!(" on CH:{channel}"));
+
+    format!("{what}{channel} ({duration:.3}s)")
+}
+
+/// The subtitles of the findings, as WebVTT or SubRip.
+fn subtitles(results: &Results, vtt: bool) -> (String, usize) {
+    let sample_rate = results.sample_rate as f64;
+    let (mut subtitles, separator) = if vtt {
+        ("WEBVTT\n".to_string(), '.')
+    } else {
+        (String::new(), ',')
+    };
+
+    let found: Vec<_> = findings(results)
+        .into_iter()
+        .filter(|finding| {
+            matches!(
+                finding.kind,
+                Kind::Silence | Kind::Underrun | Kind::StuckSample | Kind::Clipping
+            )
+        })
+        .collect();
+
+    for (number, finding) in (1..).zip(found.iter()) {
+        let start = results.file_frame(finding.start) as f64 / sample_rate;
+        let end = results.file_frame(finding.end) as f64 / sample_rate;
+
+        // Cues are separated by blank lines, and SubRip numbers them
+        if vtt || number > 1 {
+            subtitles.push('\n');
+        }
+        if !vtt {
+            let _ = writeln!(subtitles, "{number}");
+        }
+        let _ = writeln!(
+            subtitles,
+            "{} --> {}\n{}",
+            timestamp(start, separator),
+            timestamp(end.max(start + MIN_CUE_SECONDS), separator),
+            text(finding.kind, finding.channel, end - start)
+        );
+    }
+
+    (subtitles, found.len())
+}
+
+/// Writes the findings of the results to the `--srt` file.
+pub fn write_subtitles(args: &AnalyseArgs, results: &Results) -> Result<()> {
+    let Some(path) = args.srt.as_ref() else {
+        return Ok(());
+    };
+
+    let vtt = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("vtt"));
+    let (subtitles, cues) = subtitles(results, vtt);
+
+    fs::write(path, subtitles).map_err(|err| Error::io("write subtitles to", path, err))?;
+    output!("Wrote {} subtitles to {}", cues, path.display());
+
+    Ok(())
+}
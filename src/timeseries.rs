@@ -0,0 +1,403 @@
+//! The windowed series of the analysis as named arrays in one file for `--timeseries`, to
+//! load with NumPy or any Zarr reader instead of picking them out of the JSON output.
+//!
+//! A path ending in `.zarr` is written as a Zarr (v2) directory store, any other as an
+//! uncompressed `.npz` archive. Every array is little-endian f64 in C order, missing values
+//! (gated loudness, unvoiced pitch, novelty near the ends, windows without a delay) are NaN. The JSON output lists
+//! the arrays with the seconds between their points under `timeseries`.
+
+use std::{fs, path::Path};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    Error, Result,
+    analysers::{activity::ActivityChannel, alignment::AlignmentWindow, spectral::SpectralPoint},
+    cli::AnalyseArgs,
+    json::Results,
+    npy, output, warn,
+};
+
+/// An array of the time series file.
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct TimeSeriesArray {
+    pub name: String,
+    /// Length of each dimension, rows before points for 2-D arrays
+    pub shape: Vec<usize>,
+    /// Seconds between consecutive points
+    pub interval: f64,
+    /// What each row of a 2-D array is, `CH:n`, `ALL` for a meter over all channels or
+    /// `CH:a/CH:b` for a pair of channels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<Vec<String>>,
+    /// Array with the time of every point in seconds, for series that leave points out.
+    /// Point `i` of the others is at `i * interval`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+}
+
+/// Where the time series were written, with `--timeseries`.
+#[derive(Serialize, JsonSchema, Clone)]
+pub struct TimeSeriesIndex {
+    pub path: String,
+    /// `npz` or `zarr`
+    pub format: &'static str,
+    pub arrays: Vec<TimeSeriesArray>,
+}
+
+/// An array with its values, row after row.
+struct Series {
+    array: TimeSeriesArray,
+    values: Vec<f64>,
+}
+
+impl Series {
+    fn new(name: &str, interval: f64, values: Vec<f64>) -> Self {
+        Self {
+            array: TimeSeriesArray {
+                name: name.to_string(),
+                shape: vec![values.len()],
+                interval,
+                rows: None,
+                time: None,
+            },
+            values,
+        }
+    }
+
+    /// A 2-D array of rows of the same length, shorter rows are padded with NaN.
+    fn rows(name: &str, interval: f64, rows: Vec<(String, Vec<f64>)>) -> Self {
+        let length = rows.iter().map(|(_, row)| row.len()).max().unwrap_or(0);
+        let mut values = Vec::with_capacity(rows.len() * length);
+        for (_, row) in &rows {
+            values.extend(row);
+            values.resize(values.len() + length - row.len(), f64::NAN);
+        }
+
+        Self {
+            array: TimeSeriesArray {
+                name: name.to_string(),
+                shape: vec![rows.len(), length],
+                interval,
+                rows: Some(rows.into_iter().map(|(label, _)| label).collect()),
+                time: None,
+            },
+            values,
+        }
+    }
+
+    fn with_time(mut self, time: &str) -> Self {
+        self.array.time = Some(time.to_string());
+        self
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.values.len() * 8);
+        for value in &self.values {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+fn channel_label(channel: Option<usize>) -> String {
+    channel.map_or("ALL".to_string(), |channel| format!("CH:{channel}"))
+}
+
+/// The windowed series of the analysis, those of analysers that didn't run are left out.
+fn collect(results: &Results) -> Vec<Series> {
+    let analysis = &results.analysis;
+    let mut series = Vec::new();
+
+    if let Some(envelope) = analysis
+        .loudness
+        .as_ref()
+        .and_then(|loudness| loudness.envelope.as_ref())
+    {
+        let rows = envelope
+            .series
+            .iter()
+            .map(|meter| (channel_label(meter.channel), meter.loudness.clone()))
+            .collect();
+        series.push(Series::rows(
+            "loudness_envelope",
+            envelope.interval as f64,
+            rows,
+        ));
+    }
+
+    if let Some(activity) = &analysis.activity {
+        let interval = 1.0 / activity.rate;
+        let rows = |values: fn(&ActivityChannel) -> &Vec<f64>| {
+            activity
+                .channels
+                .iter()
+                .map(|channel| {
+                    (
+                        channel_label(Some(channel.channel)),
+                        values(channel).clone(),
+                    )
+                })
+                .collect()
+        };
+        series.push(Series::rows(
+            "activity_zero_crossing_rate",
+            interval,
+            rows(|channel| &channel.zero_crossing_rate),
+        ));
+        series.push(Series::rows(
+            "activity_activity",
+            interval,
+            rows(|channel| &channel.activity),
+        ));
+    }
+
+    if let Some(spectral) = &analysis.spectral {
+        let interval = 1.0 / spectral.rate;
+        let points = &spectral.results;
+        let values = |value: fn(&SpectralPoint) -> f64| points.iter().map(value).collect();
+        series.push(Series::new(
+            "spectral_time",
+            interval,
+            values(|point| point.time),
+        ));
+        for (name, value) in [
+            (
+                "spectral_centroid",
+                (|point| point.centroid) as fn(&SpectralPoint) -> f64,
+            ),
+            ("spectral_flatness", |point| point.flatness),
+            ("spectral_rolloff", |point| point.rolloff),
+            ("spectral_bandwidth", |point| point.bandwidth),
+        ] {
+            series.push(Series::new(name, interval, values(value)).with_time("spectral_time"));
+        }
+    }
+
+    if let Some(pitch) = &analysis.pitch {
+        let interval = 1.0 / pitch.rate;
+        series.push(Series::new(
+            "pitch_time",
+            interval,
+            pitch.results.iter().map(|point| point.time).collect(),
+        ));
+        series.push(
+            Series::new(
+                "pitch_frequency",
+                interval,
+                pitch
+                    .results
+                    .iter()
+                    .map(|point| point.frequency.unwrap_or(f64::NAN))
+                    .collect(),
+            )
+            .with_time("pitch_time"),
+        );
+    }
+
+    if let Some(novelty) = &analysis.novelty {
+        let interval = 1.0 / novelty.rate;
+        series.push(Series::new("novelty_flux", interval, novelty.flux.clone()));
+        series.push(Series::new(
+            "novelty_novelty",
+            interval,
+            novelty
+                .novelty
+                .iter()
+                .map(|value| value.unwrap_or(f64::NAN))
+                .collect(),
+        ));
+    }
+
+    if let Some(alignment) = &analysis.alignment {
+        let interval = alignment.window_size;
+        let rows = |value: fn(&AlignmentWindow) -> Option<f64>| {
+            alignment
+                .results
+                .iter()
+                .map(|result| {
+                    let [a, b] = result.channels;
+                    let values = result
+                        .windows
+                        .iter()
+                        .map(|window| value(window).unwrap_or(f64::NAN))
+                        .collect();
+                    (format!("CH:{a}/CH:{b}"), values)
+                })
+                .collect()
+        };
+        series.push(Series::rows(
+            "alignment_delay",
+            interval,
+            rows(|window| window.delay),
+        ));
+        series.push(Series::rows(
+            "alignment_correlation",
+            interval,
+            rows(|window| window.correlation),
+        ));
+    }
+
+    series
+}
+
+/// An uncompressed ZIP archive of `files`, as `numpy.load` reads `.npz` files.
+fn zip(files: &[(String, Vec<u8>)]) -> Option<Vec<u8>> {
+    // 1980-01-01, the earliest date ZIP can store
+    const DATE: u16 = (1 << 5) | 1;
+
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+
+    for (name, data) in files {
+        let offset = u32::try_from(archive.len()).ok()?;
+        let size = u32::try_from(data.len()).ok()?;
+        let crc = crc32fast::hash(data);
+
+        // Local file header: version, flags, stored, time, date, CRC, sizes, name and extra
+        // field lengths
+        archive.extend(0x0403_4b50u32.to_le_bytes());
+        for field in [20u16, 0, 0, 0, DATE] {
+            archive.extend(field.to_le_bytes());
+        }
+        for field in [crc, size, size] {
+            archive.extend(field.to_le_bytes());
+        }
+        archive.extend((name.len() as u16).to_le_bytes());
+        archive.extend(0u16.to_le_bytes());
+        archive.extend(name.as_bytes());
+        archive.extend(data);
+
+        // Central directory entry: the same plus comment, disk, attributes and the offset
+        directory.extend(0x0201_4b50u32.to_le_bytes());
+        for field in [20u16, 20, 0, 0, 0, DATE] {
+            directory.extend(field.to_le_bytes());
+        }
+        for field in [crc, size, size] {
+            directory.extend(field.to_le_bytes());
+        }
+        for field in [name.len() as u16, 0, 0, 0, 0] {
+            directory.extend(field.to_le_bytes());
+        }
+        directory.extend(0u32.to_le_bytes());
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+
+    let offset = u32::try_from(archive.len()).ok()?;
+    let entries = u16::try_from(files.len()).ok()?;
+    archive.extend(&directory);
+
+    // End of central directory
+    archive.extend(0x0605_4b50u32.to_le_bytes());
+    for field in [0u16, 0, entries, entries] {
+        archive.extend(field.to_le_bytes());
+    }
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(offset.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());
+
+    Some(archive)
+}
+
+fn write_npz(path: &Path, series: &[Series]) -> Result<()> {
+    let files: Vec<(String, Vec<u8>)> = series
+        .iter()
+        .map(|series| {
+            let mut data = npy::header("<f8", &series.array.shape);
+            data.extend(series.bytes());
+            (format!("{}.npy", series.array.name), data)
+        })
+        .collect();
+
+    let archive = zip(&files).ok_or_else(|| {
+        Error::Config(format!(
+            "the time series don't fit in an .npz file of 4 GB, write {} as .zarr",
+            path.display()
+        ))
+    })?;
+
+    fs::write(path, archive).map_err(|err| Error::io("write time series to", path, err))
+}
+
+fn write_zarr(path: &Path, series: &[Series]) -> Result<()> {
+    let write = |file: &Path, contents: Vec<u8>| {
+        fs::write(file, contents).map_err(|err| Error::io("write time series to", file, err))
+    };
+    let metadata = |value: serde_json::Value| value.to_string().into_bytes();
+
+    fs::create_dir_all(path).map_err(|err| Error::io("create", path, err))?;
+    write(&path.join(".zgroup"), metadata(json!({ "zarr_format": 2 })))?;
+
+    for series in series {
+        let array = &series.array;
+        let directory = path.join(&array.name);
+        fs::create_dir_all(&directory).map_err(|err| Error::io("create", &directory, err))?;
+
+        // One chunk holds the whole array, Zarr doesn't allow chunks of length 0
+        let chunks: Vec<usize> = array.shape.iter().map(|length| (*length).max(1)).collect();
+        write(
+            &directory.join(".zarray"),
+            metadata(json!({
+                "zarr_format": 2,
+                "shape": array.shape,
+                "chunks": chunks,
+                "dtype": "<f8",
+                "compressor": null,
+                "fill_value": "NaN",
+                "order": "C",
+                "filters": null,
+            })),
+        )?;
+        write(
+            &directory.join(".zattrs"),
+            metadata(json!({
+                "interval": array.interval,
+                "rows": array.rows,
+                "time": array.time,
+            })),
+        )?;
+
+        if !series.values.is_empty() {
+            let key = vec!["0"; array.shape.len()].join(".");
+            write(&directory.join(key), series.bytes())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the time series of the results to the `--timeseries` file and lists them in the
+/// results, so the JSON output written after refers to them.
+pub fn write_timeseries(args: &AnalyseArgs, results: &mut Results) -> Result<()> {
+    let Some(path) = args.timeseries.as_ref() else {
+        return Ok(());
+    };
+
+    let series = collect(results);
+    if series.is_empty() {
+        warn!(
+            "no time series for {}, they come from --loudness-rate, --activity, --spectral-stats, --pitch, --novelty and --alignment",
+            path.display()
+        );
+    }
+    let zarr = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("zarr"));
+    if zarr {
+        write_zarr(path, &series)?;
+    } else {
+        write_npz(path, &series)?;
+    }
+    output!("Wrote {} time series to {}", series.len(), path.display());
+
+    results.timeseries = Some(TimeSeriesIndex {
+        path: path.display().to_string(),
+        format: if zarr { "zarr" } else { "npz" },
+        arrays: series.into_iter().map(|series| series.array).collect(),
+    });
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::{fmt::Write, path::Path};
+
+use crate::{Error, Result, cli::AnalyseArgs, json::Results, output};
+
+/// CD frames per second, the resolution of cue sheet times.
+const CUE_FRAMES: u64 = 75;
+
+/// A track between two silence gaps, in seconds.
+struct Track {
+    /// Start of the gap before the track, if there is one
+    gap: Option<f64>,
+    start: f64,
+    end: f64,
+}
+
+/// Splits the file at silence gaps of at least `--min-gap`. Silence at the very start or end
+/// of the file doesn't start a track, and gaps that would leave a track shorter than
+/// `--min-track-length` are skipped.
+fn split(args: &AnalyseArgs, results: &Results) -> Vec<Track> {
+    // Tracks are in the file, also with --resample and --from-end
+    let rate = results.sample_rate as f64;
+    let duration = results.duration as f64;
+    let mut tracks = vec![Track {
+        gap: None,
+        start: 0.0,
+        end: duration,
+    }];
+
+    let gaps = results
+        .analysis
+        .silence
+        .iter()
+        .flat_map(|silence| silence.results.iter())
+        .filter(|segment| segment.channel.is_none())
+        .map(|segment| {
+            (
+                results.file_frame(segment.start_sample) as f64 / rate,
+                results.file_frame(segment.end_sample) as f64 / rate,
+            )
+        })
+        .filter(|(start, end)| end - start >= args.min_gap && *start > 0.0 && *end < duration);
+
+    for (gap_start, gap_end) in gaps {
+        let Some(previous) = tracks.last_mut() else {
+            continue;
+        };
+        if gap_start - previous.start < args.min_track_length {
+            continue;
+        }
+
+        previous.end = gap_start;
+        tracks.push(Track {
+            gap: Some(gap_start),
+            start: gap_end,
+            end: duration,
+        });
+    }
+
+    if tracks.len() > 1
+        && let Some(last) = tracks.last()
+        && last.end - last.start < args.min_track_length
+    {
+        tracks.pop();
+        if let Some(last) = tracks.last_mut() {
+            last.end = duration;
+        }
+    }
+
+    tracks
+}
+
+/// `mm:ss:ff` with 75 frames per second, minutes may go past 99.
+fn cue_time(seconds: f64) -> String {
+    let frames = (seconds * CUE_FRAMES as f64).round() as u64;
+
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / (60 * CUE_FRAMES),
+        frames / CUE_FRAMES % 60,
+        frames % CUE_FRAMES
+    )
+}
+
+fn cue_sheet(input: &Path, tracks: &[Track]) -> String {
+    let name = input
+        .file_name()
+        .map_or(input.to_string_lossy(), |name| name.to_string_lossy());
+    let mut cue = format!("FILE \"{}\" WAVE\n", name.replace('"', "'"));
+
+    for (index, track) in tracks.iter().enumerate() {
+        let _ = writeln!(cue, "  TRACK {:02} AUDIO", index + 1);
+        let _ = writeln!(cue, "    TITLE \"Track {:02}\"", index + 1);
+        if let Some(gap) = track.gap {
+            let _ = writeln!(cue, "    INDEX 00 {}", cue_time(gap));
+        }
+        let _ = writeln!(cue, "    INDEX 01 {}", cue_time(track.start));
+    }
+
+    cue
+}
+
+/// Chapters in FFmpeg metadata format, each starting where the audio resumes after a gap.
+fn ffmetadata(tracks: &[Track]) -> String {
+    let mut chapters = String::from(";FFMETADATA1\n");
+    let milliseconds = |seconds: f64| (seconds * 1000.0).round() as u64;
+
+    for (index, track) in tracks.iter().enumerate() {
+        let end = tracks.get(index + 1).map_or(track.end, |next| next.start);
+
+        let _ = write!(
+            chapters,
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle=Track {:02}\n",
+            milliseconds(track.start),
+            milliseconds(end),
+            index + 1
+        );
+    }
+
+    chapters
+}
+
+/// Writes the `--cue` and `--chapters` outputs.
+pub fn write_tracks(args: &AnalyseArgs, results: &Results) -> Result<()> {
+    if args.cue.is_none() && args.chapters.is_none() {
+        return Ok(());
+    }
+
+    let tracks = split(args, results);
+    let input = args
+        .input
+        .first()
+        .map_or(Path::new(""), |input| input.as_path());
+
+    for (path, contents, name) in [
+        (&args.cue, cue_sheet(input, &tracks), "cue sheet"),
+        (&args.chapters, ffmetadata(&tracks), "chapters"),
+    ] {
+        let Some(path) = path else {
+            continue;
+        };
+
+        std::fs::write(path, contents).map_err(|err| Error::io("write tracks to", path, err))?;
+
+        output!(
+            "Wrote {} with {} tracks to {}",
+            name,
+            tracks.len(),
+            path.display()
+        );
+    }
+
+    Ok(())
+}
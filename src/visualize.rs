@@ -0,0 +1,719 @@
+//! Rendering of raw FFT files for `analwave visualize` and the `fft-vis` binary, at their own
+//! size or rescaled, optionally with axes and a colour scale, and of peaks files for
+//! `analwave visualize-peaks` and the `peaks-vis` binary, and of `--loudness-graph` and
+//! `--issues-map` as PNG.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use png::{BitDepth, ColorType, Encoder};
+
+use crate::{
+    Error, Result,
+    analysers::{
+        fft::{FftScale, FftVisualizer},
+        filterbank::hz_to_mel,
+    },
+    cli::{PeaksVisArgs, VisualizeArgs},
+    findings::Kind,
+    report::charts::{LoudnessWindow, loudness_floor},
+    warn,
+};
+
+const BACKGROUND: [u8; 3] = [20, 20, 20];
+const FOREGROUND: [u8; 3] = [200, 200, 200];
+
+/// Margins around the spectrogram for the labels with `--axes`
+const LEFT: usize = 44;
+const RIGHT: usize = 64;
+const TOP: usize = 14;
+const BOTTOM: usize = 20;
+const TICK: usize = 3;
+/// Width of the colour scale and its distance from the spectrogram
+const SCALE_WIDTH: usize = 12;
+const SCALE_GAP: usize = 8;
+/// Space between the channels of a peaks visualization
+const PEAKS_GAP: usize = 2;
+/// Plot size of a loudness graph, and the colours of its parts
+const GRAPH_WIDTH: usize = 1000;
+const GRAPH_HEIGHT: usize = 200;
+const GRID: [u8; 3] = [55, 55, 55];
+const SILENCE: [u8; 3] = [90, 35, 35];
+const LOUDNESS: [u8; 3] = [66, 165, 245];
+const THRESHOLD: [u8; 3] = [229, 57, 53];
+/// Height of a channel of an issues map, and the colours of the issues on it
+const ISSUE_ROW_HEIGHT: usize = 12;
+const ISSUE_ROW: [u8; 3] = [45, 45, 45];
+const ISSUE_SILENCE: [u8; 3] = [66, 165, 245];
+const ISSUE_CLIPPING: [u8; 3] = [255, 167, 38];
+const ISSUE_UNDERRUN: [u8; 3] = [229, 57, 53];
+
+/// Glyphs of the characters in labels, 5 by 7 pixels with the leftmost pixel in bit 4
+const GLYPHS: [(char, [u8; 7]); 18] = [
+    ('0', [14, 17, 19, 21, 25, 17, 14]),
+    ('1', [4, 12, 4, 4, 4, 4, 14]),
+    ('2', [14, 17, 1, 2, 4, 8, 31]),
+    ('3', [31, 2, 4, 2, 1, 17, 14]),
+    ('4', [2, 6, 10, 18, 31, 2, 2]),
+    ('5', [31, 16, 30, 1, 1, 17, 14]),
+    ('6', [6, 8, 16, 30, 17, 17, 14]),
+    ('7', [31, 1, 2, 4, 8, 8, 8]),
+    ('8', [14, 17, 17, 14, 17, 17, 14]),
+    ('9', [14, 17, 17, 15, 1, 2, 12]),
+    ('.', [0, 0, 0, 0, 0, 12, 12]),
+    ('-', [0, 0, 0, 31, 0, 0, 0]),
+    ('k', [16, 16, 18, 20, 24, 20, 18]),
+    ('H', [17, 17, 17, 31, 17, 17, 17]),
+    ('z', [0, 0, 31, 2, 4, 8, 31]),
+    ('s', [0, 0, 14, 16, 14, 1, 30]),
+    ('d', [1, 1, 13, 19, 17, 17, 15]),
+    ('B', [30, 17, 17, 30, 17, 17, 30]),
+];
+const GLYPH_HEIGHT: usize = 7;
+/// Glyphs are a pixel apart
+const ADVANCE: usize = 6;
+
+/// Layout of a raw FFT file, from its `analwave:layout` text chunk (written with
+/// `--deterministic`) or the arguments.
+struct Layout {
+    slices: usize,
+    channels: usize,
+    /// Values per slice of one channel
+    bins: usize,
+    scale: FftScale,
+    db: bool,
+}
+
+impl Layout {
+    fn new(args: &VisualizeArgs, text: Option<&str>, width: usize, height: usize) -> Result<Self> {
+        let field = |key: &str| {
+            text?
+                .split(';')
+                .find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+        };
+
+        let channels = field("channels")
+            .and_then(|channels| channels.parse::<usize>().ok())
+            .unwrap_or(args.channels.into())
+            .max(1);
+        if !width.is_multiple_of(channels) {
+            return Err(Error::Config(format!(
+                "{width} values per slice can't be split into {channels} channels"
+            )));
+        }
+
+        Ok(Self {
+            slices: height,
+            channels,
+            bins: width / channels,
+            scale: match field("scale") {
+                Some("mel") => FftScale::Mel,
+                Some("log") => FftScale::Log,
+                _ => FftScale::Linear,
+            },
+            db: field("amplitude").is_none_or(|amplitude| amplitude == "db"),
+        })
+    }
+
+    /// Seconds between slices, if the sample rate is given. The hop is only known without
+    /// `--hop` for FFT bins, where it is half the FFT size by default.
+    fn slice_duration(&self, args: &VisualizeArgs) -> Option<f64> {
+        let hop = match args.hop {
+            Some(hop) => hop as usize,
+            None if self.scale == FftScale::Linear => self.bins.saturating_sub(1).max(1),
+            None => return None,
+        };
+
+        Some(hop as f64 / f64::from(args.sample_rate?))
+    }
+
+    /// Position of `hz` in bins from the bottom of a channel, if it can be placed. Bands of
+    /// the log scale start at the first bin of an FFT size the file doesn't record.
+    fn bin_of(&self, hz: f64, nyquist: f64) -> Option<f64> {
+        let bins = self.bins as f64;
+        match self.scale {
+            FftScale::Linear => Some(hz / nyquist * (bins - 1.0) + 0.5),
+            FftScale::Mel => Some(hz_to_mel(hz) / hz_to_mel(nyquist) * (bins + 1.0) - 0.5),
+            FftScale::Log => None,
+        }
+    }
+}
+
+/// How `visualize-peaks` draws the peaks of a channel.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PeaksStyle {
+    /// Peak amplitude, mirrored around the middle of the channel
+    #[default]
+    Waveform,
+    /// Peak level in dBFS as colour
+    Heatmap,
+}
+
+/// The values of a PNG holding little-endian f64s in 16 bit RGBA pixels, with its width and
+/// height in values and the text of its `analwave:layout` chunk.
+struct RawPng {
+    values: Vec<f64>,
+    width: usize,
+    height: usize,
+    layout: Option<String>,
+}
+
+fn read_raw_png(input: &Path) -> Result<RawPng> {
+    let decode_error = |err: png::DecodingError| Error::decode(input, err);
+
+    let decoder = png::Decoder::new(BufReader::new(
+        File::open(input).map_err(|err| Error::io("open", input, err))?,
+    ));
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+    let text = reader
+        .info()
+        .uncompressed_latin1_text
+        .iter()
+        .find(|chunk| chunk.keyword == "analwave:layout")
+        .map(|chunk| chunk.text.clone());
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| Error::decode(input, "image is too large"))?;
+    let mut buf = vec![0; size];
+    let info = reader.next_frame(&mut buf).map_err(decode_error)?;
+    let bytes = &buf[..info.buffer_size()];
+
+    Ok(RawPng {
+        values: bytes
+            .chunks_exact(8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect(),
+        width: info.width as usize,
+        height: info.height as usize,
+        layout: text,
+    })
+}
+
+/// Render a raw FFT output file (little-endian f64s in a 16 bit RGBA PNG) as a spectrogram.
+pub fn visualize(args: &VisualizeArgs) -> Result<()> {
+    let raw = read_raw_png(&args.input)?;
+
+    let mut vis = FftVisualizer::new(&args.output);
+    vis.colormap = args.colormap;
+    vis.extend(raw.values);
+
+    if vis.min.is_some() {
+        vis.min = args.min_db.or(vis.min);
+        vis.max = args.max_db.or(vis.max);
+    }
+    if let (Some(min), Some(max)) = (vis.min, vis.max)
+        && (args.min_db.is_some() || args.max_db.is_some())
+        && min >= max
+    {
+        return Err(Error::Config(format!(
+            "the lowest value shown ({min}) has to be below the highest ({max})"
+        )));
+    }
+
+    let (width, height) = (raw.width, raw.height);
+    if args.width.is_none() && args.height.is_none() && !args.axes {
+        return vis.visualize(width, height);
+    }
+    if vis.min.is_none() {
+        warn!("FFT visualization: no valid data to visualize");
+
+        return Ok(());
+    }
+
+    let layout = Layout::new(args, raw.layout.as_deref(), width, height)?;
+    render(args, &vis, &layout)
+}
+
+/// An RGB image to draw on.
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl Canvas {
+    fn put(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    /// Draws `text` with its top left corner at `x`, `y`, skipping characters without a glyph.
+    fn text(&mut self, x: usize, y: usize, text: &str) {
+        for (index, c) in text.chars().enumerate() {
+            let Some((_, rows)) = GLYPHS.iter().find(|(glyph, _)| *glyph == c) else {
+                continue;
+            };
+
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..5 {
+                    if bits & (0b10000 >> column) != 0 {
+                        self.put(x + index * ADVANCE + column, y + row, FOREGROUND);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn text_width(text: &str) -> usize {
+    (text.chars().count() * ADVANCE).saturating_sub(1)
+}
+
+/// A round distance between about `count` ticks over `span`.
+fn tick_step(span: f64, count: usize) -> f64 {
+    let rough = span / count.max(1) as f64;
+    if rough <= 0.0 || !rough.is_finite() {
+        return 1.0;
+    }
+
+    let magnitude = 10f64.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * magnitude)
+        .find(|step| *step >= rough)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Multiples of `step` from `min` to `max`.
+fn ticks(min: f64, max: f64, step: f64) -> impl Iterator<Item = f64> {
+    let first = (min / step).ceil() as i64;
+    let last = (max / step + 1e-9).floor() as i64;
+
+    (first..=last).map(move |i| i as f64 * step)
+}
+
+/// `value` with as many decimals as `step` needs.
+fn label(value: f64, step: f64) -> String {
+    let decimals = if step >= 1.0 {
+        0
+    } else {
+        (-step.log10().floor()) as usize
+    };
+
+    format!("{value:.decimals$}")
+}
+
+fn render(args: &VisualizeArgs, vis: &FftVisualizer, layout: &Layout) -> Result<()> {
+    let values = layout.channels * layout.bins;
+    let plot_width = args.width.map_or(layout.slices, |width| width as usize);
+    let plot_height = args.height.map_or(values, |height| height as usize);
+    let (left, top) = if args.axes { (LEFT, TOP) } else { (0, 0) };
+
+    let mut canvas = Canvas {
+        width: plot_width + if args.axes { LEFT + RIGHT } else { 0 },
+        height: plot_height + if args.axes { TOP + BOTTOM } else { 0 },
+        pixels: Vec::new(),
+    };
+    canvas.pixels = vec![BACKGROUND; canvas.width * canvas.height];
+
+    // Every pixel is the mean of the values it covers, or the nearest when enlarged
+    let span = |index: usize, pixels: usize, total: usize| {
+        let start = index * total / pixels;
+        start..((index + 1) * total / pixels).max(start + 1)
+    };
+    for x in 0..plot_width {
+        let slices = span(x, plot_width, layout.slices);
+        for row in 0..plot_height {
+            let bins = span(row, plot_height, values);
+            let count = slices.len() * bins.len();
+            let sum: f64 = slices
+                .clone()
+                .flat_map(|slice| &vis.data[slice * values + bins.start..slice * values + bins.end])
+                .sum();
+
+            // Low frequencies and the first channel at the bottom
+            canvas.put(
+                left + x,
+                top + plot_height - 1 - row,
+                vis.color(sum / count as f64),
+            );
+        }
+    }
+
+    if args.axes {
+        draw_axes(&mut canvas, args, vis, layout, plot_width, plot_height);
+    }
+
+    write_png(&args.output, &canvas, "write FFT visualization to")
+}
+
+fn draw_axes(
+    canvas: &mut Canvas,
+    args: &VisualizeArgs,
+    vis: &FftVisualizer,
+    layout: &Layout,
+    plot_width: usize,
+    plot_height: usize,
+) {
+    let bottom = TOP + plot_height;
+    for x in LEFT - 1..LEFT + plot_width {
+        canvas.put(x, bottom, FOREGROUND);
+    }
+    for y in TOP..=bottom {
+        canvas.put(LEFT - 1, y, FOREGROUND);
+    }
+
+    // Time along the bottom, in seconds if the sample rate is known and in slices otherwise
+    let slice_duration = layout.slice_duration(args);
+    let duration = layout.slices as f64 * slice_duration.unwrap_or(1.0);
+    let step = tick_step(duration, plot_width / 70);
+    for time in ticks(0.0, duration, step) {
+        let x = LEFT + (time / duration * plot_width as f64) as usize;
+        for y in bottom + 1..=bottom + TICK {
+            canvas.put(x, y, FOREGROUND);
+        }
+
+        let mut text = label(time, step);
+        if slice_duration.is_some() {
+            text.push('s');
+        }
+        let x = x.saturating_sub(text_width(&text) / 2);
+        canvas.text(x, bottom + TICK + 3, &text);
+    }
+
+    // Frequency up the left of every channel, in Hz if they can be placed and in bins otherwise
+    let nyquist = args.sample_rate.map(|rate| f64::from(rate) / 2.0);
+    let in_hz = nyquist.is_some() && layout.scale != FftScale::Log;
+    let channel_height = plot_height as f64 / layout.channels as f64;
+    let per_bin = channel_height / layout.bins as f64;
+    if in_hz {
+        canvas.text(LEFT - 1 - text_width("Hz"), TOP - GLYPH_HEIGHT - 4, "Hz");
+    }
+
+    // Labels closer than a line to the one below are left out, e.g. at the top of mel bands
+    let mut last_y = usize::MAX;
+    for channel in 0..layout.channels {
+        let base = bottom as f64 - channel as f64 * channel_height;
+        let count = (channel_height / 20.0) as usize;
+        let ticks: Vec<(f64, String)> = match nyquist.filter(|_| in_hz) {
+            Some(nyquist) => {
+                let step = tick_step(nyquist, count);
+                ticks(0.0, nyquist, step)
+                    .filter_map(|hz| {
+                        let text = if step >= 1000.0 && hz > 0.0 {
+                            format!("{}k", label(hz / 1000.0, step / 1000.0))
+                        } else {
+                            label(hz, step)
+                        };
+                        Some((layout.bin_of(hz, nyquist)?, text))
+                    })
+                    .collect()
+            }
+            None => {
+                let step = tick_step(layout.bins as f64, count);
+                ticks(0.0, (layout.bins - 1) as f64, step)
+                    .map(|bin| (bin + 0.5, label(bin, step)))
+                    .collect()
+            }
+        };
+
+        for (bin, text) in ticks {
+            let y = (base - bin.clamp(0.0, layout.bins as f64) * per_bin)
+                .clamp(TOP as f64, bottom as f64 - 1.0) as usize;
+            if last_y.saturating_sub(y) < GLYPH_HEIGHT + 2 {
+                continue;
+            }
+            last_y = y;
+
+            for x in LEFT - 1 - TICK..LEFT - 1 {
+                canvas.put(x, y, FOREGROUND);
+            }
+            let x = (LEFT - TICK - 3).saturating_sub(text_width(&text));
+            canvas.text(x, y.saturating_sub(GLYPH_HEIGHT / 2), &text);
+        }
+    }
+
+    // Colour scale on the right, from the lowest value shown at the bottom to the highest
+    let (Some(min), Some(max)) = (vis.min, vis.max) else {
+        return;
+    };
+    let scale_left = LEFT + plot_width + SCALE_GAP;
+    for row in 0..plot_height {
+        let value = min + (max - min) * (row as f64 + 0.5) / plot_height as f64;
+        for x in scale_left..scale_left + SCALE_WIDTH {
+            canvas.put(x, bottom - 1 - row, vis.color(value));
+        }
+    }
+    if layout.db {
+        canvas.text(scale_left, TOP - GLYPH_HEIGHT - 4, "dB");
+    }
+
+    let step = tick_step(max - min, plot_height / 24);
+    for value in ticks(min, max, step) {
+        let y = bottom - 1 - ((value - min) / (max - min) * (plot_height - 1) as f64) as usize;
+        for x in scale_left + SCALE_WIDTH..scale_left + SCALE_WIDTH + TICK {
+            canvas.put(x, y, FOREGROUND);
+        }
+        canvas.text(
+            scale_left + SCALE_WIDTH + TICK + 2,
+            y.saturating_sub(GLYPH_HEIGHT / 2),
+            &label(value, step),
+        );
+    }
+}
+
+/// Render a peaks output file (the PNG format, a square of values per channel) with the
+/// channels stacked from top to bottom.
+pub fn visualize_peaks(args: &PeaksVisArgs) -> Result<()> {
+    let raw = read_raw_png(&args.input)?;
+
+    let side = raw.width;
+    if side == 0 || !raw.height.is_multiple_of(side) {
+        return Err(Error::decode(
+            &args.input,
+            format!(
+                "a peaks file has a square of values per channel, not {}x{}",
+                raw.width, raw.height
+            ),
+        ));
+    }
+
+    // Without a layout chunk, the padding of the squares is drawn as silence
+    let square = side * side;
+    let channel_size = raw
+        .layout
+        .as_deref()
+        .and_then(|layout| {
+            layout
+                .split(';')
+                .find_map(|field| field.strip_prefix("channelSize="))
+        })
+        .and_then(|size| size.parse::<usize>().ok())
+        .map_or(square, |size| size.min(square));
+    if channel_size == 0 {
+        warn!("Peaks visualization: no peaks to visualize");
+
+        return Ok(());
+    }
+
+    // Peaks are in dB of the 32 bit sample values
+    let full_scale = 20.0 * f64::from(i32::MAX).log10();
+    let width = args.width as usize;
+    let channel_height = args.channel_height as usize;
+    let channels: Vec<&[f64]> = raw
+        .values
+        .chunks_exact(square)
+        .map(|values| &values[..channel_size])
+        .collect();
+
+    let mut canvas = Canvas {
+        width,
+        height: channels.len() * (channel_height + PEAKS_GAP) - PEAKS_GAP,
+        pixels: Vec::new(),
+    };
+    canvas.pixels = vec![BACKGROUND; canvas.width * canvas.height];
+
+    for (index, peaks) in channels.iter().enumerate() {
+        let top = index * (channel_height + PEAKS_GAP);
+
+        for x in 0..width {
+            // The largest peak the pixel covers, or the nearest when enlarged
+            let start = x * channel_size / width;
+            let end = ((x + 1) * channel_size / width).max(start + 1);
+            let level = peaks[start..end]
+                .iter()
+                .fold(f64::NEG_INFINITY, |peak, value| peak.max(*value))
+                - full_scale;
+
+            match args.style {
+                PeaksStyle::Waveform => {
+                    let amplitude = 10f64.powf(level / 20.0).clamp(0.0, 1.0);
+                    let half = (amplitude * channel_height as f64 / 2.0) as usize;
+                    let middle = top + channel_height / 2;
+                    let bottom = (middle + half).min(top + channel_height - 1);
+                    for y in middle.saturating_sub(half)..=bottom {
+                        canvas.put(x, y, FOREGROUND);
+                    }
+                }
+                PeaksStyle::Heatmap => {
+                    let value = if args.floor < 0.0 {
+                        (level - args.floor) / -args.floor
+                    } else {
+                        f64::from(level >= 0.0)
+                    };
+                    let color = args.colormap.color(value.clamp(0.0, 1.0));
+                    for y in top..top + channel_height {
+                        canvas.put(x, y, color);
+                    }
+                }
+            }
+        }
+    }
+
+    write_png(&args.output, &canvas, "write peaks visualization to")
+}
+
+/// Render the short-term loudness over time like the loudness chart of the HTML report, with
+/// silence regions shaded and the silence threshold as a dashed line.
+pub(crate) fn loudness_graph_png(
+    path: &Path,
+    windows: &[LoudnessWindow],
+    duration: f64,
+    threshold: f64,
+    silence: &[(f64, f64)],
+) -> Result<()> {
+    let floor = loudness_floor(Some(threshold));
+    let bottom = TOP + GRAPH_HEIGHT - 1;
+    let x = |t: f64| {
+        LEFT + ((t / duration.max(f64::EPSILON)).clamp(0.0, 1.0) * (GRAPH_WIDTH - 1) as f64)
+            as usize
+    };
+    let y = |lufs: f64| {
+        TOP + ((lufs.max(floor) / floor).clamp(0.0, 1.0) * (GRAPH_HEIGHT - 1) as f64) as usize
+    };
+
+    let mut canvas = Canvas {
+        width: LEFT + GRAPH_WIDTH + TOP,
+        height: TOP + GRAPH_HEIGHT + BOTTOM,
+        pixels: Vec::new(),
+    };
+    canvas.pixels = vec![BACKGROUND; canvas.width * canvas.height];
+
+    for (start, end) in silence {
+        for column in x(*start)..=x(*end) {
+            for row in TOP..=bottom {
+                canvas.put(column, row, SILENCE);
+            }
+        }
+    }
+
+    for lufs in (floor as i32..=0).rev().step_by(10) {
+        let row = y(lufs as f64);
+        for column in LEFT..LEFT + GRAPH_WIDTH {
+            canvas.put(column, row, GRID);
+        }
+        let text = lufs.to_string();
+        let column = (LEFT - TICK - 3).saturating_sub(text_width(&text));
+        canvas.text(column, row.saturating_sub(GLYPH_HEIGHT / 2), &text);
+    }
+
+    for tick in 0..=4 {
+        let time = duration * tick as f64 / 4.0;
+        let text = format!("{time:.1}s");
+        let column = x(time).saturating_sub(text_width(&text) / 2);
+        canvas.text(column, bottom + TICK + 3, &text);
+    }
+
+    // Stepped, with the steps between windows drawn as vertical lines
+    let mut previous: Option<usize> = None;
+    for window in windows {
+        let row = y(window.loudness);
+        let column = x(window.start);
+        if let Some(previous) = previous {
+            for step in previous.min(row)..=previous.max(row) {
+                canvas.put(column, step, LOUDNESS);
+            }
+        }
+        for column in column..=x(window.end) {
+            canvas.put(column, row, LOUDNESS);
+        }
+        previous = Some(row);
+    }
+
+    let row = y(threshold);
+    for column in (LEFT..LEFT + GRAPH_WIDTH).filter(|column| (column - LEFT) % 10 < 6) {
+        canvas.put(column, row, THRESHOLD);
+    }
+
+    write_png(path, &canvas, "write loudness graph to")
+}
+
+/// Render a row per channel over time with the silence, clipping and underruns found in it, for
+/// an overview of where they cluster. `issues` are the kind, the channel (`None` for all) and
+/// the start and end in seconds. Underruns are drawn over clipping over silence.
+pub(crate) fn issues_map_png(
+    path: &Path,
+    channels: usize,
+    duration: f64,
+    issues: &[(Kind, Option<usize>, f64, f64)],
+) -> Result<()> {
+    let channels = channels.max(1);
+    let bottom = TOP + channels * (ISSUE_ROW_HEIGHT + PEAKS_GAP) - PEAKS_GAP;
+    let x = |t: f64| {
+        LEFT + ((t / duration.max(f64::EPSILON)).clamp(0.0, 1.0) * (GRAPH_WIDTH - 1) as f64)
+            as usize
+    };
+    let row_top = |channel: usize| TOP + channel * (ISSUE_ROW_HEIGHT + PEAKS_GAP);
+
+    let mut canvas = Canvas {
+        width: LEFT + GRAPH_WIDTH + TOP,
+        height: bottom + BOTTOM,
+        pixels: Vec::new(),
+    };
+    canvas.pixels = vec![BACKGROUND; canvas.width * canvas.height];
+
+    for channel in 0..channels {
+        for row in row_top(channel)..row_top(channel) + ISSUE_ROW_HEIGHT {
+            for column in LEFT..LEFT + GRAPH_WIDTH {
+                canvas.put(column, row, ISSUE_ROW);
+            }
+        }
+        let text = channel.to_string();
+        let column = (LEFT - TICK - 3).saturating_sub(text_width(&text));
+        let row = row_top(channel) + (ISSUE_ROW_HEIGHT - GLYPH_HEIGHT) / 2;
+        canvas.text(column, row, &text);
+    }
+
+    for kind in [Kind::Silence, Kind::Clipping, Kind::Underrun] {
+        let color = match kind {
+            Kind::Silence => ISSUE_SILENCE,
+            Kind::Clipping => ISSUE_CLIPPING,
+            _ => ISSUE_UNDERRUN,
+        };
+
+        for (_, channel, start, end) in issues.iter().filter(|issue| issue.0 == kind) {
+            let rows = match channel {
+                Some(channel) if *channel < channels => *channel..*channel + 1,
+                Some(_) => continue,
+                None => 0..channels,
+            };
+            // Issues shorter than a pixel still get one
+            for column in x(*start)..=x(*end) {
+                for channel in rows.clone() {
+                    for row in row_top(channel)..row_top(channel) + ISSUE_ROW_HEIGHT {
+                        canvas.put(column, row, color);
+                    }
+                }
+            }
+        }
+    }
+
+    let step = tick_step(duration, GRAPH_WIDTH / 70);
+    for time in ticks(0.0, duration, step) {
+        let column = x(time);
+        for row in bottom..bottom + TICK {
+            canvas.put(column, row, FOREGROUND);
+        }
+
+        let text = format!("{}s", label(time, step));
+        canvas.text(
+            column.saturating_sub(text_width(&text) / 2),
+            bottom + TICK + 3,
+            &text,
+        );
+    }
+
+    write_png(path, &canvas, "write issues map to")
+}
+
+fn write_png(path: &Path, canvas: &Canvas, operation: &'static str) -> Result<()> {
+    let write_error = |err| Error::io(operation, path, err);
+    let file = File::create(path).map_err(write_error)?;
+    let mut w = BufWriter::new(file);
+
+    let mut encoder = Encoder::new(&mut w, canvas.width as u32, canvas.height as u32);
+    encoder.set_color(ColorType::Rgb);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut writer = encoder
+        .write_header()
+        .map_err(|err| write_error(err.into()))?;
+    writer
+        .write_image_data(canvas.pixels.as_flattened())
+        .map_err(|err| write_error(err.into()))
+}
@@ -0,0 +1,70 @@
+//! JavaScript bindings for analysing WAV data in the browser, built with the `wasm` feature.
+
+use std::path::Path;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Error, Result, cli::AnalyseArgs, input::Input, json::to_json, pipeline};
+
+/// Return code and JSON output of one analysis.
+#[wasm_bindgen]
+pub struct Report {
+    exit_code: u8,
+    json: String,
+}
+
+#[wasm_bindgen]
+impl Report {
+    /// Return code bits of the checks, as the command line tool exits with.
+    #[wasm_bindgen(getter, js_name = exitCode)]
+    pub fn exit_code(&self) -> u8 {
+        self.exit_code
+    }
+
+    /// The document `--json` writes.
+    #[wasm_bindgen(getter)]
+    pub fn json(&self) -> String {
+        self.json.clone()
+    }
+}
+
+/// Rejects the options that need a filesystem, threads or a clock, none of which the browser
+/// has.
+fn check_options(args: &AnalyseArgs) -> Result<()> {
+    let unsupported = [
+        ("--input", !args.input.is_empty()),
+        ("--timings", args.timings),
+        ("--threads", args.threads > 1),
+        ("--timeout", args.timeout.is_some()),
+    ]
+    .into_iter()
+    .find(|(_, used)| *used)
+    .map(|(option, _)| option);
+
+    match unsupported.or(args.file_output()) {
+        Some(option) => Err(Error::Config(format!(
+            "{option} is not available in the browser"
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn run(wav: Vec<u8>, options: Vec<String>) -> Result<Report> {
+    let args = AnalyseArgs::parse_options(options)?;
+    check_options(&args)?;
+
+    let mut input = Input::from_bytes(&args, wav)?;
+    let analysers = pipeline::analysers(&args, &input.spec())?;
+    let (exit_code, results) = pipeline::run(&args, &mut input, analysers)?;
+    pipeline::check_input(&args, Path::new("input"), &results)?;
+    let json = to_json(&results).map_err(|err| Error::analyser("json", err))?;
+
+    Ok(Report { exit_code, json })
+}
+
+/// Analyses a WAV file with the command line options of `analyse`, e.g.
+/// `analyse(new Uint8Array(buffer), ["--underrun", "--lufs=-50"])`.
+#[wasm_bindgen]
+pub fn analyse(wav: Vec<u8>, options: Vec<String>) -> Result<Report, JsError> {
+    run(wav, options).map_err(|err| JsError::new(&err.to_string()))
+}